@@ -0,0 +1,131 @@
+//! Conversion latency with pandoc spawned per request versus the pool of
+//! `pandoc server` workers:
+//!
+//!     cargo bench --bench pandoc_backends
+//!
+//! Needs pandoc (with `pandoc server`) on the PATH or in
+//! `MARKXIV_PANDOC_PATH`; `MARKXIV_BENCH_RUNS` sets the runs per case.
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use markxiv::convert::{Converter, LatexOptions, PandocBackend, PandocConverter, PANDOC_TIMEOUT};
+use markxiv::pandoc_pool::{PandocPool, PandocPoolConfig};
+use markxiv::report::ConversionReport;
+
+const SMALL_PAPER: &str = r"\documentclass{article}
+\title{A small paper}
+\begin{document}
+\maketitle
+\section{Introduction}
+We show that $a^2 + b^2 = c^2$ for right triangles.
+\begin{equation}
+  \int_0^1 x\,dx = \frac{1}{2}
+\end{equation}
+\section{Conclusion}
+Short papers are mostly process startup.
+\end{document}
+";
+
+fn small_tar() -> std::io::Result<Vec<u8>> {
+    let dir = std::env::temp_dir().join(format!("markxiv-bench-{}", std::process::id()));
+    std::fs::create_dir_all(&dir)?;
+    std::fs::write(dir.join("main.tex"), SMALL_PAPER)?;
+    let status = std::process::Command::new("tar")
+        .current_dir(&dir)
+        .args(["-cf", "paper.tar", "main.tex"])
+        .status()?;
+    if !status.success() {
+        return Err(std::io::Error::other("tar failed"));
+    }
+    let bytes = std::fs::read(dir.join("paper.tar"));
+    let _ = std::fs::remove_dir_all(&dir);
+    bytes
+}
+
+async fn time_runs(converter: &PandocConverter, tar: &[u8], runs: usize) -> Vec<Duration> {
+    // One untimed run warms up the file cache and, for the pool, the workers.
+    let opts = LatexOptions::default();
+    let _ = converter
+        .latex_tar_to_markdown(tar, &opts, &mut ConversionReport::new())
+        .await;
+    let mut times = Vec::with_capacity(runs);
+    for _ in 0..runs {
+        let start = Instant::now();
+        if let Err(e) = converter
+            .latex_tar_to_markdown(tar, &opts, &mut ConversionReport::new())
+            .await
+        {
+            eprintln!("  conversion failed: {}", e);
+        }
+        times.push(start.elapsed());
+    }
+    times.sort();
+    times
+}
+
+fn summary(times: &[Duration]) -> String {
+    let ms = |d: Duration| d.as_secs_f64() * 1000.0;
+    let mean = times.iter().sum::<Duration>() / times.len().max(1) as u32;
+    format!(
+        "min {:7.1} ms  median {:7.1} ms  mean {:7.1} ms",
+        ms(times[0]),
+        ms(times[times.len() / 2]),
+        ms(mean)
+    )
+}
+
+#[tokio::main]
+async fn main() {
+    let pandoc = std::env::var("MARKXIV_PANDOC_PATH").unwrap_or_else(|_| "pandoc".into());
+    if std::process::Command::new(&pandoc)
+        .arg("--version")
+        .output()
+        .is_err()
+    {
+        eprintln!("pandoc not found; skipping backend benchmarks");
+        return;
+    }
+    let runs = std::env::var("MARKXIV_BENCH_RUNS")
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(10)
+        .max(1);
+
+    let mut cases = Vec::new();
+    match small_tar() {
+        Ok(tar) => cases.push(("small paper", tar)),
+        Err(e) => eprintln!("could not build the small paper: {}", e),
+    }
+    let fixture = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/data/2509.17765.tar");
+    if let Ok(tar) = std::fs::read(&fixture) {
+        cases.push(("2509.17765", tar));
+    }
+
+    let pool = match PandocPool::start(PandocPoolConfig {
+        pandoc,
+        workers: 1,
+        timeout: PANDOC_TIMEOUT,
+        health_interval: Duration::from_secs(30),
+    })
+    .await
+    {
+        Ok(pool) => Some(pool),
+        Err(e) => {
+            eprintln!(
+                "pandoc server unavailable ({}); benchmarking spawn mode only",
+                e
+            );
+            None
+        }
+    };
+    let spawn = PandocConverter::new();
+    let pooled = pool.map(|p| PandocConverter::with_backend(PandocBackend::Pool(p)));
+
+    for (name, tar) in &cases {
+        println!("{} ({} runs)", name, runs);
+        println!("  spawn  {}", summary(&time_runs(&spawn, tar, runs).await));
+        if let Some(pooled) = &pooled {
+            println!("  pool   {}", summary(&time_runs(pooled, tar, runs).await));
+        }
+    }
+}
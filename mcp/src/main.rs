@@ -2,7 +2,10 @@ use std::fmt;
 use std::sync::Arc;
 
 use markxiv::arxiv::{ArxivClient, ArxivError, ReqwestArxivClient};
-use markxiv::convert::{ConvertError, Converter, PandocConverter};
+use markxiv::convert::{ConvertError, Converter, LatexOptions, PandocConverter};
+use markxiv::eprint;
+use markxiv::header::{prepend_metadata, BodySource};
+use markxiv::report::ConversionReport;
 use rmcp::{
     handler::server::router::tool::ToolRouter,
     handler::server::wrapper::Parameters,
@@ -71,62 +74,12 @@ impl MarkxivMcp {
         &self,
         Parameters(params): Parameters<ConvertPaperParams>,
     ) -> Result<String, String> {
-        let paper_id = params.paper_id.trim().to_string();
-        if paper_id.is_empty() || !paper_id.is_ascii() {
-            return Err("invalid paper ID".into());
-        }
-
-        // Fetch metadata
-        let metadata = match self.client.get_metadata(&paper_id).await {
-            Ok(m) => Some(m),
-            Err(ArxivError::NotFound) => return Err(format!("paper '{}' not found", paper_id)),
-            Err(ArxivError::NotImplemented) => None,
-            Err(e) => return Err(format!("metadata fetch failed: {}", e)),
-        };
-
-        // Try LaTeX source first
-        let (body, used_pdf) = match self.client.get_source_archive(&paper_id).await {
-            Ok(bytes) => match self.converter.latex_tar_to_markdown(&bytes).await {
-                Ok(md) => (md, false),
-                Err(_) => {
-                    match self
-                        .converter
-                        .latex_tar_to_markdown_without_macros(&bytes)
-                        .await
-                    {
-                        Ok(md) => (md, false),
-                        Err(_) => self.try_pdf_fallback(&paper_id).await?,
-                    }
-                }
-            },
-            Err(ArxivError::PdfOnly) => self.try_pdf_fallback(&paper_id).await?,
-            Err(ArxivError::NotFound) => return Err(format!("paper '{}' not found", paper_id)),
-            Err(e) => return Err(format!("source fetch failed: {}", e)),
-        };
-
-        // Prepend metadata if we didn't use PDF fallback
-        if !used_pdf {
-            if let Some(meta) = metadata {
-                let mut out = String::new();
-                if !meta.title.is_empty() {
-                    out.push_str(&format!("# {}\n\n", meta.title.trim()));
-                }
-                if !meta.authors.is_empty() {
-                    out.push_str("## Authors\n");
-                    out.push_str(&meta.authors.join(", "));
-                    out.push_str("\n\n");
-                }
-                if !meta.summary.is_empty() {
-                    out.push_str("## Abstract\n");
-                    out.push_str(meta.summary.trim());
-                    out.push_str("\n\n");
-                }
-                out.push_str(&body);
-                return Ok(out);
-            }
-        }
-
-        Ok(body)
+        convert_paper(
+            self.client.as_ref(),
+            self.converter.as_ref(),
+            params.paper_id.trim(),
+        )
+        .await
     }
 
     #[tool(
@@ -216,26 +169,91 @@ impl MarkxivMcp {
     }
 }
 
-impl MarkxivMcp {
-    async fn try_pdf_fallback(&self, paper_id: &str) -> Result<(String, bool), String> {
-        let pdf_bytes = self.client.get_pdf(paper_id).await.map_err(|e| match e {
-            ArxivError::NotFound => format!("paper '{}' not found", paper_id),
-            other => format!("PDF fetch failed: {}", other),
-        })?;
+/// The `convert_paper` tool: the paper's Markdown with the same header as
+/// the HTTP route, falling back from LaTeX to the PDF text.
+async fn convert_paper(
+    client: &(dyn ArxivClient + Send + Sync),
+    converter: &(dyn Converter + Send + Sync),
+    paper_id: &str,
+) -> Result<String, String> {
+    if paper_id.is_empty() || !paper_id.is_ascii() {
+        return Err("invalid paper ID".into());
+    }
 
-        let text = self
-            .converter
-            .pdf_to_markdown(&pdf_bytes)
+    // Fetch metadata
+    let metadata = match client.get_metadata(paper_id).await {
+        Ok(m) => Some(m),
+        Err(ArxivError::NotFound) => return Err(format!("paper '{}' not found", paper_id)),
+        Err(ArxivError::NotImplemented) => None,
+        Err(e) => return Err(format!("metadata fetch failed: {}", e)),
+    };
+
+    // Try LaTeX source first
+    // Diagnostics are only served over HTTP; the tool returns Markdown
+    // with the report's front matter in its header.
+    let mut report = ConversionReport::new();
+    let (body, source) = match client.get_source_archive(paper_id).await {
+        Ok(bytes) if eprint::sniff(&bytes).legacy().is_some() => {
+            match converter.legacy_eprint_to_markdown(&bytes).await {
+                Ok((md, format)) => (md, BodySource::Legacy(format)),
+                Err(_) => pdf_fallback(client, converter, paper_id).await?,
+            }
+        }
+        Ok(bytes) => match converter
+            .latex_tar_to_markdown(&bytes, &LatexOptions::default(), &mut report)
             .await
-            .map_err(|e| match e {
-                ConvertError::Failed(msg) => {
-                    format!("conversion failed (both LaTeX and PDF): {}", msg)
+        {
+            Ok(out) => (out.markdown, BodySource::Latex),
+            Err(_) => {
+                match converter
+                    .latex_tar_to_markdown_without_macros(
+                        &bytes,
+                        &LatexOptions::default(),
+                        &mut report,
+                    )
+                    .await
+                {
+                    Ok(out) => (out.markdown, BodySource::Latex),
+                    Err(_) => pdf_fallback(client, converter, paper_id).await?,
                 }
-                ConvertError::NotImplemented => "PDF conversion not implemented".into(),
-            })?;
+            }
+        },
+        Err(ArxivError::PdfOnly) => pdf_fallback(client, converter, paper_id).await?,
+        Err(ArxivError::NotFound) => return Err(format!("paper '{}' not found", paper_id)),
+        Err(e) => return Err(format!("source fetch failed: {}", e)),
+    };
+
+    // Same header as the HTTP route, with a provenance note for PDF text
+    Ok(prepend_metadata(
+        metadata.as_ref(),
+        &report.front_matter,
+        &body,
+        source,
+    ))
+}
 
-        Ok((text, true))
-    }
+async fn pdf_fallback(
+    client: &(dyn ArxivClient + Send + Sync),
+    converter: &(dyn Converter + Send + Sync),
+    paper_id: &str,
+) -> Result<(String, BodySource), String> {
+    let pdf_bytes = client.get_pdf(paper_id).await.map_err(|e| match e {
+        ArxivError::NotFound => format!("paper '{}' not found", paper_id),
+        other => format!("PDF fetch failed: {}", other),
+    })?;
+
+    let text = converter
+        .pdf_to_markdown(&pdf_bytes)
+        .await
+        .map_err(|e| match e {
+            ConvertError::Failed(msg) => {
+                format!("conversion failed (both LaTeX and PDF): {}", msg)
+            }
+            ConvertError::NotImplemented => "PDF conversion not implemented".into(),
+            other => other.to_string(),
+        })?;
+
+    Ok((text, BodySource::PdfText))
 }
 
 #[tool_handler]
@@ -272,80 +290,16 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
 #[cfg(test)]
 mod tests {
+    use std::sync::atomic::Ordering;
+
     use bytes::Bytes;
     use markxiv::arxiv::test_helpers::MockArxivClient;
     use markxiv::arxiv::{ArxivClient, ArxivError, Metadata, SearchResult};
     use markxiv::convert::test_helpers::MockConverter;
-    use markxiv::convert::Converter;
+    use markxiv::eprint::LegacyFormat;
+    use markxiv::header::PDF_TEXT_NOTE;
 
-    /// Replicate the convert_paper logic to verify output matches library.
-    async fn run_convert_paper(
-        client: &(dyn ArxivClient + Send + Sync),
-        converter: &(dyn Converter + Send + Sync),
-        paper_id: &str,
-    ) -> Result<String, String> {
-        let metadata = match client.get_metadata(paper_id).await {
-            Ok(m) => Some(m),
-            Err(ArxivError::NotFound) => return Err(format!("paper '{}' not found", paper_id)),
-            Err(ArxivError::NotImplemented) => None,
-            Err(e) => return Err(format!("metadata fetch failed: {}", e)),
-        };
-
-        let (body, used_pdf) = match client.get_source_archive(paper_id).await {
-            Ok(bytes) => match converter.latex_tar_to_markdown(&bytes).await {
-                Ok(md) => (md, false),
-                Err(_) => match converter.latex_tar_to_markdown_without_macros(&bytes).await {
-                    Ok(md) => (md, false),
-                    Err(_) => {
-                        let pdf_bytes = client
-                            .get_pdf(paper_id)
-                            .await
-                            .map_err(|e| format!("PDF fetch failed: {}", e))?;
-                        let text = converter
-                            .pdf_to_markdown(&pdf_bytes)
-                            .await
-                            .map_err(|e| format!("conversion failed: {}", e))?;
-                        (text, true)
-                    }
-                },
-            },
-            Err(ArxivError::PdfOnly) => {
-                let pdf_bytes = client
-                    .get_pdf(paper_id)
-                    .await
-                    .map_err(|e| format!("PDF fetch failed: {}", e))?;
-                let text = converter
-                    .pdf_to_markdown(&pdf_bytes)
-                    .await
-                    .map_err(|e| format!("conversion failed: {}", e))?;
-                (text, true)
-            }
-            Err(ArxivError::NotFound) => return Err(format!("paper '{}' not found", paper_id)),
-            Err(e) => return Err(format!("source fetch failed: {}", e)),
-        };
-
-        if !used_pdf {
-            if let Some(meta) = metadata {
-                let mut out = String::new();
-                if !meta.title.is_empty() {
-                    out.push_str(&format!("# {}\n\n", meta.title.trim()));
-                }
-                if !meta.authors.is_empty() {
-                    out.push_str("## Authors\n");
-                    out.push_str(&meta.authors.join(", "));
-                    out.push_str("\n\n");
-                }
-                if !meta.summary.is_empty() {
-                    out.push_str("## Abstract\n");
-                    out.push_str(meta.summary.trim());
-                    out.push_str("\n\n");
-                }
-                out.push_str(&body);
-                return Ok(out);
-            }
-        }
-        Ok(body)
-    }
+    use super::convert_paper;
 
     #[tokio::test]
     async fn convert_paper_latex_output_has_metadata_and_body() {
@@ -364,17 +318,17 @@ mod tests {
             Ok(String::new()),
         );
 
-        let out = run_convert_paper(&client, &converter, "1706.03762")
+        let out = convert_paper(&client, &converter, "1706.03762")
             .await
             .unwrap();
         assert!(out.starts_with("# Attention Is All You Need\n\n"));
         assert!(out.contains("## Authors\nVaswani, Shazeer"));
         assert!(out.contains("## Abstract\nThe dominant sequence"));
-        assert!(out.contains("## Introduction\nWe propose"));
+        assert!(out.contains("## Introduction<a id=\"introduction\"></a>\nWe propose"));
     }
 
     #[tokio::test]
-    async fn convert_paper_pdf_fallback_returns_pdf_text() {
+    async fn convert_paper_pdf_fallback_has_metadata_and_note() {
         let client = MockArxivClient::new(
             Ok(true),
             Err(ArxivError::PdfOnly),
@@ -387,11 +341,37 @@ mod tests {
         );
         let converter = MockConverter::new(Ok(String::new()), Ok("extracted pdf text".into()));
 
-        let out = run_convert_paper(&client, &converter, "1234.5678")
+        let out = convert_paper(&client, &converter, "1234.5678")
+            .await
+            .unwrap();
+        // PDF fallback gets the same header plus a provenance note
+        assert_eq!(
+            out,
+            format!(
+                "# Test Paper\n\n## Authors\nAuthor\n\n## Abstract\nAbstract\n\n{}\n\nextracted pdf text",
+                PDF_TEXT_NOTE
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn convert_paper_postscript_eprint_takes_the_legacy_route() {
+        let client = MockArxivClient::new(
+            Ok(true),
+            Ok(Bytes::from_static(b"%!PS-Adobe-2.0\n")),
+            Ok(Bytes::from_static(b"pdf-bytes")),
+            Err(ArxivError::NotImplemented),
+        );
+        let mut converter = MockConverter::new(Ok("latex".into()), Ok("pdf text".into()));
+        converter.legacy_result = Ok(("ps text".into(), LegacyFormat::PostScript));
+        let latex_calls = converter.latex_calls.clone();
+
+        let out = convert_paper(&client, &converter, "1234.5678")
             .await
             .unwrap();
-        // PDF fallback skips metadata prepending
-        assert_eq!(out, "extracted pdf text");
+        assert!(out.starts_with("> **Note:** The e-print for this paper is PostScript only"));
+        assert!(out.ends_with("\n\nps text"));
+        assert_eq!(latex_calls.load(Ordering::SeqCst), 0);
     }
 
     #[tokio::test]
@@ -404,7 +384,7 @@ mod tests {
         );
         let converter = MockConverter::new(Ok(String::new()), Ok(String::new()));
 
-        let err = run_convert_paper(&client, &converter, "0000.0000")
+        let err = convert_paper(&client, &converter, "0000.0000")
             .await
             .unwrap_err();
         assert!(err.contains("not found"));
@@ -0,0 +1,583 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::LazyLock;
+
+use regex::Regex;
+use serde_json::{json, Value};
+
+use crate::tex_util::{find_command, read_arg, read_opt_arg, tex_to_markdown_inline};
+
+/// An author as the LaTeX source describes them.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Author {
+    pub name: String,
+    pub affiliations: Vec<String>,
+    pub email: Option<String>,
+}
+
+impl Author {
+    fn named(name: String) -> Self {
+        Self {
+            name,
+            ..Self::default()
+        }
+    }
+
+    fn add_affiliation(&mut self, affiliation: String) {
+        if !affiliation.is_empty() && !self.affiliations.contains(&affiliation) {
+            self.affiliations.push(affiliation);
+        }
+    }
+
+    pub fn to_json(&self) -> Value {
+        json!({
+            "name": self.name,
+            "affiliations": self.affiliations,
+            "email": self.email,
+        })
+    }
+}
+
+/// `Name (Affiliation; Other affiliation; email)`, or just the name.
+impl fmt::Display for Author {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.name)?;
+        let details: Vec<&str> = self
+            .affiliations
+            .iter()
+            .map(String::as_str)
+            .chain(self.email.as_deref())
+            .collect();
+        if !details.is_empty() {
+            write!(f, " ({})", details.join("; "))?;
+        }
+        Ok(())
+    }
+}
+
+static AND_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\\(?:and|And|AND)\b").unwrap());
+static LINE_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\\\\(?:\[[^\]]*\])?|\\newline\b").unwrap());
+static MARK_RE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r"\\(?:inst|IEEEauthorrefmark|textsuperscript|orcidlink|orcid|footnotemark)\s*(?:\[[^\]]*\])?(?:\{[^{}]*\})?|\$[^$]*\$",
+    )
+    .unwrap()
+});
+static ACM_FIELD_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\\(department|institution|city|state|country)\s*\{").unwrap());
+
+/// Authors with their affiliations and emails, from the title block of a
+/// flattened document. Understands ICML (`\icmlauthor`), IEEE
+/// (`\IEEEauthorblockN`), authblk (`\affil`), LNCS (`\institute`),
+/// REVTeX and ACM (`\affiliation`), and plain `\author{Name \\ Place}`
+/// blocks, in that order of preference.
+pub fn extract(tex: &str) -> Vec<Author> {
+    let end = ["maketitle", "section"]
+        .iter()
+        .filter_map(|name| find_command(tex, name, 0))
+        .min()
+        .unwrap_or(tex.len());
+    let front = &tex[..end];
+    let styles: [fn(&str) -> Vec<Author>; 6] = [icml, ieee, authblk, llncs, revtex, author_lines];
+    styles
+        .iter()
+        .map(|style| style(front))
+        .find(|authors| !authors.is_empty())
+        .unwrap_or_default()
+}
+
+/// Give each arXiv author the affiliations and email of the matching
+/// LaTeX author, in arXiv's order and spelling. Without arXiv names, the
+/// LaTeX authors are returned as they are.
+pub fn merge(names: &[String], found: &[Author]) -> Vec<Author> {
+    if names.is_empty() {
+        return found.to_vec();
+    }
+    names
+        .iter()
+        .map(|name| match best_match(name, found) {
+            Some(author) => Author {
+                name: name.clone(),
+                ..author.clone()
+            },
+            None => Author::named(name.clone()),
+        })
+        .collect()
+}
+
+/// The LaTeX author with the same family name and first initial, or else
+/// the only one with the same family name.
+fn best_match<'a>(name: &str, found: &'a [Author]) -> Option<&'a Author> {
+    let words = name_words(name);
+    let last = words.last()?;
+    let same_last: Vec<&Author> = found
+        .iter()
+        .filter(|a| name_words(&a.name).last() == Some(last))
+        .collect();
+    same_last
+        .iter()
+        .find(|a| same_person(name, &a.name))
+        .or_else(|| (same_last.len() == 1).then(|| &same_last[0]))
+        .copied()
+}
+
+/// Whether two spellings of a name share the family name and first
+/// initial, like "Alice Example" and "A. Example".
+pub fn same_person(a: &str, b: &str) -> bool {
+    let (a, b) = (name_words(a), name_words(b));
+    match (a.first(), a.last(), b.first(), b.last()) {
+        (Some(a_first), Some(a_last), Some(b_first), Some(b_last)) => {
+            a_last == b_last && a_first.chars().next() == b_first.chars().next()
+        }
+        _ => false,
+    }
+}
+
+/// The name at the end of the start of an author block, e.g. `B` in
+/// `A \and B`: what a `\thanks` placed there is attached to.
+pub(crate) fn trailing_name(written: &str) -> String {
+    let start = [
+        AND_RE.find_iter(written).last().map(|m| m.end()),
+        LINE_RE.find_iter(written).last().map(|m| m.end()),
+        written.rfind([',', '&']).map(|i| i + 1),
+    ]
+    .into_iter()
+    .flatten()
+    .max()
+    .unwrap_or(0);
+    plain(&written[start..])
+}
+
+fn name_words(name: &str) -> Vec<String> {
+    name.split(|c: char| !c.is_alphabetic())
+        .filter(|w| !w.is_empty())
+        .map(str::to_lowercase)
+        .collect()
+}
+
+/// Plain text for a name or affiliation: markers like `\inst{1}` and
+/// `$^{*}$` go, as does the remaining markup.
+fn plain(tex: &str) -> String {
+    let text = tex_to_markdown_inline(&MARK_RE.replace_all(tex, " "));
+    let text = text.replace('*', "");
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    text.trim_matches([',', ';', '†', '‡', '§', ' '])
+        .to_string()
+}
+
+/// The address in an email line such as `Email: \texttt{a@b.org}`.
+fn email(tex: &str) -> String {
+    let text = plain(tex);
+    text.split_whitespace()
+        .find(|word| word.contains('@'))
+        .unwrap_or(&text)
+        .trim_matches(['<', '>', '(', ')', ',', ';', ':'])
+        .to_string()
+}
+
+/// Arguments of every `\name[...]{...}...` with `args` mandatory ones.
+fn commands<'a>(tex: &'a str, name: &str, args: usize) -> Vec<(Option<&'a str>, Vec<&'a str>)> {
+    let mut out = Vec::new();
+    let mut cursor = 0;
+    while let Some(at) = find_command(tex, name, cursor) {
+        cursor = at + name.len() + 1;
+        let (opt, mut pos) = match read_opt_arg(tex, cursor) {
+            Some((opt, end)) => (Some(opt), end),
+            None => (None, cursor),
+        };
+        let mut found = Vec::new();
+        while found.len() < args {
+            let Some((arg, end)) = read_arg(tex, pos) else {
+                break;
+            };
+            found.push(arg);
+            pos = end;
+        }
+        if found.len() == args {
+            cursor = pos;
+            out.push((opt, found));
+        }
+    }
+    out
+}
+
+/// An author block's lines: the name first, then the affiliation lines,
+/// with any line holding an `@` taken as the email.
+fn from_lines(block: &str) -> Option<Author> {
+    let mut lines = LINE_RE
+        .split(block)
+        .map(str::trim)
+        .filter(|l| !l.is_empty());
+    let name = plain(lines.next()?);
+    if name.is_empty() {
+        return None;
+    }
+    let mut author = Author::named(name);
+    let mut place = Vec::new();
+    for line in lines {
+        if let Some((_, args)) = commands(line, "email", 1).first() {
+            author.email = Some(email(args[0]));
+        } else if line.contains('@') {
+            author.email = Some(email(line));
+        } else {
+            place.push(plain(line));
+        }
+    }
+    author.add_affiliation(place.join(", "));
+    Some(author)
+}
+
+fn icml(tex: &str) -> Vec<Author> {
+    let affiliations: HashMap<&str, String> = commands(tex, "icmlaffiliation", 2)
+        .into_iter()
+        .map(|(_, args)| (args[0].trim(), plain(args[1])))
+        .collect();
+    let mut authors: Vec<Author> = commands(tex, "icmlauthor", 2)
+        .into_iter()
+        .map(|(_, args)| {
+            let mut author = Author::named(plain(args[0]));
+            for key in args[1].split(',') {
+                if let Some(affiliation) = affiliations.get(key.trim()) {
+                    author.add_affiliation(affiliation.clone());
+                }
+            }
+            author
+        })
+        .collect();
+    for (_, args) in commands(tex, "icmlcorrespondingauthor", 2) {
+        let name = plain(args[0]);
+        if let Some(author) = authors.iter_mut().find(|a| a.name == name) {
+            author.email = Some(email(args[1]));
+        }
+    }
+    authors
+}
+
+fn ieee(tex: &str) -> Vec<Author> {
+    let mut authors = Vec::new();
+    let mut cursor = 0;
+    while let Some(at) = find_command(tex, "IEEEauthorblockN", cursor) {
+        let Some((names, end)) = read_arg(tex, at + "\\IEEEauthorblockN".len()) else {
+            break;
+        };
+        cursor = end;
+        let next = find_command(tex, "IEEEauthorblockN", end).unwrap_or(tex.len());
+        let details = find_command(tex, "IEEEauthorblockA", end)
+            .filter(|&a| a < next)
+            .and_then(|a| read_arg(tex, a + "\\IEEEauthorblockA".len()))
+            .and_then(|(block, _)| from_lines(&format!("name\\\\{}", block)));
+        for name in AND_RE.split(names).flat_map(|n| n.split(',')) {
+            let name = plain(&LINE_RE.replace_all(name, " "));
+            if name.is_empty() {
+                continue;
+            }
+            let mut author = details.clone().unwrap_or_default();
+            author.name = name;
+            authors.push(author);
+        }
+    }
+    authors
+}
+
+fn authblk(tex: &str) -> Vec<Author> {
+    let affils = commands(tex, "affil", 1);
+    if affils.is_empty() {
+        return Vec::new();
+    }
+    let by_key: HashMap<&str, String> = affils
+        .iter()
+        .filter_map(|(key, args)| Some(((*key)?.trim(), plain(args[0]))))
+        .collect();
+    commands(tex, "author", 1)
+        .into_iter()
+        .flat_map(|(keys, args)| {
+            AND_RE
+                .split(args[0])
+                .map(|name| {
+                    let mut author = Author::named(plain(name));
+                    for key in keys.unwrap_or_default().split(',') {
+                        if let Some(affiliation) = by_key.get(key.trim()) {
+                            author.add_affiliation(affiliation.clone());
+                        }
+                    }
+                    author
+                })
+                .collect::<Vec<_>>()
+        })
+        .filter(|a| !a.name.is_empty())
+        .collect()
+}
+
+fn llncs(tex: &str) -> Vec<Author> {
+    let Some((_, institute)) = commands(tex, "institute", 1).into_iter().next() else {
+        return Vec::new();
+    };
+    let institutes: Vec<(String, Option<String>)> = AND_RE
+        .split(institute[0])
+        .map(|part| {
+            let email = commands(part, "email", 1).first().map(|(_, a)| email(a[0]));
+            let lines: Vec<String> = LINE_RE
+                .split(part)
+                .filter(|l| !l.contains("\\email") && !l.contains("\\url"))
+                .map(plain)
+                .filter(|l| !l.is_empty())
+                .collect();
+            (lines.join(", "), email)
+        })
+        .collect();
+    let Some((_, names)) = commands(tex, "author", 1).into_iter().next() else {
+        return Vec::new();
+    };
+    AND_RE
+        .split(names[0])
+        .filter_map(|part| {
+            let mut author = Author::named(plain(part));
+            if author.name.is_empty() {
+                return None;
+            }
+            let marks = commands(part, "inst", 1);
+            let numbers: Vec<usize> = match marks.first() {
+                Some((_, args)) => args[0]
+                    .split(',')
+                    .filter_map(|n| n.trim().parse().ok())
+                    .collect(),
+                None if institutes.len() == 1 => vec![1],
+                None => Vec::new(),
+            };
+            for n in numbers {
+                if let Some((affiliation, email)) = institutes.get(n.wrapping_sub(1)) {
+                    author.add_affiliation(affiliation.clone());
+                    if author.email.is_none() {
+                        author.email = email.clone();
+                    }
+                }
+            }
+            Some(author)
+        })
+        .collect()
+}
+
+/// REVTeX and ACM: `\affiliation` follows the authors it belongs to, and
+/// `\email` the author it belongs to.
+fn revtex(tex: &str) -> Vec<Author> {
+    if find_command(tex, "affiliation", 0).is_none() {
+        return Vec::new();
+    }
+    let mut tokens: Vec<(usize, &str)> = ["author", "affiliation", "email"]
+        .iter()
+        .flat_map(|name| {
+            let mut found = Vec::new();
+            let mut cursor = 0;
+            while let Some(at) = find_command(tex, name, cursor) {
+                cursor = at + 1;
+                found.push((at, *name));
+            }
+            found
+        })
+        .collect();
+    tokens.sort();
+    let mut authors: Vec<Author> = Vec::new();
+    // Authors since the last affiliation, who share the next ones.
+    let mut group = 0;
+    let mut after_affiliation = false;
+    for (at, name) in tokens {
+        let pos = at + name.len() + 1;
+        let pos = read_opt_arg(tex, pos).map_or(pos, |(_, end)| end);
+        let Some((arg, _)) = read_arg(tex, pos) else {
+            continue;
+        };
+        match name {
+            "author" => {
+                if after_affiliation {
+                    group = authors.len();
+                    after_affiliation = false;
+                }
+                authors.extend(
+                    AND_RE
+                        .split(arg)
+                        .map(plain)
+                        .filter(|n| !n.is_empty())
+                        .map(Author::named),
+                );
+            }
+            "affiliation" => {
+                let affiliation = affiliation_text(arg);
+                for author in &mut authors[group..] {
+                    author.add_affiliation(affiliation.clone());
+                }
+                after_affiliation = true;
+            }
+            _ => {
+                if let Some(author) = authors.last_mut() {
+                    author.email = Some(email(arg));
+                }
+            }
+        }
+    }
+    authors
+}
+
+/// An affiliation's text; ACM's `\institution{...}\city{...}` fields are
+/// joined with commas.
+fn affiliation_text(tex: &str) -> String {
+    let fields: Vec<String> = ACM_FIELD_RE
+        .find_iter(tex)
+        .filter_map(|m| read_arg(tex, m.end() - 1))
+        .map(|(arg, _)| plain(arg))
+        .filter(|f| !f.is_empty())
+        .collect();
+    if fields.is_empty() {
+        plain(&LINE_RE.replace_all(tex, ", "))
+    } else {
+        fields.join(", ")
+    }
+}
+
+fn author_lines(tex: &str) -> Vec<Author> {
+    let Some((_, args)) = commands(tex, "author", 1).into_iter().next() else {
+        return Vec::new();
+    };
+    AND_RE.split(args[0]).filter_map(from_lines).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn author(name: &str, affiliations: &[&str], email: Option<&str>) -> Author {
+        Author {
+            name: name.into(),
+            affiliations: affiliations.iter().map(|a| a.to_string()).collect(),
+            email: email.map(String::from),
+        }
+    }
+
+    #[test]
+    fn plain_author_blocks() {
+        let tex = r"\author{Alice Smith \\ Dept. of CS \\ MIT \\ \texttt{alice@mit.edu} \And Bob Jones$^{*}$ \\ Google}
+\maketitle
+\section{Intro} \author{Not An Author}";
+        assert_eq!(
+            extract(tex),
+            vec![
+                author("Alice Smith", &["Dept. of CS, MIT"], Some("alice@mit.edu")),
+                author("Bob Jones", &["Google"], None),
+            ]
+        );
+    }
+
+    #[test]
+    fn icml_and_ieee_blocks() {
+        let icml = r"\begin{icmlauthorlist}
+\icmlauthor{Alice Smith}{mit,goo}
+\icmlauthor{Bob Jones}{goo}
+\end{icmlauthorlist}
+\icmlaffiliation{mit}{Massachusetts Institute of Technology}
+\icmlaffiliation{goo}{Google Research}
+\icmlcorrespondingauthor{Alice Smith}{alice@mit.edu}";
+        assert_eq!(
+            extract(icml),
+            vec![
+                author(
+                    "Alice Smith",
+                    &["Massachusetts Institute of Technology", "Google Research"],
+                    Some("alice@mit.edu")
+                ),
+                author("Bob Jones", &["Google Research"], None),
+            ]
+        );
+
+        let ieee = r"\author{\IEEEauthorblockN{Alice Smith\IEEEauthorrefmark{1}}
+\IEEEauthorblockA{Dept. of EE\\ Stanford University\\ Email: alice@stanford.edu}
+\and
+\IEEEauthorblockN{Bob Jones}
+\IEEEauthorblockA{ETH Zurich}}";
+        assert_eq!(
+            extract(ieee),
+            vec![
+                author(
+                    "Alice Smith",
+                    &["Dept. of EE, Stanford University"],
+                    Some("alice@stanford.edu")
+                ),
+                author("Bob Jones", &["ETH Zurich"], None),
+            ]
+        );
+    }
+
+    #[test]
+    fn institute_and_affiliation_styles() {
+        let llncs = r"\author{Alice Smith\inst{1,2} \and Bob Jones\inst{2}}
+\institute{University of Oxford \\ \email{alice@ox.ac.uk} \and DeepMind, London}";
+        assert_eq!(
+            extract(llncs),
+            vec![
+                author(
+                    "Alice Smith",
+                    &["University of Oxford", "DeepMind, London"],
+                    Some("alice@ox.ac.uk")
+                ),
+                author("Bob Jones", &["DeepMind, London"], None),
+            ]
+        );
+
+        let acm = r"\author{Alice Smith}
+\email{alice@cmu.edu}
+\affiliation{\institution{Carnegie Mellon University}\city{Pittsburgh}\country{USA}}
+\author{Bob Jones}
+\author{Carol White}
+\affiliation{CERN, Geneva}";
+        assert_eq!(
+            extract(acm),
+            vec![
+                author(
+                    "Alice Smith",
+                    &["Carnegie Mellon University, Pittsburgh, USA"],
+                    Some("alice@cmu.edu")
+                ),
+                author("Bob Jones", &["CERN, Geneva"], None),
+                author("Carol White", &["CERN, Geneva"], None),
+            ]
+        );
+
+        let authblk = r"\author[1]{Alice Smith}
+\author[1,2]{Bob Jones}
+\affil[1]{University of Tokyo}
+\affil[2]{RIKEN}";
+        assert_eq!(
+            extract(authblk),
+            vec![
+                author("Alice Smith", &["University of Tokyo"], None),
+                author("Bob Jones", &["University of Tokyo", "RIKEN"], None),
+            ]
+        );
+    }
+
+    #[test]
+    fn merge_keeps_arxiv_names_and_order() {
+        let found = vec![
+            author("B. Jones", &["Google"], None),
+            author("Alice Smith", &["MIT"], Some("a@mit.edu")),
+        ];
+        let merged = merge(
+            &[
+                "Alice Smith".into(),
+                "Bob Jones".into(),
+                "Carol White".into(),
+            ],
+            &found,
+        );
+        assert_eq!(
+            merged,
+            vec![
+                author("Alice Smith", &["MIT"], Some("a@mit.edu")),
+                author("Bob Jones", &["Google"], None),
+                author("Carol White", &[], None),
+            ]
+        );
+        assert_eq!(merged[0].to_string(), "Alice Smith (MIT; a@mit.edu)");
+        assert_eq!(merged[2].to_string(), "Carol White");
+        assert_eq!(merge(&[], &found), found);
+    }
+}
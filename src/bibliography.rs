@@ -0,0 +1,805 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::LazyLock;
+
+use regex::Regex;
+
+use crate::placeholder::Placeholders;
+use crate::tex_util::{
+    anchor_id, find_command, find_environment, in_ranges, math_ranges, read_arg, read_opt_arg,
+    tex_to_markdown_inline,
+};
+
+/// One reference parsed from a `.bbl` file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BibEntry {
+    pub key: String,
+    /// Author part of a natbib label (`Vaswani et~al.(2017)...`), used by `\citet`.
+    pub authors: Option<String>,
+    pub year: Option<String>,
+    /// Entry text rendered as Markdown.
+    pub text: String,
+    pub arxiv_id: Option<String>,
+}
+
+/// Where the references for a paper come from.
+#[derive(Debug, Clone)]
+pub enum BibSource {
+    /// Pre-rendered entries from a bundled `.bbl` (or an inline `thebibliography`).
+    Entries(Vec<BibEntry>),
+    /// Raw `.bib` databases, rendered by pandoc's citeproc.
+    BibFiles(Vec<PathBuf>),
+    None,
+}
+
+/// Base URL used for links to other papers, e.g. `https://markxiv.org/abs/<id>`.
+pub fn markxiv_base_url() -> String {
+    std::env::var("MARKXIV_BASE_URL")
+        .map(|s| s.trim_end_matches('/').to_string())
+        .unwrap_or_else(|_| "https://markxiv.org".to_string())
+}
+
+/// Decide which bibliography to use. An inline `thebibliography` wins, then a
+/// `.bbl` next to the main file, then any `.bbl`, then the `.bib` files named
+/// by `\bibliography{...}` (or every `.bib` in the archive).
+pub fn select_source(
+    tex: &str,
+    main_tex: &Path,
+    bbl_files: &[(PathBuf, String)],
+    bib_files: &[PathBuf],
+) -> BibSource {
+    if tex.contains("\\begin{thebibliography}") {
+        let entries = parse_bbl(tex);
+        if !entries.is_empty() {
+            return BibSource::Entries(entries);
+        }
+    }
+    let main_stem = main_tex.file_stem();
+    let bbl = bbl_files
+        .iter()
+        .find(|(p, _)| p.file_stem() == main_stem)
+        .or_else(|| bbl_files.first());
+    if let Some((_, content)) = bbl {
+        let entries = parse_bbl(content);
+        if !entries.is_empty() {
+            return BibSource::Entries(entries);
+        }
+    }
+    if bib_files.is_empty() {
+        return BibSource::None;
+    }
+    let wanted = bibliography_names(tex);
+    let named: Vec<PathBuf> = bib_files
+        .iter()
+        .filter(|p| {
+            p.file_stem()
+                .and_then(|s| s.to_str())
+                .map(|s| wanted.iter().any(|w| w == s))
+                .unwrap_or(false)
+        })
+        .cloned()
+        .collect();
+    if named.is_empty() {
+        BibSource::BibFiles(bib_files.to_vec())
+    } else {
+        BibSource::BibFiles(named)
+    }
+}
+
+fn bibliography_names(tex: &str) -> Vec<String> {
+    let mut names = Vec::new();
+    for cmd in ["bibliography", "addbibresource"] {
+        let mut from = 0;
+        while let Some(at) = find_command(tex, cmd, from) {
+            let mut pos = at + cmd.len() + 1;
+            if let Some((_, end)) = read_opt_arg(tex, pos) {
+                pos = end;
+            }
+            let Some((arg, end)) = read_arg(tex, pos) else {
+                from = pos;
+                continue;
+            };
+            for name in arg.split(',') {
+                let name = name.trim();
+                let stem = Path::new(name)
+                    .file_stem()
+                    .and_then(|s| s.to_str())
+                    .unwrap_or(name);
+                if !stem.is_empty() {
+                    names.push(stem.to_string());
+                }
+            }
+            from = end;
+        }
+    }
+    names
+}
+
+/// Parse `\bibitem` (natbib/BibTeX) or `\entry` (biblatex) records.
+pub fn parse_bbl(bbl: &str) -> Vec<BibEntry> {
+    if bbl.contains("\\bibitem") {
+        parse_bibitems(bbl)
+    } else if bbl.contains("\\entry{") {
+        parse_biblatex(bbl)
+    } else {
+        Vec::new()
+    }
+}
+
+fn parse_bibitems(bbl: &str) -> Vec<BibEntry> {
+    let body = match find_environment(bbl, "thebibliography", 0) {
+        Some((_, body)) => &bbl[body],
+        None => bbl,
+    };
+    let mut starts = Vec::new();
+    let mut from = 0;
+    while let Some(at) = find_command(body, "bibitem", from) {
+        starts.push(at);
+        from = at + "\\bibitem".len();
+    }
+    let mut entries = Vec::new();
+    for (n, &at) in starts.iter().enumerate() {
+        let end = starts.get(n + 1).copied().unwrap_or(body.len());
+        let mut pos = at + "\\bibitem".len();
+        let mut label = None;
+        if let Some((opt, e)) = read_opt_arg(body, pos) {
+            label = Some(opt);
+            pos = e;
+        }
+        let Some((key, e)) = read_arg(body, pos) else {
+            continue;
+        };
+        let raw = &body[e.min(end)..end];
+        let (authors, year) = label.map(split_natbib_label).unwrap_or((None, None));
+        entries.push(BibEntry {
+            key: key.trim().to_string(),
+            authors,
+            year,
+            text: tex_to_markdown_inline(raw),
+            arxiv_id: find_arxiv_id(raw),
+        });
+    }
+    entries
+}
+
+/// natbib labels look like `Vaswani et~al.(2017)Vaswani, Shazeer, ...`.
+fn split_natbib_label(label: &str) -> (Option<String>, Option<String>) {
+    let Some(open) = label.find('(') else {
+        return (None, None);
+    };
+    let Some(close_rel) = label[open..].find(')') else {
+        return (None, None);
+    };
+    let authors = tex_to_markdown_inline(&label[..open]);
+    let year = tex_to_markdown_inline(&label[open + 1..open + close_rel]);
+    (
+        Some(authors).filter(|s| !s.is_empty()),
+        Some(year).filter(|s| !s.is_empty()),
+    )
+}
+
+fn parse_biblatex(bbl: &str) -> Vec<BibEntry> {
+    let mut entries = Vec::new();
+    let mut from = 0;
+    while let Some(at) = find_command(bbl, "entry", from) {
+        let Some((key, after_key)) = read_arg(bbl, at + "\\entry".len()) else {
+            break;
+        };
+        let end = bbl[after_key..]
+            .find("\\endentry")
+            .map(|r| after_key + r)
+            .unwrap_or(bbl.len());
+        let block = &bbl[after_key..end];
+        from = end;
+
+        let field = |name: &str| -> Option<String> {
+            let needle = format!("\\field{{{}}}", name);
+            let pos = block.find(&needle)? + needle.len();
+            read_arg(block, pos).map(|(v, _)| tex_to_markdown_inline(v))
+        };
+        let verb = |name: &str| -> Option<String> {
+            let needle = format!("\\verb{{{}}}", name);
+            let pos = block.find(&needle)? + needle.len();
+            let rest = &block[pos..];
+            let start = rest.find("\\verb")? + "\\verb".len();
+            let stop = rest.find("\\endverb")?;
+            (start <= stop).then(|| rest[start..stop].trim().to_string())
+        };
+
+        let names = biblatex_names(block, "author");
+        let year = field("year").or_else(|| field("labelyear"));
+        let title = field("title");
+        let venue = field("journaltitle").or_else(|| field("booktitle"));
+        let eprint = field("eprint").or_else(|| verb("eprint"));
+        let eprint_type = field("eprinttype").unwrap_or_default();
+        let url = verb("url");
+
+        let mut parts = Vec::new();
+        if !names.is_empty() {
+            parts.push(names.join(", "));
+        }
+        if let Some(t) = &title {
+            parts.push(t.clone());
+        }
+        if let Some(v) = &venue {
+            parts.push(format!("*{}*", v));
+        }
+        if let Some(y) = &year {
+            parts.push(y.clone());
+        }
+        let mut text = parts.join(". ");
+        if !text.is_empty() {
+            text.push('.');
+        }
+        if let Some(u) = &url {
+            text.push_str(&format!(" <{}>", u));
+        }
+
+        let arxiv_id = match &eprint {
+            Some(e) if eprint_type.eq_ignore_ascii_case("arxiv") || ARXIV_ID.is_match(e) => {
+                Some(e.trim().to_string())
+            }
+            _ => find_arxiv_id(block),
+        };
+        if let Some(id) = &arxiv_id {
+            if !text.contains(id.as_str()) {
+                text.push_str(&format!(" arXiv:{}", id));
+            }
+        }
+
+        let family = biblatex_families(block, "author");
+        let authors = match family.len() {
+            0 => None,
+            1 => Some(family[0].clone()),
+            2 => Some(format!("{} and {}", family[0], family[1])),
+            _ => Some(format!("{} et al.", family[0])),
+        };
+        entries.push(BibEntry {
+            key: key.trim().to_string(),
+            authors,
+            year,
+            text: text.trim().to_string(),
+            arxiv_id,
+        });
+    }
+    entries
+}
+
+fn biblatex_name_parts(block: &str, role: &str) -> Vec<(String, String)> {
+    let needle = format!("\\name{{{}}}", role);
+    let Some(pos) = block.find(&needle) else {
+        return Vec::new();
+    };
+    let mut pos = pos + needle.len();
+    // \name{author}{count}{options}{ {{...}{family=...,given=...}} ... }
+    for _ in 0..2 {
+        match read_arg(block, pos) {
+            Some((_, e)) => pos = e,
+            None => return Vec::new(),
+        }
+    }
+    let Some((list, _)) = read_arg(block, pos) else {
+        return Vec::new();
+    };
+    static RE_FAMILY: LazyLock<Regex> = LazyLock::new(|| {
+        Regex::new(r"(?:^|[,{\s])family=\{([^{}]*(?:\{[^{}]*\}[^{}]*)*)\}").unwrap()
+    });
+    static RE_GIVEN: LazyLock<Regex> = LazyLock::new(|| {
+        Regex::new(r"(?:^|[,{\s])given=\{([^{}]*(?:\{[^{}]*\}[^{}]*)*)\}").unwrap()
+    });
+    let mut out = Vec::new();
+    // Each person is a brace group at the top level of `list`.
+    let mut i = 0;
+    while let Some(rel) = list[i..].find('{') {
+        let open = i + rel;
+        let Some(end) = crate::tex_util::group_end(list, open) else {
+            break;
+        };
+        let person = &list[open..end];
+        let family = RE_FAMILY
+            .captures(person)
+            .map(|c| tex_to_markdown_inline(&c[1]))
+            .unwrap_or_default();
+        let given = RE_GIVEN
+            .captures(person)
+            .map(|c| tex_to_markdown_inline(&c[1]))
+            .unwrap_or_default();
+        if !family.is_empty() {
+            out.push((given, family));
+        }
+        i = end;
+    }
+    out
+}
+
+fn biblatex_names(block: &str, role: &str) -> Vec<String> {
+    biblatex_name_parts(block, role)
+        .into_iter()
+        .map(|(given, family)| {
+            if given.is_empty() {
+                family
+            } else {
+                format!("{} {}", given, family)
+            }
+        })
+        .collect()
+}
+
+fn biblatex_families(block: &str, role: &str) -> Vec<String> {
+    biblatex_name_parts(block, role)
+        .into_iter()
+        .map(|(_, family)| family)
+        .collect()
+}
+
+static ARXIV_ID: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^(?:\d{4}\.\d{4,5}|[a-z\-]+(?:\.[A-Z]{2})?/\d{7})(?:v\d+)?$").unwrap()
+});
+
+static ARXIV_MENTION: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r"(?i)(?:arxiv(?:\s*preprint)?\s*:?\s*(?:abs/)?|arxiv\.org/(?:abs|pdf)/)((?:\d{4}\.\d{4,5}|[a-z\-]+(?:\.[a-z]{2})?/\d{7})(?:v\d+)?)",
+    )
+    .unwrap()
+});
+
+/// Find an arXiv identifier mentioned in a reference (`arXiv:1706.03762`,
+/// `arxiv.org/abs/hep-th/9901001`, `arXiv preprint arXiv:2001.08361`, ...).
+pub fn find_arxiv_id(raw: &str) -> Option<String> {
+    ARXIV_MENTION
+        .captures(raw)
+        .map(|c| c[1].trim_end_matches('.').to_string())
+}
+
+fn abs_url(id: &str) -> String {
+    format!("{}/abs/{}", markxiv_base_url(), id)
+}
+
+/// Render the numbered References section.
+pub fn render_references(entries: &[BibEntry]) -> String {
+    let mut out = String::from("# References\n\n");
+    for (i, e) in entries.iter().enumerate() {
+        out.push_str(&format!(
+            "{}. <a id=\"{}\"></a>{}",
+            i + 1,
+            anchor_id("ref-", &e.key),
+            link_arxiv_mention(&e.text, e.arxiv_id.as_deref())
+        ));
+        out.push('\n');
+    }
+    out
+}
+
+/// Turn the first `arXiv:<id>` mention into a markxiv link, or append one
+/// when the entry only carries the id in a URL or an eprint field.
+fn link_arxiv_mention(text: &str, id: Option<&str>) -> String {
+    let Some(id) = id else {
+        return text.to_string();
+    };
+    let mention = format!("arXiv:{}", id);
+    if let Some(pos) = text.find(&mention) {
+        let before_autolink = text[..pos].ends_with('<');
+        if !before_autolink {
+            return format!(
+                "{}[{}]({}){}",
+                &text[..pos],
+                mention,
+                abs_url(id),
+                &text[pos + mention.len()..]
+            );
+        }
+    }
+    format!("{} [arXiv:{}]({})", text, id, abs_url(id))
+}
+
+const CITE_COMMANDS: &[&str] = &[
+    "cite",
+    "citep",
+    "citet",
+    "citealp",
+    "citealt",
+    "citeauthor",
+    "citeyear",
+    "citeyearpar",
+    "parencite",
+    "textcite",
+    "autocite",
+    "Cite",
+    "Citep",
+    "Citet",
+    "Textcite",
+    "Parencite",
+    "Autocite",
+    "nocite",
+];
+
+/// Replace citation commands with linked markers, put the References section
+/// where `\bibliography`/`thebibliography`/`\printbibliography` was, and drop
+/// the remaining bibliography plumbing so pandoc does not render it twice.
+pub fn apply_entries(tex: &str, entries: &[BibEntry], ph: &mut Placeholders) -> String {
+    let index: HashMap<&str, (usize, &BibEntry)> = entries
+        .iter()
+        .enumerate()
+        .map(|(i, e)| (e.key.as_str(), (i + 1, e)))
+        .collect();
+
+    let math = math_ranges(tex);
+    let mut out = String::with_capacity(tex.len());
+    let mut i = 0;
+    while i < tex.len() {
+        let Some((at, cmd)) = CITE_COMMANDS
+            .iter()
+            .filter_map(|c| find_command(tex, c, i).map(|at| (at, *c)))
+            .min_by_key(|(at, _)| *at)
+        else {
+            break;
+        };
+        let mut pos = at + cmd.len() + 1;
+        if tex[pos..].starts_with('*') {
+            pos += 1;
+        }
+        let mut opts = Vec::new();
+        while let Some((opt, e)) = read_opt_arg(tex, pos) {
+            opts.push(opt);
+            pos = e;
+        }
+        let Some((keys, end)) = read_arg(tex, pos) else {
+            out.push_str(&tex[i..pos]);
+            i = pos;
+            continue;
+        };
+        out.push_str(&tex[i..at]);
+        i = end;
+        if cmd == "nocite" {
+            continue;
+        }
+        let keys: Vec<&str> = keys
+            .split(',')
+            .map(str::trim)
+            .filter(|k| !k.is_empty())
+            .collect();
+        let md = render_citation(cmd, &keys, &opts, &index);
+        if in_ranges(&math, at) {
+            // Links cannot live inside math; keep the visible text only.
+            out.push_str(&format!("\\text{{{}}}", strip_links(&md)));
+        } else {
+            out.push_str(&ph.inline(md));
+        }
+    }
+    out.push_str(&tex[i..]);
+
+    place_references(&out, &render_references(entries), ph)
+}
+
+fn render_citation(
+    cmd: &str,
+    keys: &[&str],
+    opts: &[&str],
+    index: &HashMap<&str, (usize, &BibEntry)>,
+) -> String {
+    let marker = |key: &str| -> String {
+        match index.get(key) {
+            Some((n, e)) => format!("[{}](#{})", n, anchor_id("ref-", &e.key)),
+            None => key.to_string(),
+        }
+    };
+    // With two optional arguments natbib treats them as pre- and post-note;
+    // with one it is a post-note.
+    let (pre, post) = match opts {
+        [post] => (None, Some(tex_to_markdown_inline(post))),
+        [pre, post, ..] => (
+            Some(tex_to_markdown_inline(pre)),
+            Some(tex_to_markdown_inline(post)),
+        ),
+        [] => (None, None),
+    };
+    let pre = pre.filter(|s| !s.is_empty());
+    let post = post.filter(|s| !s.is_empty());
+
+    let bracket = |inner: String| -> String {
+        let mut s = String::from("[");
+        if let Some(p) = &pre {
+            s.push_str(p);
+            s.push(' ');
+        }
+        s.push_str(&inner);
+        if let Some(p) = &post {
+            s.push_str(", ");
+            s.push_str(p);
+        }
+        s.push(']');
+        s
+    };
+
+    match cmd.to_ascii_lowercase().as_str() {
+        "citet" | "textcite" | "citealt" => keys
+            .iter()
+            .map(
+                |k| match index.get(k).and_then(|(_, e)| e.authors.as_deref()) {
+                    Some(a) => format!("{} {}", a, bracket(marker(k))),
+                    None => bracket(marker(k)),
+                },
+            )
+            .collect::<Vec<_>>()
+            .join(", "),
+        "citeauthor" => keys
+            .iter()
+            .map(|k| {
+                index
+                    .get(k)
+                    .and_then(|(_, e)| e.authors.clone())
+                    .unwrap_or_else(|| marker(k))
+            })
+            .collect::<Vec<_>>()
+            .join(", "),
+        "citeyear" | "citeyearpar" => {
+            let years = keys
+                .iter()
+                .map(|k| {
+                    index
+                        .get(k)
+                        .and_then(|(_, e)| e.year.clone())
+                        .unwrap_or_else(|| marker(k))
+                })
+                .collect::<Vec<_>>()
+                .join(", ");
+            if cmd == "citeyearpar" {
+                format!("({})", years)
+            } else {
+                years
+            }
+        }
+        _ => bracket(
+            keys.iter()
+                .map(|k| marker(k))
+                .collect::<Vec<_>>()
+                .join(", "),
+        ),
+    }
+}
+
+fn strip_links(md: &str) -> String {
+    static RE_LINK: LazyLock<Regex> =
+        LazyLock::new(|| Regex::new(r"\[([^\]]*)\]\([^)]*\)").unwrap());
+    RE_LINK.replace_all(md, "$1").into_owned()
+}
+
+fn place_references(tex: &str, references_md: &str, ph: &mut Placeholders) -> String {
+    let mut out = tex.to_string();
+    let mut placed = false;
+
+    if let Some((range, _)) = find_environment(&out, "thebibliography", 0) {
+        let token = ph.block(references_md);
+        out.replace_range(range, &token);
+        placed = true;
+    }
+    for cmd in ["bibliography", "printbibliography"] {
+        while let Some(at) = find_command(&out, cmd, 0) {
+            let mut end = at + cmd.len() + 1;
+            if let Some((_, e)) = read_opt_arg(&out, end) {
+                end = e;
+            }
+            if cmd == "bibliography" {
+                if let Some((_, e)) = read_arg(&out, end) {
+                    end = e;
+                }
+            }
+            let replacement = if placed {
+                String::new()
+            } else {
+                placed = true;
+                ph.block(references_md)
+            };
+            out.replace_range(at..end, &replacement);
+        }
+    }
+    for cmd in ["bibliographystyle", "addbibresource"] {
+        while let Some(at) = find_command(&out, cmd, 0) {
+            let mut end = at + cmd.len() + 1;
+            if let Some((_, e)) = read_opt_arg(&out, end) {
+                end = e;
+            }
+            match read_arg(&out, end) {
+                Some((_, e)) => out.replace_range(at..e, ""),
+                None => out.replace_range(at..end, ""),
+            }
+        }
+    }
+    if !placed {
+        let token = ph.block(references_md);
+        match out.rfind("\\end{document}") {
+            Some(pos) => out.insert_str(pos, &token),
+            None => out.push_str(&token),
+        }
+    }
+    out
+}
+
+/// A CSL style numbering references in citation order, `[1]`, as the
+/// `.bbl` path does; pandoc's default is author-date.
+pub const NUMERIC_CSL: &str = include_str!("numeric.csl");
+
+/// File name [`NUMERIC_CSL`] is written under for pandoc.
+pub const NUMERIC_CSL_FILE: &str = "markxiv-numeric.csl";
+
+/// Pandoc arguments that make citeproc render `.bib` references as a
+/// numbered list, in the style at `csl`, with clickable citations.
+pub fn citeproc_args(bib_files: &[PathBuf], csl: &Path) -> Vec<String> {
+    let mut args = vec!["--citeproc".to_string()];
+    for f in bib_files {
+        args.push("--bibliography".into());
+        args.push(f.to_string_lossy().into_owned());
+    }
+    args.push("--csl".into());
+    args.push(csl.to_string_lossy().into_owned());
+    args.extend([
+        "-M".to_string(),
+        "link-citations=true".to_string(),
+        "-M".to_string(),
+        "reference-section-title=References".to_string(),
+    ]);
+    args
+}
+
+/// Turn the `<div id="ref-key">` wrapper of each citeproc entry, which
+/// HTML stripping would remove, into an `<a id>` anchor placeholder in
+/// front of the entry, so linked citations still land on it.
+pub fn anchor_citeproc_entries(md: &str, ph: &mut Placeholders) -> String {
+    static ENTRY_RE: LazyLock<Regex> =
+        LazyLock::new(|| Regex::new(r#"<div id="(ref-[^"]+)"[^>]*>\s*"#).unwrap());
+    ENTRY_RE
+        .replace_all(md, |caps: &regex::Captures| {
+            ph.inline(format!("<a id=\"{}\"></a>", &caps[1]))
+        })
+        .into_owned()
+}
+
+/// Link arXiv ids inside a citeproc-rendered references block to markxiv.
+/// Only the part of the document after the `refs` div is touched.
+pub fn link_arxiv_ids_in_refs(md: &str) -> String {
+    let Some(start) = md.find("<div id=\"refs\"") else {
+        return md.to_string();
+    };
+    let (head, refs) = md.split_at(start);
+    static RE_PLAIN: LazyLock<Regex> = LazyLock::new(|| {
+        Regex::new(r"(?i)\barxiv:\s*((?:\d{4}\.\d{4,5}|[a-z\-]+(?:\.[a-z]{2})?/\d{7})(?:v\d+)?)")
+            .unwrap()
+    });
+    let linked = RE_PLAIN.replace_all(refs, |caps: &regex::Captures| {
+        format!("[arXiv:{}]({})", &caps[1], abs_url(&caps[1]))
+    });
+    format!("{}{}", head, linked)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BBL: &str = r"\begin{thebibliography}{2}
+\providecommand{\natexlab}[1]{#1}
+
+\bibitem[Vaswani et~al.(2017)Vaswani, Shazeer, and Parmar]{vaswani2017}
+A.~Vaswani, N.~Shazeer, and N.~Parmar.
+\newblock Attention is all you need.
+\newblock \emph{arXiv preprint arXiv:1706.03762}, 2017.
+
+\bibitem[He et~al.(2016)]{he2016}
+K.~He.
+\newblock Deep residual learning.
+\newblock In \emph{CVPR}, 2016.
+
+\end{thebibliography}
+";
+
+    #[test]
+    fn parses_natbib_bibitems() {
+        let entries = parse_bbl(BBL);
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].key, "vaswani2017");
+        assert_eq!(entries[0].authors.as_deref(), Some("Vaswani et al."));
+        assert_eq!(entries[0].year.as_deref(), Some("2017"));
+        assert_eq!(entries[0].arxiv_id.as_deref(), Some("1706.03762"));
+        assert!(entries[1]
+            .text
+            .starts_with("K. He. Deep residual learning. In *CVPR*"));
+        assert!(entries[1].arxiv_id.is_none());
+    }
+
+    #[test]
+    fn parses_biblatex_entries() {
+        let bbl = r"\entry{vaswani}{article}{}
+      \name{author}{2}{}{%
+        {{hash=1}{%
+           family={Vaswani},
+           familyi={V\bibinitperiod},
+           given={Ashish},
+           giveni={A\bibinitperiod}}}%
+        {{hash=2}{%
+           family={Shazeer},
+           given={Noam}}}%
+      }
+      \field{title}{Attention is all you need}
+      \field{year}{2017}
+      \field{eprinttype}{arXiv}
+      \verb{eprint}
+      \verb 1706.03762
+      \endverb
+    \endentry";
+        let entries = parse_bbl(bbl);
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].key, "vaswani");
+        assert_eq!(entries[0].authors.as_deref(), Some("Vaswani and Shazeer"));
+        assert_eq!(entries[0].arxiv_id.as_deref(), Some("1706.03762"));
+        assert!(entries[0]
+            .text
+            .starts_with("Ashish Vaswani, Noam Shazeer. Attention is all you need. 2017."));
+    }
+
+    #[test]
+    fn rewrites_citations_and_places_references() {
+        let entries = parse_bbl(BBL);
+        let tex = "As shown by \\citet{vaswani2017} and others~\\cite{he2016,vaswani2017}.\n\\bibliographystyle{plain}\n\\bibliography{refs}\n\\end{document}";
+        let mut ph = Placeholders::new();
+        let out = apply_entries(tex, &entries, &mut ph);
+        assert!(!out.contains("\\cite"));
+        assert!(!out.contains("\\bibliography"));
+        let md = ph.resolve(&out);
+        assert!(md.contains("Vaswani et al. [[1](#ref-vaswani2017)]"));
+        assert!(md.contains("[[2](#ref-he2016), [1](#ref-vaswani2017)]"));
+        assert!(md.contains("# References"));
+        assert!(md.contains("1. <a id=\"ref-vaswani2017\"></a>"));
+        assert!(md.contains("[arXiv:1706.03762](https://markxiv.org/abs/1706.03762)"));
+    }
+
+    #[test]
+    fn citation_notes_and_unknown_keys() {
+        let entries = parse_bbl(BBL);
+        let tex = r"\citep[see][p.~3]{he2016} \cite{missing}";
+        let mut ph = Placeholders::new();
+        let out = apply_entries(tex, &entries, &mut ph);
+        let md = ph.resolve(&out);
+        assert!(md.starts_with("[see [2](#ref-he2016), p. 3] [missing]"));
+    }
+
+    #[test]
+    fn citations_in_math_stay_plain() {
+        let entries = parse_bbl(BBL);
+        let tex = r"$x \cite{he2016}$";
+        let mut ph = Placeholders::new();
+        let out = apply_entries(tex, &entries, &mut ph);
+        assert!(out.starts_with(r"$x \text{[2]}$"));
+    }
+
+    #[test]
+    fn finds_old_style_arxiv_ids() {
+        assert_eq!(
+            find_arxiv_id("see arxiv.org/abs/hep-th/9901001v2."),
+            Some("hep-th/9901001v2".into())
+        );
+    }
+
+    #[test]
+    fn links_arxiv_ids_in_citeproc_refs() {
+        let md = "Body arXiv:1111.1111\n<div id=\"refs\">\nA. 2020. arXiv:2001.08361.\n</div>";
+        let out = link_arxiv_ids_in_refs(md);
+        assert!(out.starts_with("Body arXiv:1111.1111"));
+        assert!(out.contains("[arXiv:2001.08361](https://markxiv.org/abs/2001.08361)."));
+    }
+
+    #[test]
+    fn select_source_prefers_matching_bbl_then_bib() {
+        let bbl = vec![
+            (PathBuf::from("/w/other.bbl"), String::new()),
+            (PathBuf::from("/w/main.bbl"), BBL.to_string()),
+        ];
+        match select_source("", Path::new("/w/main.tex"), &bbl, &[]) {
+            BibSource::Entries(e) => assert_eq!(e.len(), 2),
+            other => panic!("unexpected {:?}", other),
+        }
+        let bibs = vec![PathBuf::from("/w/refs.bib"), PathBuf::from("/w/unused.bib")];
+        match select_source("\\bibliography{refs}", Path::new("/w/main.tex"), &[], &bibs) {
+            BibSource::BibFiles(f) => assert_eq!(f, vec![PathBuf::from("/w/refs.bib")]),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+}
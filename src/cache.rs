@@ -1,7 +1,12 @@
 use lru::LruCache;
 use std::num::NonZeroUsize;
+use std::sync::Arc;
 
-// A thin wrapper around LruCache for markdown per arXiv id
+use crate::figures::FigureImage;
+
+// A thin wrapper around LruCache for markdown per arXiv id. Entries carry no
+// pipeline version: the in-memory caches only live as long as one build, so
+// only the disk cache can hold stale output.
 pub struct MkCache(LruCache<String, String>);
 
 impl MkCache {
@@ -27,6 +32,25 @@ impl MkCache {
     }
 }
 
+// Figures extracted from a paper's source archive, keyed like MkCache and,
+// like it, unversioned
+pub struct FigureCache(LruCache<String, Arc<Vec<FigureImage>>>);
+
+impl FigureCache {
+    pub fn new(capacity: usize) -> Self {
+        let cap = NonZeroUsize::new(capacity.max(1)).unwrap();
+        Self(LruCache::new(cap))
+    }
+
+    pub fn get(&mut self, key: &str) -> Option<Arc<Vec<FigureImage>>> {
+        self.0.get(key).cloned()
+    }
+
+    pub fn put(&mut self, key: String, figures: Arc<Vec<FigureImage>>) {
+        self.0.put(key, figures);
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -41,4 +65,19 @@ mod tests {
         assert!(c.get("b").is_none());
         assert_eq!(c.len(), 2);
     }
+
+    #[test]
+    fn figure_cache_shares_entries() {
+        use crate::figures::ImageFormat;
+        let mut c = FigureCache::new(1);
+        let figs = Arc::new(vec![FigureImage {
+            name: "1".into(),
+            format: ImageFormat::Png,
+            bytes: vec![1, 2, 3],
+        }]);
+        c.put("/abs/1".into(), figs.clone());
+        assert!(Arc::ptr_eq(&c.get("/abs/1").unwrap(), &figs));
+        c.put("/abs/2".into(), Arc::new(Vec::new()));
+        assert!(c.get("/abs/1").is_none());
+    }
 }
@@ -1,10 +1,34 @@
-use crate::tex_main::select_main_tex;
+use crate::authors;
+use crate::bibliography::{self, BibSource};
+use crate::crossref::{self, FigureGraphic};
+use crate::eprint::{self, EprintFormat, LegacyFormat};
+use crate::figures::{self, FigureImage};
+use crate::footnotes;
+use crate::headings;
+use crate::markdown;
+use crate::mathml;
+use crate::pandoc_pool::PandocPool;
+use crate::pdf_layout;
+use crate::placeholder::Placeholders;
+use crate::postprocess::Pipeline;
+use crate::pseudocode;
+use crate::report::ConversionReport;
+use crate::tables;
+use crate::tex_clean;
+use crate::tex_encoding;
+use crate::tex_main::{
+    find_main_override, rank_main_tex, secondary_documents, select_main_tex, Readme,
+};
+use crate::tex_util::flatten_inputs;
+use crate::theorems;
+use crate::unicode_math;
 use async_trait::async_trait;
 use regex::Regex;
 use std::{
+    ops::Range,
     path::{Path, PathBuf},
-    sync::LazyLock,
-    time::Duration,
+    sync::{Arc, LazyLock},
+    time::{Duration, Instant},
 };
 use thiserror::Error;
 use tokio::process::Command;
@@ -16,21 +40,91 @@ pub enum ConvertError {
     Failed(String),
     #[error("not implemented")]
     NotImplemented,
+    /// The main file asked for with [`LatexOptions::main`] isn't in the
+    /// archive.
+    #[error("main file {0} is not in the archive")]
+    MainNotFound(String),
+}
+
+/// Per-request choices for converting a source archive.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LatexOptions {
+    /// Main `.tex` file relative to the archive root, overriding
+    /// [`select_main_tex`].
+    pub main: Option<String>,
+    /// Also convert secondary documents (supplements, appendices) and
+    /// append them after the main one.
+    pub supplementary: bool,
+    /// Also rasterize the main document's figure graphics for serving,
+    /// returned in [`LatexOutput::figures`].
+    pub figures: bool,
+}
+
+/// A converted source archive.
+#[derive(Debug, Clone, Default)]
+pub struct LatexOutput {
+    pub markdown: String,
+    /// Figure graphics of the main document, taken from the same unpacked
+    /// archive and numbered from the same TeX as the Markdown, when
+    /// [`LatexOptions::figures`] asked for them.
+    pub figures: Vec<FigureImage>,
 }
 
 #[async_trait]
 pub trait Converter {
-    async fn latex_tar_to_markdown(&self, _tar_bytes: &[u8]) -> Result<String, ConvertError>;
+    /// Markdown for the source archive; what happened along the way
+    /// (main file, pandoc warnings, timings, ...) is added to `report`,
+    /// also when the conversion fails.
+    async fn latex_tar_to_markdown(
+        &self,
+        _tar_bytes: &[u8],
+        _opts: &LatexOptions,
+        _report: &mut ConversionReport,
+    ) -> Result<LatexOutput, ConvertError>;
     async fn latex_tar_to_markdown_without_macros(
         &self,
         tar_bytes: &[u8],
-    ) -> Result<String, ConvertError> {
-        self.latex_tar_to_markdown(tar_bytes).await
+        opts: &LatexOptions,
+        report: &mut ConversionReport,
+    ) -> Result<LatexOutput, ConvertError> {
+        self.latex_tar_to_markdown(tar_bytes, opts, report).await
     }
     async fn pdf_to_markdown(&self, _pdf_bytes: &[u8]) -> Result<String, ConvertError>;
+    /// Text of a PostScript or DVI e-print (bare, gzipped, or the document
+    /// in a tar archive without LaTeX), converted to PDF with local tools
+    /// and read like [`Converter::pdf_to_markdown`]. Also returns which
+    /// format, and so which conversion route, was used.
+    async fn legacy_eprint_to_markdown(
+        &self,
+        _eprint_bytes: &[u8],
+    ) -> Result<(String, LegacyFormat), ConvertError> {
+        Err(ConvertError::NotImplemented)
+    }
+    /// Figure graphics from the source archive on their own, rasterized
+    /// for serving, for when a conversion's figures are no longer cached.
+    /// Converters that cannot extract figures return none.
+    async fn latex_tar_figures(
+        &self,
+        _tar_bytes: &[u8],
+        _opts: &LatexOptions,
+    ) -> Result<Vec<FigureImage>, ConvertError> {
+        Ok(Vec::new())
+    }
 }
 
-pub struct PandocConverter;
+/// Where pandoc runs: a fresh process per conversion, or a pool of
+/// long-lived `pandoc server` workers chosen at startup.
+#[derive(Clone, Default)]
+pub enum PandocBackend {
+    #[default]
+    Spawn,
+    Pool(Arc<PandocPool>),
+}
+
+pub struct PandocConverter {
+    backend: PandocBackend,
+    pipeline: Pipeline,
+}
 
 impl Default for PandocConverter {
     fn default() -> Self {
@@ -40,22 +134,42 @@ impl Default for PandocConverter {
 
 impl PandocConverter {
     pub fn new() -> Self {
-        Self
+        Self::with_backend(PandocBackend::Spawn)
+    }
+
+    pub fn with_backend(backend: PandocBackend) -> Self {
+        Self {
+            backend,
+            pipeline: Pipeline::standard(),
+        }
+    }
+
+    /// Replace the stages that clean up pandoc's Markdown.
+    pub fn with_pipeline(mut self, pipeline: Pipeline) -> Self {
+        self.pipeline = pipeline;
+        self
     }
 }
 
 #[async_trait]
 impl Converter for PandocConverter {
-    async fn latex_tar_to_markdown(&self, tar_bytes: &[u8]) -> Result<String, ConvertError> {
-        self.convert_latex(tar_bytes, PandocLatexMode::Standard)
+    async fn latex_tar_to_markdown(
+        &self,
+        tar_bytes: &[u8],
+        opts: &LatexOptions,
+        report: &mut ConversionReport,
+    ) -> Result<LatexOutput, ConvertError> {
+        self.convert_latex(tar_bytes, opts, PandocLatexMode::Standard, report)
             .await
     }
 
     async fn latex_tar_to_markdown_without_macros(
         &self,
         tar_bytes: &[u8],
-    ) -> Result<String, ConvertError> {
-        self.convert_latex(tar_bytes, PandocLatexMode::NoMacros)
+        opts: &LatexOptions,
+        report: &mut ConversionReport,
+    ) -> Result<LatexOutput, ConvertError> {
+        self.convert_latex(tar_bytes, opts, PandocLatexMode::NoMacros, report)
             .await
     }
 
@@ -71,12 +185,68 @@ impl Converter for PandocConverter {
 
         let pdftotext =
             std::env::var("MARKXIV_PDFTOTEXT_PATH").unwrap_or_else(|_| "pdftotext".into());
-        let result = run_pdftotext(&pdftotext, &pdf_path).await;
+        // Layout analysis over word boxes first; the plain text dump when
+        // that fails or finds no text (e.g. an older pdftotext).
+        let layout = match run_pdftotext(&pdftotext, &pdf_path, "-bbox-layout").await {
+            Ok(xhtml) => Some(pdf_layout::bbox_to_markdown(&String::from_utf8_lossy(
+                &xhtml,
+            )))
+            .filter(|md| !md.trim().is_empty()),
+            Err(e) => {
+                tracing::warn!(error = %e, "pdftotext layout pass failed");
+                None
+            }
+        };
+        let result = match layout {
+            Some(md) => Ok(md),
+            None => run_pdftotext(&pdftotext, &pdf_path, "-raw")
+                .await
+                .map(|text| String::from_utf8_lossy(&text).into_owned()),
+        };
 
         cleanup(&workdir).await;
 
-        let text_bytes = result?;
-        Ok(String::from_utf8_lossy(&text_bytes).into_owned())
+        result
+    }
+
+    async fn legacy_eprint_to_markdown(
+        &self,
+        eprint_bytes: &[u8],
+    ) -> Result<(String, LegacyFormat), ConvertError> {
+        let workdir = make_temp_dir()
+            .await
+            .map_err(|e| ConvertError::Failed(format!("temp dir: {}", e)))?;
+        let pdf = legacy_to_pdf(&workdir, eprint_bytes).await;
+        cleanup(&workdir).await;
+        let (pdf_bytes, format) = pdf?;
+        tracing::info!(
+            format = format.name(),
+            route = format.route(),
+            "converting legacy e-print"
+        );
+        let md = self.pdf_to_markdown(&pdf_bytes).await?;
+        Ok((md, format))
+    }
+
+    async fn latex_tar_figures(
+        &self,
+        tar_bytes: &[u8],
+        opts: &LatexOptions,
+    ) -> Result<Vec<FigureImage>, ConvertError> {
+        let UnpackedSource {
+            workdir,
+            files,
+            main_tex,
+            ..
+        } = unpack_source(tar_bytes, opts).await?;
+        // Cleaned like the text, so commented-out figures aren't numbered.
+        let tex = flatten_inputs(&main_tex, &files)
+            .map(|tex| tex_clean::clean(&tex))
+            .unwrap_or_default();
+        let graphics = crossref::number(&tex).graphics;
+        let images = figures::extract_figures(&workdir, &main_tex, &tex, &graphics).await;
+        cleanup(&workdir).await;
+        Ok(images)
     }
 }
 
@@ -84,51 +254,437 @@ impl PandocConverter {
     async fn convert_latex(
         &self,
         tar_bytes: &[u8],
+        opts: &LatexOptions,
         mode: PandocLatexMode,
-    ) -> Result<String, ConvertError> {
-        let workdir = make_temp_dir()
-            .await
-            .map_err(|e| ConvertError::Failed(format!("temp dir: {}", e)))?;
-        let tar_path = workdir.join("source.tar");
-        // write bytes to disk
-        tokio::fs::write(&tar_path, tar_bytes)
-            .await
-            .map_err(|e| ConvertError::Failed(format!("write tar: {}", e)))?;
+        report: &mut ConversionReport,
+    ) -> Result<LatexOutput, ConvertError> {
+        let start = Instant::now();
+        let UnpackedSource {
+            workdir,
+            files,
+            main_tex,
+            readme,
+        } = unpack_source(tar_bytes, opts).await?;
+        report.time("unpack", start);
+        let relative = |p: &Path| p.strip_prefix(&workdir).unwrap_or(p).display().to_string();
+        report.main_file = Some(relative(&main_tex));
+        report.candidates = rank_main_tex(&files, readme.as_ref())
+            .iter()
+            .map(|p| relative(p))
+            .collect();
+
+        let result = convert_document(self, &workdir, &files, &main_tex, mode, report).await;
+        let doc = match result {
+            Ok(doc) => doc,
+            Err(e) => {
+                cleanup(&workdir).await;
+                return Err(e);
+            }
+        };
+        let mut md = doc.markdown;
+        if opts.supplementary {
+            let start = Instant::now();
+            let docs = secondary_documents(&files, &main_tex, readme.as_ref());
+            md = append_supplementary(self, md, &workdir, &files, &docs, mode, report).await;
+            report.time("supplementary", start);
+        }
+        let mut figures = Vec::new();
+        if opts.figures {
+            let start = Instant::now();
+            figures = figures::extract_figures(&workdir, &main_tex, &doc.tex, &doc.graphics).await;
+            report.time("figures", start);
+        }
+
+        // cleanup best-effort
+        cleanup(&workdir).await;
+        Ok(LatexOutput {
+            markdown: md,
+            figures,
+        })
+    }
+}
 
-        // extract: try plain tar, then gzip
-        if let Err(e1) = extract_tar(&workdir, &tar_path, false).await {
-            extract_tar(&workdir, &tar_path, true)
+/// A document converted by [`convert_document`], with what figure
+/// extraction needs from its TeX.
+struct ConvertedDocument {
+    markdown: String,
+    /// The preprocessed TeX given to pandoc, for its `\graphicspath`.
+    tex: String,
+    /// Graphics of its numbered figures in document order.
+    graphics: Vec<FigureGraphic>,
+}
+
+/// Convert one document of an extracted archive to sanitized Markdown.
+async fn convert_document(
+    converter: &PandocConverter,
+    workdir: &Path,
+    files: &[(PathBuf, String)],
+    main_tex: &Path,
+    mode: PandocLatexMode,
+    report: &mut ConversionReport,
+) -> Result<ConvertedDocument, ConvertError> {
+    let start = Instant::now();
+    // Flatten \input/\include into one document, drop comments and
+    // drafting notes, and rewrite the constructs we render ourselves; the
+    // result is written next to the main file so relative paths keep
+    // working for pandoc.
+    let mut placeholders = Placeholders::new();
+    let mut extra_args = Vec::new();
+    let mut tex = flatten_inputs(main_tex, files)
+        .map(|tex| tex_clean::clean(&tex))
+        .unwrap_or_default();
+    let (stripped, thanks) = footnotes::take_thanks(&tex);
+    tex = stripped;
+    report.front_matter.add_thanks(thanks);
+    // Supplementary documents have title blocks of their own.
+    if report.front_matter.authors.is_empty() {
+        report.front_matter.authors = authors::extract(&tex);
+    }
+    let bbl_files = collect_files_by_ext(workdir, &["bbl"])
+        .await
+        .unwrap_or_default();
+    let bib_files = collect_paths_by_ext(workdir, &["bib"])
+        .await
+        .unwrap_or_default();
+    let main_parent = main_tex.parent().unwrap_or(workdir);
+    let citeproc = match bibliography::select_source(&tex, main_tex, &bbl_files, &bib_files) {
+        BibSource::Entries(entries) => {
+            tex = bibliography::apply_entries(&tex, &entries, &mut placeholders);
+            false
+        }
+        BibSource::BibFiles(bibs) => {
+            let csl = main_parent.join(bibliography::NUMERIC_CSL_FILE);
+            tokio::fs::write(&csl, bibliography::NUMERIC_CSL)
                 .await
-                .map_err(|e2| ConvertError::Failed(format!("extract: {}; fallback: {}", e1, e2)))?;
+                .map_err(|e| ConvertError::Failed(format!("write csl: {}", e)))?;
+            extra_args = bibliography::citeproc_args(&bibs, &csl);
+            true
         }
+        BibSource::None => false,
+    };
+    let mut numbering = None;
+    if !tex.is_empty() {
+        let (resolved, n) = crossref::resolve(&tex, &mut placeholders);
+        let resolved = pseudocode::render(&resolved, &n, &mut placeholders);
+        let resolved = theorems::render(&resolved, &n, &mut placeholders);
+        tex = footnotes::render(&resolved, &mut placeholders);
+        numbering = Some(n);
+    }
+    report.time("preprocess", start);
 
-        // Collect .tex files
-        let files = collect_tex_files(&workdir)
+    // Run pandoc
+    let pandoc = std::env::var("MARKXIV_PANDOC_PATH").unwrap_or_else(|_| "pandoc".into());
+    let main_file = if tex.is_empty() {
+        main_tex
+            .file_name()
+            .and_then(|s| s.to_str())
+            .ok_or_else(|| ConvertError::Failed("invalid main tex path".into()))?
+            .to_string()
+    } else {
+        let name = PREPROCESSED_MAIN.to_string();
+        tokio::fs::write(main_parent.join(&name), &tex)
             .await
-            .map_err(|e| ConvertError::Failed(format!("scan: {}", e)))?;
-        let Some(main_tex) = select_main_tex(&files) else {
-            cleanup(&workdir).await;
-            return Err(ConvertError::Failed("no .tex files found".into()));
+            .map_err(|e| ConvertError::Failed(format!("write preprocessed tex: {}", e)))?;
+        name
+    };
+    let start = Instant::now();
+    let (md_bytes, warnings) = match &converter.backend {
+        PandocBackend::Spawn => {
+            run_pandoc(&pandoc, main_parent, &main_file, mode, &extra_args).await?
+        }
+        PandocBackend::Pool(pool) => {
+            pool.convert(main_parent, &main_file, mode.reader(), &extra_args)
+                .await?
+        }
+    };
+    report.time("pandoc", start);
+    report.pandoc_warnings.extend(warnings);
+    // Counted once pandoc has succeeded, so a failed attempt that is
+    // retried doesn't count the same floats twice.
+    report.count_floats(&tex);
+
+    let start = Instant::now();
+    let mut md = String::from_utf8_lossy(&md_bytes).into_owned();
+    if citeproc {
+        md = bibliography::link_arxiv_ids_in_refs(&md);
+        md = bibliography::anchor_citeproc_entries(&md, &mut placeholders);
+    }
+    md = converter.pipeline.run(&md, report);
+    md = placeholders.resolve(&md);
+    if numbering.is_some() {
+        md = crossref::anchor_figures(&md);
+    }
+    report.time("postprocess", start);
+    Ok(ConvertedDocument {
+        markdown: md,
+        tex,
+        graphics: numbering.map(|n| n.graphics).unwrap_or_default(),
+    })
+}
+
+/// Append the converted `docs` under a "Supplementary Material" heading,
+/// each under its file name when there are several. Documents that fail
+/// to convert are logged and left out.
+async fn append_supplementary(
+    converter: &PandocConverter,
+    mut md: String,
+    workdir: &Path,
+    files: &[(PathBuf, String)],
+    docs: &[PathBuf],
+    mode: PandocLatexMode,
+    report: &mut ConversionReport,
+) -> String {
+    let mut sections = Vec::new();
+    for doc in docs {
+        let name = doc
+            .strip_prefix(workdir)
+            .unwrap_or(doc)
+            .display()
+            .to_string();
+        match convert_document(converter, workdir, files, doc, mode, report).await {
+            Ok(doc) if !doc.markdown.trim().is_empty() => sections.push((name, doc.markdown)),
+            Ok(_) => {}
+            Err(e) => {
+                tracing::warn!(document = %name, error = %e, "supplementary conversion failed")
+            }
+        }
+    }
+    if sections.is_empty() {
+        return md;
+    }
+    md.truncate(md.trim_end().len());
+    md.push_str("\n\n# Supplementary Material\n");
+    let several = sections.len() > 1;
+    for (i, (name, body)) in sections.into_iter().enumerate() {
+        if several {
+            md.push_str(&format!("\n## `{}`\n", name));
+        }
+        let depth = if several { 2 } else { 1 };
+        md.push('\n');
+        md.push_str(nest_supplement(&body, i + 1, depth).trim());
+        md.push('\n');
+    }
+    md
+}
+
+static FOOTNOTE_LABEL_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\[\^([^\]\s]+)\]").unwrap());
+static ANCHOR_ID_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"(<[a-zA-Z][^>]*\sid=")([^"]+)""#).unwrap());
+static FRAGMENT_LINK_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\]\(#([^)\s]+)\)").unwrap());
+
+/// Fit the `index`th supplement under its heading: its headings are demoted
+/// by `depth` levels, and its footnote labels and anchors get an `sN-`
+/// prefix so they don't collide with the main document's, which numbers
+/// them the same way.
+fn nest_supplement(body: &str, index: usize, depth: usize) -> String {
+    let prefix = format!("s{}-", index);
+    let body = replace_outside_code(body, &FOOTNOTE_LABEL_RE, |caps| {
+        format!("[^{}{}]", prefix, &caps[1])
+    });
+    let body = replace_outside_code(&body, &ANCHOR_ID_RE, |caps| {
+        format!("{}{}{}\"", &caps[1], prefix, &caps[2])
+    });
+    let body = replace_outside_code(&body, &FRAGMENT_LINK_RE, |caps| {
+        format!("](#{}{})", prefix, &caps[1])
+    });
+    let mut out = String::with_capacity(body.len());
+    let mut rest = 0;
+    for (pos, line) in headings::lines_outside_code(&body) {
+        let Some(h) = headings::parse_heading(line.trim_end_matches(['\n', '\r'])) else {
+            continue;
         };
+        let hashes = line.len() - line.trim_start_matches('#').len();
+        out.push_str(&body[rest..pos]);
+        out.push_str(&"#".repeat((h.level + depth).min(6)));
+        rest = pos + hashes;
+    }
+    out.push_str(&body[rest..]);
+    out
+}
 
-        // Run pandoc
-        let pandoc = std::env::var("MARKXIV_PANDOC_PATH").unwrap_or_else(|_| "pandoc".into());
-        let main_parent = main_tex.parent().unwrap_or(Path::new(&workdir));
-        let main_file = main_tex
-            .file_name()
-            .and_then(|s| s.to_str())
-            .ok_or_else(|| ConvertError::Failed("invalid main tex path".into()))?;
-        let md_bytes = run_pandoc(&pandoc, main_parent, main_file, mode).await?;
+/// `re` replaced by `f` wherever it matches outside code.
+fn replace_outside_code(md: &str, re: &Regex, f: impl Fn(&regex::Captures) -> String) -> String {
+    let code = markdown::code_ranges(md);
+    re.replace_all(md, |caps: &regex::Captures| {
+        let m = caps.get(0).unwrap();
+        if code.iter().any(|r| r.contains(&m.start())) {
+            m.as_str().to_string()
+        } else {
+            f(caps)
+        }
+    })
+    .into_owned()
+}
 
-        // cleanup best-effort
-        cleanup(&workdir).await;
+/// A source archive extracted into a temp dir, with its main file chosen.
+struct UnpackedSource {
+    workdir: PathBuf,
+    files: Vec<(PathBuf, String)>,
+    main_tex: PathBuf,
+    readme: Option<Readme>,
+}
+
+async fn unpack_source(
+    tar_bytes: &[u8],
+    opts: &LatexOptions,
+) -> Result<UnpackedSource, ConvertError> {
+    let workdir = make_temp_dir()
+        .await
+        .map_err(|e| ConvertError::Failed(format!("temp dir: {}", e)))?;
+    let tar_path = workdir.join("source.tar");
+    // write bytes to disk
+    tokio::fs::write(&tar_path, tar_bytes)
+        .await
+        .map_err(|e| ConvertError::Failed(format!("write tar: {}", e)))?;
+
+    // extract: try plain tar, then gzip
+    if let Err(e1) = extract_tar(&workdir, &tar_path, false).await {
+        extract_tar(&workdir, &tar_path, true)
+            .await
+            .map_err(|e2| ConvertError::Failed(format!("extract: {}; fallback: {}", e1, e2)))?;
+    }
+
+    // Collect .tex files
+    let files = collect_tex_files(&workdir)
+        .await
+        .map_err(|e| ConvertError::Failed(format!("scan: {}", e)))?;
+    let readme = read_readme(&workdir).await;
+    let main_tex = match &opts.main {
+        Some(main) => find_main_override(&workdir, &files, main)
+            .ok_or_else(|| ConvertError::MainNotFound(main.clone())),
+        None => select_main_tex(&files, readme.as_ref())
+            .ok_or_else(|| ConvertError::Failed("no .tex files found".into())),
+    };
+    let main_tex = match main_tex {
+        Ok(p) => p,
+        Err(e) => {
+            cleanup(&workdir).await;
+            return Err(e);
+        }
+    };
+    Ok(UnpackedSource {
+        workdir,
+        files,
+        main_tex,
+        readme,
+    })
+}
 
-        let mut md = String::from_utf8_lossy(&md_bytes).into_owned();
-        md = sanitize_markdown(&md);
-        Ok(md)
+/// Turn a PostScript or DVI e-print into PDF bytes in `workdir`, with
+/// ps2pdf or dvipdf.
+async fn legacy_to_pdf(
+    workdir: &Path,
+    eprint_bytes: &[u8],
+) -> Result<(Vec<u8>, LegacyFormat), ConvertError> {
+    let (format, doc) = match eprint::sniff(eprint_bytes) {
+        EprintFormat::Tar => legacy_member(workdir, eprint_bytes).await?,
+        f => match f.legacy() {
+            Some(format) => (format, eprint::gunzip(eprint_bytes).into_owned()),
+            None => {
+                return Err(ConvertError::Failed(
+                    "not a PostScript or DVI e-print".into(),
+                ))
+            }
+        },
+    };
+    let src = workdir.join(format!("eprint.{}", format.extension()));
+    let pdf = workdir.join("eprint.pdf");
+    tokio::fs::write(&src, &doc)
+        .await
+        .map_err(|e| ConvertError::Failed(format!("write e-print: {}", e)))?;
+    let (var, default) = match format {
+        LegacyFormat::PostScript => ("MARKXIV_PS2PDF_PATH", "ps2pdf"),
+        LegacyFormat::Dvi => ("MARKXIV_DVIPDF_PATH", "dvipdf"),
+    };
+    let tool = std::env::var(var).unwrap_or_else(|_| default.into());
+    let mut cmd = Command::new(&tool);
+    cmd.current_dir(workdir).arg(&src).arg(&pdf);
+    let out = timeout(Duration::from_secs(120), cmd.output())
+        .await
+        .map_err(|_| ConvertError::Failed(format!("{} timed out", default)))
+        .and_then(|r| r.map_err(|e| ConvertError::Failed(format!("{} spawn: {}", default, e))))?;
+    if !out.status.success() {
+        return Err(ConvertError::Failed(format!(
+            "{} failed: {}",
+            default,
+            String::from_utf8_lossy(&out.stderr)
+        )));
+    }
+    let pdf_bytes = tokio::fs::read(&pdf)
+        .await
+        .map_err(|e| ConvertError::Failed(format!("read {} output: {}", default, e)))?;
+    Ok((pdf_bytes, format))
+}
+
+/// The largest `.ps`, `.ps.gz` or `.dvi` file in a tar e-print that has no
+/// LaTeX source.
+async fn legacy_member(
+    workdir: &Path,
+    tar_bytes: &[u8],
+) -> Result<(LegacyFormat, Vec<u8>), ConvertError> {
+    let tar_path = workdir.join("source.tar");
+    tokio::fs::write(&tar_path, tar_bytes)
+        .await
+        .map_err(|e| ConvertError::Failed(format!("write tar: {}", e)))?;
+    if let Err(e1) = extract_tar(workdir, &tar_path, false).await {
+        extract_tar(workdir, &tar_path, true)
+            .await
+            .map_err(|e2| ConvertError::Failed(format!("extract: {}; fallback: {}", e1, e2)))?;
+    }
+    let has_tex = !collect_tex_files(workdir)
+        .await
+        .unwrap_or_default()
+        .is_empty();
+    let mut best: Option<(LegacyFormat, Vec<u8>)> = None;
+    let mut stack = vec![workdir.to_path_buf()];
+    while let Some(dir) = stack.pop() {
+        let Ok(mut rd) = tokio::fs::read_dir(&dir).await else {
+            continue;
+        };
+        while let Ok(Some(entry)) = rd.next_entry().await {
+            let path = entry.path();
+            if path.is_dir() {
+                stack.push(path);
+                continue;
+            }
+            let name = path.file_name().and_then(|n| n.to_str()).unwrap_or("");
+            let Some(format) = LegacyFormat::from_file_name(name) else {
+                continue;
+            };
+            let Ok(bytes) = tokio::fs::read(&path).await else {
+                continue;
+            };
+            let bytes = eprint::gunzip(&bytes).into_owned();
+            if best.as_ref().is_none_or(|(_, b)| bytes.len() > b.len()) {
+                best = Some((format, bytes));
+            }
+        }
+    }
+    match best {
+        Some(found) if !has_tex => Ok(found),
+        _ => Err(ConvertError::Failed(
+            "no PostScript or DVI document in archive".into(),
+        )),
     }
 }
 
+/// arXiv's processing directives from the archive root, if it has any.
+async fn read_readme(root: &Path) -> Option<Readme> {
+    for name in ["00README.json", "00README.XXD", "00README"] {
+        if let Ok(contents) = tokio::fs::read_to_string(root.join(name)).await {
+            if let Some(readme) = Readme::parse(name, &contents) {
+                return Some(readme);
+            }
+        }
+    }
+    None
+}
+
+/// File name for the flattened, pre-processed main document.
+const PREPROCESSED_MAIN: &str = "markxiv-main.tex";
+
 use std::io;
 use std::sync::atomic::{AtomicU64, Ordering};
 static COUNTER: AtomicU64 = AtomicU64::new(0);
@@ -182,6 +738,33 @@ async fn extract_tar(workdir: &Path, tar_path: &Path, gzip: bool) -> io::Result<
 }
 
 async fn collect_tex_files(root: &Path) -> io::Result<Vec<(PathBuf, String)>> {
+    collect_files_by_ext(root, &["tex"]).await
+}
+
+async fn collect_files_by_ext(root: &Path, exts: &[&str]) -> io::Result<Vec<(PathBuf, String)>> {
+    let mut out = Vec::new();
+    for path in collect_paths_by_ext(root, exts).await? {
+        let Ok(bytes) = tokio::fs::read(&path).await else {
+            continue;
+        };
+        // pandoc only reads UTF-8, so 8-bit sources are rewritten in place
+        // once their encoding is known.
+        let (text, encoding) = tex_encoding::decode(&bytes);
+        if let Some(encoding) = encoding {
+            tracing::info!(
+                file = %path.display(),
+                encoding = encoding.name(),
+                "transcoded source file to UTF-8"
+            );
+            tokio::fs::write(&path, &text).await?;
+        }
+        out.push((path, text));
+    }
+    Ok(out)
+}
+
+/// Files under `root` with one of the extensions `exts`.
+async fn collect_paths_by_ext(root: &Path, exts: &[&str]) -> io::Result<Vec<PathBuf>> {
     let mut out = Vec::new();
     let mut stack = vec![root.to_path_buf()];
     while let Some(dir) = stack.pop() {
@@ -189,13 +772,15 @@ async fn collect_tex_files(root: &Path) -> io::Result<Vec<(PathBuf, String)>> {
         while let Some(entry) = rd.next_entry().await? {
             let path = entry.path();
             let ft = entry.file_type().await?;
+            let wanted = path
+                .extension()
+                .and_then(|e| e.to_str())
+                .map(|e| exts.contains(&e))
+                .unwrap_or(false);
             if ft.is_dir() {
                 stack.push(path);
-            } else if ft.is_file() && path.extension().map(|e| e == "tex").unwrap_or(false) {
-                match tokio::fs::read_to_string(&path).await {
-                    Ok(s) => out.push((path, s)),
-                    Err(_) => continue,
-                }
+            } else if ft.is_file() && wanted {
+                out.push(path);
             }
         }
     }
@@ -208,33 +793,53 @@ enum PandocLatexMode {
     NoMacros,
 }
 
-const PANDOC_TIMEOUT: Duration = Duration::from_secs(5);
+impl PandocLatexMode {
+    /// pandoc's `--from` format.
+    fn reader(self) -> &'static str {
+        match self {
+            Self::Standard => "latex",
+            Self::NoMacros => "latex-latex_macros",
+        }
+    }
+}
+
+/// Version of the conversion pipeline, stamped on every cached rendering.
+/// Bump it with any change that alters converted Markdown, so renderings
+/// cached by an older build count as stale and are converted again.
+pub const PIPELINE_VERSION: u32 = 1;
+
+/// Limit on one pandoc run, in either backend.
+pub const PANDOC_TIMEOUT: Duration = Duration::from_secs(5);
 
 async fn run_pandoc(
     pandoc: &str,
     cwd: &Path,
     main_file: &str,
     mode: PandocLatexMode,
-) -> Result<Vec<u8>, ConvertError> {
+    extra_args: &[String],
+) -> Result<(Vec<u8>, Vec<String>), ConvertError> {
     let mut cmd = Command::new(pandoc);
-    let format_arg = match mode {
-        PandocLatexMode::Standard => "latex",
-        PandocLatexMode::NoMacros => "latex-latex_macros",
-    };
     cmd.current_dir(cwd)
         .arg("-f")
-        .arg(format_arg)
+        .arg(mode.reader())
         .arg("-t")
         .arg("gfm")
+        .args(extra_args)
         .arg(main_file);
     let out = timeout(PANDOC_TIMEOUT, cmd.output())
         .await
         .map_err(|_| ConvertError::Failed("pandoc timed out".into()))
         .and_then(|r| r.map_err(|e| ConvertError::Failed(format!("pandoc spawn: {}", e))))?;
+    let stderr = String::from_utf8_lossy(&out.stderr);
     if out.status.success() {
-        Ok(out.stdout)
+        let warnings = stderr
+            .lines()
+            .map(str::trim)
+            .filter(|l| !l.is_empty())
+            .map(String::from)
+            .collect();
+        Ok((out.stdout, warnings))
     } else {
-        let stderr = String::from_utf8_lossy(&out.stderr);
         Err(ConvertError::Failed(format!("pandoc failed: {}", stderr)))
     }
 }
@@ -243,9 +848,13 @@ async fn cleanup(path: &Path) {
     let _ = tokio::fs::remove_dir_all(path).await;
 }
 
-async fn run_pdftotext(pdftotext: &str, pdf_path: &Path) -> Result<Vec<u8>, ConvertError> {
+async fn run_pdftotext(
+    pdftotext: &str,
+    pdf_path: &Path,
+    mode: &str,
+) -> Result<Vec<u8>, ConvertError> {
     let mut cmd = Command::new(pdftotext);
-    cmd.arg("-raw").arg(pdf_path).arg("-");
+    cmd.arg(mode).arg(pdf_path).arg("-");
     let out = timeout(Duration::from_secs(300), cmd.output())
         .await
         .map_err(|_| ConvertError::Failed("pdftotext timed out".into()))
@@ -261,23 +870,6 @@ async fn run_pdftotext(pdftotext: &str, pdf_path: &Path) -> Result<Vec<u8>, Conv
     }
 }
 
-fn fix_katex_commands(input: &str) -> String {
-    static RE_MATHCAL: LazyLock<Regex> =
-        LazyLock::new(|| Regex::new(r"(\\mathcal\{[^}]*\})\{").unwrap());
-    static RE_TEXTSC: LazyLock<Regex> =
-        LazyLock::new(|| Regex::new(r"\\textsc\{([^}]*)\}").unwrap());
-    static RE_CALL: LazyLock<Regex> =
-        LazyLock::new(|| Regex::new(r"\\Call\{([^}]*)\}\{([^}]*)\}").unwrap());
-    static RE_MATHBBM: LazyLock<Regex> =
-        LazyLock::new(|| Regex::new(r"\\mathbbm\{([^}]*)\}").unwrap());
-
-    let s = RE_MATHCAL.replace_all(input, "${1}_{");
-    let s = RE_TEXTSC.replace_all(&s, r"\textbf{$1}");
-    let s = RE_CALL.replace_all(&s, r"\textbf{$1}($2)");
-    let s = RE_MATHBBM.replace_all(&s, r"\mathbb{$1}");
-    s.into_owned()
-}
-
 /// Ensure `$$...$$` display math blocks sit on their own lines.
 ///
 /// Many markdown renderers (including KaTeX-based ones) require `$$` to start
@@ -285,7 +877,7 @@ fn fix_katex_commands(input: &str) -> String {
 /// inline with text (e.g. `text $$x^2$$ more text`) the closing `$$` can be
 /// misparsed as two separate `$` inline-math delimiters, producing errors like
 /// "Can't use function '$' in math mode".
-fn normalize_display_math(input: &str) -> String {
+pub(crate) fn normalize_display_math(input: &str) -> String {
     static RE_DISPLAY: LazyLock<Regex> = LazyLock::new(|| {
         // Match $$...$$, possibly spanning multiple lines, that have non-whitespace
         // text on the same line before or after the delimiters.
@@ -336,7 +928,7 @@ fn normalize_display_math(input: &str) -> String {
 /// Instead of stripping figures entirely, extracts the `<figcaption>` text
 /// and produces `> **Figure N:** caption` blockquotes. This preserves figure
 /// context in the output and allows downstream enrichment with ar5iv links.
-fn extract_figure_captions(input: &str) -> String {
+pub(crate) fn extract_figure_captions(input: &str) -> String {
     let mut out = input.to_string();
     let mut figure_num = 0u32;
     while let Some(start) = out.find("<figure") {
@@ -344,6 +936,13 @@ fn extract_figure_captions(input: &str) -> String {
             let end = start + rel_end + "</figure>".len();
             let block = out[start..end].to_string();
             figure_num += 1;
+            // Figures labelled by the cross-reference pass carry the number
+            // LaTeX would print; keep the sequential count in step with it.
+            let label = figure_label_number(&block);
+            if let Some(n) = label.as_deref().and_then(|l| l.parse().ok()) {
+                figure_num = n;
+            }
+            let number = label.unwrap_or_else(|| figure_num.to_string());
 
             // Extract caption from <figcaption>...</figcaption>
             let caption = if let Some(fc_start) = block.find("<figcaption") {
@@ -365,8 +964,8 @@ fn extract_figure_captions(input: &str) -> String {
             };
 
             let replacement = match caption {
-                Some(cap) => format!("\n\n> **Figure {}:** {}\n\n", figure_num, cap),
-                None => format!("\n\n> **Figure {}**\n\n", figure_num),
+                Some(cap) => format!("\n\n> **Figure {}:** {}\n\n", number, cap),
+                None => format!("\n\n> **Figure {}**\n\n", number),
             };
             out.replace_range(start..end, &replacement);
         } else {
@@ -383,6 +982,15 @@ fn extract_figure_captions(input: &str) -> String {
     out
 }
 
+/// Number encoded in a `<figure id="markxiv-figure-N">` opening tag.
+fn figure_label_number(block: &str) -> Option<String> {
+    let open = &block[..block.find('>')?];
+    let rest =
+        &open[open.find(crossref::FIGURE_LABEL_PREFIX)? + crossref::FIGURE_LABEL_PREFIX.len()..];
+    let number = &rest[..rest.find('"')?];
+    (!number.is_empty()).then(|| number.to_string())
+}
+
 /// Enrich figure placeholders with links to figure images on arxiv HTML.
 ///
 /// Finds `> **Figure N:**` blockquotes produced by [`extract_figure_captions`]
@@ -414,91 +1022,204 @@ pub fn add_arxiv_figure_html_links(md: &str, figure_image_urls: &[String]) -> St
         .into_owned()
 }
 
-fn sanitize_markdown(input: &str) -> String {
-    // 1) Convert <figure> blocks to markdown caption placeholders
-    let mut out = extract_figure_captions(input);
-    // 2) Fix KaTeX commands that are unsupported or malformed
-    out = fix_katex_commands(&out);
-    // 3) Ensure display math blocks are on their own lines
-    out = normalize_display_math(&out);
-    // 4) Strip HTML tags but preserve math blocks ($...$ and $$...$$) verbatim
-    strip_html_tags_preserve_math(out.trim_start())
+/// How math is written in served Markdown, chosen with `?math=`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MathMode {
+    /// `$...$` and `$$...$$` LaTeX, as sanitized for KaTeX.
+    #[default]
+    Latex,
+    /// Unicode text where the math is simple enough, LaTeX otherwise.
+    Unicode,
+    /// Inline `<math>` MathML elements, LaTeX where conversion fails.
+    Mathml,
+}
+
+impl MathMode {
+    pub const ALL: [Self; 3] = [Self::Latex, Self::Unicode, Self::Mathml];
+
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "latex" | "" => Some(Self::Latex),
+            "unicode" => Some(Self::Unicode),
+            "mathml" => Some(Self::Mathml),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Latex => "latex",
+            Self::Unicode => "unicode",
+            Self::Mathml => "mathml",
+        }
+    }
+
+    /// Cache key for a paper rendered in this mode. LaTeX keeps the plain
+    /// key, so entries cached before math modes existed stay valid.
+    pub fn cache_key(self, base: &str) -> String {
+        let sep = if base.contains('?') { '&' } else { '?' };
+        match self {
+            Self::Latex => base.to_string(),
+            _ => format!("{}{}math={}", base, sep, self.as_str()),
+        }
+    }
+}
+
+/// Post-processing stage that rewrites the math of finished Markdown (after
+/// sanitization, so display math is already on its own lines) into `mode`.
+pub fn render_math(md: &str, mode: MathMode) -> String {
+    match mode {
+        MathMode::Latex => md.to_string(),
+        MathMode::Unicode => unicode_math::markdown_math_to_unicode(md),
+        MathMode::Mathml => mathml::markdown_math_to_mathml(md),
+    }
 }
 
-/// Strip HTML tags from text while preserving math blocks verbatim.
+/// Strip HTML tags from text while preserving math and code verbatim.
 ///
-/// Content inside `$...$` and `$$...$$` is copied as-is so that `<` and `>`
-/// in math expressions (e.g. `\texttt{<name>}`, comparisons) survive intact.
-fn strip_html_tags_preserve_math(input: &str) -> String {
-    let bytes = input.as_bytes();
-    let len = bytes.len();
-    let mut out = String::with_capacity(len);
-    let mut i = 0;
-
-    while i < len {
-        if i + 1 < len && bytes[i] == b'$' && bytes[i + 1] == b'$' {
-            // Display math $$...$$ — copy verbatim
-            out.push_str("$$");
-            i += 2;
-            while i + 1 < len {
-                if bytes[i] == b'$' && bytes[i + 1] == b'$' {
-                    break;
-                }
-                let ch = input[i..].chars().next().unwrap();
-                out.push(ch);
-                i += ch.len_utf8();
-            }
-            if i + 1 < len && bytes[i] == b'$' && bytes[i + 1] == b'$' {
-                out.push_str("$$");
-                i += 2;
-            }
-        } else if bytes[i] == b'$' {
-            // Inline math $...$ — copy verbatim
-            out.push('$');
-            i += 1;
-            while i < len && bytes[i] != b'$' {
-                let ch = input[i..].chars().next().unwrap();
-                out.push(ch);
-                i += ch.len_utf8();
-            }
-            if i < len && bytes[i] == b'$' {
-                out.push('$');
-                i += 1;
+/// Code spans and blocks are found by parsing the Markdown (see
+/// [`markdown::code_ranges`]) and math by delimiter matching (see
+/// [`markdown::math_spans`]); both are copied as-is, so `$HOME` in a shell
+/// block or `\texttt{<name>}` in math survive intact. Only `<` that opens
+/// an actual tag or comment is removed. Table tags, and a few inline tags
+/// inside tables, are kept in the canonical form [`tables::sanitize_tag`]
+/// gives them. `<math>` elements (see [`mathml`]) are MathML islands and
+/// are copied whole.
+pub(crate) fn strip_html_tags_preserve_math(input: &str) -> String {
+    let mut verbatim: Vec<Range<usize>> = markdown::code_ranges(input);
+    for span in markdown::math_spans(input) {
+        let delim = if input[..span.start].ends_with("$$") {
+            2
+        } else {
+            1
+        };
+        verbatim.push(span.start - delim..span.end + delim);
+    }
+    verbatim.sort_by_key(|r| r.start);
+
+    let mut out = String::with_capacity(input.len());
+    let mut table_depth = 0usize;
+    let mut pos = 0;
+    for range in verbatim
+        .iter()
+        .chain(std::iter::once(&(input.len()..input.len())))
+    {
+        if range.start < pos {
+            continue;
+        }
+        strip_tags(&input[pos..range.start], &mut table_depth, &mut out);
+        out.push_str(&input[range.clone()]);
+        pos = range.end;
+    }
+    out
+}
+
+static HTML_TAG_RE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"^(?s:<!--.*?-->|</?[A-Za-z][A-Za-z0-9-]*(?:\s(?:[^<>"']|"[^"]*"|'[^']*')*)?/?>)"#)
+        .unwrap()
+});
+
+/// Remove the HTML tags in a stretch of text with no code or math.
+fn strip_tags(text: &str, table_depth: &mut usize, out: &mut String) {
+    let mut rest = text;
+    while let Some(lt) = rest.find('<') {
+        out.push_str(&rest[..lt]);
+        rest = &rest[lt..];
+        if rest.starts_with("<math ") {
+            if let Some(end) = rest.find("</math>") {
+                let end = end + "</math>".len();
+                out.push_str(&rest[..end]);
+                rest = &rest[end..];
+                continue;
             }
-        } else if bytes[i] == b'<' {
-            // Potential HTML tag — skip <...>
-            i += 1;
-            let mut found_close = false;
-            while i < len {
-                if bytes[i] == b'>' {
-                    found_close = true;
-                    i += 1;
-                    break;
+        }
+        let Some(m) = HTML_TAG_RE.find(rest) else {
+            // Not a tag, e.g. `a < b` or `List<T` — keep it
+            out.push('<');
+            rest = &rest[1..];
+            continue;
+        };
+        let tag = m.as_str();
+        if let Some((name, closing)) = tables::tag_name(tag) {
+            let structural = tables::TABLE_TAGS.contains(&name.as_str());
+            if structural || *table_depth > 0 {
+                if let Some(tag) = tables::sanitize_tag(tag) {
+                    out.push_str(&tag);
                 }
-                i += 1;
             }
-            if !found_close {
-                // Unclosed `<` at end of input — drop it
+            if name == "table" {
+                if closing {
+                    *table_depth = table_depth.saturating_sub(1);
+                } else {
+                    *table_depth += 1;
+                }
             }
-        } else if bytes[i] == b'>' {
-            // Stray > outside a tag — keep it
-            out.push('>');
-            i += 1;
-        } else {
-            let ch = input[i..].chars().next().unwrap();
-            out.push(ch);
-            i += ch.len_utf8();
         }
+        rest = &rest[m.end()..];
     }
-    out
+    out.push_str(rest);
 }
 
 #[cfg(test)]
 mod sanitize_tests {
     use super::{
-        add_arxiv_figure_html_links, extract_figure_captions, fix_katex_commands,
-        normalize_display_math, sanitize_markdown, strip_html_tags_preserve_math,
+        add_arxiv_figure_html_links, extract_figure_captions, nest_supplement,
+        normalize_display_math, strip_html_tags_preserve_math,
     };
+    use crate::katex;
+    use crate::postprocess::Pipeline;
+    use crate::report::ConversionReport;
+
+    fn sanitize_markdown(input: &str) -> String {
+        Pipeline::standard().run(input, &mut ConversionReport::new())
+    }
+
+    fn fix_katex_commands(input: &str) -> String {
+        katex::rewrite(input).markdown
+    }
+
+    #[test]
+    fn citeproc_reference_anchors_survive_sanitization() {
+        use crate::bibliography::anchor_citeproc_entries;
+        use crate::placeholder::Placeholders;
+        let md = "As shown [\\[1\\]](#ref-a).\n\n# References\n\n<div id=\"refs\" class=\"references csl-bib-body\">\n\n<div id=\"ref-a\" class=\"csl-entry\">\n\n\\[1\\] A. Author. Title. 2020.\n\n</div>\n\n</div>\n";
+        let mut ph = Placeholders::new();
+        let md = anchor_citeproc_entries(md, &mut ph);
+        let out = ph.resolve(&sanitize_markdown(&md));
+        assert!(out.contains("[\\[1\\]](#ref-a)"));
+        assert!(out.contains("<a id=\"ref-a\"></a>\\[1\\] A. Author. Title. 2020."));
+        assert!(!out.contains("<div"));
+    }
+
+    #[test]
+    fn supplements_are_demoted_and_namespaced() {
+        let body = "# Proofs\n\nSee [(1)](#eq-1)[^1].\n\n<a id=\"eq-1\"></a>$$a$$\n\n## Lemma\n\n```\n# not a heading [^1]\n```\n\n[^1]: A note.\n";
+        assert_eq!(
+            nest_supplement(body, 2, 1),
+            "## Proofs\n\nSee [(1)](#s2-eq-1)[^s2-1].\n\n<a id=\"s2-eq-1\"></a>$$a$$\n\n### Lemma\n\n```\n# not a heading [^1]\n```\n\n[^s2-1]: A note.\n"
+        );
+        assert!(nest_supplement("###### Deep", 1, 2).starts_with("###### Deep"));
+    }
+
+    #[test]
+    fn keeps_sanitized_tables_and_strips_other_html() {
+        let s = "<table>\n<thead>\n<tr>\n<th colspan=\"2\" style=\"x\">Score <span>x</span></th>\n</tr>\n</thead>\n<tbody>\n<tr>\n<td>1<sup>*</sup></td>\n<td>2</td>\n</tr>\n</tbody>\n</table>\n\n<p>after <sup>2</sup></p>";
+        let out = sanitize_markdown(s);
+        assert!(out.contains("<th colspan=\"2\">Score x</th>"));
+        assert!(out.contains("<td>1<sup>*</sup></td>"));
+        assert!(out.contains("after 2"));
+        let s = "<table>\n<tbody>\n<tr>\n<td>1</td>\n<td>2</td>\n</tr>\n</tbody>\n</table>";
+        assert_eq!(sanitize_markdown(s), "|  |  |\n| --- | --- |\n| 1 | 2 |\n");
+    }
+
+    #[test]
+    fn figure_caption_uses_crossref_label_number() {
+        let s = "<figure id=\"markxiv-figure-3\">\n<figcaption>late</figcaption>\n</figure>\n\n<figure>\n<figcaption>next</figcaption>\n</figure>\n\n<figure id=\"markxiv-figure-2.1\">\n</figure>";
+        let out = extract_figure_captions(s);
+        assert!(out.contains("> **Figure 3:** late"));
+        assert!(out.contains("> **Figure 4:** next"));
+        assert!(out.contains("> **Figure 2.1**"));
+    }
 
     #[test]
     fn converts_figure_block_to_caption() {
@@ -648,6 +1369,98 @@ mod sanitize_tests {
         assert_eq!(out, "text hello more");
     }
 
+    #[test]
+    fn gfm_footnotes_survive_sanitization() {
+        let input =
+            "Claim[^1] holds<sup>†</sup>.\n\n[^1]: See <em>Smith</em> for $a<b$.\n\n[^2]: Another.";
+        assert_eq!(
+            sanitize_markdown(input),
+            "Claim[^1] holds†.\n\n[^1]: See Smith for $a<b$.\n\n[^2]: Another."
+        );
+    }
+
+    #[test]
+    fn footnotes_from_tex_become_gfm_after_pandoc() {
+        let mut ph = crate::placeholder::Placeholders::new();
+        let tex = crate::footnotes::render("Text\\footnote{A \\emph{note}.} end.", &mut ph);
+        assert_eq!(tex, "TextMXPH0Z end.\n\nMXPH1Z A \\emph{note}.\n\n");
+        // What pandoc makes of it: tokens kept, one paragraph per note.
+        let md = "TextMXPH0Z end.\n\nMXPH1Z A *note*.\n";
+        let out = ph.resolve(&sanitize_markdown(md));
+        assert_eq!(out, "Text[^1] end.\n\n[^1]: A *note*.\n");
+    }
+
+    #[test]
+    fn footnotes_are_numbered_and_collected_at_the_end() {
+        let mut ph = crate::placeholder::Placeholders::new();
+        let tex = "\\begin{document}\nA\\footnote{First $x$.} and B\\footnote[7]{Second.}\n% \\footnote{gone}\n$a\\footnote{no}$\n\\end{document}\n";
+        let tex = crate::footnotes::render(tex, &mut ph);
+        let out = ph.resolve(&tex);
+        assert!(out.starts_with(
+            "\\begin{document}\nA[^1] and B[^2]\n% \\footnote{gone}\n$a\\footnote{no}$\n"
+        ));
+        assert!(out.ends_with("\n\n[^1]: First $x$.\n\n\n\n[^2]: Second.\n\n\\end{document}\n"));
+        assert_eq!(crate::footnotes::render("no notes", &mut ph), "no notes");
+    }
+
+    #[test]
+    fn definitions_close_their_section() {
+        let mut ph = crate::placeholder::Placeholders::new();
+        let tex = "\\section{A}\nX\\footnote{One.}\n% \\section{Old}\n\\subsection{A.1}\nY\\footnote{Two.}\n\\section*{B}\nZ\\footnote{Three.}\n\\bibliography{refs}\n";
+        let tex = crate::footnotes::render(tex, &mut ph);
+        assert_eq!(
+            ph.resolve(&tex),
+            "\\section{A}\nX[^1]\n% \\section{Old}\n\\subsection{A.1}\nY[^2]\n\n\n[^1]: One.\n\n\n\n[^2]: Two.\n\n\\section*{B}\nZ[^3]\n\n\n[^3]: Three.\n\n\\bibliography{refs}\n"
+        );
+    }
+
+    #[test]
+    fn section_breaks_inside_a_footnote_do_not_end_the_section() {
+        let mut ph = crate::placeholder::Placeholders::new();
+        let tex = "A\\footnote{one} B\\footnote{see the \\appendix for details}\n\\section{S}";
+        let tex = crate::footnotes::render(tex, &mut ph);
+        assert_eq!(
+            ph.resolve(&tex),
+            "A[^1] B[^2]\n\n\n[^1]: one\n\n\n\n[^2]: see the \\appendix for details\n\n\\section{S}"
+        );
+    }
+
+    #[test]
+    fn code_is_left_untouched() {
+        let input = "Set `$HOME` and `List<String>`.\n\n```sh\n$ echo $PATH <in.txt\n$ cat <b>x</b>\n```\n\n<em>done</em>";
+        let out = strip_html_tags_preserve_math(input);
+        assert_eq!(
+            out,
+            "Set `$HOME` and `List<String>`.\n\n```sh\n$ echo $PATH <in.txt\n$ cat <b>x</b>\n```\n\ndone"
+        );
+    }
+
+    #[test]
+    fn stray_dollar_does_not_swallow_the_paragraph() {
+        let input = "It costs $5, <em>not</em> $10.\n\nAnd $x<y$ <b>holds</b>.";
+        let out = strip_html_tags_preserve_math(input);
+        assert_eq!(out, "It costs $5, not $10.\n\nAnd $x<y$ holds.");
+    }
+
+    #[test]
+    fn lone_angle_brackets_are_text() {
+        let input = "if a < b and c > d then <span>ok</span>";
+        assert_eq!(
+            strip_html_tags_preserve_math(input),
+            "if a < b and c > d then ok"
+        );
+    }
+
+    #[test]
+    fn mathml_islands_survive_stripping() {
+        let md = crate::mathml::markdown_math_to_mathml("see <em>$x_1 < 2$</em> here");
+        let out = strip_html_tags_preserve_math(&md);
+        assert_eq!(
+            out,
+            "see <math xmlns=\"http://www.w3.org/1998/Math/MathML\" display=\"inline\"><mrow><msub><mi>x</mi><mn>1</mn></msub><mo>&lt;</mo><mn>2</mn></mrow></math> here"
+        );
+    }
+
     #[test]
     fn normalize_display_math_isolates_inline_display() {
         let input = "text $$x^2 + y^2$$ more text";
@@ -760,9 +1573,13 @@ pub mod test_helpers {
         pub latex_result: Result<String, ConvertError>,
         pub latex_nomacro_result: Option<Result<String, ConvertError>>,
         pub pdf_result: Result<String, ConvertError>,
+        pub legacy_result: Result<(String, LegacyFormat), ConvertError>,
+        pub figures: Vec<FigureImage>,
         pub latex_calls: Arc<AtomicUsize>,
         pub latex_nomacro_calls: Arc<AtomicUsize>,
         pub pdf_calls: Arc<AtomicUsize>,
+        pub legacy_calls: Arc<AtomicUsize>,
+        pub figure_calls: Arc<AtomicUsize>,
     }
 
     impl MockConverter {
@@ -774,33 +1591,77 @@ pub mod test_helpers {
                 latex_result,
                 latex_nomacro_result: None,
                 pdf_result,
+                legacy_result: Err(ConvertError::NotImplemented),
+                figures: Vec::new(),
                 latex_calls: Arc::new(AtomicUsize::new(0)),
                 latex_nomacro_calls: Arc::new(AtomicUsize::new(0)),
                 pdf_calls: Arc::new(AtomicUsize::new(0)),
+                legacy_calls: Arc::new(AtomicUsize::new(0)),
+                figure_calls: Arc::new(AtomicUsize::new(0)),
             }
         }
+
+        /// A conversion result with the mock's figures, when asked for.
+        fn output(
+            &self,
+            result: Result<String, ConvertError>,
+            opts: &LatexOptions,
+        ) -> Result<LatexOutput, ConvertError> {
+            let figures = if opts.figures {
+                self.figures.clone()
+            } else {
+                Vec::new()
+            };
+            result.map(|markdown| LatexOutput { markdown, figures })
+        }
     }
 
     #[async_trait]
     impl Converter for MockConverter {
-        async fn latex_tar_to_markdown(&self, _tar_bytes: &[u8]) -> Result<String, ConvertError> {
+        async fn latex_tar_to_markdown(
+            &self,
+            _tar_bytes: &[u8],
+            opts: &LatexOptions,
+            _report: &mut ConversionReport,
+        ) -> Result<LatexOutput, ConvertError> {
             self.latex_calls.fetch_add(1, Ordering::SeqCst);
-            self.latex_result.clone()
+            self.output(self.latex_result.clone(), opts)
         }
 
         async fn latex_tar_to_markdown_without_macros(
             &self,
             _tar_bytes: &[u8],
-        ) -> Result<String, ConvertError> {
+            opts: &LatexOptions,
+            _report: &mut ConversionReport,
+        ) -> Result<LatexOutput, ConvertError> {
             self.latex_nomacro_calls.fetch_add(1, Ordering::SeqCst);
-            self.latex_nomacro_result
+            let result = self
+                .latex_nomacro_result
                 .clone()
-                .unwrap_or_else(|| self.latex_result.clone())
+                .unwrap_or_else(|| self.latex_result.clone());
+            self.output(result, opts)
         }
 
         async fn pdf_to_markdown(&self, _pdf_bytes: &[u8]) -> Result<String, ConvertError> {
             self.pdf_calls.fetch_add(1, Ordering::SeqCst);
             self.pdf_result.clone()
         }
+
+        async fn legacy_eprint_to_markdown(
+            &self,
+            _eprint_bytes: &[u8],
+        ) -> Result<(String, LegacyFormat), ConvertError> {
+            self.legacy_calls.fetch_add(1, Ordering::SeqCst);
+            self.legacy_result.clone()
+        }
+
+        async fn latex_tar_figures(
+            &self,
+            _tar_bytes: &[u8],
+            _opts: &LatexOptions,
+        ) -> Result<Vec<FigureImage>, ConvertError> {
+            self.figure_calls.fetch_add(1, Ordering::SeqCst);
+            Ok(self.figures.clone())
+        }
     }
 }
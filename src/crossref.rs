@@ -0,0 +1,1185 @@
+use std::collections::HashMap;
+use std::ops::Range;
+
+use crate::placeholder::Placeholders;
+use crate::tex_util::{
+    anchor_id, apply_edits, find_command, in_ranges, is_escaped, math_ranges, read_arg,
+    read_opt_arg, skip_ws, tex_to_markdown_inline,
+};
+
+/// Label prefix given to every `figure` environment so the numbers LaTeX
+/// would print survive pandoc as `<figure id="markxiv-figure-3">`.
+pub const FIGURE_LABEL_PREFIX: &str = "markxiv-figure-";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TargetKind {
+    Part,
+    Chapter,
+    Section,
+    Appendix,
+    Figure,
+    Table,
+    Equation,
+    Theorem,
+    Algorithm,
+}
+
+/// What a `\label` points at.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Target {
+    pub kind: TargetKind,
+    /// Display name used by `\autoref`/`\cref`, e.g. "Figure" or "Lemma".
+    pub name: String,
+    pub number: String,
+    pub anchor: String,
+    /// Section title, used by `\nameref`.
+    pub title: Option<String>,
+}
+
+/// A `\newtheorem` (or `\declaretheorem`) declaration.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TheoremDecl {
+    pub env: String,
+    pub title: String,
+    /// Counter shared with another environment, `\newtheorem{lem}[thm]{Lemma}`.
+    pub counter: String,
+    /// Sectioning level the counter is reset by, `\newtheorem{thm}{Theorem}[section]`.
+    pub within: Option<String>,
+    pub numbered: bool,
+}
+
+/// A graphic included by a numbered figure.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FigureGraphic {
+    /// Figure number as printed, e.g. "2" or "3.1".
+    pub figure: String,
+    /// Asset name: the figure number, with the subfigure letter for
+    /// subfigures ("2b") and a "-k" suffix for further graphics in the same
+    /// figure or subfigure ("2-2").
+    pub name: String,
+    /// Path as written in `\includegraphics`.
+    pub path: String,
+}
+
+/// Numbering computed for a document.
+#[derive(Debug, Clone, Default)]
+pub struct Numbering {
+    pub labels: HashMap<String, Target>,
+    /// Numbers of numbered environments in document order, keyed by
+    /// environment name (`algorithm`, `theorem`, `lemma`, ...). Unnumbered
+    /// occurrences are recorded as `None` so positions stay aligned.
+    pub environments: HashMap<String, Vec<Option<String>>>,
+    pub theorems: Vec<TheoremDecl>,
+    /// Graphics of numbered figures in document order.
+    pub graphics: Vec<FigureGraphic>,
+}
+
+impl Numbering {
+    /// Number of the `n`-th occurrence (0-based) of `env`.
+    pub fn environment_number(&self, env: &str, n: usize) -> Option<&str> {
+        self.environments.get(env)?.get(n)?.as_deref()
+    }
+
+    pub fn theorem(&self, env: &str) -> Option<&TheoremDecl> {
+        self.theorems.iter().find(|t| t.env == env)
+    }
+}
+
+const SECTION_LEVELS: &[&str] = &[
+    "part",
+    "chapter",
+    "section",
+    "subsection",
+    "subsubsection",
+    "paragraph",
+    "subparagraph",
+];
+
+const REF_COMMANDS: &[&str] = &[
+    "ref", "eqref", "autoref", "Autoref", "cref", "Cref", "nameref", "pageref", "vref", "Vref",
+    "hyperref", "subref",
+];
+
+const ROW_ENVS: &[&str] = &["align", "gather", "eqnarray", "flalign", "alignat"];
+const SINGLE_EQ_ENVS: &[&str] = &["equation", "multline"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CtxKind {
+    Figure,
+    Subfigure,
+    Table,
+    Algorithm,
+    Theorem,
+    Equation,
+    Rows,
+    Other,
+}
+
+#[derive(Debug)]
+struct Ctx {
+    env: String,
+    kind: CtxKind,
+    start: usize,
+    number: Option<String>,
+    anchor: Option<String>,
+    labelled: bool,
+    // Row-numbered math environments
+    row_start: usize,
+    row_numbered: bool,
+    row_tag: Option<String>,
+    row_labels: Vec<String>,
+    has_tag: bool,
+}
+
+impl Ctx {
+    fn new(env: &str, kind: CtxKind, start: usize) -> Self {
+        Self {
+            env: env.to_string(),
+            kind,
+            start,
+            number: None,
+            anchor: None,
+            labelled: false,
+            row_start: start,
+            row_numbered: true,
+            row_tag: None,
+            row_labels: Vec::new(),
+            has_tag: false,
+        }
+    }
+}
+
+#[derive(Default)]
+struct Scanner {
+    numbering: Numbering,
+    has_chapters: bool,
+    appendix: bool,
+    sections: [u32; 7],
+    figure: u32,
+    subfigure: u32,
+    table: u32,
+    equation: u32,
+    algorithm: u32,
+    theorem_counters: HashMap<String, u32>,
+    last_section: Option<Target>,
+    stack: Vec<Ctx>,
+    edits: Vec<(Range<usize>, Edit)>,
+    refs: Vec<(Range<usize>, RefUse)>,
+}
+
+#[derive(Debug)]
+enum Edit {
+    Delete,
+    /// Anchor for the label's target, placed inline at the label position.
+    LabelAnchor(String),
+    /// Anchor placed before a float/theorem environment.
+    BlockAnchor(String),
+    /// Anchor placed before a display equation (kept in the paragraph).
+    InlineAnchor(String),
+    Insert(String),
+    /// Inline Markdown carried through pandoc by a placeholder.
+    Markdown(String),
+}
+
+#[derive(Debug)]
+struct RefUse {
+    cmd: String,
+    keys: Vec<String>,
+    text: Option<String>,
+}
+
+/// Number everything LaTeX numbers, then rewrite references into Markdown
+/// links and plant anchors at their targets. Returns the rewritten TeX and
+/// the numbering so later passes (pseudocode, theorems) can print the same
+/// numbers.
+pub fn resolve(tex: &str, ph: &mut Placeholders) -> (String, Numbering) {
+    let mut sc = Scanner::run(tex);
+    let math = math_ranges(tex);
+
+    let mut edits: Vec<(Range<usize>, String)> = Vec::new();
+    for (range, edit) in std::mem::take(&mut sc.edits) {
+        let replacement = match edit {
+            Edit::Delete => String::new(),
+            Edit::LabelAnchor(id) | Edit::InlineAnchor(id) => ph.inline(anchor_html(&id)),
+            Edit::BlockAnchor(id) => ph.block(anchor_html(&id)),
+            Edit::Insert(s) => s,
+            Edit::Markdown(md) => ph.inline(md),
+        };
+        edits.push((range, replacement));
+    }
+    for (range, r) in std::mem::take(&mut sc.refs) {
+        let md = render_ref(&r, &sc.numbering.labels);
+        let replacement = if in_ranges(&math, range.start) {
+            math_ref_text(&r, &sc.numbering.labels)
+        } else {
+            ph.inline(md)
+        };
+        edits.push((range, replacement));
+    }
+    (apply_edits(tex, edits), sc.numbering)
+}
+
+/// Number the document without rewriting it.
+pub fn number(tex: &str) -> Numbering {
+    Scanner::run(tex).numbering
+}
+
+fn anchor_html(id: &str) -> String {
+    format!("<a id=\"{}\"></a>", id)
+}
+
+impl Scanner {
+    fn run(tex: &str) -> Self {
+        let mut sc = Scanner {
+            has_chapters: find_command(tex, "chapter", 0).is_some(),
+            ..Default::default()
+        };
+        sc.numbering.theorems = parse_theorem_decls(tex);
+        sc.scan(tex);
+        sc
+    }
+
+    fn scan(&mut self, tex: &str) {
+        let bytes = tex.as_bytes();
+        let mut i = 0;
+        while i < bytes.len() {
+            match bytes[i] {
+                b'%' if !is_escaped(tex, i) => {
+                    i = tex[i..].find('\n').map(|r| i + r).unwrap_or(bytes.len());
+                }
+                b'\\' => {
+                    if bytes.get(i + 1) == Some(&b'\\') {
+                        self.row_break(tex, i);
+                        i += 2;
+                        continue;
+                    }
+                    let name_len = tex[i + 1..]
+                        .bytes()
+                        .take_while(|b| b.is_ascii_alphabetic())
+                        .count();
+                    if name_len == 0 {
+                        i += 2;
+                        continue;
+                    }
+                    let name = &tex[i + 1..i + 1 + name_len];
+                    let mut after = i + 1 + name_len;
+                    let starred = bytes.get(after) == Some(&b'*');
+                    if starred {
+                        after += 1;
+                    }
+                    i = self.command(tex, i, name, starred, after);
+                }
+                _ => i += 1,
+            }
+        }
+    }
+
+    /// Handle one control word; returns where scanning continues.
+    fn command(&mut self, tex: &str, at: usize, name: &str, starred: bool, after: usize) -> usize {
+        match name {
+            "begin" => {
+                let Some((env, end)) = read_arg(tex, after) else {
+                    return after;
+                };
+                self.begin(tex, at, env.trim(), end);
+                end
+            }
+            "end" => {
+                let Some((env, end)) = read_arg(tex, after) else {
+                    return after;
+                };
+                self.end(tex, at, env.trim());
+                end
+            }
+            "appendix" => {
+                self.appendix = true;
+                let top = if self.has_chapters { 1 } else { 2 };
+                self.sections[top] = 0;
+                after
+            }
+            "label" => {
+                let Some((label, end)) = read_arg(tex, after) else {
+                    return after;
+                };
+                self.label(at..end, label.trim());
+                end
+            }
+            "nonumber" | "notag" => {
+                if let Some(ctx) = self.stack.last_mut() {
+                    if ctx.kind == CtxKind::Rows {
+                        ctx.row_numbered = false;
+                    }
+                }
+                after
+            }
+            "tag" => {
+                let Some((tag, end)) = read_arg(tex, after) else {
+                    return after;
+                };
+                let tag = tag.trim().to_string();
+                let Some(ctx) = self
+                    .stack
+                    .iter_mut()
+                    .rev()
+                    .find(|c| matches!(c.kind, CtxKind::Rows | CtxKind::Equation))
+                else {
+                    return end;
+                };
+                ctx.has_tag = true;
+                if ctx.kind == CtxKind::Rows {
+                    ctx.row_tag = Some(tag);
+                } else if ctx.number.replace(tag).is_some() {
+                    // A tagged equation does not step the counter.
+                    self.equation -= 1;
+                }
+                end
+            }
+            "subfloat" | "subcaptionbox" => {
+                self.subfigure += 1;
+                after
+            }
+            "caption" if !starred => {
+                self.table_caption(tex, after);
+                after
+            }
+            "includegraphics" => {
+                let mut pos = after;
+                if let Some((_, e)) = read_opt_arg(tex, pos) {
+                    pos = e;
+                }
+                let Some((path, end)) = read_arg(tex, pos) else {
+                    return after;
+                };
+                self.graphic(path.trim());
+                end
+            }
+            n if SECTION_LEVELS.contains(&n) => self.section(tex, n, starred, after),
+            n if REF_COMMANDS.contains(&n) => self.reference(tex, at, n, after),
+            _ => after,
+        }
+    }
+
+    /// Prefix a table caption with its number; pandoc drops table numbers,
+    /// and figure captions are numbered later from their labels.
+    fn table_caption(&mut self, tex: &str, after: usize) {
+        let Some(ctx) = self.stack.iter().rev().find(|c| {
+            matches!(
+                c.kind,
+                CtxKind::Figure | CtxKind::Subfigure | CtxKind::Table
+            )
+        }) else {
+            return;
+        };
+        let (CtxKind::Table, Some(number)) = (ctx.kind, ctx.number.clone()) else {
+            return;
+        };
+        let mut pos = after;
+        if let Some((_, e)) = read_opt_arg(tex, pos) {
+            pos = e;
+        }
+        let open = skip_ws(tex, pos);
+        if tex.as_bytes().get(open) == Some(&b'{') {
+            self.edits.push((
+                open + 1..open + 1,
+                Edit::Markdown(format!("**Table {}:** ", number)),
+            ));
+        }
+    }
+
+    /// Attribute an `\includegraphics` to the figure (or subfigure) it sits in.
+    fn graphic(&mut self, path: &str) {
+        let Some(ctx) = self.stack.iter().rev().find(|c| {
+            matches!(
+                c.kind,
+                CtxKind::Figure | CtxKind::Subfigure | CtxKind::Table
+            )
+        }) else {
+            return;
+        };
+        let Some(number) = ctx.number.clone() else {
+            return;
+        };
+        let (figure, slot) = match ctx.kind {
+            CtxKind::Figure if self.subfigure > 0 => (
+                number.clone(),
+                format!("{}{}", number, letter(self.subfigure)),
+            ),
+            CtxKind::Figure => (number.clone(), number),
+            CtxKind::Subfigure => match self.stack.iter().rev().find(|c| c.kind == CtxKind::Figure)
+            {
+                Some(parent) => (parent.number.clone().unwrap_or_default(), number),
+                None => return,
+            },
+            _ => return,
+        };
+        if path.is_empty() || figure.is_empty() {
+            return;
+        }
+        let taken = self
+            .numbering
+            .graphics
+            .iter()
+            .filter(|g| g.name == slot || g.name.starts_with(&format!("{}-", slot)))
+            .count();
+        let name = if taken == 0 {
+            slot
+        } else {
+            format!("{}-{}", slot, taken + 1)
+        };
+        self.numbering.graphics.push(FigureGraphic {
+            figure,
+            name,
+            path: path.to_string(),
+        });
+    }
+
+    fn section(&mut self, tex: &str, name: &str, starred: bool, after: usize) -> usize {
+        let mut pos = after;
+        if let Some((_, e)) = read_opt_arg(tex, pos) {
+            pos = e;
+        }
+        let Some((title, end)) = read_arg(tex, pos) else {
+            return after;
+        };
+        if starred {
+            return end;
+        }
+        let level = SECTION_LEVELS.iter().position(|l| *l == name).unwrap_or(2);
+        // secnumdepth defaults: subsubsection in articles, subsection in books.
+        let max_level = if self.has_chapters { 3 } else { 4 };
+        if level > max_level {
+            return end;
+        }
+        self.sections[level] += 1;
+        for l in self.sections.iter_mut().skip(level + 1) {
+            *l = 0;
+        }
+        if self.has_chapters && level == 1 {
+            self.figure = 0;
+            self.table = 0;
+            self.equation = 0;
+            self.algorithm = 0;
+        }
+        let within_name = SECTION_LEVELS[level];
+        for decl in &self.numbering.theorems {
+            if decl.within.as_deref() == Some(within_name) {
+                self.theorem_counters.insert(decl.counter.clone(), 0);
+            }
+        }
+
+        let number = if level == 0 {
+            roman(self.sections[0])
+        } else {
+            self.section_number(level)
+        };
+        let kind = match level {
+            0 => TargetKind::Part,
+            1 => TargetKind::Chapter,
+            _ if self.appendix => TargetKind::Appendix,
+            _ => TargetKind::Section,
+        };
+        let display = match kind {
+            TargetKind::Part => "Part",
+            TargetKind::Chapter if self.appendix => "Appendix",
+            TargetKind::Chapter => "Chapter",
+            TargetKind::Appendix => "Appendix",
+            _ => "Section",
+        };
+        self.last_section = Some(Target {
+            kind,
+            name: display.to_string(),
+            anchor: anchor_id("section-", &number),
+            number,
+            title: Some(tex_to_markdown_inline(title)),
+        });
+        end
+    }
+
+    /// Dotted number for sectioning `level`, starting at the chapter (books)
+    /// or section (articles) and lettering the top level in appendices.
+    fn section_number(&self, level: usize) -> String {
+        let top = if self.has_chapters { 1 } else { 2 };
+        let mut parts = Vec::new();
+        for l in top..=level {
+            let n = self.sections[l];
+            if l == top && self.appendix {
+                parts.push(letter(n).to_ascii_uppercase());
+            } else {
+                parts.push(n.to_string());
+            }
+        }
+        parts.join(".")
+    }
+
+    /// Prefix float and equation numbers with the chapter in books.
+    fn float_number(&self, n: u32) -> String {
+        if self.has_chapters && self.sections[1] > 0 {
+            format!("{}.{}", self.section_number(1), n)
+        } else {
+            n.to_string()
+        }
+    }
+
+    fn begin(&mut self, tex: &str, at: usize, env: &str, body_start: usize) {
+        let base = env.trim_end_matches('*');
+        let starred = env.ends_with('*');
+        let ctx = match base {
+            "figure" | "wrapfigure" => {
+                self.figure += 1;
+                self.subfigure = 0;
+                let mut c = Ctx::new(env, CtxKind::Figure, at);
+                let number = self.float_number(self.figure);
+                c.anchor = Some(anchor_id("figure-", &number));
+                // Give pandoc a label that encodes the number; remaining
+                // labels inside the figure are dropped.
+                let mut pos = body_start;
+                if let Some((_, e)) = read_opt_arg(tex, pos) {
+                    pos = e;
+                }
+                if base == "wrapfigure" {
+                    if let Some((_, e)) = read_arg(tex, pos) {
+                        pos = e;
+                    }
+                    if let Some((_, e)) = read_arg(tex, pos) {
+                        pos = e;
+                    }
+                }
+                self.edits.push((
+                    pos..pos,
+                    Edit::Insert(format!("\\label{{{}{}}}", FIGURE_LABEL_PREFIX, number)),
+                ));
+                c.number = Some(number);
+                c
+            }
+            "subfigure" | "subtable" => {
+                self.subfigure += 1;
+                let mut c = Ctx::new(env, CtxKind::Subfigure, at);
+                let parent = self
+                    .stack
+                    .iter()
+                    .rev()
+                    .find(|c| matches!(c.kind, CtxKind::Figure | CtxKind::Table));
+                if let Some(p) = parent {
+                    c.number = p
+                        .number
+                        .as_ref()
+                        .map(|n| format!("{}{}", n, letter(self.subfigure)));
+                    c.anchor = p.anchor.clone();
+                }
+                c
+            }
+            "table" => {
+                self.table += 1;
+                let mut c = Ctx::new(env, CtxKind::Table, at);
+                let number = self.float_number(self.table);
+                c.anchor = Some(anchor_id("table-", &number));
+                c.number = Some(number);
+                c
+            }
+            "algorithm" => {
+                self.algorithm += 1;
+                let mut c = Ctx::new(env, CtxKind::Algorithm, at);
+                let number = self.float_number(self.algorithm);
+                c.anchor = Some(anchor_id("algorithm-", &number));
+                c.number = Some(number);
+                self.record_env("algorithm", c.number.clone());
+                c
+            }
+            b if SINGLE_EQ_ENVS.contains(&b) => {
+                let mut c = Ctx::new(env, CtxKind::Equation, at);
+                if !starred {
+                    self.equation += 1;
+                    c.number = Some(self.float_number(self.equation));
+                }
+                c
+            }
+            b if ROW_ENVS.contains(&b) => {
+                let mut c = Ctx::new(env, CtxKind::Rows, at);
+                c.row_start = body_start;
+                c.row_numbered = !starred;
+                c
+            }
+            _ => match self.numbering.theorem(env).cloned() {
+                Some(decl) => {
+                    let mut c = Ctx::new(env, CtxKind::Theorem, at);
+                    if decl.numbered {
+                        let n = self
+                            .theorem_counters
+                            .entry(decl.counter.clone())
+                            .or_insert(0);
+                        *n += 1;
+                        let n = *n;
+                        let number = match decl.within.as_deref() {
+                            Some(w) => {
+                                let level =
+                                    SECTION_LEVELS.iter().position(|l| *l == w).unwrap_or(2);
+                                format!("{}.{}", self.section_number(level), n)
+                            }
+                            None => n.to_string(),
+                        };
+                        c.anchor = Some(anchor_id("", &format!("{} {}", decl.title, number)));
+                        c.number = Some(number);
+                    }
+                    self.record_env(env, c.number.clone());
+                    c
+                }
+                None => Ctx::new(env, CtxKind::Other, at),
+            },
+        };
+        self.stack.push(ctx);
+    }
+
+    fn record_env(&mut self, env: &str, number: Option<String>) {
+        self.numbering
+            .environments
+            .entry(env.to_string())
+            .or_default()
+            .push(number);
+    }
+
+    fn end(&mut self, tex: &str, at: usize, env: &str) {
+        let Some(idx) = self.stack.iter().rposition(|c| c.env == env) else {
+            return;
+        };
+        // Close the final row of row-numbered environments.
+        if self.stack[idx].kind == CtxKind::Rows && idx == self.stack.len() - 1 {
+            self.finish_row(tex, at);
+        }
+        let ctx = self.stack.remove(idx);
+        self.stack.truncate(idx);
+        match ctx.kind {
+            CtxKind::Equation => {
+                if let Some(n) = &ctx.number {
+                    if !ctx.has_tag {
+                        self.edits
+                            .push((at..at, Edit::Insert(format!("\\tag{{{}}}", n))));
+                    }
+                }
+                if ctx.labelled {
+                    if let Some(id) = &ctx.anchor {
+                        self.edits
+                            .push((ctx.start..ctx.start, Edit::InlineAnchor(id.clone())));
+                    }
+                }
+            }
+            CtxKind::Table | CtxKind::Algorithm | CtxKind::Theorem if ctx.labelled => {
+                if let Some(id) = &ctx.anchor {
+                    self.edits
+                        .push((ctx.start..ctx.start, Edit::BlockAnchor(id.clone())));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn row_break(&mut self, tex: &str, at: usize) {
+        if matches!(self.stack.last(), Some(c) if c.kind == CtxKind::Rows) {
+            self.finish_row(tex, at);
+            if let Some(ctx) = self.stack.last_mut() {
+                ctx.row_start = at + 2;
+                ctx.row_numbered = !ctx.env.ends_with('*');
+                ctx.row_tag = None;
+            }
+        }
+    }
+
+    fn finish_row(&mut self, tex: &str, at: usize) {
+        let (tag, numbered, empty) = match self.stack.last_mut() {
+            Some(ctx) => (
+                ctx.row_tag.take(),
+                ctx.row_numbered,
+                tex[ctx.row_start.min(at)..at].trim().is_empty(),
+            ),
+            None => return,
+        };
+        let number = if tag.is_some() {
+            tag
+        } else if numbered && !empty {
+            self.equation += 1;
+            Some(self.float_number(self.equation))
+        } else {
+            None
+        };
+        let Some(ctx) = self.stack.last_mut() else {
+            return;
+        };
+        let labels = std::mem::take(&mut ctx.row_labels);
+        let start = ctx.start;
+        let Some(number) = number else {
+            return;
+        };
+        for label in labels {
+            let anchor = anchor_id("eq-", &number);
+            self.edits
+                .push((start..start, Edit::InlineAnchor(anchor.clone())));
+            self.numbering.labels.insert(
+                label,
+                Target {
+                    kind: TargetKind::Equation,
+                    name: "Eq.".into(),
+                    number: number.clone(),
+                    anchor,
+                    title: None,
+                },
+            );
+        }
+    }
+
+    fn label(&mut self, range: Range<usize>, label: &str) {
+        // Find the innermost context that gives the label a number.
+        let target_ctx = self.stack.iter_mut().rev().find(|c| {
+            matches!(
+                c.kind,
+                CtxKind::Figure
+                    | CtxKind::Subfigure
+                    | CtxKind::Table
+                    | CtxKind::Algorithm
+                    | CtxKind::Theorem
+                    | CtxKind::Equation
+                    | CtxKind::Rows
+            )
+        });
+        let target = match target_ctx {
+            Some(ctx) if ctx.kind == CtxKind::Rows => {
+                ctx.row_labels.push(label.to_string());
+                self.edits.push((range, Edit::Delete));
+                return;
+            }
+            Some(ctx) if ctx.kind == CtxKind::Equation && ctx.number.is_none() => {
+                self.edits.push((range, Edit::Delete));
+                return;
+            }
+            Some(ctx) => {
+                ctx.labelled = true;
+                let (kind, name) = match ctx.kind {
+                    CtxKind::Figure | CtxKind::Subfigure => (TargetKind::Figure, "Figure".into()),
+                    CtxKind::Table => (TargetKind::Table, "Table".into()),
+                    CtxKind::Algorithm => (TargetKind::Algorithm, "Algorithm".into()),
+                    CtxKind::Equation => (TargetKind::Equation, "Eq.".into()),
+                    _ => (
+                        TargetKind::Theorem,
+                        self.numbering
+                            .theorem(&ctx.env)
+                            .map(|d| d.title.clone())
+                            .unwrap_or_else(|| ctx.env.clone()),
+                    ),
+                };
+                let anchor = match (ctx.kind, &ctx.anchor) {
+                    (_, Some(a)) => a.clone(),
+                    (CtxKind::Equation, None) => {
+                        let a = anchor_id("eq-", ctx.number.as_deref().unwrap_or(label));
+                        ctx.anchor = Some(a.clone());
+                        a
+                    }
+                    _ => anchor_id("", label),
+                };
+                self.edits.push((range, Edit::Delete));
+                Target {
+                    kind,
+                    name,
+                    number: ctx.number.clone().unwrap_or_default(),
+                    anchor,
+                    title: None,
+                }
+            }
+            None => match &self.last_section {
+                Some(section) => {
+                    self.edits
+                        .push((range, Edit::LabelAnchor(section.anchor.clone())));
+                    section.clone()
+                }
+                None => {
+                    self.edits.push((range, Edit::Delete));
+                    return;
+                }
+            },
+        };
+        self.numbering.labels.insert(label.to_string(), target);
+    }
+
+    fn reference(&mut self, tex: &str, at: usize, cmd: &str, after: usize) -> usize {
+        let mut pos = after;
+        let mut keys = None;
+        if cmd == "hyperref" {
+            // \hyperref[label]{text}
+            let Some((label, e)) = read_opt_arg(tex, pos) else {
+                return after;
+            };
+            keys = Some(label.to_string());
+            pos = e;
+        }
+        let Some((arg, end)) = read_arg(tex, pos) else {
+            return after;
+        };
+        let (keys, text) = match keys {
+            Some(k) => (k, Some(tex_to_markdown_inline(arg))),
+            None => (arg.to_string(), None),
+        };
+        let keys = keys
+            .split(',')
+            .map(|k| k.trim().to_string())
+            .filter(|k| !k.is_empty())
+            .collect();
+        self.refs.push((
+            at..end,
+            RefUse {
+                cmd: cmd.to_string(),
+                keys,
+                text,
+            },
+        ));
+        end
+    }
+}
+
+fn render_ref(r: &RefUse, labels: &HashMap<String, Target>) -> String {
+    let parts: Vec<String> = r
+        .keys
+        .iter()
+        .map(|k| match labels.get(k) {
+            Some(t) => {
+                let text = match r.cmd.as_str() {
+                    "hyperref" => r.text.clone().unwrap_or_else(|| t.number.clone()),
+                    "nameref" => t.title.clone().unwrap_or_else(|| t.number.clone()),
+                    "eqref" => format!("({})", t.number),
+                    "subref" => subfigure_letter(&t.number),
+                    "autoref" | "Autoref" | "cref" | "Cref" | "Vref" => named(t),
+                    _ => t.number.clone(),
+                };
+                format!("[{}](#{})", text, t.anchor)
+            }
+            None => "??".to_string(),
+        })
+        .collect();
+    parts.join(", ")
+}
+
+fn named(t: &Target) -> String {
+    match t.kind {
+        TargetKind::Equation => format!("{} ({})", t.name, t.number),
+        _ => format!("{} {}", t.name, t.number),
+    }
+}
+
+fn subfigure_letter(number: &str) -> String {
+    let letters: String = number
+        .chars()
+        .rev()
+        .take_while(|c| c.is_ascii_lowercase())
+        .collect();
+    if letters.is_empty() {
+        number.to_string()
+    } else {
+        format!("({})", letters.chars().rev().collect::<String>())
+    }
+}
+
+fn math_ref_text(r: &RefUse, labels: &HashMap<String, Target>) -> String {
+    let text = r
+        .keys
+        .iter()
+        .map(|k| match labels.get(k) {
+            Some(t) if r.cmd == "eqref" => format!("({})", t.number),
+            Some(t) => t.number.clone(),
+            None => "??".into(),
+        })
+        .collect::<Vec<_>>()
+        .join(", ");
+    format!("\\text{{{}}}", text)
+}
+
+/// Read `\newtheorem` and `\declaretheorem` declarations.
+pub fn parse_theorem_decls(tex: &str) -> Vec<TheoremDecl> {
+    let mut decls: Vec<TheoremDecl> = Vec::new();
+    let mut from = 0;
+    while let Some(at) = find_command(tex, "newtheorem", from) {
+        let mut pos = at + "\\newtheorem".len();
+        let numbered = !tex[pos..].starts_with('*');
+        if !numbered {
+            pos += 1;
+        }
+        from = pos;
+        let Some((env, e)) = read_arg(tex, pos) else {
+            continue;
+        };
+        pos = e;
+        let mut shared = None;
+        if let Some((c, e)) = read_opt_arg(tex, pos) {
+            shared = Some(c.trim().to_string());
+            pos = e;
+        }
+        let Some((title, e)) = read_arg(tex, pos) else {
+            continue;
+        };
+        pos = e;
+        let mut within = None;
+        if shared.is_none() {
+            if let Some((w, e)) = read_opt_arg(tex, pos) {
+                within = Some(w.trim().to_string());
+                pos = e;
+            }
+        }
+        from = pos;
+        let env = env.trim().to_string();
+        // A shared counter inherits the reset level of the environment it
+        // borrows from.
+        if let Some(s) = &shared {
+            within = decls
+                .iter()
+                .find(|d| &d.env == s)
+                .and_then(|d| d.within.clone());
+        }
+        let counter = shared
+            .and_then(|s| {
+                decls
+                    .iter()
+                    .find(|d| d.env == s)
+                    .map(|d| d.counter.clone())
+                    .or(Some(s))
+            })
+            .unwrap_or_else(|| env.clone());
+        decls.push(TheoremDecl {
+            counter,
+            title: tex_to_markdown_inline(title),
+            env,
+            within,
+            numbered,
+        });
+    }
+    let mut from = 0;
+    while let Some(at) = find_command(tex, "declaretheorem", from) {
+        let mut pos = at + "\\declaretheorem".len();
+        let mut opts = "";
+        if let Some((o, e)) = read_opt_arg(tex, pos) {
+            opts = o;
+            pos = e;
+        }
+        from = pos;
+        let Some((env, e)) = read_arg(tex, pos) else {
+            continue;
+        };
+        from = e;
+        let env = env.trim().to_string();
+        let opt = |key: &str| -> Option<String> {
+            opts.split(',').find_map(|kv| {
+                let (k, v) = kv.split_once('=')?;
+                (k.trim() == key).then(|| v.trim().trim_matches(['{', '}']).to_string())
+            })
+        };
+        let title = opt("name").unwrap_or_else(|| capitalize(&env));
+        let numbered = opt("numbered").map(|v| v != "no").unwrap_or(true);
+        let shared = opt("sibling").or_else(|| opt("numberlike"));
+        let counter = shared
+            .as_ref()
+            .and_then(|s| {
+                decls
+                    .iter()
+                    .find(|d| &d.env == s)
+                    .map(|d| d.counter.clone())
+            })
+            .or(shared.clone())
+            .unwrap_or_else(|| env.clone());
+        let within = opt("numberwithin").or_else(|| opt("parent")).or_else(|| {
+            shared
+                .as_ref()
+                .and_then(|s| decls.iter().find(|d| &d.env == s))
+                .and_then(|d| d.within.clone())
+        });
+        decls.push(TheoremDecl {
+            env,
+            title,
+            counter,
+            within,
+            numbered,
+        });
+    }
+    decls
+}
+
+fn capitalize(s: &str) -> String {
+    let mut c = s.chars();
+    match c.next() {
+        Some(f) => f.to_uppercase().collect::<String>() + c.as_str(),
+        None => String::new(),
+    }
+}
+
+fn letter(n: u32) -> String {
+    if n == 0 {
+        return "0".into();
+    }
+    let mut n = n;
+    let mut s = Vec::new();
+    while n > 0 {
+        n -= 1;
+        s.push((b'a' + (n % 26) as u8) as char);
+        n /= 26;
+    }
+    s.iter().rev().collect()
+}
+
+fn roman(n: u32) -> String {
+    const TABLE: &[(u32, &str)] = &[(10, "X"), (9, "IX"), (5, "V"), (4, "IV"), (1, "I")];
+    let mut n = n;
+    let mut out = String::new();
+    for &(v, s) in TABLE {
+        while n >= v {
+            out.push_str(s);
+            n -= v;
+        }
+    }
+    out
+}
+
+/// Put an `<a id="figure-N"></a>` anchor in front of every `> **Figure N`
+/// caption so references resolved by [`resolve`] have somewhere to land.
+pub fn anchor_figures(md: &str) -> String {
+    let mut out = String::with_capacity(md.len());
+    for line in md.split_inclusive('\n') {
+        if let Some(rest) = line.strip_prefix("> **Figure ") {
+            let end = rest.find([':', '*']).unwrap_or(rest.len());
+            let number = rest[..end].trim();
+            if !number.is_empty() {
+                out.push_str(&format!(
+                    "<a id=\"{}\"></a>\n\n",
+                    anchor_id("figure-", number)
+                ));
+            }
+        }
+        out.push_str(line);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(tex: &str) -> (String, Numbering) {
+        let mut ph = Placeholders::new();
+        let (out, numbering) = resolve(tex, &mut ph);
+        (ph.resolve(&out), numbering)
+    }
+
+    #[test]
+    fn numbers_sections_and_appendix() {
+        let tex = r"\section{Intro}\label{sec:intro}
+\subsection{Setup}\label{sec:setup}
+\section*{Unnumbered}
+\section{Method}
+\appendix
+\section{Proofs}\label{app:proofs}
+See \ref{sec:setup}, \autoref{app:proofs} and \nameref{sec:intro}.";
+        let (md, n) = run(tex);
+        assert_eq!(n.labels["sec:setup"].number, "1.1");
+        assert_eq!(n.labels["app:proofs"].number, "A");
+        assert!(md.contains(
+            "See [1.1](#section-1-1), [Appendix A](#section-a) and [Intro](#section-1)."
+        ));
+        assert!(md.contains("\\section{Intro}<a id=\"section-1\"></a>"));
+    }
+
+    #[test]
+    fn numbers_figures_tables_and_labels_figures_for_pandoc() {
+        let tex = r"\begin{figure}[t]\includegraphics{a}\caption{A}\label{fig:a}\end{figure}
+\begin{table}\caption{T}\label{tab:t}\end{table}
+\begin{figure*}\caption{B}\label{fig:b}\end{figure*}
+Figure~\ref{fig:b} and \Cref{tab:t}.";
+        let (md, n) = run(tex);
+        assert_eq!(n.labels["fig:b"].number, "2");
+        assert!(md.contains(r"\begin{figure}[t]\label{markxiv-figure-1}\includegraphics{a}"));
+        assert!(!md.contains("fig:a"));
+        assert!(md.contains("<a id=\"table-1\"></a>"));
+        assert!(md.contains(r"\caption{**Table 1:** T}"));
+        assert!(md.contains(r"\caption{B}"));
+        assert!(md.contains("Figure~[2](#figure-2) and [Table 1](#table-1)."));
+    }
+
+    #[test]
+    fn numbers_equations_and_rows() {
+        let tex = r"\begin{equation}a=b\label{eq:a}\end{equation}
+\begin{align}x&=1\\y&=2\nonumber\\z&=3\label{eq:z}\end{align}
+\begin{equation*}w\end{equation*}
+By \eqref{eq:a} and \cref{eq:z}; in math $\eqref{eq:z}$.";
+        let (md, n) = run(tex);
+        assert_eq!(n.labels["eq:a"].number, "1");
+        assert_eq!(n.labels["eq:z"].number, "3");
+        assert!(md.contains(r#"<a id="eq-1"></a>\begin{equation}a=b\tag{1}\end{equation}"#));
+        assert!(md.contains("By [(1)](#eq-1) and [Eq. (3)](#eq-3); in math $\\text{(3)}$."));
+        assert!(!md.contains("\\label"));
+    }
+
+    #[test]
+    fn numbers_theorems_with_shared_counters() {
+        let tex = r"\newtheorem{theorem}{Theorem}[section]
+\newtheorem{lemma}[theorem]{Lemma}
+\newtheorem*{remark}{Remark}
+\section{A}
+\begin{lemma}\label{lem:a}x\end{lemma}
+\begin{theorem}[Main]\label{thm:main}y\end{theorem}
+\begin{remark}z\end{remark}
+\autoref{thm:main} uses \ref{lem:a}.";
+        let (md, n) = run(tex);
+        assert_eq!(n.labels["lem:a"].number, "1.1");
+        assert_eq!(n.labels["thm:main"].number, "1.2");
+        assert_eq!(n.environment_number("theorem", 0), Some("1.2"));
+        assert_eq!(n.environment_number("remark", 0), None);
+        assert!(md.contains("[Theorem 1.2](#theorem-1-2) uses [1.1](#lemma-1-1)."));
+    }
+
+    #[test]
+    fn numbers_algorithms_and_subfigures() {
+        let tex = r"\begin{figure}\begin{subfigure}{.5\linewidth}\caption{x}\label{fig:x}\end{subfigure}\begin{subfigure}{.5\linewidth}\label{fig:y}\end{subfigure}\caption{Both}\end{figure}
+\begin{algorithm}\caption{Train}\label{alg:train}\end{algorithm}
+\ref{fig:y}, \subref{fig:y}, \autoref{alg:train}, \ref{missing}";
+        let (md, n) = run(tex);
+        assert_eq!(n.labels["fig:y"].number, "1b");
+        assert_eq!(n.environment_number("algorithm", 0), Some("1"));
+        assert!(md.contains("[1b](#figure-1), [(b)](#figure-1), [Algorithm 1](#algorithm-1), ??"));
+    }
+
+    #[test]
+    fn books_prefix_floats_with_chapter() {
+        let tex = r"\chapter{One}\begin{figure}\label{f}\end{figure}\chapter{Two}\section{S}\label{s}\begin{figure}\label{g}\end{figure}";
+        let (_, n) = run(tex);
+        assert_eq!(n.labels["f"].number, "1.1");
+        assert_eq!(n.labels["s"].number, "2.1");
+        assert_eq!(n.labels["g"].number, "2.1");
+    }
+
+    #[test]
+    fn anchors_figure_captions() {
+        let md = "Text\n\n> **Figure 2.1:** Cap\n\n> **Figure 3**\n";
+        let out = anchor_figures(md);
+        assert!(out.contains("<a id=\"figure-2-1\"></a>\n\n> **Figure 2.1:** Cap"));
+        assert!(out.contains("<a id=\"figure-3\"></a>\n\n> **Figure 3**"));
+    }
+
+    #[test]
+    fn records_figure_graphics_with_subfigures() {
+        let tex = r"\begin{figure}\includegraphics[width=\linewidth]{plots/a}\end{figure}
+\begin{figure}
+\begin{subfigure}{.5\linewidth}\includegraphics{b1.pdf}\end{subfigure}
+\begin{subfigure}{.5\linewidth}\includegraphics{b2}\includegraphics{b3}\end{subfigure}
+\end{figure}
+\begin{figure}\subfloat[x]{\includegraphics{c1}}\subfloat[y]{\includegraphics{c2}}\end{figure}
+\includegraphics{outside}";
+        let n = number(tex);
+        let names: Vec<(&str, &str, &str)> = n
+            .graphics
+            .iter()
+            .map(|g| (g.figure.as_str(), g.name.as_str(), g.path.as_str()))
+            .collect();
+        assert_eq!(
+            names,
+            vec![
+                ("1", "1", "plots/a"),
+                ("2", "2a", "b1.pdf"),
+                ("2", "2b", "b2"),
+                ("2", "2b-2", "b3"),
+                ("3", "3a", "c1"),
+                ("3", "3b", "c2"),
+            ]
+        );
+    }
+}
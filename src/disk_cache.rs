@@ -11,13 +11,24 @@ use flate2::read::{GzDecoder, GzEncoder};
 use flate2::Compression;
 use tokio::sync::Mutex;
 
+use crate::figures::{is_valid_name, FigureImage, ImageFormat};
+
 #[derive(Clone)]
 pub struct DiskCacheConfig {
     pub root: PathBuf,
     pub cap_bytes: u64,
     pub sweep_interval: Duration,
+    /// Pipeline version stamped on written entries; entries with another
+    /// stamp, or none, are stale.
+    pub pipeline_version: u32,
 }
 
+/// First line of every cached Markdown file, followed by the version.
+const STAMP_PREFIX: &str = "markxiv-pipeline ";
+
+/// File in a figures directory holding the version that extracted them.
+const FIGURES_STAMP: &str = ".pipeline";
+
 pub struct DiskCache {
     cfg: DiskCacheConfig,
     size_bytes: Arc<Mutex<u64>>, // cached approximate current size
@@ -49,6 +60,8 @@ impl DiskCache {
         });
     }
 
+    /// The entry for `key`. A stale entry, from another pipeline version,
+    /// is deleted and reported as missing so the paper is converted again.
     pub async fn get(&self, key: &str) -> io::Result<Option<String>> {
         let path = self.path_for(key);
         let Some(p) = path else { return Ok(None) };
@@ -68,7 +81,19 @@ impl DiskCache {
         use std::io::Read;
         dec.read_to_string(&mut s)
             .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
-        Ok(Some(s))
+        match split_stamp(&s) {
+            Some((version, value)) if version == self.cfg.pipeline_version => {
+                Ok(Some(value.to_string()))
+            }
+            _ => {
+                tracing::debug!(cache_key = %key, "stale disk cache entry");
+                if tokio::fs::remove_file(&p).await.is_ok() {
+                    let mut size = self.size_bytes.lock().await;
+                    *size = size.saturating_sub(gz.len() as u64);
+                }
+                Ok(None)
+            }
+        }
     }
 
     pub async fn put(&self, key: &str, value: &str) -> io::Result<()> {
@@ -88,12 +113,14 @@ impl DiskCache {
                 )
             })?;
         }
-        // compress
-        let mut enc = GzEncoder::new(value.as_bytes(), Compression::default());
+        // stamp and compress
+        let stamped = format!("{}{}\n{}", STAMP_PREFIX, self.cfg.pipeline_version, value);
+        let mut enc = GzEncoder::new(stamped.as_bytes(), Compression::default());
         let mut buf = Vec::new();
         use std::io::Read;
         enc.read_to_end(&mut buf).map_err(io::Error::other)?;
         // write atomically
+        let replaced = file_len(&path).await;
         let tmp = path.with_extension("tmp");
         tokio::fs::write(&tmp, &buf).await.map_err(|e| {
             io::Error::new(
@@ -115,7 +142,9 @@ impl DiskCache {
         })?;
         // update size counter
         let mut size = self.size_bytes.lock().await;
-        *size = size.saturating_add(buf.len() as u64);
+        *size = size
+            .saturating_sub(replaced)
+            .saturating_add(buf.len() as u64);
         drop(size);
         // sweeper thread enforces cap periodically
         Ok(())
@@ -152,7 +181,104 @@ impl DiskCache {
         Ok(())
     }
 
+    /// Store a paper's figures in a directory next to its cached Markdown,
+    /// stamped with the pipeline version like the Markdown is.
+    pub async fn put_figures(&self, key: &str, figures: &[FigureImage]) -> io::Result<()> {
+        let dir = self.figures_dir_for(key);
+        if !self.figures_are_current(&dir).await {
+            self.remove_figures(&dir).await;
+        }
+        tokio::fs::create_dir_all(&dir).await.map_err(|e| {
+            io::Error::new(
+                e.kind(),
+                format!(
+                    "create_dir_all {} for key {} failed: {}",
+                    dir.display(),
+                    key,
+                    e
+                ),
+            )
+        })?;
+        let stamp = dir.join(FIGURES_STAMP);
+        let version = self.cfg.pipeline_version.to_string();
+        let mut replaced = file_len(&stamp).await;
+        tokio::fs::write(&stamp, &version).await?;
+        let mut written = version.len() as u64;
+        for fig in figures {
+            if !is_valid_name(&fig.name) {
+                continue;
+            }
+            let path = dir.join(format!("{}.{}", fig.name, fig.format.extension()));
+            replaced = replaced.saturating_add(file_len(&path).await);
+            let tmp = path.with_extension("tmp");
+            tokio::fs::write(&tmp, &fig.bytes).await?;
+            tokio::fs::rename(&tmp, &path).await?;
+            written = written.saturating_add(fig.bytes.len() as u64);
+        }
+        let mut size = self.size_bytes.lock().await;
+        *size = size.saturating_sub(replaced).saturating_add(written);
+        Ok(())
+    }
+
+    /// True when the figures in `dir` were extracted by this pipeline
+    /// version; a missing directory counts as current.
+    async fn figures_are_current(&self, dir: &Path) -> bool {
+        match tokio::fs::read_to_string(dir.join(FIGURES_STAMP)).await {
+            Ok(stamp) => stamp.trim().parse() == Ok(self.cfg.pipeline_version),
+            Err(_) => !dir.exists(),
+        }
+    }
+
+    /// Delete a figures directory, keeping the size counter in step.
+    async fn remove_figures(&self, dir: &Path) {
+        let len = dir_size(dir).await.unwrap_or(0);
+        if tokio::fs::remove_dir_all(dir).await.is_ok() {
+            let mut size = self.size_bytes.lock().await;
+            *size = size.saturating_sub(len);
+        }
+    }
+
+    pub async fn get_figure(&self, key: &str, name: &str) -> io::Result<Option<FigureImage>> {
+        if !is_valid_name(name) {
+            return Ok(None);
+        }
+        let dir = self.figures_dir_for(key);
+        if !self.figures_are_current(&dir).await {
+            tracing::debug!(cache_key = %key, "stale disk cache figures");
+            self.remove_figures(&dir).await;
+            return Ok(None);
+        }
+        for format in [ImageFormat::Png, ImageFormat::Jpeg, ImageFormat::Gif] {
+            let path = dir.join(format!("{}.{}", name, format.extension()));
+            match tokio::fs::read(&path).await {
+                Ok(bytes) => {
+                    let _ = set_file_mtime(&path, FileTime::from_system_time(SystemTime::now()));
+                    return Ok(Some(FigureImage {
+                        name: name.to_string(),
+                        format,
+                        bytes,
+                    }));
+                }
+                Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(None)
+    }
+
     fn path_for(&self, key: &str) -> Option<PathBuf> {
+        let mut path = self.stem_for(key).into_os_string();
+        path.push(".md.gz");
+        Some(PathBuf::from(path))
+    }
+
+    fn figures_dir_for(&self, key: &str) -> PathBuf {
+        let mut path = self.stem_for(key).into_os_string();
+        path.push(".figures");
+        PathBuf::from(path)
+    }
+
+    fn stem_for(&self, key: &str) -> PathBuf {
         // shard by simple FNV-1a 64-bit hash of key
         let h = fnv1a64(key.as_bytes());
         let a = ((h >> 56) & 0xff) as u8;
@@ -160,16 +286,26 @@ impl DiskCache {
         let file = sanitize_filename(key);
         let trimmed = file.trim_start_matches(['/', '\\']);
         let safe = if trimmed.is_empty() { "_" } else { trimmed };
-        let path = self
-            .cfg
+        self.cfg
             .root
             .join(format!("{:02x}", a))
             .join(format!("{:02x}", b))
-            .join(format!("{}.md.gz", safe));
-        Some(path)
+            .join(safe)
     }
 }
 
+/// The pipeline version and the entry of a cached file's contents; `None`
+/// for files written before entries were stamped.
+fn split_stamp(contents: &str) -> Option<(u32, &str)> {
+    let (stamp, value) = contents.strip_prefix(STAMP_PREFIX)?.split_once('\n')?;
+    Some((stamp.parse().ok()?, value))
+}
+
+/// Size of the file at `path`, 0 if there is none.
+async fn file_len(path: &Path) -> u64 {
+    tokio::fs::metadata(path).await.map_or(0, |m| m.len())
+}
+
 fn sanitize_filename(id: &str) -> String {
     // arXiv ids are ASCII; replace any unexpected chars just in case
     id.chars()
@@ -249,6 +385,7 @@ mod tests {
             root: tmp.clone(),
             cap_bytes: 10_000_000,
             sweep_interval: Duration::from_secs(3600),
+            pipeline_version: 1,
         };
         let dc = DiskCache::new(cfg).await.unwrap();
         dc.put("1234.5678", "hello world").await.unwrap();
@@ -264,6 +401,7 @@ mod tests {
             root: tmp.clone(),
             cap_bytes: 10_000_000,
             sweep_interval: Duration::from_secs(3600),
+            pipeline_version: 1,
         };
         let dc = DiskCache::new(cfg).await.unwrap();
         dc.put("/abs/1234.5678", "hello world").await.unwrap();
@@ -287,6 +425,33 @@ mod tests {
         let _ = tokio::fs::remove_dir_all(tmp).await;
     }
 
+    #[tokio::test]
+    async fn figures_roundtrip_next_to_markdown() {
+        let tmp = std::env::temp_dir().join(format!("mk-dc-{}", uuid()));
+        let cfg = DiskCacheConfig {
+            root: tmp.clone(),
+            cap_bytes: 10_000_000,
+            sweep_interval: Duration::from_secs(3600),
+            pipeline_version: 1,
+        };
+        let dc = DiskCache::new(cfg).await.unwrap();
+        dc.put("/abs/1234.5678", "md").await.unwrap();
+        let figs = vec![FigureImage {
+            name: "2b".into(),
+            format: ImageFormat::Jpeg,
+            bytes: b"jpeg".to_vec(),
+        }];
+        dc.put_figures("/abs/1234.5678", &figs).await.unwrap();
+        let md_path = dc.path_for("/abs/1234.5678").unwrap();
+        let dir = dc.figures_dir_for("/abs/1234.5678");
+        assert_eq!(md_path.parent(), dir.parent());
+        let got = dc.get_figure("/abs/1234.5678", "2b").await.unwrap();
+        assert_eq!(got, Some(figs[0].clone()));
+        assert_eq!(dc.get_figure("/abs/1234.5678", "3").await.unwrap(), None);
+        assert_eq!(dc.get_figure("/abs/1234.5678", "../x").await.unwrap(), None);
+        let _ = tokio::fs::remove_dir_all(tmp).await;
+    }
+
     #[tokio::test]
     async fn enforce_cap_deletes_oldest() {
         let tmp = std::env::temp_dir().join(format!("mk-dc-{}", uuid()));
@@ -294,6 +459,7 @@ mod tests {
             root: tmp.clone(),
             cap_bytes: 200,
             sweep_interval: Duration::from_secs(3600),
+            pipeline_version: 1,
         };
         let dc = DiskCache::new(cfg).await.unwrap();
         for i in 0..20 {
@@ -306,6 +472,102 @@ mod tests {
         let _ = tokio::fs::remove_dir_all(tmp).await;
     }
 
+    #[tokio::test]
+    async fn entries_from_other_pipeline_versions_are_stale() {
+        let tmp = std::env::temp_dir().join(format!("mk-dc-{}", uuid()));
+        let cfg = DiskCacheConfig {
+            root: tmp.clone(),
+            cap_bytes: 10_000_000,
+            sweep_interval: Duration::from_secs(3600),
+            pipeline_version: 1,
+        };
+        let old = DiskCache::new(cfg.clone()).await.unwrap();
+        old.put("/abs/1234.5678", "old").await.unwrap();
+        let new = DiskCache::new(DiskCacheConfig {
+            pipeline_version: 2,
+            ..cfg
+        })
+        .await
+        .unwrap();
+        assert_eq!(new.get("/abs/1234.5678").await.unwrap(), None);
+        assert!(!new.path_for("/abs/1234.5678").unwrap().exists());
+        new.put("/abs/1234.5678", "new").await.unwrap();
+        assert_eq!(
+            new.get("/abs/1234.5678").await.unwrap().as_deref(),
+            Some("new")
+        );
+
+        // Files from before stamping are stale too.
+        let path = new.path_for("/abs/1").unwrap();
+        tokio::fs::create_dir_all(path.parent().unwrap())
+            .await
+            .unwrap();
+        let mut enc = GzEncoder::new(&b"unstamped"[..], Compression::default());
+        let mut buf = Vec::new();
+        std::io::Read::read_to_end(&mut enc, &mut buf).unwrap();
+        tokio::fs::write(&path, buf).await.unwrap();
+        assert_eq!(new.get("/abs/1").await.unwrap(), None);
+        let _ = tokio::fs::remove_dir_all(tmp).await;
+    }
+
+    #[tokio::test]
+    async fn figures_from_other_pipeline_versions_are_stale() {
+        let tmp = std::env::temp_dir().join(format!("mk-dc-{}", uuid()));
+        let cfg = DiskCacheConfig {
+            root: tmp.clone(),
+            cap_bytes: 10_000_000,
+            sweep_interval: Duration::from_secs(3600),
+            pipeline_version: 1,
+        };
+        let fig = |bytes: &[u8]| FigureImage {
+            name: "1".into(),
+            format: ImageFormat::Png,
+            bytes: bytes.to_vec(),
+        };
+        let old = DiskCache::new(cfg.clone()).await.unwrap();
+        old.put_figures("/abs/1", &[fig(b"old")]).await.unwrap();
+        assert!(old.get_figure("/abs/1", "1").await.unwrap().is_some());
+        let new = DiskCache::new(DiskCacheConfig {
+            pipeline_version: 2,
+            ..cfg
+        })
+        .await
+        .unwrap();
+        assert_eq!(new.get_figure("/abs/1", "1").await.unwrap(), None);
+        assert!(!new.figures_dir_for("/abs/1").exists());
+        new.put_figures("/abs/1", &[fig(b"new")]).await.unwrap();
+        assert_eq!(
+            new.get_figure("/abs/1", "1").await.unwrap(),
+            Some(fig(b"new"))
+        );
+        let _ = tokio::fs::remove_dir_all(tmp).await;
+    }
+
+    #[tokio::test]
+    async fn overwrites_do_not_inflate_the_size_counter() {
+        let tmp = std::env::temp_dir().join(format!("mk-dc-{}", uuid()));
+        let cfg = DiskCacheConfig {
+            root: tmp.clone(),
+            cap_bytes: 10_000_000,
+            sweep_interval: Duration::from_secs(3600),
+            pipeline_version: 1,
+        };
+        let dc = DiskCache::new(cfg).await.unwrap();
+        let figs = vec![FigureImage {
+            name: "1".into(),
+            format: ImageFormat::Png,
+            bytes: vec![0; 1000],
+        }];
+        dc.put("/abs/1", "md").await.unwrap();
+        dc.put_figures("/abs/1", &figs).await.unwrap();
+        let once = *dc.size_bytes.lock().await;
+        dc.put("/abs/1", "md").await.unwrap();
+        dc.put_figures("/abs/1", &figs).await.unwrap();
+        assert_eq!(*dc.size_bytes.lock().await, once);
+        assert_eq!(once, initial_size(&tmp).await.unwrap());
+        let _ = tokio::fs::remove_dir_all(tmp).await;
+    }
+
     #[test]
     fn sanitize_filename_replaces_unexpected_chars() {
         let sanitized = sanitize_filename("/abs/12:34*56?");
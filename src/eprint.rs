@@ -0,0 +1,164 @@
+use std::borrow::Cow;
+use std::io::Read;
+
+use flate2::read::GzDecoder;
+
+/// What an arXiv e-print payload holds, after undoing gzip.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EprintFormat {
+    Tar,
+    PostScript,
+    Dvi,
+    Pdf,
+    /// Anything else, e.g. a single (gzipped) `.tex` file.
+    Other,
+}
+
+impl EprintFormat {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Tar => "tar",
+            Self::PostScript => "PostScript",
+            Self::Dvi => "DVI",
+            Self::Pdf => "PDF",
+            Self::Other => "other",
+        }
+    }
+
+    /// PostScript or DVI, which need [`LegacyFormat`] conversion rather
+    /// than pandoc.
+    pub fn legacy(self) -> Option<LegacyFormat> {
+        match self {
+            Self::PostScript => Some(LegacyFormat::PostScript),
+            Self::Dvi => Some(LegacyFormat::Dvi),
+            _ => None,
+        }
+    }
+}
+
+/// Pre-LaTeX-source e-prints, turned into PDF with local tools and then
+/// read like the PDF fallback.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LegacyFormat {
+    PostScript,
+    Dvi,
+}
+
+impl LegacyFormat {
+    /// Human-readable name of the e-print format.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::PostScript => "PostScript",
+            Self::Dvi => "DVI",
+        }
+    }
+
+    /// The conversion route, for logs and the provenance note.
+    pub fn route(self) -> &'static str {
+        match self {
+            Self::PostScript => "ps2pdf → pdftotext",
+            Self::Dvi => "dvipdf → pdftotext",
+        }
+    }
+
+    /// File extension the converter tools expect.
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::PostScript => "ps",
+            Self::Dvi => "dvi",
+        }
+    }
+
+    /// The format of an archive member from its name: `.ps`, `.ps.gz` or
+    /// `.dvi` (but not `.eps` figures).
+    pub fn from_file_name(name: &str) -> Option<Self> {
+        let name = name.to_ascii_lowercase();
+        let name = name.strip_suffix(".gz").unwrap_or(&name);
+        if name.ends_with(".ps") {
+            Some(Self::PostScript)
+        } else if name.ends_with(".dvi") {
+            Some(Self::Dvi)
+        } else {
+            None
+        }
+    }
+}
+
+fn is_gzip(bytes: &[u8]) -> bool {
+    bytes.starts_with(&[0x1f, 0x8b])
+}
+
+/// `bytes` with one layer of gzip removed, when it has one.
+pub fn gunzip(bytes: &[u8]) -> Cow<'_, [u8]> {
+    if !is_gzip(bytes) {
+        return Cow::Borrowed(bytes);
+    }
+    let mut out = Vec::new();
+    match GzDecoder::new(bytes).read_to_end(&mut out) {
+        Ok(_) => Cow::Owned(out),
+        Err(_) => Cow::Borrowed(bytes),
+    }
+}
+
+/// Identify an e-print payload by its magic bytes, looking through gzip.
+pub fn sniff(bytes: &[u8]) -> EprintFormat {
+    let bytes = gunzip(bytes);
+    if bytes.len() > 262 && &bytes[257..262] == b"ustar" {
+        EprintFormat::Tar
+    } else if bytes.starts_with(b"%!PS") || bytes.starts_with(b"%!\n") {
+        EprintFormat::PostScript
+    } else if bytes.starts_with(&[0xf7, 0x02]) {
+        EprintFormat::Dvi
+    } else if bytes.starts_with(b"%PDF-") {
+        EprintFormat::Pdf
+    } else {
+        EprintFormat::Other
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use std::io::Write;
+
+    fn gzip(bytes: &[u8]) -> Vec<u8> {
+        let mut enc = GzEncoder::new(Vec::new(), Compression::fast());
+        enc.write_all(bytes).unwrap();
+        enc.finish().unwrap()
+    }
+
+    #[test]
+    fn sniffs_formats_through_gzip() {
+        let mut tar = vec![0u8; 512];
+        tar[257..262].copy_from_slice(b"ustar");
+        assert_eq!(sniff(&tar), EprintFormat::Tar);
+        assert_eq!(sniff(&gzip(&tar)), EprintFormat::Tar);
+        assert_eq!(sniff(b"%!PS-Adobe-2.0\n"), EprintFormat::PostScript);
+        assert_eq!(sniff(&gzip(b"%!PS-Adobe-2.0\n")), EprintFormat::PostScript);
+        assert_eq!(sniff(&[0xf7, 0x02, 0x01, 0x83]), EprintFormat::Dvi);
+        assert_eq!(sniff(b"%PDF-1.4"), EprintFormat::Pdf);
+        assert_eq!(
+            sniff(&gzip(b"\\documentclass{article}")),
+            EprintFormat::Other
+        );
+        assert_eq!(sniff(&[0x1f, 0x8b, 0x00]), EprintFormat::Other);
+        assert_eq!(sniff(b"%!PS").legacy(), Some(LegacyFormat::PostScript));
+        assert_eq!(EprintFormat::Tar.legacy(), None);
+    }
+
+    #[test]
+    fn legacy_members_by_name() {
+        assert_eq!(
+            LegacyFormat::from_file_name("paper.ps.gz"),
+            Some(LegacyFormat::PostScript)
+        );
+        assert_eq!(
+            LegacyFormat::from_file_name("PAPER.DVI"),
+            Some(LegacyFormat::Dvi)
+        );
+        assert_eq!(LegacyFormat::from_file_name("fig.eps"), None);
+        assert_eq!(LegacyFormat::from_file_name("main.tex"), None);
+    }
+}
@@ -0,0 +1,474 @@
+use std::path::{Component, Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use tokio::process::Command;
+use tokio::time::timeout;
+
+use crate::bibliography::markxiv_base_url;
+use crate::crossref::FigureGraphic;
+use crate::tex_util::{find_command, read_arg};
+
+/// Extensions `\includegraphics` tries, in the order pdflatex does.
+const GRAPHIC_EXTENSIONS: &[&str] = &["pdf", "png", "jpg", "jpeg", "eps", "ps", "gif"];
+
+/// Upper bound on figures rasterized per paper.
+const MAX_FIGURES: usize = 64;
+
+/// Source graphics larger than this are skipped.
+const MAX_GRAPHIC_BYTES: u64 = 32 * 1024 * 1024;
+
+const RASTERIZE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Total rasterization time per paper. Figures are extracted while the
+/// paper request waits, so once this is spent the remaining vector
+/// graphics are skipped; bitmaps are still served as they are.
+const EXTRACTION_BUDGET: Duration = Duration::from_secs(60);
+
+/// Resolution used when rasterizing vector figures.
+const RASTER_DPI: &str = "150";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageFormat {
+    Png,
+    Jpeg,
+    Gif,
+}
+
+impl ImageFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            ImageFormat::Png => "png",
+            ImageFormat::Jpeg => "jpg",
+            ImageFormat::Gif => "gif",
+        }
+    }
+
+    pub fn content_type(self) -> &'static str {
+        match self {
+            ImageFormat::Png => "image/png",
+            ImageFormat::Jpeg => "image/jpeg",
+            ImageFormat::Gif => "image/gif",
+        }
+    }
+
+    pub fn from_extension(ext: &str) -> Option<Self> {
+        match ext.to_ascii_lowercase().as_str() {
+            "png" => Some(ImageFormat::Png),
+            "jpg" | "jpeg" => Some(ImageFormat::Jpeg),
+            "gif" => Some(ImageFormat::Gif),
+            _ => None,
+        }
+    }
+}
+
+/// A figure graphic ready to serve at `/abs/:id/figures/:name`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FigureImage {
+    /// Asset name from [`FigureGraphic::name`], e.g. "3" or "2b".
+    pub name: String,
+    pub format: ImageFormat,
+    pub bytes: Vec<u8>,
+}
+
+/// Asset names are figure numbers with optional subfigure letters and
+/// suffixes; anything else is rejected before touching caches or disk.
+pub fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= 32
+        && !name.starts_with('.')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '-')
+}
+
+/// URL a figure is served from.
+pub fn figure_url(id: &str, name: &str) -> String {
+    format!("{}/abs/{}/figures/{}", markxiv_base_url(), id, name)
+}
+
+/// Directories listed by `\graphicspath{{figs/}{img/}}`.
+pub fn parse_graphicspath(tex: &str) -> Vec<String> {
+    let mut dirs = Vec::new();
+    let mut from = 0;
+    while let Some(at) = find_command(tex, "graphicspath", from) {
+        let after = at + "\\graphicspath".len();
+        let Some((list, end)) = read_arg(tex, after) else {
+            from = after;
+            continue;
+        };
+        let mut pos = 0;
+        while let Some((dir, e)) = read_arg(list, pos) {
+            let dir = dir.trim();
+            if !dir.is_empty() {
+                dirs.push(dir.to_string());
+            }
+            pos = e;
+        }
+        from = end;
+    }
+    dirs
+}
+
+/// Find the file an `\includegraphics{path}` refers to, relative to the
+/// main file's directory or a `\graphicspath` entry, never leaving `root`.
+pub fn locate_graphic(
+    root: &Path,
+    main_dir: &Path,
+    graphicspath: &[String],
+    path: &str,
+) -> Option<PathBuf> {
+    let path = path.trim_matches('"');
+    let rel = Path::new(path);
+    if rel.is_absolute() || rel.components().any(|c| matches!(c, Component::ParentDir)) {
+        return None;
+    }
+    let mut dirs = vec![main_dir.to_path_buf()];
+    for d in graphicspath {
+        let d = Path::new(d);
+        if d.is_absolute() || d.components().any(|c| matches!(c, Component::ParentDir)) {
+            continue;
+        }
+        dirs.push(main_dir.join(d));
+    }
+    let has_known_ext = rel
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| GRAPHIC_EXTENSIONS.contains(&e.to_ascii_lowercase().as_str()))
+        .unwrap_or(false);
+    for dir in dirs {
+        let base = dir.join(rel);
+        if has_known_ext && base.is_file() {
+            return Some(base).filter(|p| resolve_within(root, p).is_some());
+        }
+        for ext in GRAPHIC_EXTENSIONS {
+            let mut name = base.clone().into_os_string();
+            name.push(".");
+            name.push(ext);
+            let candidate = PathBuf::from(name);
+            if candidate.is_file() {
+                return Some(candidate).filter(|p| resolve_within(root, p).is_some());
+            }
+        }
+    }
+    None
+}
+
+/// `path` with symlinks resolved, if it still lies inside `root`. Archives
+/// can ship links like `fig.png -> /etc/passwd`, which a textual prefix
+/// check would let through.
+fn resolve_within(root: &Path, path: &Path) -> Option<PathBuf> {
+    let root = root.canonicalize().ok()?;
+    let path = path.canonicalize().ok()?;
+    path.starts_with(&root).then_some(path)
+}
+
+/// Load every figure graphic, rasterizing PDF and EPS sources to PNG.
+/// Graphics that cannot be found or converted are logged and skipped, as
+/// are vector graphics left over once [`EXTRACTION_BUDGET`] is spent.
+pub async fn extract_figures(
+    root: &Path,
+    main_tex: &Path,
+    tex: &str,
+    graphics: &[FigureGraphic],
+) -> Vec<FigureImage> {
+    extract_figures_within(root, main_tex, tex, graphics, EXTRACTION_BUDGET).await
+}
+
+async fn extract_figures_within(
+    root: &Path,
+    main_tex: &Path,
+    tex: &str,
+    graphics: &[FigureGraphic],
+    budget: Duration,
+) -> Vec<FigureImage> {
+    let deadline = Instant::now() + budget;
+    let main_dir = main_tex.parent().unwrap_or(root);
+    let graphicspath = parse_graphicspath(tex);
+    let mut out = Vec::new();
+    for (i, g) in graphics.iter().take(MAX_FIGURES).enumerate() {
+        let Some(src) = locate_graphic(root, main_dir, &graphicspath, &g.path) else {
+            tracing::debug!(figure = %g.name, path = %g.path, "figure graphic not found");
+            continue;
+        };
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        match load_graphic(root, &src, i, remaining).await {
+            Ok(Some((format, bytes))) => out.push(FigureImage {
+                name: g.name.clone(),
+                format,
+                bytes,
+            }),
+            Ok(None) => {}
+            Err(e) => {
+                tracing::warn!(figure = %g.name, path = %src.display(), error = %e, "figure rasterization failed")
+            }
+        }
+    }
+    out
+}
+
+async fn load_graphic(
+    root: &Path,
+    src: &Path,
+    index: usize,
+    remaining: Duration,
+) -> Result<Option<(ImageFormat, Vec<u8>)>, String> {
+    let Some(resolved) = resolve_within(root, src) else {
+        return Err("graphic resolves outside the archive".into());
+    };
+    let src = resolved.as_path();
+    let len = tokio::fs::metadata(src)
+        .await
+        .map_err(|e| e.to_string())?
+        .len();
+    if len > MAX_GRAPHIC_BYTES {
+        return Ok(None);
+    }
+    let ext = src
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or("")
+        .to_ascii_lowercase();
+    if let Some(format) = ImageFormat::from_extension(&ext) {
+        let bytes = tokio::fs::read(src).await.map_err(|e| e.to_string())?;
+        return Ok(Some((format, bytes)));
+    }
+    if !matches!(ext.as_str(), "pdf" | "eps" | "ps") {
+        return Ok(None);
+    }
+    if remaining.is_zero() {
+        return Err("figure extraction time used up".into());
+    }
+    let limit = remaining.min(RASTERIZE_TIMEOUT);
+    let out = root.join(format!("markxiv-figure-{}.png", index));
+    match ext.as_str() {
+        "pdf" => rasterize_pdf(src, &out, limit).await?,
+        "eps" | "ps" => rasterize_eps(src, &out, limit).await?,
+        _ => return Ok(None),
+    }
+    let bytes = tokio::fs::read(&out).await.map_err(|e| e.to_string())?;
+    Ok(Some((ImageFormat::Png, bytes)))
+}
+
+/// First page of a PDF figure via poppler's `pdftoppm`.
+async fn rasterize_pdf(src: &Path, out: &Path, limit: Duration) -> Result<(), String> {
+    let pdftoppm = std::env::var("MARKXIV_PDFTOPPM_PATH").unwrap_or_else(|_| "pdftoppm".into());
+    // pdftoppm appends the extension to the output prefix itself.
+    let prefix = out.with_extension("");
+    let mut cmd = Command::new(pdftoppm);
+    cmd.args([
+        "-png",
+        "-singlefile",
+        "-f",
+        "1",
+        "-l",
+        "1",
+        "-r",
+        RASTER_DPI,
+    ])
+    .arg(src)
+    .arg(&prefix);
+    run_tool(cmd, "pdftoppm", limit).await
+}
+
+/// EPS/PS figure cropped to its bounding box via Ghostscript.
+async fn rasterize_eps(src: &Path, out: &Path, limit: Duration) -> Result<(), String> {
+    let gs = std::env::var("MARKXIV_GS_PATH").unwrap_or_else(|_| "gs".into());
+    let mut cmd = Command::new(gs);
+    cmd.args([
+        "-q",
+        "-dSAFER",
+        "-dBATCH",
+        "-dNOPAUSE",
+        "-dEPSCrop",
+        "-dTextAlphaBits=4",
+        "-dGraphicsAlphaBits=4",
+        "-sDEVICE=png16m",
+    ])
+    .arg(format!("-r{}", RASTER_DPI))
+    .arg(format!("-sOutputFile={}", out.display()))
+    .arg(src);
+    run_tool(cmd, "gs", limit).await
+}
+
+async fn run_tool(mut cmd: Command, name: &str, limit: Duration) -> Result<(), String> {
+    let out = timeout(limit, cmd.output())
+        .await
+        .map_err(|_| format!("{} timed out", name))?
+        .map_err(|e| format!("{} spawn: {}", name, e))?;
+    if out.status.success() {
+        Ok(())
+    } else {
+        Err(format!(
+            "{} failed: {}",
+            name,
+            String::from_utf8_lossy(&out.stderr)
+        ))
+    }
+}
+
+/// Put the served figure images above each `> **Figure N` caption produced
+/// by `extract_figure_captions`. Subfigures ("2a", "2b") and further
+/// graphics ("2-2") of figure N are listed together. `query` is appended
+/// to each image URL (e.g. `?main=paper.tex`).
+pub fn add_figure_images(md: &str, id: &str, names: &[String], query: &str) -> String {
+    if names.is_empty() {
+        return md.to_string();
+    }
+    let mut out = String::with_capacity(md.len());
+    for line in md.split_inclusive('\n') {
+        if let Some(rest) = line.strip_prefix("> **Figure ") {
+            let end = rest.find([':', '*']).unwrap_or(rest.len());
+            let number = rest[..end].trim();
+            let images: Vec<String> = names
+                .iter()
+                .filter(|n| belongs_to(n, number))
+                .map(|n| format!("![Figure {}]({}{})", n, figure_url(id, n), query))
+                .collect();
+            if !images.is_empty() {
+                out.push_str("> ");
+                out.push_str(&images.join(" "));
+                out.push_str("\n>\n");
+            }
+        }
+        out.push_str(line);
+    }
+    out
+}
+
+fn belongs_to(name: &str, number: &str) -> bool {
+    match name.strip_prefix(number) {
+        Some(rest) => {
+            rest.is_empty()
+                || rest.starts_with('-')
+                || rest.starts_with(|c: char| c.is_ascii_lowercase())
+        }
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_graphicspath_entries() {
+        let tex = r"\graphicspath{{figs/}{./img/}} \graphicspath{{more}}";
+        assert_eq!(parse_graphicspath(tex), vec!["figs/", "./img/", "more"]);
+    }
+
+    #[test]
+    fn validates_asset_names() {
+        assert!(is_valid_name("2b-2"));
+        assert!(is_valid_name("3.1"));
+        assert!(!is_valid_name("../x"));
+        assert!(!is_valid_name("a/b"));
+        assert!(!is_valid_name(""));
+    }
+
+    #[test]
+    fn locates_graphics_with_implicit_extension() {
+        let root = std::env::temp_dir().join(format!("markxiv-fig-{}", std::process::id()));
+        let figs = root.join("paper").join("figs");
+        std::fs::create_dir_all(&figs).unwrap();
+        std::fs::write(figs.join("plot.pdf"), b"%PDF").unwrap();
+        std::fs::write(root.join("paper").join("b.png"), b"png").unwrap();
+        let main_dir = root.join("paper");
+        let gp = vec!["figs/".to_string()];
+        assert_eq!(
+            locate_graphic(&root, &main_dir, &gp, "plot"),
+            Some(figs.join("plot.pdf"))
+        );
+        assert_eq!(
+            locate_graphic(&root, &main_dir, &gp, "b.png"),
+            Some(main_dir.join("b.png"))
+        );
+        assert_eq!(locate_graphic(&root, &main_dir, &gp, "../b"), None);
+        assert_eq!(locate_graphic(&root, &main_dir, &gp, "missing"), None);
+        let _ = std::fs::remove_dir_all(root);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn symlinked_graphics_outside_the_archive_are_refused() {
+        let base = std::env::temp_dir().join(format!("markxiv-fig-ln-{}", std::process::id()));
+        let root = base.join("archive");
+        std::fs::create_dir_all(&root).unwrap();
+        std::fs::write(base.join("secret.png"), b"secret").unwrap();
+        std::os::unix::fs::symlink(base.join("secret.png"), root.join("leak.png")).unwrap();
+        std::os::unix::fs::symlink(&base, root.join("up")).unwrap();
+        std::fs::write(root.join("ok.png"), b"png").unwrap();
+        std::os::unix::fs::symlink(root.join("ok.png"), root.join("alias.png")).unwrap();
+        let gp = vec!["up/".to_string()];
+        assert_eq!(locate_graphic(&root, &root, &gp, "leak.png"), None);
+        assert_eq!(locate_graphic(&root, &root, &gp, "secret"), None);
+        assert_eq!(
+            locate_graphic(&root, &root, &gp, "alias"),
+            Some(root.join("alias.png"))
+        );
+        let graphics: Vec<FigureGraphic> = ["leak", "secret", "alias"]
+            .iter()
+            .map(|p| FigureGraphic {
+                figure: "1".into(),
+                name: p.to_string(),
+                path: p.to_string(),
+            })
+            .collect();
+        let tex = "\\graphicspath{{up/}}";
+        let figs = extract_figures(&root, &root.join("main.tex"), tex, &graphics).await;
+        assert_eq!(figs.len(), 1);
+        assert_eq!(figs[0].bytes, b"png");
+        let _ = std::fs::remove_dir_all(base);
+    }
+
+    #[tokio::test]
+    async fn extracts_bitmap_figures_without_tools() {
+        let root = std::env::temp_dir().join(format!("markxiv-fig-x-{}", std::process::id()));
+        std::fs::create_dir_all(&root).unwrap();
+        std::fs::write(root.join("a.png"), b"png-bytes").unwrap();
+        let main = root.join("main.tex");
+        let graphics = vec![FigureGraphic {
+            figure: "1".into(),
+            name: "1".into(),
+            path: "a".into(),
+        }];
+        let figs = extract_figures(&root, &main, "", &graphics).await;
+        assert_eq!(figs.len(), 1);
+        assert_eq!(figs[0].format, ImageFormat::Png);
+        assert_eq!(figs[0].bytes, b"png-bytes");
+        let _ = std::fs::remove_dir_all(root);
+    }
+
+    #[tokio::test]
+    async fn spent_budget_skips_vector_figures_only() {
+        let root = std::env::temp_dir().join(format!("markxiv-fig-b-{}", std::process::id()));
+        std::fs::create_dir_all(&root).unwrap();
+        std::fs::write(root.join("a.pdf"), b"%PDF").unwrap();
+        std::fs::write(root.join("b.png"), b"png-bytes").unwrap();
+        let graphics: Vec<FigureGraphic> = ["a", "b"]
+            .iter()
+            .map(|p| FigureGraphic {
+                figure: "1".into(),
+                name: p.to_string(),
+                path: p.to_string(),
+            })
+            .collect();
+        let main = root.join("main.tex");
+        let figs = extract_figures_within(&root, &main, "", &graphics, Duration::ZERO).await;
+        assert_eq!(figs.len(), 1);
+        assert_eq!(figs[0].name, "b");
+        let _ = std::fs::remove_dir_all(root);
+    }
+
+    #[test]
+    fn adds_images_above_captions_with_subfigures() {
+        let md = "> **Figure 1:** One\n\n> **Figure 2:** Two\n\n> **Figure 12**\n";
+        let names = vec!["1".to_string(), "2a".to_string(), "2b".to_string()];
+        let out = add_figure_images(md, "1234.5678", &names, "");
+        assert!(out.contains(
+            "> ![Figure 1](https://markxiv.org/abs/1234.5678/figures/1)\n>\n> **Figure 1:** One"
+        ));
+        assert!(out.contains("> ![Figure 2a](https://markxiv.org/abs/1234.5678/figures/2a) ![Figure 2b](https://markxiv.org/abs/1234.5678/figures/2b)\n>\n> **Figure 2:** Two"));
+        assert!(out.contains("\n\n> **Figure 12**\n"));
+        assert!(!out.contains("figures/12"));
+    }
+}
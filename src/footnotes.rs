@@ -0,0 +1,187 @@
+use std::collections::BTreeMap;
+use std::ops::Range;
+
+use crate::authors;
+use crate::header::{FrontMatter, Thanks, ThanksOn};
+use crate::placeholder::Placeholders;
+use crate::tex_util::{
+    apply_edits, find_command, in_ranges, is_escaped, line_is_commented, math_ranges, read_arg,
+    read_opt_arg, tex_to_markdown_inline,
+};
+
+/// Commands that end a section's text: the next top-level division, the
+/// back matter and the end of the document.
+const SECTION_BREAKS: [&str; 8] = [
+    "part",
+    "chapter",
+    "section",
+    "appendix",
+    "bibliography",
+    "printbibliography",
+    "begin{thebibliography}",
+    "end{document}",
+];
+
+/// Turn `\footnote{...}` into GitHub footnotes: the mark becomes `[^n]`,
+/// numbered in document order, and the text becomes a `[^n]: ...`
+/// paragraph at the end of the enclosing section, still TeX for pandoc.
+pub fn render(tex: &str, ph: &mut Placeholders) -> String {
+    let math = math_ranges(tex);
+    let mut found: Vec<(Range<usize>, &str)> = Vec::new();
+    let mut cursor = 0;
+    while let Some(at) = find_command(tex, "footnote", cursor) {
+        cursor = at + "\\footnote".len();
+        if line_is_commented(tex, at) || in_ranges(&math, at) {
+            continue;
+        }
+        // The optional argument overrides LaTeX's number; ours are always
+        // sequential.
+        let pos = read_opt_arg(tex, cursor).map_or(cursor, |(_, end)| end);
+        let Some((text, end)) = read_arg(tex, pos) else {
+            continue;
+        };
+        cursor = end;
+        found.push((at..end, text));
+    }
+    if found.is_empty() {
+        return tex.to_string();
+    }
+    // A `\section` or `\appendix` mentioned inside a note doesn't end the
+    // section the note sits in.
+    let inside: Vec<Range<usize>> = found.iter().map(|(r, _)| r.clone()).collect();
+    let mut edits: Vec<(Range<usize>, String)> = Vec::new();
+    let mut notes: BTreeMap<usize, String> = BTreeMap::new();
+    for (n, (range, text)) in found.into_iter().enumerate() {
+        let n = n + 1;
+        let end = range.end;
+        edits.push((range, ph.inline(format!("[^{}]", n))));
+        // A definition is a single paragraph.
+        let text = text.split("\n\n").collect::<Vec<_>>().join(" ");
+        notes
+            .entry(section_end(tex, end, &inside))
+            .or_default()
+            .push_str(&format!(
+                "\n\n{} {}\n\n",
+                ph.inline(format!("[^{}]:", n)),
+                text
+            ));
+    }
+    edits.extend(notes.into_iter().map(|(at, notes)| (at..at, notes)));
+    apply_edits(tex, edits)
+}
+
+/// Where the section containing `from` ends: the first section break after
+/// it outside the `skip` ranges, or the end of the text.
+fn section_end(tex: &str, from: usize, skip: &[Range<usize>]) -> usize {
+    SECTION_BREAKS
+        .iter()
+        .filter_map(|name| {
+            let mut cursor = from;
+            while let Some(at) = find_command(tex, name, cursor) {
+                if !line_is_commented(tex, at) && !in_ranges(skip, at) {
+                    return Some(at);
+                }
+                cursor = at + 1;
+            }
+            None
+        })
+        .min()
+        .unwrap_or(tex.len())
+}
+
+/// Remove the `\thanks{...}` notes of the title and author block, which
+/// pandoc only keeps as metadata, returning the TeX and the notes in order,
+/// without repeats, with the title or author each was given on.
+pub fn take_thanks(tex: &str) -> (String, Vec<Thanks>) {
+    let mut out = String::with_capacity(tex.len());
+    let mut front = FrontMatter::default();
+    let mut last = 0;
+    let mut cursor = 0;
+    while let Some(at) = find_command(tex, "thanks", cursor) {
+        cursor = at + "\\thanks".len();
+        if line_is_commented(tex, at) {
+            continue;
+        }
+        let Some((text, end)) = read_arg(tex, cursor) else {
+            continue;
+        };
+        cursor = end;
+        out.push_str(&tex[last..at]);
+        last = end;
+        let text = tex_to_markdown_inline(text).trim().to_string();
+        if !text.is_empty() {
+            // Earlier notes are already gone from `out`, which ends where
+            // this one was.
+            let on = thanks_on(&out).into_iter().collect();
+            front.add_thanks(vec![Thanks { text, on }]);
+        }
+    }
+    out.push_str(&tex[last..]);
+    (out, front.thanks)
+}
+
+/// What a `\thanks` at the end of `before` was given on: the title, or the
+/// author whose name it follows.
+fn thanks_on(before: &str) -> Option<ThanksOn> {
+    let (command, arg_start) = enclosing_command(before)?;
+    if command.contains("title") {
+        Some(ThanksOn::Title)
+    } else if command.contains("author") {
+        let name = authors::trailing_name(&before[arg_start..]);
+        (!name.is_empty()).then_some(ThanksOn::Author(name))
+    } else {
+        None
+    }
+}
+
+/// The command whose still open `{...}` argument `tex` ends in, and where
+/// that argument's text starts.
+fn enclosing_command(tex: &str) -> Option<(&str, usize)> {
+    let bytes = tex.as_bytes();
+    let mut depth = 0usize;
+    let mut i = tex.len();
+    let open = loop {
+        i = i.checked_sub(1)?;
+        match bytes[i] {
+            b'}' if !is_escaped(tex, i) => depth += 1,
+            b'{' if !is_escaped(tex, i) => match depth.checked_sub(1) {
+                Some(d) => depth = d,
+                None => break i,
+            },
+            _ => {}
+        }
+    };
+    let mut end = tex[..open].trim_end().len();
+    if tex[..end].ends_with(']') {
+        end = tex[..tex[..end].rfind('[')?].trim_end().len();
+    }
+    let start = tex[..end]
+        .trim_end_matches(|c: char| c.is_ascii_alphabetic())
+        .len();
+    (start < end && tex[..start].ends_with('\\')).then(|| (&tex[start..end], open + 1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn thanks_are_taken_out_of_the_author_block() {
+        let tex = "\\title{T\\thanks{Funded by \\emph{X}.}}\n\\author{A\\thanks{Equal contribution.} \\and B\\thanks{Equal contribution.}}";
+        let (out, thanks) = take_thanks(tex);
+        assert_eq!(out, "\\title{T}\n\\author{A \\and B}");
+        assert_eq!(
+            thanks,
+            vec![
+                Thanks {
+                    text: "Funded by *X*.".into(),
+                    on: vec![ThanksOn::Title],
+                },
+                Thanks {
+                    text: "Equal contribution.".into(),
+                    on: vec![ThanksOn::Author("A".into()), ThanksOn::Author("B".into())],
+                },
+            ]
+        );
+    }
+}
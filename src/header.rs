@@ -0,0 +1,362 @@
+use crate::arxiv::Metadata;
+use crate::authors::{self, Author};
+use crate::eprint::LegacyFormat;
+use crate::headings;
+
+/// How a paper's Markdown body was produced.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BodySource {
+    /// Converted from the LaTeX source.
+    Latex,
+    /// Extracted from the PDF because the source was missing or failed to
+    /// convert.
+    PdfText,
+    /// Converted from a PostScript or DVI e-print by way of a PDF.
+    Legacy(LegacyFormat),
+}
+
+/// Where a `\thanks` note was given.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ThanksOn {
+    Title,
+    /// An author, by the name the source gives them.
+    Author(String),
+}
+
+/// A `\thanks` note of the title block.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Thanks {
+    /// The note, as Markdown.
+    pub text: String,
+    /// Everywhere the same note was given; empty when it was outside the
+    /// title and the author names.
+    pub on: Vec<ThanksOn>,
+}
+
+/// Header details only the LaTeX source has, gathered while converting it.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FrontMatter {
+    /// The title and author `\thanks` notes.
+    pub thanks: Vec<Thanks>,
+    /// Authors with their affiliations, from the title block.
+    pub authors: Vec<Author>,
+}
+
+impl FrontMatter {
+    /// Add `\thanks` notes, merging repeats of the same text.
+    pub fn add_thanks(&mut self, thanks: Vec<Thanks>) {
+        for note in thanks {
+            match self.thanks.iter_mut().find(|t| t.text == note.text) {
+                Some(known) => {
+                    for on in note.on {
+                        if !known.on.contains(&on) {
+                            known.on.push(on);
+                        }
+                    }
+                }
+                None => self.thanks.push(note),
+            }
+        }
+    }
+}
+
+/// Provenance note put under the header of PDF-extracted papers.
+pub const PDF_TEXT_NOTE: &str = "> **Note:** The LaTeX source for this paper was unavailable or could not be converted, so the text below was extracted from the PDF. Math, tables and some formatting may be lost.";
+
+/// Provenance note for papers whose e-print is PostScript or DVI only.
+pub fn legacy_note(format: LegacyFormat) -> String {
+    format!(
+        "> **Note:** The e-print for this paper is {} only, so the text below was converted with {}. Math, tables and some formatting may be lost.",
+        format.name(),
+        format.route()
+    )
+}
+
+/// Put the `# Title`, `## Authors` and `## Abstract` header above the
+/// body, the same for every paper, with the authors' affiliations and the
+/// `\thanks` notes from the source, followed by [`PDF_TEXT_NOTE`] when the
+/// body came from the PDF, or [`legacy_note`] for PostScript and DVI.
+/// The notes are `[^tN]` footnotes on the title or author they were given
+/// on, defined after the header.
+/// The body's headings are [`headings::normalize`]d to fit under the title.
+pub fn prepend_metadata(
+    meta: Option<&Metadata>,
+    front: &FrontMatter,
+    body_md: &str,
+    source: BodySource,
+) -> String {
+    let mut out = String::new();
+    let title = meta.map_or(String::new(), |m| {
+        strip_html_tags(&m.title).trim().to_string()
+    });
+    let abstract_text = meta.map_or(String::new(), |m| {
+        strip_html_tags(&m.summary).trim().to_string()
+    });
+    // Without arXiv metadata the authors and notes found in the LaTeX
+    // still make a header.
+    let names: Vec<String> = meta
+        .map(|m| m.authors.as_slice())
+        .unwrap_or_default()
+        .iter()
+        .map(|a| strip_html_tags(a).trim().to_string())
+        .filter(|a| !a.is_empty())
+        .collect();
+    let authors = authors::merge(&names, &front.authors);
+    let marks = ThanksMarks::place(&front.thanks, !title.is_empty(), &authors);
+    if !title.is_empty() {
+        out.push_str("# ");
+        out.push_str(&title);
+        out.push_str(&marks.title);
+        out.push_str("\n\n");
+    }
+    if !authors.is_empty() {
+        let names: Vec<String> = authors
+            .iter()
+            .zip(&marks.authors)
+            .map(|(author, marks)| format!("{}{}", author, marks))
+            .collect();
+        out.push_str("## Authors\n");
+        out.push_str(&names.join(", "));
+        out.push_str(&marks.line);
+        out.push_str("\n\n");
+    }
+    if !abstract_text.is_empty() {
+        out.push_str("## Abstract\n");
+        out.push_str(&abstract_text);
+        out.push_str("\n\n");
+    }
+    if !front.thanks.is_empty() {
+        for (i, note) in front.thanks.iter().enumerate() {
+            // With neither title nor authors there is nothing to mark.
+            if title.is_empty() && authors.is_empty() {
+                out.push_str(&format!("- {}\n", note.text));
+            } else {
+                out.push_str(&format!("[^t{}]: {}\n", i + 1, note.text));
+            }
+        }
+        out.push('\n');
+    }
+    match source {
+        BodySource::Latex => {}
+        BodySource::PdfText => {
+            out.push_str(PDF_TEXT_NOTE);
+            out.push_str("\n\n");
+        }
+        BodySource::Legacy(format) => {
+            out.push_str(&legacy_note(format));
+            out.push_str("\n\n");
+        }
+    }
+    out.push_str(&headings::normalize(body_md));
+    out
+}
+
+/// The `[^tN]` markers of the `\thanks` notes, by where they go.
+struct ThanksMarks {
+    title: String,
+    /// One per header author.
+    authors: Vec<String>,
+    /// After the author list, for notes without a place of their own when
+    /// there is no title.
+    line: String,
+}
+
+impl ThanksMarks {
+    fn place(thanks: &[Thanks], has_title: bool, authors: &[Author]) -> Self {
+        let mut marks = ThanksMarks {
+            title: String::new(),
+            authors: vec![String::new(); authors.len()],
+            line: String::new(),
+        };
+        for (i, note) in thanks.iter().enumerate() {
+            let mark = format!("[^t{}]", i + 1);
+            let mut placed = false;
+            for on in &note.on {
+                match on {
+                    ThanksOn::Title if has_title => {
+                        marks.title.push_str(&mark);
+                        placed = true;
+                    }
+                    ThanksOn::Title => {}
+                    ThanksOn::Author(name) => {
+                        for (author, author_marks) in authors.iter().zip(&mut marks.authors) {
+                            if authors::same_person(name, &author.name)
+                                && !author_marks.contains(&mark)
+                            {
+                                author_marks.push_str(&mark);
+                                placed = true;
+                            }
+                        }
+                    }
+                }
+            }
+            if !placed {
+                if has_title {
+                    marks.title.push_str(&mark);
+                } else {
+                    marks.line.push_str(&mark);
+                }
+            }
+        }
+        marks
+    }
+}
+
+fn strip_html_tags(input: &str) -> String {
+    let mut out = String::with_capacity(input.len());
+    let mut in_tag = false;
+    let chars = input.chars();
+    for ch in chars {
+        match ch {
+            '<' => {
+                in_tag = true;
+            }
+            '>' => {
+                if in_tag {
+                    in_tag = false;
+                } else {
+                    out.push(ch);
+                }
+            }
+            _ => {
+                if !in_tag {
+                    out.push(ch);
+                }
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn meta() -> Metadata {
+        Metadata {
+            title: "Sample Title".into(),
+            summary: "Sample abstract".into(),
+            authors: vec!["Alice Example".into(), "Bob <i>Author</i>".into()],
+        }
+    }
+
+    #[test]
+    fn prepend_metadata_includes_authors_section() {
+        let out = prepend_metadata(
+            Some(&meta()),
+            &FrontMatter::default(),
+            "Body",
+            BodySource::Latex,
+        );
+        assert_eq!(out, "# Sample Title\n\n## Authors\nAlice Example, Bob Author\n\n## Abstract\nSample abstract\n\nBody");
+    }
+
+    #[test]
+    fn pdf_text_gets_the_same_header_and_a_note() {
+        let out = prepend_metadata(
+            Some(&meta()),
+            &FrontMatter::default(),
+            "Body",
+            BodySource::PdfText,
+        );
+        assert_eq!(
+            out,
+            format!("# Sample Title\n\n## Authors\nAlice Example, Bob Author\n\n## Abstract\nSample abstract\n\n{}\n\nBody", PDF_TEXT_NOTE)
+        );
+        let out = prepend_metadata(None, &FrontMatter::default(), "Body", BodySource::PdfText);
+        assert_eq!(out, format!("{}\n\nBody", PDF_TEXT_NOTE));
+    }
+
+    #[test]
+    fn legacy_eprints_name_their_route() {
+        let out = prepend_metadata(
+            None,
+            &FrontMatter::default(),
+            "Body",
+            BodySource::Legacy(LegacyFormat::Dvi),
+        );
+        assert!(out.starts_with("> **Note:** The e-print for this paper is DVI only"));
+        assert!(out.contains("dvipdf → pdftotext"));
+        assert!(out.ends_with("\n\nBody"));
+    }
+
+    fn note(text: &str, on: Vec<ThanksOn>) -> Thanks {
+        Thanks {
+            text: text.into(),
+            on,
+        }
+    }
+
+    #[test]
+    fn thanks_notes_are_footnotes_on_their_title_or_author() {
+        let front = FrontMatter {
+            thanks: vec![
+                note("Funded by X.", vec![ThanksOn::Title]),
+                note(
+                    "Equal contribution.",
+                    vec![
+                        ThanksOn::Author("A. Example".into()),
+                        ThanksOn::Author("B. Author".into()),
+                    ],
+                ),
+                note("Work done at Y.", Vec::new()),
+            ],
+            ..FrontMatter::default()
+        };
+        let out = prepend_metadata(Some(&meta()), &front, "Body", BodySource::Latex);
+        assert_eq!(
+            out,
+            "# Sample Title[^t1][^t3]\n\n## Authors\nAlice Example[^t2], Bob Author[^t2]\n\n## Abstract\nSample abstract\n\n[^t1]: Funded by X.\n[^t2]: Equal contribution.\n[^t3]: Work done at Y.\n\nBody"
+        );
+    }
+
+    #[test]
+    fn thanks_and_source_authors_stand_in_for_missing_metadata() {
+        let front = FrontMatter {
+            thanks: vec![note("Funded by X.", Vec::new())],
+            authors: vec![Author {
+                name: "A. Example".into(),
+                affiliations: Vec::new(),
+                email: None,
+            }],
+        };
+        let out = prepend_metadata(None, &front, "Body", BodySource::Latex);
+        assert_eq!(
+            out,
+            "## Authors\nA. Example[^t1]\n\n[^t1]: Funded by X.\n\nBody"
+        );
+        let front = FrontMatter {
+            thanks: vec![note("Funded by X.", Vec::new())],
+            ..FrontMatter::default()
+        };
+        let out = prepend_metadata(None, &front, "Body", BodySource::PdfText);
+        assert_eq!(out, format!("- Funded by X.\n\n{}\n\nBody", PDF_TEXT_NOTE));
+    }
+
+    #[test]
+    fn affiliations_from_the_source_join_arxiv_names() {
+        let front = FrontMatter {
+            authors: vec![Author {
+                name: "A. Example".into(),
+                affiliations: vec!["MIT".into()],
+                email: Some("alice@mit.edu".into()),
+            }],
+            ..FrontMatter::default()
+        };
+        let out = prepend_metadata(Some(&meta()), &front, "Body", BodySource::Latex);
+        assert!(out.contains("## Authors\nAlice Example (MIT; alice@mit.edu), Bob Author\n\n"));
+    }
+
+    #[test]
+    fn body_sections_sit_below_the_title() {
+        let out = prepend_metadata(
+            Some(&meta()),
+            &FrontMatter::default(),
+            "# Introduction\n\nText",
+            BodySource::Latex,
+        );
+        assert!(
+            out.contains("Sample abstract\n\n## Introduction<a id=\"introduction\"></a>\n\nText")
+        );
+    }
+}
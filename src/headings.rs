@@ -0,0 +1,233 @@
+use std::collections::HashSet;
+use std::sync::LazyLock;
+
+use regex::Regex;
+
+use crate::markdown::code_ranges;
+use crate::tex_util::anchor_id;
+
+static ANCHOR_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"<a id="([^"]+)"></a>"#).unwrap());
+static ID_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r#"\sid="([^"]+)""#).unwrap());
+static LINK_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"!?\[([^\]]*)\]\([^)]*\)").unwrap());
+
+/// The level body headings start at, one below the paper's `# Title`.
+const TOP_LEVEL: usize = 2;
+
+/// An ATX heading on one line of a document.
+pub(crate) struct Heading<'a> {
+    pub(crate) level: usize,
+    /// The heading text, without its `#`s and any anchor.
+    pub(crate) text: &'a str,
+    pub(crate) anchor: Option<&'a str>,
+}
+
+/// Parse `line` (without its newline) as an ATX heading.
+pub(crate) fn parse_heading(line: &str) -> Option<Heading<'_>> {
+    let indent = line.len() - line.trim_start_matches(' ').len();
+    if indent > 3 {
+        return None;
+    }
+    let rest = &line[indent..];
+    let level = rest.len() - rest.trim_start_matches('#').len();
+    let rest = &rest[level..];
+    if !(1..=6).contains(&level) || !(rest.is_empty() || rest.starts_with([' ', '\t'])) {
+        return None;
+    }
+    let mut text = rest.trim();
+    // A closing sequence of `#`s is not part of the text.
+    let unclosed = text.trim_end_matches('#');
+    if unclosed.is_empty() || unclosed.ends_with([' ', '\t']) {
+        text = unclosed.trim_end();
+    }
+    let anchor = ANCHOR_RE.captures(text).map(|c| c.get(1).unwrap().as_str());
+    let text = match ANCHOR_RE.find(text) {
+        Some(m) if m.end() == text.len() => text[..m.start()].trim_end(),
+        _ => text,
+    };
+    Some(Heading {
+        level,
+        text,
+        anchor,
+    })
+}
+
+/// Lines of `md` with their byte offsets, skipping those inside code.
+pub(crate) fn lines_outside_code(md: &str) -> Vec<(usize, &str)> {
+    let code = code_ranges(md);
+    let mut lines = Vec::new();
+    let mut pos = 0;
+    for line in md.split_inclusive('\n') {
+        if !code.iter().any(|r| r.contains(&pos)) {
+            lines.push((pos, line));
+        }
+        pos += line.len();
+    }
+    lines
+}
+
+/// Fit a converted body under the paper's `# Title`: headings are shifted
+/// so the top-level divisions (`\part`, else `\chapter`, else `\section`)
+/// are `##` and the rest keep their depth below them. Headings that would
+/// go deeper than `######` (a `\paragraph` under parts, say) become bold
+/// run-in lines. Every heading gets an `<a id>` anchor unique in the
+/// document, keeping the one cross-references already point at.
+pub fn normalize(body: &str) -> String {
+    let lines = lines_outside_code(body);
+    let Some(top) = lines
+        .iter()
+        .filter_map(|(_, line)| parse_heading(line.trim_end_matches(['\n', '\r'])))
+        .map(|h| h.level)
+        .min()
+    else {
+        return body.to_string();
+    };
+    let mut used: HashSet<String> = ID_RE
+        .captures_iter(body)
+        .map(|c| c[1].to_string())
+        .collect();
+    let mut out = String::with_capacity(body.len() + body.len() / 16);
+    let mut copied = 0;
+    for (i, &(pos, line)) in lines.iter().enumerate() {
+        let content = line.trim_end_matches(['\n', '\r']);
+        let Some(heading) = parse_heading(content) else {
+            continue;
+        };
+        out.push_str(&body[copied..pos]);
+        copied = pos + content.len();
+        let anchor = match (heading.anchor, label_anchor(&lines[i + 1..])) {
+            (Some(id), _) => id.to_string(),
+            // A `\label` anchor pandoc put in a paragraph of its own moves
+            // into the heading.
+            (None, Some((id, end))) => {
+                copied = end;
+                id.to_string()
+            }
+            (None, None) => unique_anchor(heading.text, &mut used),
+        };
+        let level = heading.level + TOP_LEVEL - top;
+        if level <= 6 {
+            out.push_str(&"#".repeat(level));
+            out.push(' ');
+            out.push_str(heading.text);
+        } else {
+            out.push_str("**");
+            out.push_str(heading.text);
+            out.push_str("**");
+        }
+        out.push_str(&format!("<a id=\"{}\"></a>", anchor));
+    }
+    out.push_str(&body[copied..]);
+    out
+}
+
+/// The id of an anchor alone in the paragraph after a heading, and the
+/// end of its line, where the heading's line should now end.
+fn label_anchor<'a>(next: &[(usize, &'a str)]) -> Option<(&'a str, usize)> {
+    let [(_, blank), (pos, line), rest @ ..] = next else {
+        return None;
+    };
+    let content = line.trim_end_matches(['\n', '\r']);
+    let m = ANCHOR_RE.captures(content.trim())?;
+    let alone = blank.trim().is_empty()
+        && m.get(0)?.len() == content.trim().len()
+        && rest.first().is_none_or(|(_, l)| l.trim().is_empty());
+    alone.then(|| (m.get(1).unwrap().as_str(), pos + content.len()))
+}
+
+/// A slug for `text` not yet in `used`, which it is added to.
+fn unique_anchor(text: &str, used: &mut HashSet<String>) -> String {
+    let mut base = anchor_id("", &LINK_RE.replace_all(text, "$1"));
+    if base.is_empty() {
+        base = "section".to_string();
+    }
+    let mut slug = base.clone();
+    let mut n = 1;
+    while used.contains(&slug) {
+        slug = format!("{}-{}", base, n);
+        n += 1;
+    }
+    used.insert(slug.clone());
+    slug
+}
+
+/// Insert a `## Contents` list linking to every anchored heading (the
+/// body's, once [`normalize`]d) in front of the first of them. Documents
+/// without anchored headings are returned unchanged.
+pub fn insert_toc(md: &str) -> String {
+    let mut first = None;
+    let mut entries = Vec::new();
+    for (pos, line) in lines_outside_code(md) {
+        let Some(heading) = parse_heading(line.trim_end_matches(['\n', '\r'])) else {
+            continue;
+        };
+        let Some(anchor) = heading.anchor else {
+            continue;
+        };
+        first.get_or_insert(pos);
+        entries.push((
+            heading.level,
+            LINK_RE.replace_all(heading.text, "$1"),
+            anchor,
+        ));
+    }
+    let Some(first) = first else {
+        return md.to_string();
+    };
+    let top = entries
+        .iter()
+        .map(|(level, _, _)| *level)
+        .min()
+        .unwrap_or(1);
+    let mut toc = String::from("## Contents\n\n");
+    for (level, text, anchor) in &entries {
+        toc.push_str(&"  ".repeat(level - top));
+        toc.push_str(&format!("- [{}](#{})\n", text, anchor));
+    }
+    toc.push('\n');
+    format!("{}{}{}", &md[..first], toc, &md[first..])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sections_go_below_the_title_with_unique_anchors() {
+        let body = "# 1 Introduction\n\n<a id=\"section-1\"></a>\n\nText.\n\n## Setup\n\n# Results\n\n## Setup\n\n```\n# not a heading\n```\n";
+        assert_eq!(
+            normalize(body),
+            "## 1 Introduction<a id=\"section-1\"></a>\n\nText.\n\n### Setup<a id=\"setup\"></a>\n\n## Results<a id=\"results\"></a>\n\n### Setup<a id=\"setup-1\"></a>\n\n```\n# not a heading\n```\n"
+        );
+    }
+
+    #[test]
+    fn parts_and_chapters_keep_their_depth() {
+        // \part, \chapter, \section, \subsection, \subsubsection, \paragraph
+        let body = "# Part I\n\n## Chapter\n\n### Section\n\n#### Sub\n\n##### Subsub\n\n###### Paragraph\n\nRun-in text.";
+        let out = normalize(body);
+        assert!(out.starts_with("## Part I<a id=\"part-i\"></a>\n\n### Chapter"));
+        assert!(out.contains("###### Subsub<a id=\"subsub\"></a>"));
+        assert!(out.contains("**Paragraph**<a id=\"paragraph\"></a>\n\nRun-in text."));
+
+        // Deeper bodies are lifted to the same place.
+        assert_eq!(
+            normalize("### A\n\n#### B"),
+            "## A<a id=\"a\"></a>\n\n### B<a id=\"b\"></a>"
+        );
+    }
+
+    #[test]
+    fn toc_lists_body_headings_after_the_header() {
+        let md = format!(
+            "# Title\n\n## Abstract\nText\n\n{}",
+            normalize("# Intro\n\n## Why [1](#ref-1)\n\n# Method")
+        );
+        let out = insert_toc(&md);
+        assert!(out.starts_with("# Title\n\n## Abstract\nText\n\n## Contents\n\n- [Intro](#intro)\n  - [Why 1](#why-1)\n- [Method](#method)\n\n## Intro"));
+        assert_eq!(
+            insert_toc("# Title\n\nNo sections."),
+            "# Title\n\nNo sections."
+        );
+    }
+}
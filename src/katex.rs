@@ -0,0 +1,803 @@
+use std::collections::{BTreeSet, HashSet};
+use std::sync::LazyLock;
+
+use crate::markdown::math_spans;
+use crate::tex_util::group_end;
+
+/// Result of [`rewrite`]: the Markdown with KaTeX-compatible math, and the
+/// commands that had no KaTeX equivalent and were left as written.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Rewritten {
+    pub markdown: String,
+    /// Sorted, without duplicates, with the leading backslash.
+    pub unsupported: Vec<String>,
+}
+
+/// Rewrite the `$...$` and `$$...$$` math in `md` so KaTeX can render it.
+///
+/// Math is tokenized into commands, groups and characters, so arguments
+/// with nested braces are handled. Commands KaTeX lacks but that have a
+/// close equivalent (`\mathbbm`, `\bm`, `\textsc`, `\nicefrac`, siunitx's
+/// `\SI`, the physics package's `\qty`, ...) are rewritten; anything else
+/// KaTeX doesn't know is kept and reported.
+pub fn rewrite(md: &str) -> Rewritten {
+    let mut unsupported = BTreeSet::new();
+    let mut out = String::with_capacity(md.len());
+    let mut last = 0;
+    for span in math_spans(md) {
+        out.push_str(&md[last..span.start]);
+        out.push_str(&rewrite_math(&md[span.clone()], &mut unsupported));
+        last = span.end;
+    }
+    out.push_str(&md[last..]);
+    Rewritten {
+        markdown: out,
+        unsupported: unsupported.into_iter().collect(),
+    }
+}
+
+/// A command name starting at `at` (a backslash) and the index after it.
+/// Control symbols such as `\{` or `\,` have a one-character name.
+fn command(s: &str, at: usize) -> (&str, usize) {
+    let rest = &s[at + 1..];
+    let len = rest.bytes().take_while(|b| b.is_ascii_alphabetic()).count();
+    let len = if len == 0 {
+        rest.chars().next().map(char::len_utf8).unwrap_or(0)
+    } else {
+        len
+    };
+    (&rest[..len], at + 1 + len)
+}
+
+/// Read one macro argument at `pos`: a braced group (returned without its
+/// braces), a command, or a single character.
+fn arg(s: &str, pos: usize) -> Option<(&str, usize)> {
+    let start = pos + (s[pos..].len() - s[pos..].trim_start().len());
+    let c = s[start..].chars().next()?;
+    match c {
+        '{' => {
+            let end = group_end(s, start)?;
+            Some((&s[start + 1..end - 1], end))
+        }
+        '\\' => {
+            let (_, end) = command(s, start);
+            Some((&s[start..end], end))
+        }
+        '}' => None,
+        _ => Some((&s[start..start + c.len_utf8()], start + c.len_utf8())),
+    }
+}
+
+/// Read `n` arguments, or none if any is missing.
+fn args(s: &str, pos: usize, n: usize) -> Option<(Vec<&str>, usize)> {
+    let mut out = Vec::with_capacity(n);
+    let mut pos = pos;
+    for _ in 0..n {
+        let (a, e) = arg(s, pos)?;
+        out.push(a);
+        pos = e;
+    }
+    Some((out, pos))
+}
+
+/// An optional `[...]` argument directly at `pos`.
+fn opt_arg(s: &str, pos: usize) -> Option<(&str, usize)> {
+    if !s[pos..].starts_with('[') {
+        return None;
+    }
+    let end = s[pos..].find(']')?;
+    Some((&s[pos + 1..pos + end], pos + end + 1))
+}
+
+fn rewrite_math(s: &str, unsupported: &mut BTreeSet<String>) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut i = 0;
+    while i < s.len() {
+        let c = s[i..].chars().next().unwrap();
+        match c {
+            '\\' => {
+                let (name, after) = command(s, i);
+                if !name.starts_with(|c: char| c.is_ascii_alphabetic()) {
+                    out.push_str(&s[i..after]);
+                    i = after;
+                    continue;
+                }
+                if let Some((replacement, end)) = expand(name, s, after, unsupported) {
+                    out.push_str(&replacement);
+                    i = end;
+                    continue;
+                }
+                if !SUPPORTED.contains(name) {
+                    unsupported.insert(format!("\\{}", name));
+                }
+                out.push_str(&s[i..after]);
+                i = after;
+            }
+            '{' => match group_end(s, i) {
+                Some(end) => {
+                    out.push('{');
+                    out.push_str(&rewrite_math(&s[i + 1..end - 1], unsupported));
+                    out.push('}');
+                    i = end;
+                }
+                None => {
+                    out.push('{');
+                    i += 1;
+                }
+            },
+            _ => {
+                out.push(c);
+                i += c.len_utf8();
+            }
+        }
+    }
+    out
+}
+
+/// Commands renamed to a KaTeX equivalent taking the same arguments.
+const RENAMES: &[(&str, &str)] = &[
+    ("mathbbm", "mathbb"),
+    ("mathds", "mathbb"),
+    ("mathbbold", "mathbb"),
+    ("bm", "boldsymbol"),
+    ("bmath", "boldsymbol"),
+    ("mathbold", "boldsymbol"),
+    ("mathbfit", "boldsymbol"),
+    ("textsc", "textbf"),
+    ("textsl", "textit"),
+    ("hdots", "ldots"),
+];
+
+/// Argument-less commands replaced by a KaTeX expression.
+const SYMBOLS: &[(&str, &str)] = &[
+    ("coloneqq", r"\mathrel{:=}"),
+    ("coloneq", r"\mathrel{:-}"),
+    ("Coloneqq", r"\mathrel{::=}"),
+    ("eqqcolon", r"\mathrel{=:}"),
+    ("eqcolon", r"\mathrel{-:}"),
+    ("vcentcolon", ":"),
+    ("lparen", "("),
+    ("rparen", ")"),
+    ("eqdef", r"\stackrel{\text{def}}{=}"),
+    ("defeq", r"\stackrel{\text{def}}{=}"),
+    ("argmin", r"\operatorname*{arg\,min}"),
+    ("argmax", r"\operatorname*{arg\,max}"),
+    ("sgn", r"\operatorname{sgn}"),
+    ("sign", r"\operatorname{sign}"),
+    ("tr", r"\operatorname{tr}"),
+    ("Tr", r"\operatorname{Tr}"),
+    ("rank", r"\operatorname{rank}"),
+    ("diag", r"\operatorname{diag}"),
+    ("Var", r"\operatorname{Var}"),
+    ("Cov", r"\operatorname{Cov}"),
+    ("independent", r"\perp\!\!\!\perp"),
+    ("indep", r"\perp\!\!\!\perp"),
+    ("grad", r"\nabla"),
+    ("curl", r"\nabla\times"),
+    ("divergence", r"\nabla\cdot"),
+    ("dd", r"\mathrm{d}"),
+    ("xspace", ""),
+];
+
+/// Rewrite one command (and its arguments) starting before `after`.
+/// Returns the replacement and where scanning continues, or `None` when the
+/// command is left alone.
+fn expand(
+    name: &str,
+    s: &str,
+    after: usize,
+    unsupported: &mut BTreeSet<String>,
+) -> Option<(String, usize)> {
+    let mut sub = |tex: &str| rewrite_math(tex, unsupported);
+    if let Some((_, to)) = RENAMES.iter().find(|(from, _)| *from == name) {
+        return Some((format!("\\{}", to), after));
+    }
+    if let Some((_, to)) = SYMBOLS.iter().find(|(from, _)| *from == name) {
+        // Keep a separating space when the next token is a letter.
+        let glue = if s[after..].starts_with(|c: char| c.is_ascii_alphabetic())
+            && to.ends_with(|c: char| c.is_ascii_alphabetic())
+        {
+            " "
+        } else {
+            ""
+        };
+        return Some((format!("{}{}", to, glue), after));
+    }
+    match name {
+        // `\mathcal{X}{Y}` is a common typo for `\mathcal{X}_{Y}`.
+        "mathcal" => {
+            let (x, end) = arg(s, after)?;
+            if !s[end..].starts_with('{') {
+                return None;
+            }
+            Some((format!("\\mathcal{{{}}}_", sub(x)), end))
+        }
+        "Call" => {
+            let (a, end) = args(s, after, 2)?;
+            Some((format!("\\textbf{{{}}}({})", sub(a[0]), sub(a[1])), end))
+        }
+        "nicefrac" | "sfrac" | "xfrac" => {
+            let mut pos = after;
+            if let Some((_, e)) = opt_arg(s, pos) {
+                pos = e;
+            }
+            let (a, end) = args(s, pos, 2)?;
+            Some((
+                format!("{{}}^{{{}}}\\!/{{}}_{{{}}}", sub(a[0]), sub(a[1])),
+                end,
+            ))
+        }
+        "ensuremath" => {
+            let (x, end) = arg(s, after)?;
+            Some((sub(x), end))
+        }
+        "label" => {
+            let (_, end) = arg(s, after)?;
+            Some((String::new(), end))
+        }
+        "textsuperscript" | "textsubscript" => {
+            let (x, end) = arg(s, after)?;
+            let op = if name == "textsuperscript" { '^' } else { '_' };
+            Some((format!("{}{{\\text{{{}}}}}", op, sub(x)), end))
+        }
+        // siunitx
+        "num" => {
+            let (x, end) = arg(s, skip_opt(s, after))?;
+            Some((number(x), end))
+        }
+        "si" | "unit" => {
+            let (u, end) = arg(s, skip_opt(s, after))?;
+            Some((units(u), end))
+        }
+        "SI" => {
+            let pos = skip_opt(s, after);
+            let (v, e1) = arg(s, pos)?;
+            let e1 = skip_opt(s, e1);
+            let (u, end) = arg(s, e1)?;
+            Some((format!("{}\\,{}", number(v), units(u)), end))
+        }
+        "SIrange" | "qtyrange" => {
+            let (a, end) = args(s, skip_opt(s, after), 3)?;
+            let u = units(a[2]);
+            Some((
+                format!(
+                    "{}\\,{}\\text{{ to }}{}\\,{}",
+                    number(a[0]),
+                    u,
+                    number(a[1]),
+                    u
+                ),
+                end,
+            ))
+        }
+        "numrange" => {
+            let (a, end) = args(s, skip_opt(s, after), 2)?;
+            Some((
+                format!("{}\\text{{ to }}{}", number(a[0]), number(a[1])),
+                end,
+            ))
+        }
+        "ang" => {
+            let (x, end) = arg(s, skip_opt(s, after))?;
+            Some((format!("{}^\\circ", number(x)), end))
+        }
+        // `\qty{1}{\metre}` is siunitx; `\qty(x)` and `\qty{x}` are physics.
+        // Only a second braced group makes it a quantity.
+        "qty" => {
+            let pos = skip_opt(s, after);
+            let unit_follows = |(_, end): &(&str, usize)| s[*end..].trim_start().starts_with('{');
+            if let Some((v, end)) = arg(s, pos)
+                .filter(|_| s[pos..].starts_with('{'))
+                .filter(unit_follows)
+            {
+                let (u, end) = arg(s, end)?;
+                return Some((format!("{}\\,{}", number(v), units(u)), end));
+            }
+            let (open, close) = match s[after..].chars().next()? {
+                '(' => ("(", ")"),
+                '[' => ("[", "]"),
+                '|' => ("|", "|"),
+                '{' => ("\\{", "\\}"),
+                _ => return None,
+            };
+            let (inner, end) = delimited(s, after)?;
+            Some((format!("\\left{}{}\\right{}", open, sub(inner), close), end))
+        }
+        "pqty" | "bqty" | "Bqty" | "vqty" | "abs" | "norm" | "expval" => {
+            let (x, end) = arg(s, after)?;
+            let (open, close) = match name {
+                "pqty" => ("(", ")"),
+                "bqty" => ("[", "]"),
+                "Bqty" => ("\\{", "\\}"),
+                "norm" => ("\\|", "\\|"),
+                "expval" => ("\\langle", "\\rangle"),
+                _ => ("|", "|"),
+            };
+            Some((format!("\\left{} {} \\right{}", open, sub(x), close), end))
+        }
+        "comm" | "acomm" => {
+            let (a, end) = args(s, after, 2)?;
+            let (open, close) = if name == "comm" {
+                ("[", "]")
+            } else {
+                ("\\{", "\\}")
+            };
+            Some((
+                format!("\\left{}{},{}\\right{}", open, sub(a[0]), sub(a[1]), close),
+                end,
+            ))
+        }
+        "order" => {
+            let (x, end) = arg(s, after)?;
+            Some((format!("\\mathcal{{O}}\\left({}\\right)", sub(x)), end))
+        }
+        "vb" | "vu" => {
+            let (x, end) = arg(s, after)?;
+            let x = format!("\\mathbf{{{}}}", sub(x));
+            let x = if name == "vu" {
+                format!("\\hat{{{}}}", x)
+            } else {
+                x
+            };
+            Some((x, end))
+        }
+        // `\dv{f}{x}`, `\dv{x}`, `\pdv[2]{f}{x}`
+        "dv" | "pdv" => {
+            let d = if name == "dv" { "d" } else { "\\partial" };
+            let mut pos = after;
+            let mut order = None;
+            if let Some((n, e)) = opt_arg(s, pos) {
+                order = Some(n.trim().to_string());
+                pos = e;
+            }
+            let (first, e1) = arg(s, pos)?;
+            let power = order.map(|n| format!("^{{{}}}", n)).unwrap_or_default();
+            match arg(s, e1).filter(|_| s[e1..].trim_start().starts_with('{')) {
+                Some((second, end)) => Some((
+                    format!(
+                        "\\frac{{{d}{p} {}}}{{{d} {}{p}}}",
+                        sub(first),
+                        sub(second),
+                        d = d,
+                        p = power
+                    ),
+                    end,
+                )),
+                None => Some((
+                    format!(
+                        "\\frac{{{d}{p}}}{{{d} {}{p}}}",
+                        sub(first),
+                        d = d,
+                        p = power
+                    ),
+                    e1,
+                )),
+            }
+        }
+        _ => None,
+    }
+}
+
+fn skip_opt(s: &str, pos: usize) -> usize {
+    opt_arg(s, pos).map(|(_, e)| e).unwrap_or(pos)
+}
+
+/// Contents of a `(...)`, `[...]`, `|...|` or `{...}` group at `pos`,
+/// respecting nesting of the same delimiter.
+fn delimited(s: &str, pos: usize) -> Option<(&str, usize)> {
+    let open = s[pos..].chars().next()?;
+    if open == '{' {
+        let end = group_end(s, pos)?;
+        return Some((&s[pos + 1..end - 1], end));
+    }
+    let close = match open {
+        '(' => ')',
+        '[' => ']',
+        _ => open,
+    };
+    let mut depth = 0usize;
+    for (k, c) in s[pos + 1..].char_indices() {
+        if c == close && depth == 0 {
+            let end = pos + 1 + k;
+            return Some((&s[pos + 1..end], end + 1));
+        }
+        if c == open && open != close {
+            depth += 1;
+        } else if c == close {
+            depth -= 1;
+        }
+    }
+    None
+}
+
+/// A siunitx number: `1.5e-3` becomes `1.5\times10^{-3}`, `+-` becomes `\pm`.
+fn number(x: &str) -> String {
+    let x = x.trim().replace("+-", "\\pm ");
+    match x.split_once(['e', 'E']) {
+        Some((mantissa, exp))
+            if !exp.is_empty()
+                && exp
+                    .trim_start_matches(['-', '+'])
+                    .chars()
+                    .all(|c| c.is_ascii_digit()) =>
+        {
+            let exp = exp.trim_start_matches('+');
+            if mantissa.trim().is_empty() {
+                format!("10^{{{}}}", exp)
+            } else {
+                format!("{}\\times10^{{{}}}", mantissa.trim(), exp)
+            }
+        }
+        _ => x,
+    }
+}
+
+/// siunitx prefixes, spelled out.
+const UNIT_PREFIXES: &[(&str, &str)] = &[
+    ("yocto", "y"),
+    ("zepto", "z"),
+    ("atto", "a"),
+    ("femto", "f"),
+    ("pico", "p"),
+    ("nano", "n"),
+    ("micro", "\\mu "),
+    ("milli", "m"),
+    ("centi", "c"),
+    ("deci", "d"),
+    ("deca", "da"),
+    ("hecto", "h"),
+    ("kilo", "k"),
+    ("mega", "M"),
+    ("giga", "G"),
+    ("tera", "T"),
+    ("peta", "P"),
+    ("exa", "E"),
+];
+
+/// siunitx units, spelled out.
+const UNIT_NAMES: &[(&str, &str)] = &[
+    ("metre", "m"),
+    ("meter", "m"),
+    ("gram", "g"),
+    ("second", "s"),
+    ("minute", "min"),
+    ("hour", "h"),
+    ("day", "d"),
+    ("ampere", "A"),
+    ("kelvin", "K"),
+    ("mole", "mol"),
+    ("candela", "cd"),
+    ("hertz", "Hz"),
+    ("newton", "N"),
+    ("pascal", "Pa"),
+    ("joule", "J"),
+    ("watt", "W"),
+    ("coulomb", "C"),
+    ("volt", "V"),
+    ("farad", "F"),
+    ("ohm", "\\Omega"),
+    ("siemens", "S"),
+    ("weber", "Wb"),
+    ("tesla", "T"),
+    ("henry", "H"),
+    ("lumen", "lm"),
+    ("lux", "lx"),
+    ("becquerel", "Bq"),
+    ("gray", "Gy"),
+    ("sievert", "Sv"),
+    ("radian", "rad"),
+    ("steradian", "sr"),
+    ("litre", "L"),
+    ("liter", "L"),
+    ("tonne", "t"),
+    ("electronvolt", "eV"),
+    ("dalton", "Da"),
+    ("bel", "B"),
+    ("decibel", "dB"),
+    ("neper", "Np"),
+    ("bit", "bit"),
+    ("byte", "B"),
+    ("percent", "\\%"),
+    ("degree", "^\\circ"),
+    ("degreeCelsius", "^\\circ C"),
+    ("celsius", "^\\circ C"),
+    ("arcminute", "'"),
+    ("arcsecond", "''"),
+    ("angstrom", "\\mathring{A}"),
+    ("astronomicalunit", "au"),
+    ("hectare", "ha"),
+    ("bar", "bar"),
+    ("barn", "b"),
+    ("m", "m"),
+    ("g", "g"),
+    ("s", "s"),
+    ("kg", "kg"),
+    ("km", "km"),
+    ("cm", "cm"),
+    ("mm", "mm"),
+    ("um", "\\mu m"),
+    ("nm", "nm"),
+    ("ms", "ms"),
+    ("us", "\\mu s"),
+    ("ns", "ns"),
+    ("Hz", "Hz"),
+    ("kHz", "kHz"),
+    ("MHz", "MHz"),
+    ("GHz", "GHz"),
+    ("eV", "eV"),
+    ("keV", "keV"),
+    ("MeV", "MeV"),
+    ("GeV", "GeV"),
+    ("TeV", "TeV"),
+    ("K", "K"),
+    ("J", "J"),
+    ("W", "W"),
+    ("V", "V"),
+    ("A", "A"),
+    ("N", "N"),
+];
+
+/// A siunitx unit, e.g. `\kilo\gram\metre\per\second\squared`, as
+/// `\mathrm{kg\,m/s^{2}}`. Literal units (`m/s`, `kg.m`) pass through.
+fn units(u: &str) -> String {
+    let mut pieces: Vec<String> = Vec::new();
+    let mut prefix = String::new();
+    let mut per = false;
+    let mut power: Option<String> = None;
+    let mut literal = String::new();
+    let mut i = 0;
+    while i < u.len() {
+        let c = u[i..].chars().next().unwrap();
+        if c != '\\' {
+            literal.push(match c {
+                '.' | '~' => ' ',
+                _ => c,
+            });
+            i += c.len_utf8();
+            continue;
+        }
+        let (name, after) = command(u, i);
+        i = after;
+        match name {
+            "per" => per = true,
+            "square" => power = Some("2".into()),
+            "cubic" => power = Some("3".into()),
+            "squared" | "cubed" => {
+                if let Some(last) = pieces.last_mut() {
+                    last.push_str(if name == "squared" { "^{2}" } else { "^{3}" });
+                }
+            }
+            "tothe" | "raiseto" => {
+                if let Some((n, e)) = arg(u, i) {
+                    i = e;
+                    if name == "raiseto" {
+                        power = Some(n.to_string());
+                    } else if let Some(last) = pieces.last_mut() {
+                        last.push_str(&format!("^{{{}}}", n));
+                    }
+                }
+            }
+            _ => {
+                if let Some((_, sym)) = UNIT_PREFIXES.iter().find(|(n, _)| *n == name) {
+                    prefix.push_str(sym);
+                    continue;
+                }
+                let Some((_, sym)) = UNIT_NAMES.iter().find(|(n, _)| *n == name) else {
+                    literal.push_str(&u[i - name.len() - 1..i]);
+                    continue;
+                };
+                let mut piece = format!("{}{}", std::mem::take(&mut prefix), sym);
+                if let Some(p) = power.take() {
+                    piece.push_str(&format!("^{{{}}}", p));
+                }
+                if std::mem::take(&mut per) {
+                    piece.insert(0, '/');
+                }
+                pieces.push(piece);
+            }
+        }
+    }
+    let literal = literal.trim();
+    if pieces.is_empty() {
+        return format!("\\mathrm{{{}}}", literal.replace(' ', "\\,"));
+    }
+    let mut out = String::new();
+    for piece in pieces {
+        if !out.is_empty() && !piece.starts_with('/') {
+            out.push_str("\\,");
+        }
+        out.push_str(&piece);
+    }
+    format!("\\mathrm{{{}}}", out)
+}
+
+/// Commands KaTeX renders, without the leading backslash.
+static SUPPORTED: LazyLock<HashSet<&'static str>> = LazyLock::new(|| {
+    KATEX_COMMANDS
+        .split_whitespace()
+        .collect::<HashSet<&'static str>>()
+});
+
+const KATEX_COMMANDS: &str = "
+alpha beta gamma delta epsilon varepsilon zeta eta theta vartheta iota kappa
+varkappa lambda mu nu xi omicron pi varpi rho varrho sigma varsigma tau upsilon
+phi varphi chi psi omega Gamma Delta Theta Lambda Xi Pi Sigma Upsilon Phi Psi
+Omega varGamma varDelta varTheta varLambda varXi varPi varSigma varUpsilon
+varPhi varPsi varOmega digamma Alpha Beta Epsilon Zeta Eta Iota Kappa Mu Nu
+Omicron Rho Tau Chi
+aleph beth gimel daleth ell hbar hslash imath jmath wp Re Im partial nabla
+infty emptyset varnothing forall exists nexists neg lnot top bot angle
+measuredangle sphericalangle prime backprime complement eth Finv Game mho
+diagdown diagup checkmark dag ddag dagger ddagger S P copyright pounds yen
+circledS circledR maltese degree flat natural sharp surd clubsuit diamondsuit
+heartsuit spadesuit triangle triangledown blacktriangle blacktriangledown
+blacktriangleleft blacktriangleright square blacksquare lozenge blacklozenge
+bigstar Box Diamond minuso
+sum prod coprod int iint iiint oint oiint oiiint intop smallint bigcup bigcap
+bigvee bigwedge bigoplus bigotimes bigodot biguplus bigsqcup
+pm mp times div cdot ast star circ bullet oplus ominus otimes oslash odot cup
+cap sqcup sqcap vee wedge lor land setminus smallsetminus wr amalg uplus
+triangleleft triangleright bigtriangleup bigtriangledown diamond ltimes rtimes
+leftthreetimes rightthreetimes boxplus boxminus boxtimes boxdot circleddash
+circledast circledcirc centerdot dotplus intercal barwedge veebar
+doublebarwedge curlyvee curlywedge divideontimes Cap Cup doublecap doublecup
+gtrdot lessdot mod bmod pmod pod bigcirc
+leq le geq ge neq ne equiv approx approxeq sim simeq cong propto subset supset
+subseteq supseteq subsetneq supsetneq subseteqq supseteqq subsetneqq
+supsetneqq varsubsetneq varsupsetneq varsubsetneqq varsupsetneqq nsubseteq
+nsupseteq nsubseteqq nsupseteqq Subset Supset sqsubset sqsupset sqsubseteq
+sqsupseteq in notin ni owns mid nmid shortmid nshortmid parallel nparallel
+shortparallel nshortparallel perp models vdash dashv vDash Vdash Vvdash nvdash
+nvDash nVdash nVDash ll gg lll ggg llless gggtr lesssim gtrsim lessapprox
+gtrapprox leqslant geqslant leqq geqq lneq gneq lneqq gneqq lvertneqq
+gvertneqq lnsim gnsim lnapprox gnapprox nless ngtr nleq ngeq nleqslant
+ngeqslant nleqq ngeqq prec succ preceq succeq precsim succsim precapprox
+succapprox preccurlyeq succcurlyeq curlyeqprec curlyeqsucc precneqq succneqq
+precnsim succnsim precnapprox succnapprox nprec nsucc npreceq nsucceq asymp
+bowtie Join smile frown smallsmile smallfrown doteq doteqdot Doteq
+fallingdotseq risingdotseq eqcirc circeq triangleq bumpeq Bumpeq thicksim
+thickapprox backsim backsimeq eqsim lessgtr gtrless lesseqgtr gtreqless
+lesseqqgtr gtreqqless eqslantless eqslantgtr therefore because vartriangle
+vartriangleleft vartriangleright trianglelefteq trianglerighteq
+ntriangleleft ntriangleright ntrianglelefteq ntrianglerighteq ncong nsim
+pitchfork between blacktriangleleft varpropto backepsilon lhd rhd unlhd unrhd
+dblcolon ratio colon Colonapprox colonapprox Colonsim colonsim Coloneq
+approxcolon approxcoloncolon simcolon simcoloncolon Eqcolon Eqqcolon notni
+leftarrow rightarrow to gets uparrow downarrow updownarrow Leftarrow
+Rightarrow Uparrow Downarrow Updownarrow leftrightarrow Leftrightarrow
+longleftarrow longrightarrow longleftrightarrow Longleftarrow Longrightarrow
+Longleftrightarrow iff implies impliedby mapsto longmapsto hookleftarrow
+hookrightarrow nearrow searrow swarrow nwarrow leftharpoonup leftharpoondown
+rightharpoonup rightharpoondown rightleftharpoons leftrightharpoons
+upharpoonleft upharpoonright downharpoonleft downharpoonright leadsto
+rightsquigarrow leftrightsquigarrow twoheadrightarrow twoheadleftarrow
+rightarrowtail leftarrowtail looparrowright looparrowleft curvearrowright
+curvearrowleft circlearrowright circlearrowleft Lsh Rsh nleftarrow
+nrightarrow nLeftarrow nRightarrow nleftrightarrow nLeftrightarrow
+leftleftarrows rightrightarrows leftrightarrows rightleftarrows upuparrows
+downdownarrows Lleftarrow Rrightarrow dashrightarrow dashleftarrow
+restriction multimap xrightarrow xleftarrow xRightarrow xLeftarrow
+xleftrightarrow xLeftrightarrow xmapsto xhookrightarrow xhookleftarrow
+xtwoheadrightarrow xtwoheadleftarrow xrightharpoonup xrightharpoondown
+xleftharpoonup xleftharpoondown xrightleftharpoons xleftrightharpoons
+xlongequal xtofrom
+left right middle big Big bigg Bigg bigl bigr Bigl Bigr biggl biggr Biggl
+Biggr bigm Bigm biggm Biggm langle rangle lang rang lceil rceil lfloor rfloor
+lvert rvert lVert rVert vert Vert lbrace rbrace lbrack rbrack lgroup rgroup
+lmoustache rmoustache llbracket rrbracket lBrace rBrace backslash ulcorner
+urcorner llcorner lrcorner uparrow
+hat widehat check widecheck tilde widetilde bar overline underline vec
+overrightarrow overleftarrow overleftrightarrow underrightarrow
+underleftarrow underleftrightarrow overrightharpoon overleftharpoon
+Overrightarrow dot ddot dddot ddddot acute grave breve mathring overbrace
+underbrace overset underset stackrel overgroup undergroup utilde boxed
+cancel bcancel xcancel sout not overlinesegment underlinesegment
+mathrm mathit mathbf mathsf mathtt mathcal mathscr mathfrak mathbb
+mathnormal boldsymbol bm pmb text textrm textit textbf textsf texttt
+textnormal textup textmd emph mbox hbox fbox Bbb bold frak rm it bf sf tt cal
+mit bfseries itshape mdseries normalfont rmfamily sffamily ttfamily upshape
+textbackslash textasciitilde textasciicircum textdollar textunderscore
+textbraceleft textbraceright textless textgreater textbar textendash
+textemdash textquoteleft textquoteright textquotedblleft textquotedblright
+textdegree textregistered textcopyright textdagger textdaggerdbl textsterling
+textellipsis textbullet textperiodcentered textvisiblespace
+operatorname operatornamewithlimits mathop mathbin mathrel mathopen
+mathclose mathpunct mathord mathinner
+sin cos tan cot sec csc arcsin arccos arctan arccot arcsec arccsc sinh cosh
+tanh coth sech csch exp log ln lg lim liminf limsup sup inf max min arg det
+dim deg gcd hom ker Pr injlim projlim varliminf varlimsup varinjlim
+varprojlim sh ch tg ctg cotg cth th arctg arcctg limits nolimits
+displaylimits
+frac dfrac tfrac cfrac binom dbinom tbinom choose over atop genfrac sqrt
+above brace brack
+quad qquad enspace thinspace medspace thickspace negthinspace negmedspace
+negthickspace hspace hskip kern mkern mskip mspace space nobreakspace
+phantom hphantom vphantom smash mathstrut strut enskip nobreak allowbreak
+newline cr
+begin end displaystyle textstyle scriptstyle scriptscriptstyle tag notag
+nonumber color textcolor colorbox fcolorbox cdots ldots dots dotsb dotsc
+dotsi dotsm dotso vdots ddots mathellipsis cdotp ldotp iddots hline hdashline
+substack sideset href url includegraphics htmlClass htmlId htmlStyle
+htmlData char relax newcommand renewcommand providecommand def gdef edef
+xdef let futurelet global long raisebox vcenter llap rlap clap mathllap
+mathrlap mathclap tiny scriptsize footnotesize small normalsize large Large
+LARGE huge Huge rule verb lq rq aa AA ae AE oe OE o O ss i j
+isin varnothing bigodot Im Re infin KaTeX LaTeX TeX
+";
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fix(s: &str) -> String {
+        rewrite(s).markdown
+    }
+
+    #[test]
+    fn handles_nested_braces() {
+        // The old `[^}]*` regexes stopped at the first closing brace.
+        assert_eq!(
+            fix(r"$\mathbbm{1}_{\{x \in \bm{\hat{y}}\}} + \Call{F}{\frac{a}{b}, c}$"),
+            r"$\mathbb{1}_{\{x \in \boldsymbol{\hat{y}}\}} + \textbf{F}(\frac{a}{b}, c)$"
+        );
+        assert_eq!(
+            fix(r"$\textsc{Alg-\textsc{B}}$"),
+            r"$\textbf{Alg-\textbf{B}}$"
+        );
+    }
+
+    #[test]
+    fn maps_common_unsupported_commands() {
+        assert_eq!(
+            fix(r"$x \coloneqq \nicefrac{1}{2}$"),
+            r"$x \mathrel{:=} {}^{1}\!/{}_{2}$"
+        );
+        assert_eq!(
+            fix(r"$\argmin_\theta L$"),
+            r"$\operatorname*{arg\,min}_\theta L$"
+        );
+        assert_eq!(fix(r"$\tr A$"), r"$\operatorname{tr} A$");
+    }
+
+    #[test]
+    fn maps_siunitx() {
+        assert_eq!(
+            fix(r"$\SI{9.81}{\metre\per\second\squared}$"),
+            r"$9.81\,\mathrm{m/s^{2}}$"
+        );
+        assert_eq!(
+            fix(r"$\SI{3e8}{\kilo\gram}$"),
+            r"$3\times10^{8}\,\mathrm{kg}$"
+        );
+        assert_eq!(fix(r"$\qty{5}{\milli\second}$"), r"$5\,\mathrm{ms}$");
+        assert_eq!(fix(r"$\num{1.5e-3}$"), r"$1.5\times10^{-3}$");
+        assert_eq!(fix(r"$\si{m/s}$"), r"$\mathrm{m/s}$");
+    }
+
+    #[test]
+    fn maps_physics_package() {
+        assert_eq!(
+            fix(r"$\qty(\frac{a}{b}) + \abs{x} + \norm{\vb{v}}$"),
+            r"$\left(\frac{a}{b}\right) + \left| x \right| + \left\| \mathbf{v} \right\|$"
+        );
+        assert_eq!(fix(r"$\qty{x} = 1$"), r"$\left\{x\right\} = 1$");
+        assert_eq!(fix(r"$\qty{a+b} + c$"), r"$\left\{a+b\right\} + c$");
+        assert_eq!(fix(r"$\qty{1.5}{\metre}$"), r"$1.5\,\mathrm{m}$");
+        assert_eq!(fix(r"$\dv{f}{x}$"), r"$\frac{d f}{d x}$");
+        assert_eq!(
+            fix(r"$\pdv[2]{f}{x}$"),
+            r"$\frac{\partial^{2} f}{\partial x^{2}}$"
+        );
+    }
+
+    #[test]
+    fn reports_unknown_commands_and_skips_code() {
+        let md = "A $\\foo{x} + \\alpha$ and $$\\bar{y} \\qux$$\n\n```\n$\\notmath$\n```\n`$\\code$` \\$5 and \\$6";
+        let out = rewrite(md);
+        assert_eq!(out.markdown, md);
+        assert_eq!(
+            out.unsupported,
+            vec!["\\foo".to_string(), "\\qux".to_string()]
+        );
+    }
+}
@@ -1,7 +1,31 @@
 pub mod arxiv;
+pub mod authors;
+pub mod bibliography;
 pub mod cache;
 pub mod convert;
+pub mod crossref;
 pub mod disk_cache;
+pub mod eprint;
+pub mod figures;
+pub mod footnotes;
+pub mod header;
+pub mod headings;
+pub mod katex;
+pub mod markdown;
+pub mod mathml;
+pub mod pandoc_pool;
+pub mod pdf_layout;
+pub mod placeholder;
+pub mod postprocess;
+pub mod profile;
+pub mod pseudocode;
+pub mod report;
 pub mod routes;
 pub mod state;
+pub mod tables;
+pub mod tex_clean;
+pub mod tex_encoding;
 pub mod tex_main;
+pub mod tex_util;
+pub mod theorems;
+pub mod unicode_math;
@@ -6,8 +6,10 @@ use axum::{routing::get, Router};
 use tower_http::trace::{DefaultMakeSpan, DefaultOnFailure, DefaultOnResponse, TraceLayer};
 
 use markxiv::arxiv::ReqwestArxivClient;
-use markxiv::convert::PandocConverter;
+use markxiv::convert::{PandocBackend, PandocConverter, PANDOC_TIMEOUT};
 use markxiv::disk_cache::{DiskCache, DiskCacheConfig};
+use markxiv::pandoc_pool::{PandocPool, PandocPoolConfig};
+use markxiv::postprocess::Pipeline;
 use markxiv::routes;
 use markxiv::state::AppState;
 use tracing::Level;
@@ -74,6 +76,50 @@ fn init_tracing() {
     }
 }
 
+/// `MARKXIV_PANDOC_MODE=pool` keeps `MARKXIV_PANDOC_WORKERS` pandoc servers
+/// running; the default spawns pandoc per conversion, which is also the
+/// fallback when the pool can't start.
+async fn pandoc_backend() -> PandocBackend {
+    if std::env::var("MARKXIV_PANDOC_MODE").as_deref() != Ok("pool") {
+        return PandocBackend::Spawn;
+    }
+    let cfg = PandocPoolConfig {
+        pandoc: std::env::var("MARKXIV_PANDOC_PATH").unwrap_or_else(|_| "pandoc".into()),
+        workers: std::env::var("MARKXIV_PANDOC_WORKERS")
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(2),
+        timeout: PANDOC_TIMEOUT,
+        health_interval: std::time::Duration::from_secs(30),
+    };
+    match PandocPool::start(cfg).await {
+        Ok(pool) => {
+            tracing::info!(workers = pool.workers(), "pandoc worker pool started");
+            PandocBackend::Pool(pool)
+        }
+        Err(e) => {
+            tracing::error!(error = %e, "pandoc worker pool failed to start; spawning per request");
+            PandocBackend::Spawn
+        }
+    }
+}
+
+/// `MARKXIV_POSTPROCESSORS` lists the Markdown clean-up stages to run, in
+/// order (e.g. `figures,tables,strip-html`); unset means all of them.
+fn postprocessors() -> Pipeline {
+    let Ok(list) = std::env::var("MARKXIV_POSTPROCESSORS") else {
+        return Pipeline::standard();
+    };
+    let names: Vec<&str> = list.split(',').filter(|n| !n.trim().is_empty()).collect();
+    match Pipeline::from_names(&names) {
+        Ok(pipeline) => pipeline,
+        Err(e) => {
+            tracing::error!(error = %e, "invalid MARKXIV_POSTPROCESSORS; using all stages");
+            Pipeline::standard()
+        }
+    }
+}
+
 #[tokio::main]
 async fn main() {
     let _ = dotenvy::dotenv();
@@ -86,7 +132,8 @@ async fn main() {
         .unwrap_or(128);
 
     let client = ReqwestArxivClient::new();
-    let converter = PandocConverter::new();
+    let converter =
+        PandocConverter::with_backend(pandoc_backend().await).with_pipeline(postprocessors());
 
     // Optional disk cache
     let disk_cap_bytes = std::env::var("MARKXIV_DISK_CACHE_CAP_BYTES")
@@ -105,6 +152,7 @@ async fn main() {
             root,
             cap_bytes: disk_cap_bytes,
             sweep_interval: std::time::Duration::from_secs(sweep_secs),
+            pipeline_version: markxiv::convert::PIPELINE_VERSION,
         };
         match DiskCache::new(cfg).await {
             Ok(dc) => Some(dc),
@@ -123,6 +171,8 @@ async fn main() {
         .route("/", get(routes::index))
         .route("/health", get(routes::health))
         .route("/abs/:id", get(routes::paper))
+        .route("/abs/:id/report", get(routes::report))
+        .route("/abs/:id/figures/:n", get(routes::figure))
         .route("/pdf/:id", get(routes::paper))
         .layer(
             TraceLayer::new_for_http()
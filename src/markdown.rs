@@ -0,0 +1,136 @@
+use std::ops::Range;
+
+use pulldown_cmark::{Event, Options, Parser, Tag};
+
+/// Byte ranges of code in `md` (fenced and indented blocks, and code spans
+/// with their backticks), from pulldown-cmark's parse of the document.
+pub fn code_ranges(md: &str) -> Vec<Range<usize>> {
+    let mut opts = Options::empty();
+    opts.insert(Options::ENABLE_TABLES);
+    opts.insert(Options::ENABLE_FOOTNOTES);
+    opts.insert(Options::ENABLE_STRIKETHROUGH);
+    let mut ranges: Vec<Range<usize>> = Vec::new();
+    for (event, range) in Parser::new_ext(md, opts).into_offset_iter() {
+        if matches!(event, Event::Code(_) | Event::Start(Tag::CodeBlock(_))) {
+            // Ranges arrive in document order, and blocks before their
+            // contents, so nested ranges are already covered.
+            if ranges.last().is_none_or(|last| range.start >= last.end) {
+                ranges.push(range);
+            }
+        }
+    }
+    ranges
+}
+
+/// Byte ranges of the contents of `$...$` and `$$...$$` math outside code.
+///
+/// Delimiters follow pandoc's `tex_math_dollars` rules: escaped `\$` is
+/// text, an opening `$` must be followed by a non-space, a closing `$`
+/// preceded by a non-space and not followed by a digit, and math never
+/// spans a blank line. A `$` without a valid partner (a price, say) is
+/// just a dollar sign.
+pub fn math_spans(md: &str) -> Vec<Range<usize>> {
+    let code = code_ranges(md);
+    let mut spans = Vec::new();
+    let mut start = 0;
+    for range in code.iter().chain(std::iter::once(&(md.len()..md.len()))) {
+        math_spans_in(md, start..range.start, &mut spans);
+        start = range.end;
+    }
+    spans
+}
+
+fn math_spans_in(md: &str, region: Range<usize>, spans: &mut Vec<Range<usize>>) {
+    let bytes = md.as_bytes();
+    let mut i = region.start;
+    while i < region.end {
+        match bytes[i] {
+            b'\\' => i += 2,
+            b'$' => {
+                let delim = if md[i..region.end].starts_with("$$") {
+                    "$$"
+                } else {
+                    "$"
+                };
+                let open = i + delim.len();
+                match closing_delim(md, open, region.end, delim) {
+                    Some(close) => {
+                        spans.push(open..close);
+                        i = close + delim.len();
+                    }
+                    None => i = open,
+                }
+            }
+            _ => i += 1,
+        }
+    }
+}
+
+/// Position of the delimiter closing math opened just before `open`.
+fn closing_delim(md: &str, open: usize, end: usize, delim: &str) -> Option<usize> {
+    let bytes = md.as_bytes();
+    let inline = delim == "$";
+    if inline && bytes.get(open).is_none_or(|b| b.is_ascii_whitespace()) {
+        return None;
+    }
+    let mut i = open;
+    while i < end {
+        match bytes[i] {
+            b'\\' => {
+                i += 2;
+                continue;
+            }
+            b'\n'
+                if md[i + 1..end]
+                    .trim_start_matches([' ', '\t'])
+                    .starts_with('\n') =>
+            {
+                return None;
+            }
+            b'$' if md[i..end].starts_with(delim) && i > open => {
+                let after = bytes.get(i + delim.len());
+                let closes = !inline
+                    || (!bytes[i - 1].is_ascii_whitespace()
+                        && !after.is_some_and(|b| b.is_ascii_digit()));
+                if closes {
+                    return Some(i);
+                }
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn math(md: &str) -> Vec<&str> {
+        math_spans(md).into_iter().map(|r| &md[r]).collect()
+    }
+
+    #[test]
+    fn code_is_found_by_the_parser() {
+        let md = "Run `echo $HOME` now.\n\n```sh\n$ ls <dir>\n```\n\n    indented $x$\n";
+        let ranges: Vec<&str> = code_ranges(md).into_iter().map(|r| &md[r]).collect();
+        assert_eq!(ranges[0], "`echo $HOME`");
+        assert!(ranges[1].starts_with("```sh\n$ ls <dir>"));
+        assert!(ranges[2].contains("indented $x$"));
+    }
+
+    #[test]
+    fn math_skips_code_and_matches_delimiters() {
+        assert_eq!(math("`$a$` and $b$"), vec!["b"]);
+        assert_eq!(math("$$\nx = 1\n$$ then $y$"), vec!["\nx = 1\n", "y"]);
+        assert_eq!(math(r"costs \$5 or $x$"), vec!["x"]);
+    }
+
+    #[test]
+    fn stray_dollars_do_not_swallow_text() {
+        assert_eq!(math("It costs $5 and $10."), Vec::<&str>::new());
+        assert_eq!(math("Pay $5.\n\nThen $x$ holds."), vec!["x"]);
+        assert_eq!(math("$ x$ and $z$"), vec!["z"]);
+    }
+}
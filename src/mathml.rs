@@ -0,0 +1,821 @@
+use crate::markdown::math_spans;
+use crate::unicode_math::symbol;
+
+const NS: &str = "http://www.w3.org/1998/Math/MathML";
+
+/// Convert TeX math to a `<math>` element, or `None` when it uses
+/// something this converter doesn't know (the caller keeps the LaTeX).
+///
+/// Covers what papers mostly write: identifiers, numbers and operators,
+/// scripts and limits, fractions, roots, accents, font commands, `\text`,
+/// `\left`/`\right` and the matrix, `cases` and aligned environments.
+pub fn latex_to_mathml(tex: &str, display: bool) -> Option<String> {
+    let mut p = Parser {
+        s: tex,
+        pos: 0,
+        display,
+        variant: None,
+    };
+    let body = p.row(Stop::End)?;
+    if p.pos < tex.len() {
+        return None;
+    }
+    Some(format!(
+        "<math xmlns=\"{}\" display=\"{}\">{}</math>",
+        NS,
+        if display { "block" } else { "inline" },
+        body
+    ))
+}
+
+/// Replace the `$...$` and `$$...$$` math in Markdown with MathML islands.
+/// Display math keeps its own line. Spans the converter can't handle stay
+/// LaTeX.
+pub fn markdown_math_to_mathml(md: &str) -> String {
+    let mut out = String::with_capacity(md.len() * 2);
+    let mut last = 0;
+    for span in math_spans(md) {
+        let display = md[..span.start].ends_with("$$");
+        let delim = if display { 2 } else { 1 };
+        let Some(mathml) = latex_to_mathml(md[span.clone()].trim(), display) else {
+            continue;
+        };
+        out.push_str(&md[last..span.start - delim]);
+        out.push_str(&mathml);
+        last = span.end + delim;
+    }
+    out.push_str(&md[last..]);
+    out
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Stop {
+    End,
+    Brace,
+    Right,
+    /// Inside an environment: `&`, `\\` and `\end` end a cell.
+    Cell,
+}
+
+struct Parser<'a> {
+    s: &'a str,
+    pos: usize,
+    display: bool,
+    /// `mathvariant` from an enclosing font command.
+    variant: Option<&'static str>,
+}
+
+/// Large operators that take limits above and below in display math.
+const LIMIT_OPERATORS: &[&str] = &[
+    "sum",
+    "prod",
+    "coprod",
+    "bigcup",
+    "bigcap",
+    "bigvee",
+    "bigwedge",
+    "bigoplus",
+    "bigotimes",
+    "bigodot",
+    "biguplus",
+    "bigsqcup",
+    "lim",
+    "liminf",
+    "limsup",
+    "max",
+    "min",
+    "sup",
+    "inf",
+    "argmin",
+    "argmax",
+    "det",
+    "Pr",
+    "gcd",
+];
+
+/// Extra symbols beyond [`symbol`]'s table.
+const MORE_SYMBOLS: &[(&str, &str)] = &[
+    ("iint", "∬"),
+    ("iiint", "∭"),
+    ("coprod", "∐"),
+    ("bigvee", "⋁"),
+    ("bigwedge", "⋀"),
+    ("bigoplus", "⨁"),
+    ("bigotimes", "⨂"),
+    ("bigodot", "⨀"),
+    ("biguplus", "⨄"),
+    ("bigsqcup", "⨆"),
+    ("sqcup", "⊔"),
+    ("sqcap", "⊓"),
+    ("uplus", "⊎"),
+    ("ominus", "⊖"),
+    ("oslash", "⊘"),
+    ("dagger", "†"),
+    ("ddagger", "‡"),
+    ("preceq", "⪯"),
+    ("succeq", "⪰"),
+    ("prec", "≺"),
+    ("succ", "≻"),
+    ("subsetneq", "⊊"),
+    ("supsetneq", "⊋"),
+    ("leqslant", "⩽"),
+    ("geqslant", "⩾"),
+    ("lesssim", "≲"),
+    ("gtrsim", "≳"),
+    ("asymp", "≍"),
+    ("doteq", "≐"),
+    ("models", "⊨"),
+    ("vdash", "⊢"),
+    ("dashv", "⊣"),
+    ("longrightarrow", "⟶"),
+    ("longleftarrow", "⟵"),
+    ("Longrightarrow", "⟹"),
+    ("Longleftarrow", "⟸"),
+    ("longmapsto", "⟼"),
+    ("hookrightarrow", "↪"),
+    ("hookleftarrow", "↩"),
+    ("rightharpoonup", "⇀"),
+    ("updownarrow", "↕"),
+    ("nearrow", "↗"),
+    ("searrow", "↘"),
+    ("lvert", "|"),
+    ("rvert", "|"),
+    ("lVert", "‖"),
+    ("rVert", "‖"),
+    ("|", "‖"),
+    ("aleph", "ℵ"),
+    ("Re", "ℜ"),
+    ("Im", "ℑ"),
+    ("wp", "℘"),
+    ("imath", "ı"),
+    ("jmath", "ȷ"),
+    ("angle", "∠"),
+    ("square", "□"),
+    ("Box", "□"),
+    ("checkmark", "✓"),
+    ("therefore", "∴"),
+    ("because", "∵"),
+    ("lbrace", "{"),
+    ("rbrace", "}"),
+    ("{", "{"),
+    ("}", "}"),
+    ("backslash", "∖"),
+    ("nexists", "∄"),
+    ("complement", "∁"),
+    ("varepsilon", "ε"),
+    ("sinh", "sinh"),
+    ("cosh", "cosh"),
+    ("cot", "cot"),
+    ("sec", "sec"),
+    ("csc", "csc"),
+    ("arcsin", "arcsin"),
+    ("arccos", "arccos"),
+    ("arctan", "arctan"),
+    ("liminf", "lim inf"),
+    ("limsup", "lim sup"),
+    ("dim", "dim"),
+    ("ker", "ker"),
+    ("deg", "deg"),
+    ("gcd", "gcd"),
+    ("hom", "hom"),
+    ("lg", "lg"),
+    ("bmod", "mod"),
+];
+
+/// Identifiers rather than operators among the symbols.
+fn is_identifier(name: &str, sym: &str) -> bool {
+    sym.chars().all(|c| c.is_alphabetic())
+        && !matches!(name, "neg" | "lnot" | "land" | "lor" | "wedge" | "vee")
+        || matches!(
+            name,
+            "infty"
+                | "emptyset"
+                | "varnothing"
+                | "partial"
+                | "nabla"
+                | "ell"
+                | "hbar"
+                | "aleph"
+                | "Re"
+                | "Im"
+                | "wp"
+                | "imath"
+                | "jmath"
+                | "top"
+                | "bot"
+                | "prime"
+        )
+}
+
+fn lookup(name: &str) -> Option<&'static str> {
+    symbol(name).or_else(|| {
+        MORE_SYMBOLS
+            .iter()
+            .find(|(n, _)| *n == name)
+            .map(|(_, s)| *s)
+    })
+}
+
+/// Escape text for MathML inside Markdown: XML specials, plus the
+/// characters Markdown would read as emphasis, links or escapes.
+fn escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '&' => out.push_str("&amp;"),
+            '*' | '_' | '\\' | '`' | '[' | ']' | '$' => {
+                out.push_str(&format!("&#{};", c as u32));
+            }
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+fn mo(op: &str) -> String {
+    format!("<mo>{}</mo>", escape(op))
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<char> {
+        self.s[self.pos..].chars().next()
+    }
+
+    fn skip_ws(&mut self) {
+        while let Some(c) = self.peek() {
+            if !c.is_whitespace() {
+                break;
+            }
+            self.pos += c.len_utf8();
+        }
+    }
+
+    fn starts_with(&self, s: &str) -> bool {
+        self.s[self.pos..].starts_with(s)
+    }
+
+    /// The command name at the cursor (after a backslash), without moving.
+    fn command_name(&self) -> &'a str {
+        let rest = &self.s[self.pos + 1..];
+        let len = rest.bytes().take_while(|b| b.is_ascii_alphabetic()).count();
+        if len > 0 {
+            &rest[..len]
+        } else {
+            rest.chars()
+                .next()
+                .map(|c| &rest[..c.len_utf8()])
+                .unwrap_or("")
+        }
+    }
+
+    /// Parse a sequence of atoms until `stop`; returns them wrapped in an
+    /// `<mrow>` when there is more than one.
+    fn row(&mut self, stop: Stop) -> Option<String> {
+        let mut items = Vec::new();
+        loop {
+            self.skip_ws();
+            let Some(c) = self.peek() else {
+                return (stop == Stop::End || stop == Stop::Cell).then(|| wrap(items));
+            };
+            match c {
+                '}' if stop == Stop::Brace => {
+                    self.pos += 1;
+                    return Some(wrap(items));
+                }
+                '}' => return None,
+                '&' if stop == Stop::Cell => return Some(wrap(items)),
+                '\\' => {
+                    let name = self.command_name();
+                    match name {
+                        "right" if stop == Stop::Right => return Some(wrap(items)),
+                        "\\" | "end" | "cr" if stop == Stop::Cell => return Some(wrap(items)),
+                        "right" | "end" => return None,
+                        "\\" => {
+                            // Line break outside an environment.
+                            self.pos += 2;
+                            continue;
+                        }
+                        _ => {}
+                    }
+                    if let Some(item) = self.scripted()? {
+                        items.push(item);
+                    }
+                }
+                _ => {
+                    if let Some(item) = self.scripted()? {
+                        items.push(item);
+                    }
+                }
+            }
+        }
+    }
+
+    /// An atom with its sub/superscripts. `Some(None)` for atoms that
+    /// produce nothing (spacing-neutral commands like `\displaystyle`).
+    fn scripted(&mut self) -> Option<Option<String>> {
+        let start = self.pos;
+        let Some(base) = self.atom()? else {
+            return Some(None);
+        };
+        let name = if self.s[start..].starts_with('\\') {
+            let rest = &self.s[start + 1..];
+            let len = rest.bytes().take_while(|b| b.is_ascii_alphabetic()).count();
+            &rest[..len]
+        } else {
+            ""
+        };
+        let mut limits = self.display && LIMIT_OPERATORS.contains(&name)
+            || name == "operatorname" && self.s[start..].starts_with("\\operatorname*");
+        let (mut sub, mut sup) = (None, None);
+        loop {
+            self.skip_ws();
+            if self.starts_with("\\limits") {
+                self.pos += "\\limits".len();
+                limits = true;
+            } else if self.starts_with("\\nolimits") {
+                self.pos += "\\nolimits".len();
+                limits = false;
+            } else if self.starts_with("_") && sub.is_none() {
+                self.pos += 1;
+                sub = Some(self.script_arg()?);
+            } else if self.starts_with("^") && sup.is_none() {
+                self.pos += 1;
+                sup = Some(self.script_arg()?);
+            } else if self.starts_with("'") && sup.is_none() {
+                let mut primes = String::new();
+                while self.starts_with("'") {
+                    self.pos += 1;
+                    primes.push('′');
+                }
+                sup = Some(mo(&primes));
+            } else {
+                break;
+            }
+        }
+        let (under, over, both) = if limits {
+            ("munder", "mover", "munderover")
+        } else {
+            ("msub", "msup", "msubsup")
+        };
+        Some(Some(match (sub, sup) {
+            (None, None) => base,
+            (Some(b), None) => format!("<{0}>{1}{2}</{0}>", under, base, b),
+            (None, Some(p)) => format!("<{0}>{1}{2}</{0}>", over, base, p),
+            (Some(b), Some(p)) => format!("<{0}>{1}{2}{3}</{0}>", both, base, b, p),
+        }))
+    }
+
+    fn script_arg(&mut self) -> Option<String> {
+        self.skip_ws();
+        match self.atom()? {
+            Some(a) => Some(a),
+            None => Some("<mrow></mrow>".into()),
+        }
+    }
+
+    /// A braced group as a row, or a single atom.
+    fn arg(&mut self) -> Option<String> {
+        self.skip_ws();
+        if self.starts_with("{") {
+            self.pos += 1;
+            return self.row(Stop::Brace);
+        }
+        self.atom()?.or_else(|| Some("<mrow></mrow>".into()))
+    }
+
+    /// The raw text of a braced argument.
+    fn raw_arg(&mut self) -> Option<&'a str> {
+        self.skip_ws();
+        let end = crate::tex_util::group_end(self.s, self.pos)?;
+        let text = &self.s[self.pos + 1..end - 1];
+        self.pos = end;
+        Some(text)
+    }
+
+    fn opt_arg(&mut self) -> Option<&'a str> {
+        self.skip_ws();
+        if !self.starts_with("[") {
+            return None;
+        }
+        let end = self.s[self.pos..].find(']')? + self.pos;
+        let text = &self.s[self.pos + 1..end];
+        self.pos = end + 1;
+        Some(text)
+    }
+
+    fn with_variant(&mut self, variant: &'static str) -> Option<String> {
+        let saved = self.variant.replace(variant);
+        let out = self.arg();
+        self.variant = saved;
+        out
+    }
+
+    fn mi(&self, text: &str) -> String {
+        match self.variant {
+            Some(v) => format!("<mi mathvariant=\"{}\">{}</mi>", v, escape(text)),
+            None => format!("<mi>{}</mi>", escape(text)),
+        }
+    }
+
+    /// A fence for `\left`, `\right` or a sized delimiter.
+    fn delimiter(&mut self) -> Option<String> {
+        self.skip_ws();
+        let c = self.peek()?;
+        if c == '\\' {
+            let name = self.command_name();
+            self.pos += 1 + name.len();
+            return lookup(name).map(str::to_string);
+        }
+        self.pos += c.len_utf8();
+        Some(if c == '.' {
+            String::new()
+        } else {
+            c.to_string()
+        })
+    }
+
+    fn atom(&mut self) -> Option<Option<String>> {
+        self.skip_ws();
+        let c = self.peek()?;
+        match c {
+            '{' => {
+                self.pos += 1;
+                self.row(Stop::Brace).map(Some)
+            }
+            '0'..='9' | '.' => {
+                let len = self.s[self.pos..]
+                    .char_indices()
+                    .take_while(|(i, c)| c.is_ascii_digit() || (*c == '.' && *i > 0))
+                    .map(|(_, c)| c.len_utf8())
+                    .sum::<usize>()
+                    .max(1);
+                let text = &self.s[self.pos..self.pos + len];
+                self.pos += len;
+                if text == "." {
+                    return Some(Some(mo(".")));
+                }
+                Some(Some(format!("<mn>{}</mn>", text)))
+            }
+            '\\' => self.command(),
+            // A script with an empty base, as in `{}^{a}`.
+            '^' | '_' => Some(Some("<mrow></mrow>".into())),
+            '~' => {
+                self.pos += 1;
+                Some(Some("<mspace width=\"0.333em\"></mspace>".into()))
+            }
+            '#' | '%' | '$' => None,
+            c if c.is_alphabetic() => {
+                self.pos += c.len_utf8();
+                Some(Some(self.mi(&c.to_string())))
+            }
+            c => {
+                self.pos += c.len_utf8();
+                Some(Some(mo(&c.to_string())))
+            }
+        }
+    }
+
+    fn command(&mut self) -> Option<Option<String>> {
+        let name = self.command_name();
+        self.pos += 1 + name.len();
+        let space = |w: &str| Some(Some(format!("<mspace width=\"{}\"></mspace>", w)));
+        match name {
+            "," | "thinspace" => space("0.167em"),
+            ":" | ">" | "medspace" => space("0.222em"),
+            ";" | "thickspace" => space("0.278em"),
+            " " | "space" => space("0.333em"),
+            "quad" => space("1em"),
+            "qquad" => space("2em"),
+            "!" | "negthinspace" => Some(None),
+            "%" | "#" | "&" | "$" | "_" => Some(Some(mo(name))),
+            "frac" | "dfrac" | "tfrac" | "cfrac" => {
+                let num = self.arg()?;
+                let den = self.arg()?;
+                Some(Some(format!("<mfrac>{}{}</mfrac>", num, den)))
+            }
+            "binom" | "dbinom" | "tbinom" => {
+                let top = self.arg()?;
+                let bottom = self.arg()?;
+                Some(Some(format!(
+                    "<mrow><mo>(</mo><mfrac linethickness=\"0\">{}{}</mfrac><mo>)</mo></mrow>",
+                    top, bottom
+                )))
+            }
+            "sqrt" => {
+                let index = self.opt_arg();
+                let body = self.arg()?;
+                Some(Some(match index {
+                    Some(n) => {
+                        let n = latex_inner(n, self.display)?;
+                        format!("<mroot>{}{}</mroot>", body, n)
+                    }
+                    None => format!("<msqrt>{}</msqrt>", body),
+                }))
+            }
+            "mathbb" | "Bbb" => self.with_variant("double-struck").map(Some),
+            "mathbf" => self.with_variant("bold").map(Some),
+            "boldsymbol" | "bm" | "pmb" => self.with_variant("bold-italic").map(Some),
+            "mathit" => self.with_variant("italic").map(Some),
+            "mathrm" | "mathup" => self.with_variant("normal").map(Some),
+            "mathsf" => self.with_variant("sans-serif").map(Some),
+            "mathtt" => self.with_variant("monospace").map(Some),
+            "mathcal" | "mathscr" => self.with_variant("script").map(Some),
+            "mathfrak" => self.with_variant("fraktur").map(Some),
+            "text" | "textrm" | "textnormal" | "textup" | "textbf" | "textit" | "textsf"
+            | "texttt" | "mbox" | "hbox" | "emph" => {
+                let raw = self.raw_arg()?;
+                let variant = match name {
+                    "textbf" => " mathvariant=\"bold\"",
+                    "textit" | "emph" => " mathvariant=\"italic\"",
+                    _ => "",
+                };
+                Some(Some(format!(
+                    "<mtext{}>{}</mtext>",
+                    variant,
+                    escape(&text_content(raw))
+                )))
+            }
+            "operatorname" => {
+                if self.starts_with("*") {
+                    self.pos += 1;
+                }
+                let raw = self.raw_arg()?;
+                Some(Some(format!("<mi>{}</mi>", escape(&text_content(raw)))))
+            }
+            "hat" | "widehat" | "bar" | "overline" | "tilde" | "widetilde" | "vec" | "dot"
+            | "ddot" | "check" | "breve" | "acute" | "grave" | "overrightarrow" => {
+                let base = self.arg()?;
+                let mark = match name {
+                    "hat" | "widehat" => "^",
+                    "bar" | "overline" => "¯",
+                    "tilde" | "widetilde" => "~",
+                    "vec" | "overrightarrow" => "→",
+                    "dot" => "˙",
+                    "ddot" => "¨",
+                    "check" => "ˇ",
+                    "breve" => "˘",
+                    "acute" => "´",
+                    _ => "`",
+                };
+                Some(Some(format!(
+                    "<mover accent=\"true\">{}{}</mover>",
+                    base,
+                    mo(mark)
+                )))
+            }
+            "underline" => {
+                let base = self.arg()?;
+                Some(Some(format!(
+                    "<munder accentunder=\"true\">{}{}</munder>",
+                    base,
+                    mo("_")
+                )))
+            }
+            "overbrace" | "underbrace" => {
+                let base = self.arg()?;
+                Some(Some(if name == "overbrace" {
+                    format!("<mover>{}{}</mover>", base, mo("⏞"))
+                } else {
+                    format!("<munder>{}{}</munder>", base, mo("⏟"))
+                }))
+            }
+            "overset" | "underset" | "stackrel" => {
+                let top = self.arg()?;
+                let base = self.arg()?;
+                let tag = if name == "underset" {
+                    "munder"
+                } else {
+                    "mover"
+                };
+                Some(Some(format!("<{0}>{1}{2}</{0}>", tag, base, top)))
+            }
+            "left" => {
+                let open = self.delimiter()?;
+                let body = self.row(Stop::Right)?;
+                self.pos += "\\right".len();
+                let close = self.delimiter()?;
+                Some(Some(format!(
+                    "<mrow>{}{}{}</mrow>",
+                    fence(&open),
+                    body,
+                    fence(&close)
+                )))
+            }
+            "big" | "Big" | "bigg" | "Bigg" | "bigl" | "bigr" | "Bigl" | "Bigr" | "biggl"
+            | "biggr" | "Biggl" | "Biggr" | "bigm" | "Bigm" | "middle" => {
+                let d = self.delimiter()?;
+                Some(Some(mo(&d)))
+            }
+            "begin" => self.environment().map(Some),
+            "color" => {
+                self.raw_arg()?;
+                Some(None)
+            }
+            "textcolor" => {
+                self.raw_arg()?;
+                self.arg().map(Some)
+            }
+            "label" | "tag" | "phantom" | "hphantom" | "vphantom" => {
+                self.raw_arg()?;
+                Some(None)
+            }
+            "displaystyle" | "textstyle" | "scriptstyle" | "limits" | "nolimits" | "nonumber"
+            | "notag" | "nobreak" | "allowbreak" => Some(None),
+            "mathrel" | "mathbin" | "mathop" | "mathord" | "ensuremath" => self.arg().map(Some),
+            "not" => {
+                let next = self.arg()?;
+                Some(Some(format!("<mrow>{}{}</mrow>", next, mo("̸"))))
+            }
+            "mod" | "pmod" => {
+                let x = self.arg()?;
+                Some(Some(if name == "pmod" {
+                    format!("<mrow><mo>(</mo><mi>mod</mi>{}<mo>)</mo></mrow>", x)
+                } else {
+                    format!("<mrow><mi>mod</mi>{}</mrow>", x)
+                }))
+            }
+            _ => {
+                let sym = lookup(name)?;
+                Some(Some(if is_identifier(name, sym) {
+                    if sym.chars().count() == 1 && sym.chars().all(char::is_uppercase) {
+                        format!("<mi mathvariant=\"normal\">{}</mi>", sym)
+                    } else {
+                        self.mi(sym)
+                    }
+                } else {
+                    mo(sym.trim())
+                }))
+            }
+        }
+    }
+
+    /// `\begin{env}` ... `\end{env}` as an `<mtable>`; the cursor is just
+    /// past `\begin`.
+    fn environment(&mut self) -> Option<String> {
+        let env = self.raw_arg()?;
+        let (open, close, align) = match env {
+            "matrix" | "smallmatrix" | "array" => ("", "", None),
+            "pmatrix" => ("(", ")", None),
+            "bmatrix" => ("[", "]", None),
+            "Bmatrix" => ("{", "}", None),
+            "vmatrix" => ("|", "|", None),
+            "Vmatrix" => ("‖", "‖", None),
+            "cases" => ("{", "", Some("left")),
+            "aligned" | "align" | "align*" | "split" | "alignat" | "alignat*" | "gathered"
+            | "gather" | "gather*" | "eqnarray" | "eqnarray*" => ("", "", Some("right left")),
+            _ => return None,
+        };
+        if env == "array" || env.starts_with("alignat") {
+            self.raw_arg()?;
+        }
+        let mut rows = Vec::new();
+        let mut cells = Vec::new();
+        loop {
+            cells.push(format!("<mtd>{}</mtd>", self.row(Stop::Cell)?));
+            self.skip_ws();
+            if self.starts_with("&") {
+                self.pos += 1;
+            } else if self.starts_with("\\\\") || self.starts_with("\\cr") {
+                self.pos += if self.starts_with("\\cr") { 3 } else { 2 };
+                rows.push(format!("<mtr>{}</mtr>", cells.concat()));
+                cells.clear();
+            } else if self.starts_with("\\end") {
+                self.pos += "\\end".len();
+                if self.raw_arg()? != env {
+                    return None;
+                }
+                break;
+            } else {
+                return None;
+            }
+        }
+        if cells.iter().any(|c| c != "<mtd><mrow></mrow></mtd>") {
+            rows.push(format!("<mtr>{}</mtr>", cells.concat()));
+        }
+        let align = align
+            .map(|a| format!(" columnalign=\"{}\"", a))
+            .unwrap_or_default();
+        let table = format!("<mtable{}>{}</mtable>", align, rows.concat());
+        if open.is_empty() && close.is_empty() {
+            return Some(table);
+        }
+        Some(format!(
+            "<mrow>{}{}{}</mrow>",
+            fence(open),
+            table,
+            fence(close)
+        ))
+    }
+}
+
+fn wrap(items: Vec<String>) -> String {
+    if items.len() == 1 {
+        items.into_iter().next().unwrap()
+    } else {
+        format!("<mrow>{}</mrow>", items.concat())
+    }
+}
+
+fn fence(d: &str) -> String {
+    if d.is_empty() {
+        return String::new();
+    }
+    format!("<mo fence=\"true\" stretchy=\"true\">{}</mo>", escape(d))
+}
+
+/// Parse a nested piece of math (e.g. a root index) on its own.
+fn latex_inner(tex: &str, display: bool) -> Option<String> {
+    let mut p = Parser {
+        s: tex,
+        pos: 0,
+        display,
+        variant: None,
+    };
+    p.row(Stop::End)
+}
+
+/// Text of a `\text{...}` argument: braces dropped, escapes resolved.
+fn text_content(raw: &str) -> String {
+    let mut out = String::with_capacity(raw.len());
+    let mut chars = raw.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' | '}' => {}
+            '~' => out.push('\u{a0}'),
+            '\\' => match chars.peek() {
+                Some(&n) if !n.is_ascii_alphabetic() => {
+                    chars.next();
+                    out.push(if n == ',' || n == ' ' { ' ' } else { n });
+                }
+                _ => {
+                    // Drop the command name, keep its argument's text.
+                    while chars.peek().is_some_and(|c| c.is_ascii_alphabetic()) {
+                        chars.next();
+                    }
+                }
+            },
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn inline(tex: &str) -> String {
+        let m = latex_to_mathml(tex, false).unwrap();
+        let open = format!("<math xmlns=\"{}\" display=\"inline\">", NS);
+        m.strip_prefix(&open)
+            .and_then(|m| m.strip_suffix("</math>"))
+            .unwrap()
+            .to_string()
+    }
+
+    #[test]
+    fn converts_common_math() {
+        assert_eq!(
+            inline(r"\alpha \le \beta^2"),
+            "<mrow><mi>α</mi><mo>≤</mo><msup><mi>β</mi><mn>2</mn></msup></mrow>"
+        );
+        assert_eq!(
+            inline(r"\frac{a}{b} < \sqrt[3]{x}"),
+            "<mrow><mfrac><mi>a</mi><mi>b</mi></mfrac><mo>&lt;</mo><mroot><mi>x</mi><mn>3</mn></mroot></mrow>"
+        );
+        assert_eq!(
+            inline(r"\mathbb{R}^{d}"),
+            "<msup><mi mathvariant=\"double-struck\">R</mi><mi>d</mi></msup>"
+        );
+        assert_eq!(
+            inline(r"\text{if } x_*"),
+            "<mrow><mtext>if </mtext><msub><mi>x</mi><mo>&#42;</mo></msub></mrow>"
+        );
+    }
+
+    #[test]
+    fn display_operators_take_limits_and_environments_become_tables() {
+        let m = latex_to_mathml(r"\sum_{i=1}^{n} x_i", true).unwrap();
+        assert!(m.contains("display=\"block\""));
+        assert!(m.contains("<munderover><mo>∑</mo><mrow><mi>i</mi><mo>=</mo><mn>1</mn></mrow><mi>n</mi></munderover>"));
+        let m = latex_to_mathml(
+            r"\left( \begin{matrix} a & b \\ c & d \end{matrix} \right)",
+            true,
+        )
+        .unwrap();
+        assert!(m.contains("<mo fence=\"true\" stretchy=\"true\">(</mo><mtable><mtr><mtd><mi>a</mi></mtd><mtd><mi>b</mi></mtd></mtr><mtr><mtd><mi>c</mi></mtd><mtd><mi>d</mi></mtd></mtr></mtable>"));
+    }
+
+    #[test]
+    fn unknown_commands_keep_latex() {
+        assert_eq!(latex_to_mathml(r"\foo{x}", false), None);
+        assert_eq!(latex_to_mathml(r"\begin{tikzcd}a\end{tikzcd}", false), None);
+        let md = "See $x^2$ and $\\foo$.\n\n$$\na = b\n$$\n";
+        let out = markdown_math_to_mathml(md);
+        assert!(out.starts_with("See <math xmlns="));
+        assert!(out.contains("and $\\foo$."));
+        assert!(out.contains("\n\n<math xmlns=\"http://www.w3.org/1998/Math/MathML\" display=\"block\"><mrow><mi>a</mi><mo>=</mo><mi>b</mi></mrow></math>\n"));
+    }
+}
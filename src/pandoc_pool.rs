@@ -0,0 +1,349 @@
+use std::io;
+use std::net::TcpListener;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use base64::Engine;
+use serde_json::{json, Map, Value};
+use tokio::process::{Child, Command};
+use tokio::sync::Mutex;
+
+use crate::convert::ConvertError;
+
+/// How to run long-lived pandoc workers.
+#[derive(Debug, Clone)]
+pub struct PandocPoolConfig {
+    /// The pandoc binary; workers run `pandoc server`.
+    pub pandoc: String,
+    pub workers: usize,
+    /// Per-conversion limit, enforced by the server and by the client.
+    pub timeout: Duration,
+    /// How often idle workers are checked and restarted if they died.
+    pub health_interval: Duration,
+}
+
+/// `pandoc server` processes on loopback ports, used round-robin. A worker
+/// that stops answering is killed and started again, by the periodic
+/// health check or by the request that found it dead.
+pub struct PandocPool {
+    cfg: PandocPoolConfig,
+    workers: Vec<Mutex<Worker>>,
+    next: AtomicUsize,
+    http: reqwest::Client,
+}
+
+struct Worker {
+    port: u16,
+    child: Child,
+    restarts: usize,
+}
+
+/// How long a freshly started worker gets to answer its first health check.
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(15);
+
+impl PandocPool {
+    /// Start the workers and wait until each answers, then keep them
+    /// healthy in the background.
+    pub async fn start(cfg: PandocPoolConfig) -> io::Result<Arc<Self>> {
+        let http = reqwest::Client::builder()
+            .timeout(cfg.timeout + Duration::from_secs(1))
+            .build()
+            .map_err(io::Error::other)?;
+        let mut workers = Vec::new();
+        for _ in 0..cfg.workers.max(1) {
+            let worker = spawn_worker(&cfg, &http).await?;
+            workers.push(Mutex::new(worker));
+        }
+        let pool = Arc::new(Self {
+            cfg,
+            workers,
+            next: AtomicUsize::new(0),
+            http,
+        });
+        Self::spawn_health_checks(Arc::downgrade(&pool));
+        Ok(pool)
+    }
+
+    pub fn workers(&self) -> usize {
+        self.workers.len()
+    }
+
+    fn spawn_health_checks(pool: std::sync::Weak<Self>) {
+        tokio::spawn(async move {
+            loop {
+                let Some(interval) = pool.upgrade().map(|p| p.cfg.health_interval) else {
+                    return;
+                };
+                tokio::time::sleep(interval).await;
+                let Some(pool) = pool.upgrade() else {
+                    return;
+                };
+                for i in 0..pool.workers.len() {
+                    let port = pool.workers[i].lock().await.port;
+                    if !is_healthy(&pool.http, port).await {
+                        tracing::warn!(worker = i, port, "pandoc worker failed health check");
+                        pool.restart(i, port).await;
+                    }
+                }
+            }
+        });
+    }
+
+    /// Convert `main_file` in `cwd` like `pandoc -f {from} -t gfm
+    /// {extra_args} {main_file}` would, returning the output and pandoc's
+    /// warnings.
+    pub async fn convert(
+        &self,
+        cwd: &Path,
+        main_file: &str,
+        from: &str,
+        extra_args: &[String],
+    ) -> Result<(Vec<u8>, Vec<String>), ConvertError> {
+        let text = tokio::fs::read_to_string(cwd.join(main_file))
+            .await
+            .map_err(|e| ConvertError::Failed(format!("read {}: {}", main_file, e)))?;
+        let body = request_body(&text, from, extra_args, cwd).await?;
+        let i = self.next.fetch_add(1, Ordering::Relaxed) % self.workers.len();
+        let port = self.workers[i].lock().await.port;
+        let res = self
+            .http
+            .post(format!("http://127.0.0.1:{}/", port))
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .header(reqwest::header::ACCEPT, "application/json")
+            .body(body.to_string())
+            .send()
+            .await;
+        let res = match res {
+            Ok(res) => res,
+            Err(e) => {
+                // Timeouts and dead workers both leave it unusable.
+                self.restart(i, port).await;
+                return Err(ConvertError::Failed(format!("pandoc server: {}", e)));
+            }
+        };
+        let status = res.status();
+        let bytes = res
+            .bytes()
+            .await
+            .map_err(|e| ConvertError::Failed(format!("pandoc server: {}", e)))?;
+        if !status.is_success() {
+            return Err(ConvertError::Failed(format!(
+                "pandoc failed: {}",
+                String::from_utf8_lossy(&bytes)
+            )));
+        }
+        parse_response(&bytes)
+    }
+
+    /// Replace worker `i`, unless someone already did since it was seen on
+    /// `port`.
+    async fn restart(&self, i: usize, port: u16) {
+        let mut worker = self.workers[i].lock().await;
+        if worker.port != port {
+            return;
+        }
+        let _ = worker.child.start_kill();
+        match spawn_worker(&self.cfg, &self.http).await {
+            Ok(mut fresh) => {
+                fresh.restarts = worker.restarts + 1;
+                tracing::info!(
+                    worker = i,
+                    port = fresh.port,
+                    restarts = fresh.restarts,
+                    "restarted pandoc worker"
+                );
+                *worker = fresh;
+            }
+            Err(e) => tracing::error!(worker = i, error = %e, "pandoc worker restart failed"),
+        }
+    }
+}
+
+async fn spawn_worker(cfg: &PandocPoolConfig, http: &reqwest::Client) -> io::Result<Worker> {
+    let port = free_port()?;
+    let mut child = Command::new(&cfg.pandoc)
+        .arg("server")
+        .arg("--port")
+        .arg(port.to_string())
+        .arg("--timeout")
+        .arg(cfg.timeout.as_secs().max(1).to_string())
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .kill_on_drop(true)
+        .spawn()?;
+    let deadline = tokio::time::Instant::now() + STARTUP_TIMEOUT;
+    while tokio::time::Instant::now() < deadline {
+        if let Some(status) = child.try_wait()? {
+            return Err(io::Error::other(format!(
+                "pandoc server exited at startup: {}",
+                status
+            )));
+        }
+        if is_healthy(http, port).await {
+            return Ok(Worker {
+                port,
+                child,
+                restarts: 0,
+            });
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    let _ = child.start_kill();
+    Err(io::Error::new(
+        io::ErrorKind::TimedOut,
+        "pandoc server did not become healthy",
+    ))
+}
+
+fn free_port() -> io::Result<u16> {
+    Ok(TcpListener::bind("127.0.0.1:0")?.local_addr()?.port())
+}
+
+async fn is_healthy(http: &reqwest::Client, port: u16) -> bool {
+    match http
+        .get(format!("http://127.0.0.1:{}/version", port))
+        .timeout(Duration::from_secs(2))
+        .send()
+        .await
+    {
+        Ok(res) => res.status().is_success(),
+        Err(_) => false,
+    }
+}
+
+/// The server's JSON request for the command-line arguments the spawn
+/// backend would pass. Bibliographies are read from disk and sent along,
+/// since the server has no file system access.
+async fn request_body(
+    text: &str,
+    from: &str,
+    extra_args: &[String],
+    cwd: &Path,
+) -> Result<Value, ConvertError> {
+    let mut body = json!({ "text": text, "from": from, "to": "gfm" });
+    let mut metadata = Map::new();
+    let mut bibliography = Vec::new();
+    let mut files = Map::new();
+    let mut args = extra_args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--citeproc" => body["citeproc"] = Value::Bool(true),
+            "--csl" => {
+                let Some(path) = args.next() else { break };
+                files.insert(path.clone(), encode_file(cwd, path).await?);
+                body["csl"] = Value::String(path.clone());
+            }
+            "--bibliography" => {
+                let Some(path) = args.next() else { break };
+                files.insert(path.clone(), encode_file(cwd, path).await?);
+                bibliography.push(Value::String(path.clone()));
+            }
+            "-M" => {
+                let Some((key, value)) = args.next().and_then(|kv| kv.split_once('=')) else {
+                    continue;
+                };
+                let value = match value {
+                    "true" => Value::Bool(true),
+                    "false" => Value::Bool(false),
+                    v => Value::String(v.to_string()),
+                };
+                metadata.insert(key.to_string(), value);
+            }
+            other => tracing::warn!(arg = other, "pandoc argument not supported by the server"),
+        }
+    }
+    if !bibliography.is_empty() {
+        body["bibliography"] = Value::Array(bibliography);
+    }
+    if !files.is_empty() {
+        body["files"] = Value::Object(files);
+    }
+    if !metadata.is_empty() {
+        body["metadata"] = Value::Object(metadata);
+    }
+    Ok(body)
+}
+
+/// A file for the request's `files` map, base64-encoded.
+async fn encode_file(cwd: &Path, path: &str) -> Result<Value, ConvertError> {
+    let bytes = tokio::fs::read(cwd.join(path))
+        .await
+        .map_err(|e| ConvertError::Failed(format!("read {}: {}", path, e)))?;
+    Ok(Value::String(
+        base64::engine::general_purpose::STANDARD.encode(bytes),
+    ))
+}
+
+/// Output and warnings from the server's JSON response.
+fn parse_response(bytes: &[u8]) -> Result<(Vec<u8>, Vec<String>), ConvertError> {
+    let value: Value = serde_json::from_slice(bytes)
+        .map_err(|e| ConvertError::Failed(format!("pandoc server response: {}", e)))?;
+    if let Some(err) = value.get("error").and_then(Value::as_str) {
+        return Err(ConvertError::Failed(format!("pandoc failed: {}", err)));
+    }
+    let output = value
+        .get("output")
+        .and_then(Value::as_str)
+        .ok_or_else(|| ConvertError::Failed("pandoc server response has no output".into()))?;
+    let warnings = value
+        .get("messages")
+        .and_then(Value::as_array)
+        .map(|messages| {
+            messages
+                .iter()
+                .filter_map(|m| {
+                    let text = m.get("message").and_then(Value::as_str)?;
+                    let level = m
+                        .get("verbosity")
+                        .and_then(Value::as_str)
+                        .unwrap_or("WARNING");
+                    Some(format!("[{}] {}", level, text))
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    Ok((output.as_bytes().to_vec(), warnings))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn citeproc_args_become_request_fields() {
+        let dir = std::env::temp_dir().join(format!("mk-pool-{}", std::process::id()));
+        tokio::fs::create_dir_all(&dir).await.unwrap();
+        tokio::fs::write(dir.join("refs.bib"), "@misc{a}")
+            .await
+            .unwrap();
+        tokio::fs::write(dir.join("n.csl"), "<style/>")
+            .await
+            .unwrap();
+        let args = crate::bibliography::citeproc_args(&["refs.bib".into()], Path::new("n.csl"));
+        let body = request_body("\\cite{a}", "latex-latex_macros", &args, &dir)
+            .await
+            .unwrap();
+        let _ = tokio::fs::remove_dir_all(&dir).await;
+        assert_eq!(body["from"], "latex-latex_macros");
+        assert_eq!(body["to"], "gfm");
+        assert_eq!(body["citeproc"], true);
+        assert_eq!(body["bibliography"][0], "refs.bib");
+        assert_eq!(body["files"]["refs.bib"], "QG1pc2N7YX0=");
+        assert_eq!(body["csl"], "n.csl");
+        assert_eq!(body["files"]["n.csl"], "PHN0eWxlLz4=");
+        assert_eq!(body["metadata"]["link-citations"], true);
+        assert_eq!(body["metadata"]["reference-section-title"], "References");
+    }
+
+    #[test]
+    fn parses_output_messages_and_errors() {
+        let ok = br##"{"output":"# Hi\n","base64":false,"messages":[{"verbosity":"WARNING","message":"Could not convert TeX math"}]}"##;
+        let (out, warnings) = parse_response(ok).unwrap();
+        assert_eq!(out, b"# Hi\n");
+        assert_eq!(warnings, vec!["[WARNING] Could not convert TeX math"]);
+        assert!(parse_response(br#"{"error":"Unknown reader: x"}"#).is_err());
+        assert!(parse_response(b"not json").is_err());
+    }
+}
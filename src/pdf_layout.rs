@@ -0,0 +1,542 @@
+//! Layout analysis for the PDF fallback: turns `pdftotext -bbox-layout`
+//! output into Markdown with headings and paragraphs in reading order.
+
+/// A word, line or block bounding box in PDF points, origin top left.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+struct Bbox {
+    x0: f64,
+    y0: f64,
+    x1: f64,
+    y1: f64,
+}
+
+#[derive(Debug, Default)]
+struct Line {
+    bbox: Bbox,
+    words: Vec<String>,
+}
+
+impl Line {
+    fn text(&self) -> String {
+        self.words.join(" ")
+    }
+
+    /// Glyph height, which tracks the font size.
+    fn size(&self) -> f64 {
+        self.bbox.y1 - self.bbox.y0
+    }
+}
+
+#[derive(Debug, Default)]
+struct Block {
+    bbox: Bbox,
+    lines: Vec<Line>,
+}
+
+impl Block {
+    /// Median line size.
+    fn size(&self) -> f64 {
+        let mut sizes: Vec<f64> = self.lines.iter().map(Line::size).collect();
+        sizes.sort_by(f64::total_cmp);
+        sizes.get(sizes.len() / 2).copied().unwrap_or(0.0)
+    }
+}
+
+#[derive(Debug, Default)]
+struct Page {
+    width: f64,
+    height: f64,
+    blocks: Vec<Block>,
+}
+
+/// Share of the page height at the top and bottom where running headers,
+/// footers and page numbers live.
+const MARGIN: f64 = 0.08;
+
+/// Blocks at least this much larger than body text are headings.
+const HEADING_RATIO: f64 = 1.15;
+
+/// Section names that are headings even at body size.
+const SECTION_NAMES: &[&str] = &[
+    "abstract",
+    "introduction",
+    "related work",
+    "background",
+    "method",
+    "methods",
+    "results",
+    "discussion",
+    "conclusion",
+    "conclusions",
+    "acknowledgments",
+    "acknowledgements",
+    "acknowledgment",
+    "acknowledgement",
+    "references",
+    "bibliography",
+    "appendix",
+];
+
+/// Convert `pdftotext -bbox-layout` XHTML to Markdown. Returns an empty
+/// string when the input has no words.
+pub fn bbox_to_markdown(xhtml: &str) -> String {
+    let mut pages = parse(xhtml);
+    remove_running_lines(&mut pages);
+    let body = body_size(&pages);
+    let blocks: Vec<&Block> = pages.iter().flat_map(reading_order).collect();
+    let heading_sizes = heading_sizes(&blocks, body);
+
+    let mut out: Vec<String> = Vec::new();
+    // Whether the last entry is a paragraph a following block may continue.
+    let mut open_paragraph = false;
+    for block in blocks {
+        let text = join_lines(&block.lines);
+        if text.is_empty() {
+            continue;
+        }
+        if let Some(level) = heading_level(block, &text, body, &heading_sizes) {
+            out.push(format!("{} {}", "#".repeat(level), text));
+            open_paragraph = false;
+            continue;
+        }
+        match out.last_mut() {
+            Some(prev) if open_paragraph && continues(prev, &text) => {
+                join_text(prev, &text);
+            }
+            _ => out.push(text),
+        }
+        open_paragraph = true;
+    }
+    let mut md = out.join("\n\n");
+    if !md.is_empty() {
+        md.push('\n');
+    }
+    md
+}
+
+/// Minimal scanner for the `page`/`block`/`line`/`word` elements.
+fn parse(xhtml: &str) -> Vec<Page> {
+    let mut pages: Vec<Page> = Vec::new();
+    let mut i = 0;
+    while let Some(rel) = xhtml[i..].find('<') {
+        let start = i + rel;
+        let Some(len) = xhtml[start..].find('>') else {
+            break;
+        };
+        let tag = &xhtml[start + 1..start + len];
+        i = start + len + 1;
+        let name = tag
+            .split(|c: char| c.is_whitespace() || c == '/')
+            .next()
+            .unwrap_or("");
+        match name {
+            "page" => pages.push(Page {
+                width: attr(tag, "width").unwrap_or(0.0),
+                height: attr(tag, "height").unwrap_or(0.0),
+                blocks: Vec::new(),
+            }),
+            "block" => {
+                if let Some(page) = pages.last_mut() {
+                    page.blocks.push(Block {
+                        bbox: bbox(tag),
+                        lines: Vec::new(),
+                    });
+                }
+            }
+            "line" => {
+                if let Some(block) = pages.last_mut().and_then(|p| p.blocks.last_mut()) {
+                    block.lines.push(Line {
+                        bbox: bbox(tag),
+                        words: Vec::new(),
+                    });
+                }
+            }
+            "word" => {
+                let end = xhtml[i..].find("</word>").map(|e| i + e).unwrap_or(i);
+                let word = decode_entities(xhtml[i..end].trim());
+                i = end;
+                if let Some(line) = pages
+                    .last_mut()
+                    .and_then(|p| p.blocks.last_mut())
+                    .and_then(|b| b.lines.last_mut())
+                {
+                    if !word.is_empty() {
+                        line.words.push(word);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    for page in &mut pages {
+        for block in &mut page.blocks {
+            block.lines.retain(|l| !l.words.is_empty());
+        }
+        page.blocks.retain(|b| !b.lines.is_empty());
+    }
+    pages
+}
+
+fn attr(tag: &str, name: &str) -> Option<f64> {
+    let needle = format!("{}=\"", name);
+    let at = tag.find(&needle)? + needle.len();
+    let end = tag[at..].find('"')? + at;
+    tag[at..end].parse().ok()
+}
+
+fn bbox(tag: &str) -> Bbox {
+    Bbox {
+        x0: attr(tag, "xMin").unwrap_or(0.0),
+        y0: attr(tag, "yMin").unwrap_or(0.0),
+        x1: attr(tag, "xMax").unwrap_or(0.0),
+        y1: attr(tag, "yMax").unwrap_or(0.0),
+    }
+}
+
+fn decode_entities(s: &str) -> String {
+    if !s.contains('&') {
+        return s.to_string();
+    }
+    let mut out = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(at) = rest.find('&') {
+        out.push_str(&rest[..at]);
+        rest = &rest[at..];
+        let Some(end) = rest.find(';').filter(|&e| e <= 10) else {
+            out.push('&');
+            rest = &rest[1..];
+            continue;
+        };
+        let entity = &rest[1..end];
+        let decoded = match entity {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            _ => entity
+                .strip_prefix("#x")
+                .and_then(|h| u32::from_str_radix(h, 16).ok())
+                .or_else(|| entity.strip_prefix('#').and_then(|d| d.parse().ok()))
+                .and_then(char::from_u32),
+        };
+        match decoded {
+            Some(c) => {
+                out.push(c);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Text of a margin line with digits masked, so "Page 3" on every page
+/// counts as the same line.
+fn running_key(text: &str) -> String {
+    text.chars()
+        .map(|c| if c.is_ascii_digit() { '#' } else { c })
+        .collect::<String>()
+        .to_lowercase()
+}
+
+fn is_page_number(text: &str) -> bool {
+    let t = text
+        .trim()
+        .trim_start_matches("Page")
+        .trim_start_matches("page");
+    let t = t.trim();
+    !t.is_empty()
+        && (t.chars().all(|c| c.is_ascii_digit())
+            || t.chars().all(|c| "ivxlcdm".contains(c))
+            || t.split(" of ")
+                .all(|p| !p.is_empty() && p.chars().all(|c| c.is_ascii_digit())))
+}
+
+/// Drop running headers and footers (margin lines repeated on at least half
+/// the pages), page numbers, and text in the left margin such as arXiv's
+/// rotated submission stamp.
+fn remove_running_lines(pages: &mut [Page]) {
+    let in_margin = |page: &Page, line: &Line| {
+        line.bbox.y1 <= page.height * MARGIN || line.bbox.y0 >= page.height * (1.0 - MARGIN)
+    };
+    let mut counts: std::collections::HashMap<String, usize> = Default::default();
+    for page in pages.iter() {
+        let mut keys: Vec<String> = page
+            .blocks
+            .iter()
+            .flat_map(|b| &b.lines)
+            .filter(|l| in_margin(page, l))
+            .map(|l| running_key(&l.text()))
+            .collect();
+        keys.sort();
+        keys.dedup();
+        for key in keys {
+            *counts.entry(key).or_default() += 1;
+        }
+    }
+    let threshold = (pages.len() / 2).max(2);
+    for page in pages.iter_mut() {
+        let (width, height) = (page.width, page.height);
+        for block in &mut page.blocks {
+            block.lines.retain(|l| {
+                let margin = l.bbox.y1 <= height * MARGIN || l.bbox.y0 >= height * (1.0 - MARGIN);
+                let text = l.text();
+                let running = margin
+                    && (is_page_number(&text)
+                        || counts.get(&running_key(&text)).copied().unwrap_or(0) >= threshold);
+                let stamp = width > 0.0 && l.bbox.x1 <= width * MARGIN;
+                !running && !stamp
+            });
+        }
+        page.blocks.retain(|b| !b.lines.is_empty());
+    }
+}
+
+/// The most common line size, weighted by text length.
+fn body_size(pages: &[Page]) -> f64 {
+    let mut weights: Vec<(i64, usize)> = Vec::new();
+    for line in pages.iter().flat_map(|p| &p.blocks).flat_map(|b| &b.lines) {
+        let key = (line.size() * 2.0).round() as i64;
+        let n = line.words.iter().map(|w| w.len()).sum::<usize>();
+        match weights.iter_mut().find(|(k, _)| *k == key) {
+            Some((_, w)) => *w += n,
+            None => weights.push((key, n)),
+        }
+    }
+    weights
+        .into_iter()
+        .max_by_key(|(_, w)| *w)
+        .map(|(k, _)| k as f64 / 2.0)
+        .unwrap_or(0.0)
+}
+
+/// Blocks in reading order: top to bottom, with two-column stretches read
+/// left column first. Blocks spanning the middle of the page (titles,
+/// wide figures) separate the stretches.
+fn reading_order(page: &Page) -> Vec<&Block> {
+    let mid = page.width / 2.0;
+    let slack = page.width * 0.02;
+    let mut blocks: Vec<&Block> = page.blocks.iter().collect();
+    blocks.sort_by(|a, b| a.bbox.y0.total_cmp(&b.bbox.y0));
+    let is_full = |b: &Block| b.bbox.x0 < mid - slack && b.bbox.x1 > mid + slack;
+
+    let mut out = Vec::with_capacity(blocks.len());
+    let mut left: Vec<&Block> = Vec::new();
+    let mut right: Vec<&Block> = Vec::new();
+    for block in blocks {
+        if page.width > 0.0 && is_full(block) {
+            out.append(&mut left);
+            out.append(&mut right);
+            out.push(block);
+        } else if block.bbox.x0 >= mid - slack {
+            right.push(block);
+        } else {
+            left.push(block);
+        }
+    }
+    out.append(&mut left);
+    out.append(&mut right);
+    out
+}
+
+/// Distinct heading sizes, largest first, rounded to half points.
+fn heading_sizes(blocks: &[&Block], body: f64) -> Vec<i64> {
+    let mut sizes: Vec<i64> = blocks
+        .iter()
+        .filter(|b| looks_like_heading(b) && b.size() >= body * HEADING_RATIO)
+        .map(|b| (b.size() * 2.0).round() as i64)
+        .collect();
+    sizes.sort_unstable_by(|a, b| b.cmp(a));
+    sizes.dedup();
+    sizes
+}
+
+fn looks_like_heading(block: &Block) -> bool {
+    block.lines.len() <= 3 && block.lines.iter().map(|l| l.text().len()).sum::<usize>() <= 120
+}
+
+/// `#` level for a heading block: by font size rank when larger than body
+/// text, by section number depth ("2.1 Setup") or a known section name
+/// otherwise.
+fn heading_level(block: &Block, text: &str, body: f64, sizes: &[i64]) -> Option<usize> {
+    if !looks_like_heading(block) {
+        return None;
+    }
+    if block.size() >= body * HEADING_RATIO {
+        let key = (block.size() * 2.0).round() as i64;
+        let rank = sizes.iter().position(|&s| s == key).unwrap_or(0);
+        return Some((rank + 1).min(3));
+    }
+    if block.lines.len() > 1 || text.ends_with('.') || text.len() > 60 {
+        return None;
+    }
+    let name = text.trim_start_matches(|c: char| c.is_ascii_digit() || c == '.' || c == ' ');
+    if SECTION_NAMES.contains(&name.to_lowercase().as_str()) {
+        return Some(2);
+    }
+    let (number, title) = text.split_once(' ')?;
+    let number = number.trim_end_matches('.');
+    let numbered = !number.is_empty()
+        && number
+            .split('.')
+            .all(|p| !p.is_empty() && p.chars().all(|c| c.is_ascii_digit()));
+    let titled = title.chars().next().is_some_and(char::is_uppercase);
+    (numbered && titled).then(|| (number.split('.').count() + 1).min(4))
+}
+
+/// Join a block's lines into one paragraph, undoing end-of-line hyphens.
+fn join_lines(lines: &[Line]) -> String {
+    let mut text = String::new();
+    for line in lines {
+        join_text(&mut text, &line.text());
+    }
+    text
+}
+
+/// Append `next` to `text`: a trailing hyphen between letters joins the
+/// word halves ("exam-" + "ple"), anything else gets a space.
+fn join_text(text: &mut String, next: &str) {
+    if text.is_empty() {
+        text.push_str(next);
+        return;
+    }
+    let hyphenated = text.ends_with('-')
+        && text[..text.len() - 1]
+            .chars()
+            .next_back()
+            .is_some_and(char::is_alphabetic)
+        && next.chars().next().is_some_and(char::is_lowercase);
+    if hyphenated {
+        text.pop();
+    } else {
+        text.push(' ');
+    }
+    text.push_str(next);
+}
+
+/// A paragraph cut by a column or page break: the previous text stops
+/// mid-sentence and the next starts in lowercase.
+fn continues(prev: &str, next: &str) -> bool {
+    !prev.ends_with(['.', '?', '!', ':']) && next.chars().next().is_some_and(char::is_lowercase)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A `<block>` with one `<line>` per (y, size, text) entry.
+    fn block(x0: f64, x1: f64, lines: &[(f64, f64, &str)]) -> String {
+        let mut out = format!(
+            "<block xMin=\"{}\" yMin=\"{}\" xMax=\"{}\" yMax=\"{}\">\n",
+            x0,
+            lines[0].0,
+            x1,
+            lines[lines.len() - 1].0 + lines[lines.len() - 1].1
+        );
+        for (y, size, text) in lines {
+            out.push_str(&format!(
+                "<line xMin=\"{}\" yMin=\"{}\" xMax=\"{}\" yMax=\"{}\">\n",
+                x0,
+                y,
+                x1,
+                y + size
+            ));
+            for w in text.split(' ') {
+                out.push_str(&format!(
+                    "<word xMin=\"{}\" yMin=\"{}\" xMax=\"{}\" yMax=\"{}\">{}</word>\n",
+                    x0,
+                    y,
+                    x1,
+                    y + size,
+                    w
+                ));
+            }
+            out.push_str("</line>\n");
+        }
+        out.push_str("</block>\n");
+        out
+    }
+
+    fn page(blocks: &[String]) -> String {
+        format!(
+            "<page width=\"600\" height=\"800\">\n<flow>\n{}</flow>\n</page>\n",
+            blocks.concat()
+        )
+    }
+
+    fn document(pages: &[String]) -> String {
+        format!(
+            "<!DOCTYPE html>\n<html><head><title></title></head><body>\n<doc>\n{}</doc>\n</body></html>",
+            pages.concat()
+        )
+    }
+
+    #[test]
+    fn orders_columns_and_infers_headings() {
+        let header = |n: &str| {
+            block(
+                200.0,
+                400.0,
+                &[(20.0, 8.0, &format!("Journal of Tests {}", n))],
+            )
+        };
+        let xhtml = document(&[
+            page(&[
+                header("1"),
+                block(100.0, 500.0, &[(80.0, 18.0, "A Layout Study")]),
+                block(60.0, 290.0, &[(150.0, 10.0, "1 Introduction")]),
+                block(
+                    60.0,
+                    290.0,
+                    &[
+                        (170.0, 10.0, "Left column text with an exam-"),
+                        (182.0, 10.0, "ple that continues"),
+                    ],
+                ),
+                block(310.0, 540.0, &[(150.0, 10.0, "into the right column.")]),
+                block(20.0, 40.0, &[(300.0, 10.0, "arXiv:2401.00001v1")]),
+                block(290.0, 310.0, &[(770.0, 8.0, "1")]),
+            ]),
+            page(&[
+                header("2"),
+                block(60.0, 290.0, &[(100.0, 13.0, "2.1 Setup &amp; Data")]),
+                block(60.0, 290.0, &[(120.0, 10.0, "Body & more text here.")]),
+                block(290.0, 310.0, &[(770.0, 8.0, "2")]),
+            ]),
+        ]);
+        let md = bbox_to_markdown(&xhtml);
+        assert_eq!(
+            md,
+            "# A Layout Study\n\n\
+             ## 1 Introduction\n\n\
+             Left column text with an example that continues into the right column.\n\n\
+             ## 2.1 Setup & Data\n\n\
+             Body & more text here.\n"
+        );
+    }
+
+    #[test]
+    fn body_size_section_names_and_numbers_become_headings() {
+        let xhtml = document(&[page(&[
+            block(60.0, 540.0, &[(100.0, 10.0, "Abstract")]),
+            block(60.0, 540.0, &[(120.0, 10.0, "We study things.")]),
+            block(60.0, 540.0, &[(140.0, 10.0, "3.2 Ablations")]),
+            block(60.0, 540.0, &[(160.0, 10.0, "2 apples were eaten.")]),
+        ])]);
+        let md = bbox_to_markdown(&xhtml);
+        assert!(md.contains("## Abstract\n\nWe study things."));
+        assert!(md.contains("### 3.2 Ablations"));
+        assert!(md.contains("\n\n2 apples were eaten.\n"));
+    }
+
+    #[test]
+    fn empty_input_gives_empty_output() {
+        assert_eq!(bbox_to_markdown(""), "");
+        assert_eq!(bbox_to_markdown(&document(&[page(&[])])), "");
+    }
+}
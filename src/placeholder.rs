@@ -0,0 +1,103 @@
+/// Opaque tokens that survive a pandoc round trip unchanged.
+///
+/// TeX pre-processing passes that know better than pandoc how to render a
+/// construct (citations, cross-references, pseudocode, ...) substitute a
+/// token such as `MXPH3Z` into the source and register the Markdown that
+/// should eventually replace it. Tokens are plain ASCII letters and digits so
+/// the gfm writer neither escapes nor wraps them; [`Placeholders::resolve`]
+/// swaps them back after sanitization.
+#[derive(Debug, Default, Clone)]
+pub struct Placeholders {
+    items: Vec<Entry>,
+}
+
+#[derive(Debug, Clone)]
+struct Entry {
+    markdown: String,
+    block: bool,
+}
+
+impl Placeholders {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register inline Markdown and return the token to put into the TeX.
+    pub fn inline(&mut self, markdown: impl Into<String>) -> String {
+        self.push(markdown.into(), false)
+    }
+
+    /// Register block-level Markdown. When resolved, a token that sits alone
+    /// on its line (pandoc gives it a paragraph of its own) is replaced by the
+    /// block surrounded by blank lines.
+    pub fn block(&mut self, markdown: impl Into<String>) -> String {
+        let token = self.push(markdown.into(), true);
+        format!("\n\n{}\n\n", token)
+    }
+
+    fn push(&mut self, markdown: String, block: bool) -> String {
+        self.items.push(Entry { markdown, block });
+        token(self.items.len() - 1)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    /// Replace every token in `md` with its registered Markdown. Tokens are
+    /// resolved in reverse registration order, so a block registered after
+    /// the citations inside it has those citations resolved afterwards.
+    pub fn resolve(&self, md: &str) -> String {
+        let mut out = md.to_string();
+        for (i, entry) in self.items.iter().enumerate().rev() {
+            let tok = token(i);
+            if !out.contains(&tok) {
+                continue;
+            }
+            if entry.block {
+                let mut rebuilt = String::with_capacity(out.len());
+                for line in out.split_inclusive('\n') {
+                    if line.trim() == tok {
+                        rebuilt.push('\n');
+                        rebuilt.push_str(entry.markdown.trim_end());
+                        rebuilt.push_str("\n\n");
+                    } else {
+                        rebuilt.push_str(line);
+                    }
+                }
+                out = rebuilt.replace(&tok, entry.markdown.trim());
+            } else {
+                out = out.replace(&tok, &entry.markdown);
+            }
+        }
+        out
+    }
+}
+
+fn token(i: usize) -> String {
+    format!("MXPH{}Z", i)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolves_inline_and_block_tokens() {
+        let mut ph = Placeholders::new();
+        let a = ph.inline("[1](#ref-a)");
+        let b = ph.block("## References\n\n1. A");
+        let md = format!("See {}.\n\n{}\n\nEnd", a, b.trim());
+        let out = ph.resolve(&md);
+        assert!(out.contains("See [1](#ref-a)."));
+        assert!(out.contains("\n## References\n\n1. A\n\n"));
+    }
+
+    #[test]
+    fn resolution_does_not_confuse_prefixes() {
+        let mut ph = Placeholders::new();
+        let toks: Vec<String> = (0..13).map(|i| ph.inline(format!("<{}>", i))).collect();
+        let out = ph.resolve(&format!("{} {}", toks[1], toks[12]));
+        assert_eq!(out, "<1> <12>");
+    }
+}
@@ -0,0 +1,230 @@
+use std::sync::Arc;
+use std::time::Instant;
+
+use crate::convert::{
+    extract_figure_captions, normalize_display_math, strip_html_tags_preserve_math,
+};
+use crate::katex;
+use crate::report::ConversionReport;
+use crate::tables;
+
+/// One step of cleaning up pandoc's Markdown. Stages run in the order of
+/// their [`Pipeline`]; library users can add their own.
+pub trait PostProcessor: Send + Sync {
+    /// Short name used to configure the pipeline and to label the stage's
+    /// timing in the conversion report.
+    fn name(&self) -> &str;
+    fn process(&self, md: &str, report: &mut ConversionReport) -> String;
+}
+
+/// `<figure>` blocks become Markdown caption lines.
+pub struct FigureCaptions;
+
+impl PostProcessor for FigureCaptions {
+    fn name(&self) -> &str {
+        "figures"
+    }
+
+    fn process(&self, md: &str, _report: &mut ConversionReport) -> String {
+        extract_figure_captions(md)
+    }
+}
+
+/// HTML tables become pipe tables, or sanitized HTML when they have
+/// merged cells.
+pub struct Tables;
+
+impl PostProcessor for Tables {
+    fn name(&self) -> &str {
+        "tables"
+    }
+
+    fn process(&self, md: &str, _report: &mut ConversionReport) -> String {
+        tables::convert_tables(md)
+    }
+}
+
+/// Math commands KaTeX doesn't support are rewritten; the ones it can't
+/// be rewritten into are reported as unknown macros.
+pub struct KatexFixes;
+
+impl PostProcessor for KatexFixes {
+    fn name(&self) -> &str {
+        "katex"
+    }
+
+    fn process(&self, md: &str, report: &mut ConversionReport) -> String {
+        let math = katex::rewrite(md);
+        if !math.unsupported.is_empty() {
+            tracing::warn!(
+                commands = %math.unsupported.join(" "),
+                "math commands without a KaTeX equivalent"
+            );
+        }
+        report.add_unknown_macros(&math.unsupported);
+        math.markdown
+    }
+}
+
+/// Display math blocks are put on their own lines.
+pub struct DisplayMath;
+
+impl PostProcessor for DisplayMath {
+    fn name(&self) -> &str {
+        "display-math"
+    }
+
+    fn process(&self, md: &str, _report: &mut ConversionReport) -> String {
+        normalize_display_math(md)
+    }
+}
+
+/// HTML tags are stripped, keeping math, sanitized tables and MathML
+/// verbatim.
+pub struct StripHtml;
+
+impl PostProcessor for StripHtml {
+    fn name(&self) -> &str {
+        "strip-html"
+    }
+
+    fn process(&self, md: &str, _report: &mut ConversionReport) -> String {
+        strip_html_tags_preserve_math(md.trim_start())
+    }
+}
+
+/// An ordered list of post-processing stages.
+#[derive(Clone, Default)]
+pub struct Pipeline {
+    stages: Vec<Arc<dyn PostProcessor>>,
+}
+
+/// Names of the built-in stages, in their default order.
+pub const STANDARD_STAGES: [&str; 5] = ["figures", "tables", "katex", "display-math", "strip-html"];
+
+impl Pipeline {
+    /// A pipeline with no stages.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The built-in stages in their default order.
+    pub fn standard() -> Self {
+        Self::from_names(&STANDARD_STAGES).expect("standard stages exist")
+    }
+
+    /// Built-in stages by name, in the given order; stages left out are
+    /// disabled. Errors on an unknown name.
+    pub fn from_names<S: AsRef<str>>(names: &[S]) -> Result<Self, String> {
+        let mut pipeline = Self::new();
+        for name in names {
+            let stage: Arc<dyn PostProcessor> = match name.as_ref().trim() {
+                "figures" => Arc::new(FigureCaptions),
+                "tables" => Arc::new(Tables),
+                "katex" => Arc::new(KatexFixes),
+                "display-math" => Arc::new(DisplayMath),
+                "strip-html" => Arc::new(StripHtml),
+                other => return Err(format!("unknown post-processing stage: {}", other)),
+            };
+            pipeline.stages.push(stage);
+        }
+        Ok(pipeline)
+    }
+
+    /// Append a stage.
+    pub fn with(mut self, stage: impl PostProcessor + 'static) -> Self {
+        self.stages.push(Arc::new(stage));
+        self
+    }
+
+    /// Insert a stage before the stage called `before`, or at the end if
+    /// there is none.
+    pub fn with_before(mut self, before: &str, stage: impl PostProcessor + 'static) -> Self {
+        let at = self
+            .stages
+            .iter()
+            .position(|s| s.name() == before)
+            .unwrap_or(self.stages.len());
+        self.stages.insert(at, Arc::new(stage));
+        self
+    }
+
+    /// Remove the stage called `name`.
+    pub fn without(mut self, name: &str) -> Self {
+        self.stages.retain(|s| s.name() != name);
+        self
+    }
+
+    pub fn names(&self) -> Vec<&str> {
+        self.stages.iter().map(|s| s.name()).collect()
+    }
+
+    /// Run every stage in order, timing each in `report`.
+    pub fn run(&self, md: &str, report: &mut ConversionReport) -> String {
+        let mut out = md.to_string();
+        for stage in &self.stages {
+            let start = Instant::now();
+            out = stage.process(&out, report);
+            report.time(&format!("postprocess:{}", stage.name()), start);
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Shout;
+
+    impl PostProcessor for Shout {
+        fn name(&self) -> &str {
+            "shout"
+        }
+
+        fn process(&self, md: &str, _report: &mut ConversionReport) -> String {
+            md.to_uppercase()
+        }
+    }
+
+    #[test]
+    fn standard_stages_run_in_order_and_are_timed() {
+        let mut report = ConversionReport::new();
+        let md = "<figure><img src=\"a.png\" /><figcaption>Cap</figcaption></figure>\n\n$\\textsc{x}$ and <span>text</span>";
+        let out = Pipeline::standard().run(md, &mut report);
+        assert!(!out.contains("<span>"));
+        assert!(!out.contains("<figure>"));
+        let stages: Vec<&str> = report.stages.iter().map(|(s, _)| s.as_str()).collect();
+        assert_eq!(
+            stages,
+            STANDARD_STAGES
+                .iter()
+                .map(|s| format!("postprocess:{}", s))
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn stages_can_be_chosen_reordered_and_extended() {
+        let pipeline = Pipeline::from_names(&["strip-html", "tables"]).unwrap();
+        assert_eq!(pipeline.names(), vec!["strip-html", "tables"]);
+        assert!(Pipeline::from_names(&["nope"]).is_err());
+
+        let pipeline = Pipeline::standard()
+            .without("katex")
+            .with_before("strip-html", Shout);
+        assert_eq!(
+            pipeline.names(),
+            vec!["figures", "tables", "display-math", "shout", "strip-html"]
+        );
+        let out = pipeline.run("<b>bold</b> $x$", &mut ConversionReport::new());
+        assert_eq!(out, "BOLD $X$");
+    }
+
+    #[test]
+    fn katex_stage_reports_unknown_macros() {
+        let mut report = ConversionReport::new();
+        KatexFixes.process("$\\unknowncmd{x}$", &mut report);
+        assert_eq!(report.unknown_macros, vec!["\\unknowncmd"]);
+    }
+}
@@ -0,0 +1,463 @@
+use std::collections::HashSet;
+use std::ops::Range;
+use std::sync::LazyLock;
+
+use regex::Regex;
+
+use crate::headings::{lines_outside_code, parse_heading};
+use crate::markdown::code_ranges;
+
+/// What a section of a paper is, judged by its heading.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SectionKind {
+    Content,
+    Acknowledgments,
+    References,
+    /// NeurIPS-style paper and reproducibility checklists.
+    Checklist,
+    Appendix,
+}
+
+/// A heading and everything under it, up to the next heading at the same
+/// or a higher level.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Section {
+    pub title: String,
+    pub level: usize,
+    pub kind: SectionKind,
+    pub range: Range<usize>,
+}
+
+static NUMBER_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^(?:[A-Z]|\d+)(?:\.\d+)*[.:]?\s+").unwrap());
+static APPENDIX_NUMBER_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^[A-Z]\.\d+\s|^section-[a-z](?:-|$)").unwrap());
+
+/// Classify a section by its heading text and anchor. Appendix sections
+/// are recognised by title or by the letter numbering cross-references
+/// give them; [`sections`] also counts what follows an appendix as one.
+pub fn classify(title: &str, anchor: Option<&str>) -> SectionKind {
+    let text = title.replace(['*', '_', '`'], "");
+    let text = text.trim();
+    let appendix_numbered =
+        APPENDIX_NUMBER_RE.is_match(text) || anchor.is_some_and(|a| APPENDIX_NUMBER_RE.is_match(a));
+    let name = NUMBER_RE.replace(text, "").to_lowercase();
+    let name = name.trim_end_matches(['.', ':']).trim();
+    if name.starts_with("acknowledg") || name == "funding" {
+        SectionKind::Acknowledgments
+    } else if matches!(
+        name,
+        "references" | "reference" | "bibliography" | "literature cited" | "works cited"
+    ) {
+        SectionKind::References
+    } else if name.contains("checklist") {
+        SectionKind::Checklist
+    } else if appendix_numbered
+        || [
+            "appendix",
+            "appendices",
+            "supplementary material",
+            "supplemental material",
+        ]
+        .iter()
+        .any(|p| name.starts_with(p))
+    {
+        SectionKind::Appendix
+    } else {
+        SectionKind::Content
+    }
+}
+
+/// Every section of `md`, in document order; nested sections lie inside
+/// their parents' ranges.
+pub fn sections(md: &str) -> Vec<Section> {
+    let headings: Vec<(usize, usize, String, SectionKind)> = lines_outside_code(md)
+        .into_iter()
+        .filter_map(|(pos, line)| {
+            let h = parse_heading(line.trim_end_matches(['\n', '\r']))?;
+            Some((pos, h.level, h.text.to_string(), classify(h.text, h.anchor)))
+        })
+        .collect();
+    let mut out = Vec::with_capacity(headings.len());
+    // Once the appendices start, later sections at their level are
+    // appendices too, whatever they are called.
+    let mut appendix_level: Option<usize> = None;
+    for (i, (pos, level, title, kind)) in headings.iter().enumerate() {
+        let end = headings[i + 1..]
+            .iter()
+            .find(|(_, l, _, _)| l <= level)
+            .map_or(md.len(), |(p, _, _, _)| *p);
+        let mut kind = *kind;
+        match kind {
+            SectionKind::Appendix if appendix_level.is_none_or(|l| *level <= l) => {
+                appendix_level = Some(*level)
+            }
+            SectionKind::Content if appendix_level.is_some_and(|l| *level <= l) => {
+                kind = SectionKind::Appendix
+            }
+            _ => {}
+        }
+        out.push(Section {
+            title: title.clone(),
+            level: *level,
+            kind,
+            range: *pos..end,
+        });
+    }
+    out
+}
+
+/// How much of a paper to serve, chosen with `?profile=`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Profile {
+    #[default]
+    Full,
+    /// Fewer tokens for language models: no acknowledgments, references,
+    /// checklists or uncaptioned figures, collapsed whitespace and a short
+    /// author list. Appendices go too unless `appendices` is set.
+    Lean { appendices: bool },
+}
+
+/// Author lists longer than this are shortened to [`SHOWN_AUTHORS`].
+const MAX_AUTHORS: usize = 6;
+const SHOWN_AUTHORS: usize = 3;
+
+impl Profile {
+    pub const ALL: [Self; 3] = [
+        Self::Full,
+        Self::Lean { appendices: true },
+        Self::Lean { appendices: false },
+    ];
+
+    /// The profile for `?profile=` and, for `lean`, `?appendices=0|1`
+    /// (default `1`, keeping them).
+    pub fn parse(profile: &str, appendices: Option<&str>) -> Option<Self> {
+        match profile {
+            "full" | "" => Some(Self::Full),
+            "lean" => {
+                let appendices = match appendices {
+                    None | Some("1") => true,
+                    Some("0") => false,
+                    Some(_) => return None,
+                };
+                Some(Self::Lean { appendices })
+            }
+            _ => None,
+        }
+    }
+
+    /// Cache key for a rendering served with this profile.
+    pub fn cache_key(self, base: &str) -> String {
+        let sep = if base.contains('?') { '&' } else { '?' };
+        match self {
+            Self::Full => base.to_string(),
+            Self::Lean { appendices: true } => format!("{}{}profile=lean", base, sep),
+            Self::Lean { appendices: false } => {
+                format!("{}{}profile=lean&appendices=0", base, sep)
+            }
+        }
+    }
+
+    /// `md` as this profile serves it.
+    pub fn apply(self, md: &str) -> String {
+        let Self::Lean { appendices } = self else {
+            return md.to_string();
+        };
+        let md = drop_sections(md, |kind| match kind {
+            SectionKind::Content => false,
+            SectionKind::Appendix => !appendices,
+            _ => true,
+        });
+        let md = unlink_dangling(&md);
+        let md = drop_uncaptioned_figures(&md);
+        let md = shorten_authors(&md);
+        collapse_whitespace(&md)
+    }
+}
+
+/// A rough token count: about four characters per token for English
+/// prose and Markdown, which is what the saving is reported in.
+pub fn estimate_tokens(text: &str) -> usize {
+    text.chars().count().div_ceil(4)
+}
+
+/// `md` without the sections `drop` picks. Footnote definitions in them
+/// whose markers are still in the text move to the end.
+fn drop_sections(md: &str, drop: impl Fn(SectionKind) -> bool) -> String {
+    let mut out = String::with_capacity(md.len());
+    let mut notes = Vec::new();
+    let mut copied = 0;
+    for section in sections(md) {
+        if section.range.start < copied || !drop(section.kind) {
+            continue;
+        }
+        out.push_str(&md[copied..section.range.start]);
+        notes.extend(footnote_definitions(&md[section.range.clone()]));
+        copied = section.range.end;
+    }
+    out.push_str(&md[copied..]);
+    let kept: Vec<String> = notes
+        .into_iter()
+        .filter(|(label, _)| out.contains(&format!("[^{}]", label)))
+        .map(|(_, block)| block.trim_end().to_string())
+        .collect();
+    if !kept.is_empty() {
+        out.truncate(out.trim_end().len());
+        out.push_str("\n\n");
+        out.push_str(&kept.join("\n\n"));
+        out.push('\n');
+    }
+    out
+}
+
+static FOOTNOTE_DEF_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^\[\^([^\]\s]+)\]:").unwrap());
+
+/// The `[^label]: ...` definitions in `md` with their labels, each with
+/// its lazy and indented continuation lines.
+fn footnote_definitions(md: &str) -> Vec<(String, String)> {
+    let code = code_ranges(md);
+    let mut lines = md
+        .split_inclusive('\n')
+        .scan(0, |pos, line| {
+            let start = *pos;
+            *pos += line.len();
+            Some((start, line))
+        })
+        .peekable();
+    let mut out = Vec::new();
+    while let Some((pos, line)) = lines.next() {
+        if code.iter().any(|r| r.contains(&pos)) {
+            continue;
+        }
+        let Some(caps) = FOOTNOTE_DEF_RE.captures(line) else {
+            continue;
+        };
+        let mut block = line.to_string();
+        let mut blank = String::new();
+        while let Some(&(_, next)) = lines.peek() {
+            if next.trim().is_empty() {
+                blank.push_str(next);
+            } else if next.starts_with([' ', '\t'])
+                || (blank.is_empty() && !next.starts_with("[^") && !next.starts_with('#'))
+            {
+                block.push_str(&blank);
+                block.push_str(next);
+                blank.clear();
+            } else {
+                break;
+            }
+            lines.next();
+        }
+        out.push((caps[1].to_string(), block));
+    }
+    out
+}
+
+static ID_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r#"\sid="([^"]+)""#).unwrap());
+static FRAGMENT_LINK_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\[([^\[\]]*)\]\(#([^)\s]+)\)").unwrap());
+
+/// Links to anchors that are gone, like citations of a dropped reference
+/// list, become their text.
+fn unlink_dangling(md: &str) -> String {
+    let ids: HashSet<&str> = ID_RE
+        .captures_iter(md)
+        .filter_map(|c| c.get(1))
+        .map(|m| m.as_str())
+        .collect();
+    let code = code_ranges(md);
+    FRAGMENT_LINK_RE
+        .replace_all(md, |caps: &regex::Captures| {
+            let m = caps.get(0).unwrap();
+            if ids.contains(&caps[2]) || code.iter().any(|r| r.contains(&m.start())) {
+                m.as_str().to_string()
+            } else {
+                caps[1].to_string()
+            }
+        })
+        .into_owned()
+}
+
+static UNCAPTIONED_RE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r#"(?m)^(?:<a id="figure-[^"]*"></a>\n\n)?(?:> !\[[^\n]*\n>\n)?> \[?\*\*Figure [^*\n]*\*\*(?:\]\([^)\n]*\))?(?:\n+|\z)"#,
+    )
+    .unwrap()
+});
+
+/// Remove `> **Figure N**` placeholders that have no caption, with their
+/// images and anchor.
+fn drop_uncaptioned_figures(md: &str) -> String {
+    let code = code_ranges(md);
+    UNCAPTIONED_RE
+        .replace_all(md, |caps: &regex::Captures| {
+            let m = caps.get(0).unwrap();
+            if code.iter().any(|r| r.contains(&m.start())) {
+                m.as_str().to_string()
+            } else {
+                String::new()
+            }
+        })
+        .into_owned()
+}
+
+static AUTHORS_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?m)^## Authors\n(.+)$").unwrap());
+
+/// Keep the first few names of a long `## Authors` line.
+fn shorten_authors(md: &str) -> String {
+    AUTHORS_RE
+        .replace(md, |caps: &regex::Captures| {
+            let authors = split_top_level(&caps[1]);
+            if authors.len() <= MAX_AUTHORS {
+                return caps[0].to_string();
+            }
+            format!(
+                "## Authors\n{}, et al. ({} more)",
+                authors[..SHOWN_AUTHORS].join(", "),
+                authors.len() - SHOWN_AUTHORS
+            )
+        })
+        .into_owned()
+}
+
+/// Split a `Name (Affiliation, City), Name` list on the commas between
+/// authors.
+fn split_top_level(line: &str) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut depth = 0usize;
+    let mut start = 0;
+    for (i, c) in line.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth = depth.saturating_sub(1),
+            ',' if depth == 0 => {
+                parts.push(line[start..i].trim());
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(line[start..].trim());
+    parts
+}
+
+/// Trailing spaces, runs of spaces and runs of blank lines go, except in
+/// code.
+fn collapse_whitespace(md: &str) -> String {
+    let code = code_ranges(md);
+    let mut out = String::with_capacity(md.len());
+    let mut blank = true;
+    let mut pos = 0;
+    for line in md.split_inclusive('\n') {
+        let start = pos;
+        pos += line.len();
+        if code.iter().any(|r| r.contains(&start)) {
+            out.push_str(line);
+            blank = false;
+            continue;
+        }
+        let text = line.trim_end();
+        if text.is_empty() {
+            if !blank {
+                out.push('\n');
+            }
+            blank = true;
+            continue;
+        }
+        blank = false;
+        let body = text.trim_start();
+        out.push_str(&text[..text.len() - body.len()]);
+        if body.contains('`') {
+            out.push_str(body);
+        } else {
+            out.push_str(
+                &body
+                    .split(' ')
+                    .filter(|w| !w.is_empty())
+                    .collect::<Vec<_>>()
+                    .join(" "),
+            );
+        }
+        out.push('\n');
+    }
+    let trimmed = out.trim_end().len();
+    out.truncate(trimmed);
+    out.push('\n');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PAPER: &str = "# Title\n\n## Authors\nA One, B Two (X, Y), C Three, D Four, E Five, F Six, G Seven\n\n## Abstract\nText.\n\n## 1 Introduction<a id=\"section-1\"></a>\n\nIntro   text.   \n\n\n\n<a id=\"figure-1\"></a>\n\n> **Figure 1**\n\n> **Figure 2:** A plot.\n\n## Acknowledgments<a id=\"acknowledgments\"></a>\n\nThanks.\n\n## References<a id=\"references\"></a>\n\n1. Ref.\n\n## Appendix<a id=\"appendix\"></a>\n\n### A.1 Proofs<a id=\"a-1-proofs\"></a>\n\nProof.\n\n## Extra experiments<a id=\"extra\"></a>\n\nMore.\n\n## NeurIPS Paper Checklist<a id=\"checklist\"></a>\n\n1. Claims\n";
+
+    #[test]
+    fn sections_are_classified() {
+        let kinds: Vec<(String, SectionKind)> = sections(PAPER)
+            .into_iter()
+            .map(|s| (s.title, s.kind))
+            .collect();
+        use SectionKind::*;
+        assert_eq!(
+            kinds,
+            vec![
+                ("Title".into(), Content),
+                ("Authors".into(), Content),
+                ("Abstract".into(), Content),
+                ("1 Introduction".into(), Content),
+                ("Acknowledgments".into(), Acknowledgments),
+                ("References".into(), References),
+                ("Appendix".into(), Appendix),
+                ("A.1 Proofs".into(), Appendix),
+                ("Extra experiments".into(), Appendix),
+                ("NeurIPS Paper Checklist".into(), Checklist),
+            ]
+        );
+        assert_eq!(classify("B Proofs", Some("section-b")), Appendix);
+        assert_eq!(classify("A Survey", Some("a-survey")), Content);
+    }
+
+    #[test]
+    fn lean_profile_trims_the_paper() {
+        let lean = Profile::Lean { appendices: true }.apply(PAPER);
+        assert_eq!(
+            lean,
+            "# Title\n\n## Authors\nA One, B Two (X, Y), C Three, et al. (4 more)\n\n## Abstract\nText.\n\n## 1 Introduction<a id=\"section-1\"></a>\n\nIntro text.\n\n> **Figure 2:** A plot.\n\n## Appendix<a id=\"appendix\"></a>\n\n### A.1 Proofs<a id=\"a-1-proofs\"></a>\n\nProof.\n\n## Extra experiments<a id=\"extra\"></a>\n\nMore.\n"
+        );
+        let leaner = Profile::Lean { appendices: false }.apply(PAPER);
+        assert!(leaner.ends_with("> **Figure 2:** A plot.\n"));
+        assert!(estimate_tokens(&leaner) < estimate_tokens(&lean));
+        assert_eq!(Profile::Full.apply(PAPER), PAPER);
+    }
+
+    #[test]
+    fn lean_profile_keeps_footnotes_and_unlinks_citations() {
+        let md = "## Intro<a id=\"intro\"></a>\n\nAs shown [[1](#ref-a)][^1], see [Intro](#intro) and [B](#section-b).\n\n## References<a id=\"references\"></a>\n\n1. <a id=\"ref-a\"></a>A. Paper.\n\n## Paper Checklist<a id=\"checklist\"></a>\n\nYes[^2].\n\n[^1]: A note\nthat wraps.\n\n    And goes on.\n\n[^2]: Checklist note.\n";
+        let lean = Profile::Lean { appendices: true }.apply(md);
+        assert_eq!(
+            lean,
+            "## Intro<a id=\"intro\"></a>\n\nAs shown [1][^1], see [Intro](#intro) and B.\n\n[^1]: A note\nthat wraps.\n\n    And goes on.\n"
+        );
+    }
+
+    #[test]
+    fn profile_parsing_and_cache_keys() {
+        assert_eq!(Profile::parse("", None), Some(Profile::Full));
+        assert_eq!(
+            Profile::parse("lean", Some("0")),
+            Some(Profile::Lean { appendices: false })
+        );
+        assert_eq!(Profile::parse("lean", Some("x")), None);
+        assert_eq!(Profile::parse("tiny", None), None);
+        let lean = Profile::Lean { appendices: true };
+        assert_eq!(lean.cache_key("/abs/1"), "/abs/1?profile=lean");
+        assert_eq!(
+            Profile::Lean { appendices: false }.cache_key("/abs/1?math=unicode"),
+            "/abs/1?math=unicode&profile=lean&appendices=0"
+        );
+        assert_eq!(Profile::Full.cache_key("/abs/1"), "/abs/1");
+    }
+}
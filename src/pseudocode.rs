@@ -0,0 +1,809 @@
+use std::collections::HashMap;
+
+use crate::crossref::Numbering;
+use crate::placeholder::Placeholders;
+use crate::tex_util::{
+    find_command, find_environment, is_escaped, math_ranges, read_arg, read_opt_arg,
+    tex_to_markdown_inline,
+};
+use crate::unicode_math::tex_to_plain;
+
+/// One rendered line of pseudocode.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Line {
+    indent: usize,
+    /// Raw TeX, converted to plain text when the block is rendered.
+    tex: String,
+    numbered: bool,
+}
+
+/// Collects lines while tracking the current nesting depth.
+#[derive(Default)]
+struct Lines {
+    lines: Vec<Line>,
+    indent: usize,
+}
+
+impl Lines {
+    fn push(&mut self, tex: impl Into<String>, numbered: bool) {
+        let tex = tex.into();
+        if tex.trim().is_empty() {
+            return;
+        }
+        self.lines.push(Line {
+            indent: self.indent,
+            tex: tex.trim().to_string(),
+            numbered,
+        });
+    }
+
+    fn open(&mut self, tex: impl Into<String>) {
+        self.push(tex, true);
+        self.indent += 1;
+    }
+
+    fn close(&mut self, tex: impl Into<String>) {
+        self.indent = self.indent.saturating_sub(1);
+        self.push(tex, true);
+    }
+
+    /// `else`-style lines sit one level out from the branch they end.
+    fn reopen(&mut self, tex: impl Into<String>) {
+        self.indent = self.indent.saturating_sub(1);
+        self.open(tex);
+    }
+}
+
+/// Replace `algorithm` floats and standalone `algorithmic` blocks with
+/// numbered, indented pseudocode listings under their caption. Handles the
+/// `algorithmic` package (`\STATE`, `\IF`), `algpseudocode`/`algorithmicx`
+/// (`\State`, `\If`) and `algorithm2e` (`\For{..}{..}`, `\;`). Runs after
+/// [`crate::crossref::resolve`], whose numbering it uses for the captions.
+pub fn render(tex: &str, numbering: &Numbering, ph: &mut Placeholders) -> String {
+    let a2e = A2eKeywords::parse(tex);
+    let a2e_doc = tex.contains("algorithm2e");
+    let a2e_numbered = find_command(tex, "LinesNumbered", 0).is_some();
+
+    let mut out = String::with_capacity(tex.len());
+    let mut cursor = 0;
+    let mut index = 0;
+    while let Some((range, body)) = next_algorithm(tex, cursor) {
+        out.push_str(&tex[cursor..range.start]);
+        // Skip the float placement option, e.g. `[t]`.
+        let body_tex = match read_opt_arg(tex, body.start) {
+            Some((_, end)) => &tex[end..body.end],
+            None => &tex[body],
+        };
+        let number = numbering.environment_number("algorithm", index);
+        index += 1;
+        let caption = caption(body_tex);
+        let lines = if let Some((_, inner)) = find_environment(body_tex, "algorithmic", 0) {
+            algorithmic(
+                &body_tex[inner.start..inner.end],
+                algorithmic_numbered(body_tex, inner.start),
+            )
+        } else if a2e_doc || body_tex.contains("\\;") {
+            let mut lines = Lines::default();
+            a2e.block(body_tex, &mut lines);
+            let numbered = a2e_numbered || find_command(body_tex, "LinesNumbered", 0).is_some();
+            (lines.lines, numbered)
+        } else {
+            (Vec::new(), false)
+        };
+        out.push_str(&ph.block(listing(number, caption.as_deref(), &lines.0, lines.1)));
+        cursor = range.end;
+    }
+    out.push_str(&tex[cursor..]);
+
+    // Standalone algorithmic environments outside any float.
+    let tex = out;
+    let mut out = String::with_capacity(tex.len());
+    let mut cursor = 0;
+    while let Some((range, body)) = find_environment(&tex, "algorithmic", cursor) {
+        out.push_str(&tex[cursor..range.start]);
+        let numbered = algorithmic_numbered(&tex, body.start);
+        let (lines, numbered) = algorithmic(&tex[body], numbered);
+        out.push_str(&ph.block(listing(None, None, &lines, numbered)));
+        cursor = range.end;
+    }
+    out.push_str(&tex[cursor..]);
+    out
+}
+
+/// The next `algorithm` or `algorithm*` environment at or after `from`.
+fn next_algorithm(
+    tex: &str,
+    from: usize,
+) -> Option<(std::ops::Range<usize>, std::ops::Range<usize>)> {
+    let plain = find_environment(tex, "algorithm", from);
+    let starred = find_environment(tex, "algorithm*", from);
+    match (plain, starred) {
+        (Some(a), Some(b)) => Some(if a.0.start <= b.0.start { a } else { b }),
+        (a, b) => a.or(b),
+    }
+}
+
+fn caption(body: &str) -> Option<String> {
+    let at = find_command(body, "caption", 0)?;
+    let mut pos = at + "\\caption".len();
+    if body[pos..].starts_with('*') {
+        pos += 1;
+    }
+    if let Some((_, e)) = read_opt_arg(body, pos) {
+        pos = e;
+    }
+    let (text, _) = read_arg(body, pos)?;
+    let text = tex_to_markdown_inline(text);
+    let text = text.trim();
+    (!text.is_empty()).then(|| text.to_string())
+}
+
+/// `\begin{algorithmic}[1]` numbers every line; without the option lines
+/// are unnumbered. `body_start` is where the environment's body begins.
+fn algorithmic_numbered(tex: &str, body_start: usize) -> bool {
+    read_opt_arg(tex, body_start)
+        .map(|(n, _)| n.trim().parse::<u32>().map(|n| n > 0).unwrap_or(false))
+        .unwrap_or(false)
+}
+
+fn listing(number: Option<&str>, caption: Option<&str>, lines: &[Line], numbered: bool) -> String {
+    let mut out = String::new();
+    match (number, caption) {
+        (Some(n), Some(c)) => out.push_str(&format!("**Algorithm {}** {}\n\n", n, c)),
+        (Some(n), None) => out.push_str(&format!("**Algorithm {}**\n\n", n)),
+        (None, Some(c)) => out.push_str(&format!("**Algorithm** {}\n\n", c)),
+        (None, None) => {}
+    }
+    let total = lines.iter().filter(|l| l.numbered).count();
+    let width = total.to_string().len();
+    out.push_str("```text\n");
+    let mut n = 0;
+    for line in lines {
+        if numbered {
+            if line.numbered {
+                n += 1;
+                out.push_str(&format!("{:>width$}: ", n, width = width));
+            } else {
+                out.push_str(&" ".repeat(width + 2));
+            }
+        }
+        out.push_str(&"    ".repeat(line.indent));
+        out.push_str(&tex_to_plain(&line.tex));
+        out.push('\n');
+    }
+    out.push_str("```\n");
+    out
+}
+
+/// Parse an `algorithmic` body (either package). Returns the lines and
+/// whether they are numbered.
+fn algorithmic(body: &str, numbered: bool) -> (Vec<Line>, bool) {
+    let mut lines = Lines::default();
+    // Numbering option `[1]` directly after \begin{algorithmic}.
+    let body = match read_opt_arg(body, 0) {
+        Some((_, end)) if body.trim_start().starts_with('[') => &body[end..],
+        _ => body,
+    };
+    let math = math_ranges(body);
+    let mut current: Option<(String, bool)> = None;
+    let flush = |lines: &mut Lines, current: &mut Option<(String, bool)>| {
+        if let Some((tex, numbered)) = current.take() {
+            lines.push(tex, numbered);
+        }
+    };
+    let bytes = body.as_bytes();
+    let mut i = 0;
+    while i < bytes.len() {
+        if let Some(r) = math.iter().find(|r| r.start == i) {
+            current
+                .get_or_insert_with(|| (String::new(), true))
+                .0
+                .push_str(&body[r.clone()]);
+            i = r.end;
+            continue;
+        }
+        match bytes[i] {
+            b'%' if !is_escaped(body, i) => {
+                i = body[i..].find('\n').map(|r| i + r).unwrap_or(bytes.len());
+            }
+            b'\\' => {
+                let name_len = body[i + 1..]
+                    .bytes()
+                    .take_while(|b| b.is_ascii_alphabetic())
+                    .count();
+                let name = &body[i + 1..i + 1 + name_len];
+                let after = i + 1 + name_len;
+                let keyword = name
+                    .chars()
+                    .next()
+                    .is_some_and(|c| c.is_ascii_uppercase())
+                    .then(|| name.to_ascii_lowercase());
+                let arg = |pos: usize| {
+                    let (a, e) = read_arg(body, pos).unwrap_or(("", pos));
+                    (inline_keywords(a), e)
+                };
+                let mut next = after;
+                match keyword.as_deref() {
+                    Some("state") => {
+                        flush(&mut lines, &mut current);
+                        current = Some((String::new(), true));
+                    }
+                    Some("statex") => {
+                        flush(&mut lines, &mut current);
+                        current = Some((String::new(), false));
+                    }
+                    Some(k @ ("require" | "ensure" | "input" | "output")) => {
+                        flush(&mut lines, &mut current);
+                        let mut label = k.to_string();
+                        label[..1].make_ascii_uppercase();
+                        current = Some((format!("{}: ", label), false));
+                    }
+                    Some("if") => {
+                        flush(&mut lines, &mut current);
+                        let (c, e) = arg(after);
+                        lines.open(format!("if {} then", c));
+                        next = e;
+                    }
+                    Some("elsif") => {
+                        flush(&mut lines, &mut current);
+                        let (c, e) = arg(after);
+                        lines.reopen(format!("else if {} then", c));
+                        next = e;
+                    }
+                    Some("else") => {
+                        flush(&mut lines, &mut current);
+                        lines.reopen("else");
+                    }
+                    Some(k @ ("for" | "forall" | "while")) => {
+                        flush(&mut lines, &mut current);
+                        let (c, e) = arg(after);
+                        let kw = if k == "forall" { "for all" } else { k };
+                        lines.open(format!("{} {} do", kw, c));
+                        next = e;
+                    }
+                    Some(k @ ("repeat" | "loop")) => {
+                        flush(&mut lines, &mut current);
+                        lines.open(k);
+                    }
+                    Some("until") => {
+                        flush(&mut lines, &mut current);
+                        let (c, e) = arg(after);
+                        lines.close(format!("until {}", c));
+                        next = e;
+                    }
+                    Some(k @ ("procedure" | "function")) => {
+                        flush(&mut lines, &mut current);
+                        let (f, e1) = arg(after);
+                        let (a, e2) = arg(e1);
+                        lines.open(format!("{} {}({})", k, f, a));
+                        next = e2;
+                    }
+                    Some(
+                        k @ ("endif" | "endfor" | "endwhile" | "endloop" | "endprocedure"
+                        | "endfunction"),
+                    ) => {
+                        flush(&mut lines, &mut current);
+                        lines.close(format!("end {}", &k[3..]));
+                    }
+                    Some(k @ ("return" | "print")) => {
+                        let line = current.get_or_insert_with(|| (String::new(), true));
+                        line.0.push_str(k);
+                        line.0.push(' ');
+                    }
+                    Some("comment") => {
+                        let (c, e) = arg(after);
+                        let line = current.get_or_insert_with(|| (String::new(), true));
+                        line.0.push_str(&format!(" ▷ {}", c));
+                        next = e;
+                    }
+                    Some(k @ ("and" | "or" | "not" | "true" | "false" | "to")) => {
+                        let line = current.get_or_insert_with(|| (String::new(), true));
+                        line.0.push_str(&format!(" {} ", k));
+                    }
+                    _ if name == "label" => {
+                        next = arg(after).1;
+                    }
+                    _ => {
+                        // Anything else (\Call, \textbf, \\, ...) is line text.
+                        let end = if name_len == 0 {
+                            (i + 2).min(bytes.len())
+                        } else {
+                            after
+                        };
+                        current
+                            .get_or_insert_with(|| (String::new(), true))
+                            .0
+                            .push_str(&body[i..end]);
+                        next = end;
+                    }
+                }
+                i = next;
+            }
+            _ => {
+                let c = body[i..].chars().next().unwrap();
+                if let Some(line) = current.as_mut() {
+                    line.0.push(c);
+                } else if !c.is_whitespace() {
+                    current = Some((c.to_string(), true));
+                }
+                i += c.len_utf8();
+            }
+        }
+    }
+    flush(&mut lines, &mut current);
+    (lines.lines, numbered)
+}
+
+/// Spell out `\AND`, `\TO`, `\TRUE` and friends inside `algorithmic`
+/// conditions, in either package's capitalisation.
+fn inline_keywords(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(at) = rest.find('\\') {
+        out.push_str(&rest[..at]);
+        let name_len = rest[at + 1..]
+            .bytes()
+            .take_while(|b| b.is_ascii_alphabetic())
+            .count();
+        let name = &rest[at + 1..at + 1 + name_len];
+        let word = name.to_ascii_lowercase();
+        if name.starts_with(|c: char| c.is_ascii_uppercase())
+            && matches!(
+                word.as_str(),
+                "and" | "or" | "not" | "true" | "false" | "to"
+            )
+        {
+            out.push_str(&format!(" {} ", word));
+            rest = &rest[at + 1 + name_len..];
+        } else {
+            let end = at + 1 + name_len.max(1).min(rest.len() - at - 1);
+            out.push_str(&rest[at..end]);
+            rest = &rest[end..];
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Keywords `algorithm2e` documents define for themselves.
+#[derive(Default)]
+struct A2eKeywords {
+    /// `\SetKwInOut{Input}{input}` and friends: a labelled, unnumbered line.
+    inputs: HashMap<String, String>,
+    /// `\SetKw{KwTo}{to}`: inline keyword text.
+    inline: HashMap<String, String>,
+    /// `\SetKwFunction{FMain}{Main}`: `\FMain{x}` prints `Main(x)`.
+    functions: HashMap<String, String>,
+    /// `\SetKwProg{Fn}{Function}{:}{end}`: a block with a header and end.
+    programs: HashMap<String, (String, String, String)>,
+}
+
+impl A2eKeywords {
+    fn parse(tex: &str) -> Self {
+        let mut kw = Self::default();
+        for (cmd, target) in [
+            ("SetKwInOut", 0),
+            ("SetKwInput", 0),
+            ("SetKw", 1),
+            ("SetKwData", 1),
+            ("SetKwFunction", 2),
+        ] {
+            let mut from = 0;
+            while let Some(at) = find_command(tex, cmd, from) {
+                let after = at + cmd.len() + 1;
+                from = after;
+                let Some((name, e1)) = read_arg(tex, after) else {
+                    continue;
+                };
+                let Some((text, _)) = read_arg(tex, e1) else {
+                    continue;
+                };
+                let map = match target {
+                    0 => &mut kw.inputs,
+                    1 => &mut kw.inline,
+                    _ => &mut kw.functions,
+                };
+                map.insert(name.trim().to_string(), text.trim().to_string());
+            }
+        }
+        let mut from = 0;
+        while let Some(at) = find_command(tex, "SetKwProg", from) {
+            let after = at + "\\SetKwProg".len();
+            from = after;
+            let mut args = Vec::new();
+            let mut pos = after;
+            while args.len() < 4 {
+                let Some((a, e)) = read_arg(tex, pos) else {
+                    break;
+                };
+                args.push(a.trim().to_string());
+                pos = e;
+            }
+            if let [name, head, sep, end] = &args[..] {
+                kw.programs
+                    .insert(name.clone(), (head.clone(), sep.clone(), end.clone()));
+            }
+        }
+        kw
+    }
+
+    /// Render an `algorithm2e` block body into `lines`.
+    fn block(&self, body: &str, lines: &mut Lines) {
+        let math = math_ranges(body);
+        let mut pending = String::new();
+        let flush = |lines: &mut Lines, pending: &mut String| {
+            lines.push(std::mem::take(pending), true);
+        };
+        let bytes = body.as_bytes();
+        let mut i = 0;
+        while i < bytes.len() {
+            if let Some(r) = math.iter().find(|r| r.start == i) {
+                pending.push_str(&body[r.clone()]);
+                i = r.end;
+                continue;
+            }
+            match bytes[i] {
+                b'%' if !is_escaped(body, i) => {
+                    i = body[i..].find('\n').map(|r| i + r).unwrap_or(bytes.len());
+                }
+                b'\\' if bytes.get(i + 1) == Some(&b';') => {
+                    flush(lines, &mut pending);
+                    i += 2;
+                }
+                b'\\' => {
+                    let name_len = body[i + 1..]
+                        .bytes()
+                        .take_while(|b| b.is_ascii_alphabetic())
+                        .count();
+                    let name = &body[i + 1..i + 1 + name_len];
+                    let mut after = i + 1 + name_len;
+                    if body[after..].starts_with('*') {
+                        after += 1;
+                    }
+                    i = self.command(body, i, name, after, lines, &mut pending);
+                }
+                _ => {
+                    let c = body[i..].chars().next().unwrap();
+                    pending.push(c);
+                    i += c.len_utf8();
+                }
+            }
+        }
+        flush(lines, &mut pending);
+    }
+
+    /// Handle one `algorithm2e` command; returns where scanning continues.
+    fn command(
+        &self,
+        body: &str,
+        at: usize,
+        name: &str,
+        after: usize,
+        lines: &mut Lines,
+        pending: &mut String,
+    ) -> usize {
+        let arg = |pos: usize| read_arg(body, pos).unwrap_or(("", pos));
+        let flush = |lines: &mut Lines, pending: &mut String| {
+            lines.push(std::mem::take(pending), true);
+        };
+        match name {
+            "For" | "ForEach" | "ForAll" | "While" => {
+                flush(lines, pending);
+                let (c, e1) = arg(after);
+                let (b, e2) = arg(e1);
+                let kw = match name {
+                    "For" => "for",
+                    "ForEach" => "foreach",
+                    "ForAll" => "forall",
+                    _ => "while",
+                };
+                lines.open(format!("{} {} do", kw, c));
+                self.block(b, lines);
+                lines.close("end");
+                e2
+            }
+            "If" | "uIf" => {
+                flush(lines, pending);
+                let (c, e1) = arg(after);
+                let (b, e2) = arg(e1);
+                lines.open(format!("if {} then", c));
+                self.block(b, lines);
+                if name == "If" {
+                    lines.close("end");
+                } else {
+                    lines.indent = lines.indent.saturating_sub(1);
+                }
+                e2
+            }
+            "eIf" => {
+                flush(lines, pending);
+                let (c, e1) = arg(after);
+                let (t, e2) = arg(e1);
+                let (f, e3) = arg(e2);
+                lines.open(format!("if {} then", c));
+                self.block(t, lines);
+                lines.reopen("else");
+                self.block(f, lines);
+                lines.close("end");
+                e3
+            }
+            "ElseIf" | "uElseIf" => {
+                flush(lines, pending);
+                let (c, e1) = arg(after);
+                let (b, e2) = arg(e1);
+                lines.open(format!("else if {} then", c));
+                self.block(b, lines);
+                if name == "ElseIf" {
+                    lines.close("end");
+                } else {
+                    lines.indent = lines.indent.saturating_sub(1);
+                }
+                e2
+            }
+            "Else" | "uElse" => {
+                flush(lines, pending);
+                let (b, e) = arg(after);
+                lines.open("else");
+                self.block(b, lines);
+                if name == "Else" {
+                    lines.close("end");
+                } else {
+                    lines.indent = lines.indent.saturating_sub(1);
+                }
+                e
+            }
+            "lIf" | "lElseIf" | "lFor" | "lForEach" | "lForAll" | "lWhile" => {
+                flush(lines, pending);
+                let (c, e1) = arg(after);
+                let (s, e2) = arg(e1);
+                let line = match name {
+                    "lIf" => format!("if {} then {}", c, s),
+                    "lElseIf" => format!("else if {} then {}", c, s),
+                    "lWhile" => format!("while {} do {}", c, s),
+                    _ => format!("{} {} do {}", name[1..].to_ascii_lowercase(), c, s),
+                };
+                lines.push(line, true);
+                e2
+            }
+            "lElse" => {
+                flush(lines, pending);
+                let (s, e) = arg(after);
+                lines.push(format!("else {}", s), true);
+                e
+            }
+            "Repeat" => {
+                flush(lines, pending);
+                let (c, e1) = arg(after);
+                let (b, e2) = arg(e1);
+                lines.open("repeat");
+                self.block(b, lines);
+                lines.close(format!("until {}", c));
+                e2
+            }
+            "Begin" => {
+                flush(lines, pending);
+                let mut pos = after;
+                if let Some((_, e)) = read_opt_arg(body, pos) {
+                    pos = e;
+                }
+                let (b, e) = arg(pos);
+                lines.open("begin");
+                self.block(b, lines);
+                lines.close("end");
+                e
+            }
+            "KwIn" | "KwOut" | "KwData" | "KwResult" => {
+                flush(lines, pending);
+                let (x, e) = arg(after);
+                let label = match name {
+                    "KwIn" => "Input",
+                    "KwOut" => "Output",
+                    "KwData" => "Data",
+                    _ => "Result",
+                };
+                lines.push(format!("{}: {}", label, x), false);
+                e
+            }
+            "KwRet" | "Return" => {
+                let (x, e) = arg(after);
+                pending.push_str(&format!("return {}", x));
+                e
+            }
+            "KwTo" => {
+                pending.push_str(" to ");
+                after
+            }
+            "tcp" | "tcc" => {
+                let mut pos = after;
+                if let Some((_, e)) = read_opt_arg(body, pos) {
+                    pos = e;
+                }
+                let (c, e) = arg(pos);
+                let comment = if name == "tcp" {
+                    format!("// {}", c)
+                } else {
+                    format!("/* {} */", c)
+                };
+                if pending.trim().is_empty() {
+                    lines.push(comment, false);
+                } else {
+                    pending.push_str(&format!("  {}", comment));
+                    flush(lines, pending);
+                }
+                e
+            }
+            "caption" => {
+                let mut pos = after;
+                if let Some((_, e)) = read_opt_arg(body, pos) {
+                    pos = e;
+                }
+                arg(pos).1
+            }
+            "label" | "IncMargin" | "DecMargin" | "SetAlCapFnt" | "SetAlCapNameFnt"
+            | "SetArgSty" | "SetKwSty" | "SetFuncSty" | "SetCommentSty" | "SetDataSty" => {
+                arg(after).1
+            }
+            "SetKw" | "SetKwData" | "SetKwInOut" | "SetKwInput" | "SetKwFunction" | "SetInd" => {
+                arg(arg(after).1).1
+            }
+            "SetNlSty" | "SetAlgorithmName" => arg(arg(arg(after).1).1).1,
+            "SetKwProg" => arg(arg(arg(arg(after).1).1).1).1,
+            "SetAlgoLined"
+            | "SetAlgoNoLine"
+            | "SetAlgoVlined"
+            | "SetAlgoNoEnd"
+            | "DontPrintSemicolon"
+            | "PrintSemicolon"
+            | "LinesNumbered"
+            | "LinesNotNumbered"
+            | "LinesNumberedHidden"
+            | "BlankLine"
+            | "Indp"
+            | "Indm"
+            | "nl"
+            | "centering"
+            | "ResetInOut" => after,
+            n if self.inputs.contains_key(n) => {
+                flush(lines, pending);
+                let (x, e) = arg(after);
+                let mut label = self.inputs[n].clone();
+                if let Some(first) = label.get_mut(..1) {
+                    first.make_ascii_uppercase();
+                }
+                lines.push(format!("{}: {}", label, x), false);
+                e
+            }
+            n if self.inline.contains_key(n) => {
+                pending.push_str(&format!(" {} ", self.inline[n]));
+                after
+            }
+            n if self.functions.contains_key(n) => {
+                let (x, e) = arg(after);
+                pending.push_str(&format!("{}({})", self.functions[n], x));
+                e
+            }
+            n if self.programs.contains_key(n) => {
+                flush(lines, pending);
+                let (head, sep, end) = &self.programs[n];
+                let (sig, e1) = arg(after);
+                let (b, e2) = arg(e1);
+                lines.open(format!("{} {}{}", head, sig, sep));
+                self.block(b, lines);
+                lines.close(end.clone());
+                e2
+            }
+            _ => {
+                // Not an algorithm2e keyword: keep it as line text.
+                let end = if name.is_empty() {
+                    (at + 2).min(body.len())
+                } else {
+                    after
+                };
+                pending.push_str(&body[at..end]);
+                end
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crossref;
+
+    fn run(tex: &str) -> String {
+        let mut ph = Placeholders::new();
+        let (resolved, numbering) = crossref::resolve(tex, &mut ph);
+        let out = render(&resolved, &numbering, &mut ph);
+        ph.resolve(&out)
+    }
+
+    #[test]
+    fn renders_algorithmic_package() {
+        let tex = r"\begin{algorithm}
+\caption{Gradient descent}\label{alg:gd}
+\begin{algorithmic}[1]
+\REQUIRE step size $\eta > 0$
+\FOR{$t = 1$ \TO $T$}
+  \STATE $w \gets w - \eta \nabla f(w)$ \COMMENT{update}
+  \IF{$\|\nabla f(w)\| < \epsilon$}
+    \RETURN $w$
+  \ENDIF
+\ENDFOR
+\end{algorithmic}
+\end{algorithm}";
+        let md = run(tex);
+        assert!(md.contains("<a id=\"algorithm-1\"></a>"));
+        assert!(md.contains("**Algorithm 1** Gradient descent\n\n```text\n"));
+        assert!(md.contains(
+            "   Require: step size η > 0\n\
+             1: for t = 1 to T do\n\
+             2:     w ← w - η ∇ f(w) ▷ update\n\
+             3:     if ‖∇ f(w)‖ < ϵ then\n\
+             4:         return w\n\
+             5:     end if\n\
+             6: end for\n```"
+        ));
+    }
+
+    #[test]
+    fn renders_algpseudocode_package() {
+        let tex = r"\begin{algorithm}
+\caption{Euclid}
+\begin{algorithmic}
+\Procedure{Euclid}{$a,b$}
+  \While{$r\not=0$}
+    \State $a \gets b$
+  \EndWhile
+  \State \Return $b$ \Comment{gcd}
+\EndProcedure
+\end{algorithmic}
+\end{algorithm}
+\begin{algorithmic}
+\State \Call{Euclid}{$x, y$}
+\end{algorithmic}";
+        let md = run(tex);
+        assert!(md.contains(
+            "**Algorithm 1** Euclid\n\n```text\n\
+             procedure Euclid(a,b)\n    while r≠0 do\n        a ← b\n    end while\n    return b ▷ gcd\nend procedure\n```"
+        ));
+        assert!(md.contains("```text\nEuclid(x, y)\n```"));
+    }
+
+    #[test]
+    fn renders_algorithm2e_package() {
+        let tex = r"\usepackage[ruled,linesnumbered]{algorithm2e}
+\SetKwInOut{Input}{input}
+\SetKwFunction{Train}{Train}
+\LinesNumbered
+\begin{algorithm}[t]
+\caption{Training loop}
+\Input{dataset $\mathcal{D}$}
+\KwOut{weights $\theta$}
+$\theta \gets 0$\;
+\ForEach{batch $B \subset \mathcal{D}$}{
+  \eIf{$|B| > 0$}{
+    \Train{$B$}\tcp*{one step}
+  }{
+    skip\;
+  }
+}
+\KwRet{$\theta$}\;
+\end{algorithm}";
+        let md = run(tex);
+        assert!(md.contains(
+            "**Algorithm 1** Training loop\n\n```text\n\
+             \x20  Input: dataset D\n\
+             \x20  Output: weights θ\n\
+             1: θ ← 0\n\
+             2: foreach batch B ⊂ D do\n\
+             3:     if |B| > 0 then\n\
+             4:         Train(B) // one step\n\
+             5:     else\n\
+             6:         skip\n\
+             7:     end\n\
+             8: end\n\
+             9: return θ\n```"
+        ));
+    }
+}
@@ -0,0 +1,168 @@
+use std::time::{Duration, Instant};
+
+use serde_json::json;
+
+use crate::header::FrontMatter;
+
+/// Diagnostics gathered while converting one paper, cached next to its
+/// Markdown and served by `/abs/:id/report` and `?debug=1`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ConversionReport {
+    /// What the e-print payload was (see [`crate::eprint::sniff`]); `None`
+    /// when arXiv has no source for the paper.
+    pub archive_format: Option<String>,
+    /// The main file that was converted, relative to the archive root.
+    pub main_file: Option<String>,
+    /// Main-file candidates, best first.
+    pub candidates: Vec<String>,
+    /// The conversion that produced the body, e.g. `pandoc` or `pdftotext`.
+    pub mode: Option<String>,
+    /// Conversions that were tried and failed, in order.
+    pub errors: Vec<String>,
+    /// What pandoc wrote to stderr on successful runs.
+    pub pandoc_warnings: Vec<String>,
+    /// Math commands with no KaTeX equivalent.
+    pub unknown_macros: Vec<String>,
+    pub figures: usize,
+    pub tables: usize,
+    /// What the LaTeX source says that arXiv's metadata doesn't.
+    pub front_matter: FrontMatter,
+    /// Time spent in each stage, in the order they ran.
+    pub stages: Vec<(String, Duration)>,
+}
+
+impl ConversionReport {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record a stage that started at `start` and just finished.
+    pub fn time(&mut self, stage: &str, start: Instant) {
+        self.stages.push((stage.to_string(), start.elapsed()));
+    }
+
+    /// Prefix the stages recorded since the first `from` with `label`, to
+    /// tell the attempts of a retried conversion apart.
+    pub fn label_stages(&mut self, from: usize, label: &str) {
+        for (stage, _) in self.stages.iter_mut().skip(from) {
+            *stage = format!("{}: {}", label, stage);
+        }
+    }
+
+    /// Add math commands, keeping the list free of duplicates.
+    pub fn add_unknown_macros(&mut self, commands: &[String]) {
+        for cmd in commands {
+            if !self.unknown_macros.contains(cmd) {
+                self.unknown_macros.push(cmd.clone());
+            }
+        }
+    }
+
+    /// Count the figure and table environments in a flattened document.
+    pub fn count_floats(&mut self, tex: &str) {
+        self.figures += count_environments(tex, &["figure", "figure*", "wrapfigure"]);
+        self.tables += count_environments(tex, &["table", "table*", "wraptable"]);
+    }
+
+    pub fn to_json(&self) -> String {
+        let stages: Vec<_> = self
+            .stages
+            .iter()
+            .map(|(stage, took)| json!({ "stage": stage, "ms": took.as_millis() as u64 }))
+            .collect();
+        let value = json!({
+            "archive_format": self.archive_format,
+            "main_file": self.main_file,
+            "candidates": self.candidates,
+            "mode": self.mode,
+            "errors": self.errors,
+            "pandoc_warnings": self.pandoc_warnings,
+            "unknown_macros": self.unknown_macros,
+            "figures": self.figures,
+            "tables": self.tables,
+            "thanks": self
+                .front_matter
+                .thanks
+                .iter()
+                .map(|t| &t.text)
+                .collect::<Vec<_>>(),
+            "authors": self
+                .front_matter
+                .authors
+                .iter()
+                .map(|a| a.to_json())
+                .collect::<Vec<_>>(),
+            "stages": stages,
+        });
+        serde_json::to_string_pretty(&value).unwrap_or_default()
+    }
+}
+
+/// Markdown with a report (as [`ConversionReport::to_json`] wrote it)
+/// appended under its own heading, for `?debug=1`.
+pub fn append_to_markdown(md: &str, report_json: &str) -> String {
+    format!(
+        "{}\n\n---\n\n## Conversion report\n\n```json\n{}\n```\n",
+        md.trim_end(),
+        report_json
+    )
+}
+
+fn count_environments(tex: &str, names: &[&str]) -> usize {
+    names
+        .iter()
+        .map(|name| tex.matches(&format!("\\begin{{{}}}", name)).count())
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_floats_and_dedups_macros() {
+        let mut report = ConversionReport::new();
+        report.count_floats(
+            "\\begin{figure}a\\end{figure}\\begin{figure*}b\\end{figure*}\\begin{table}c\\end{table}",
+        );
+        report.add_unknown_macros(&["\\foo".into(), "\\bar".into()]);
+        report.add_unknown_macros(&["\\foo".into()]);
+        assert_eq!((report.figures, report.tables), (2, 1));
+        assert_eq!(report.unknown_macros, vec!["\\foo", "\\bar"]);
+    }
+
+    #[test]
+    fn labels_the_stages_of_an_attempt() {
+        let mut report = ConversionReport::new();
+        report.time("fetch", Instant::now());
+        report.time("pandoc", Instant::now());
+        report.label_stages(1, "attempt 1");
+        let names: Vec<_> = report.stages.iter().map(|(s, _)| s.as_str()).collect();
+        assert_eq!(names, vec!["fetch", "attempt 1: pandoc"]);
+    }
+
+    #[test]
+    fn json_has_every_field() {
+        let mut report = ConversionReport::new();
+        report.main_file = Some("main.tex".into());
+        report
+            .stages
+            .push(("pandoc".into(), Duration::from_millis(12)));
+        let value: serde_json::Value = serde_json::from_str(&report.to_json()).unwrap();
+        assert_eq!(value["main_file"], "main.tex");
+        assert_eq!(value["archive_format"], serde_json::Value::Null);
+        assert_eq!(value["stages"][0]["stage"], "pandoc");
+        assert_eq!(value["stages"][0]["ms"], 12);
+        for field in [
+            "candidates",
+            "mode",
+            "errors",
+            "pandoc_warnings",
+            "unknown_macros",
+        ] {
+            assert!(value.get(field).is_some(), "missing {}", field);
+        }
+        let md = append_to_markdown("Body\n", &report.to_json());
+        assert!(md.starts_with("Body\n\n---\n\n## Conversion report\n\n```json\n{"));
+    }
+}
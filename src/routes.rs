@@ -7,11 +7,22 @@ use axum::{
 };
 
 use crate::{
-    arxiv::{ArxivClient, ArxivError, Metadata},
-    cache::MkCache,
-    convert::{add_arxiv_figure_html_links, ConvertError, Converter},
+    arxiv::{ArxivClient, ArxivError},
+    authors,
+    cache::{FigureCache, MkCache},
+    convert::{
+        add_arxiv_figure_html_links, render_math, ConvertError, Converter, LatexOptions,
+        LatexOutput, MathMode,
+    },
     disk_cache::DiskCache,
+    eprint::{self, EprintFormat, LegacyFormat},
+    figures::{add_figure_images, is_valid_name, FigureImage},
+    header::{prepend_metadata, BodySource},
+    headings,
+    profile::{estimate_tokens, Profile},
+    report::{self, ConversionReport},
 };
+use std::time::Instant;
 use tokio::sync::{Mutex, Semaphore};
 
 pub async fn index(headers: HeaderMap) -> Response {
@@ -119,6 +130,7 @@ pub async fn health() -> &'static str {
 #[allow(clippy::too_many_arguments)]
 pub async fn paper(
     State(cache): State<Arc<Mutex<MkCache>>>,
+    State(figure_cache): State<Arc<Mutex<FigureCache>>>,
     State(client): State<Arc<dyn ArxivClient + Send + Sync>>,
     State(converter): State<Arc<dyn Converter + Send + Sync>>,
     State(disk): State<Option<Arc<DiskCache>>>,
@@ -131,7 +143,6 @@ pub async fn paper(
     let normalized = normalize_id(trimmed);
 
     let original_path = original_uri.path().to_string();
-    let canonical_path = format!("/abs/{}", normalized);
 
     // Minimal id validation: non-empty and ascii
     if normalized.is_empty() || !normalized.is_ascii() {
@@ -139,40 +150,48 @@ pub async fn paper(
     }
 
     let id = normalized.to_string();
-    let cache_key = canonical_path.clone();
-
-    let refresh = raw_query
-        .and_then(|q| q.0)
-        .unwrap_or_default()
-        .split('&')
-        .find_map(|kv| {
-            let mut it = kv.splitn(2, '=');
-            let k = it.next()?;
-            let v = it.next().unwrap_or("");
-            if k == "refresh" && v == "1" {
-                Some(())
-            } else {
-                None
-            }
-        })
-        .is_some();
-
+    let query = raw_query.and_then(|q| q.0).unwrap_or_default();
+    let Some(opts) = latex_options(&query) else {
+        return (StatusCode::BAD_REQUEST, "invalid main file").into_response();
+    };
+    let base_key = paper_key(&id, &opts);
+    let refresh = query_param(&query, "refresh") == Some("1");
+    let math = match MathMode::parse(query_param(&query, "math").unwrap_or_default()) {
+        Some(m) => m,
+        None => return (StatusCode::BAD_REQUEST, "invalid math mode").into_response(),
+    };
+    let profile = match Profile::parse(
+        query_param(&query, "profile").unwrap_or_default(),
+        query_param(&query, "appendices"),
+    ) {
+        Some(p) => p,
+        None => return (StatusCode::BAD_REQUEST, "invalid profile").into_response(),
+    };
+    let cache_key = profile.cache_key(&math.cache_key(&base_key));
+    let serve = Serve {
+        cache: &cache,
+        disk: disk.as_deref(),
+        base_key: &base_key,
+        cache_key: &cache_key,
+        debug: query_param(&query, "debug") == Some("1"),
+        toc: query_param(&query, "toc") == Some("1"),
+        content_location: &original_path,
+    };
     if !refresh {
-        if let Some(md) = cache.lock().await.get(&cache_key) {
-            return markdown_response(md, &original_path);
-        }
-        if let Some(dc) = &disk {
-            match dc.get(&cache_key).await {
-                Ok(Some(md)) => {
-                    cache.lock().await.put(cache_key.clone(), md.clone());
-                    return markdown_response(md, &original_path);
-                }
-                Ok(None) => {}
-                Err(e) => tracing::error!(error = %e, "disk cache read error"),
+        if let Some(md) = cached(&cache, disk.as_deref(), &cache_key).await {
+            return serve.respond(md).await;
+        }
+        // Other math modes and profiles are derived from the LaTeX rendering.
+        if cache_key != base_key {
+            if let Some(md) = cached(&cache, disk.as_deref(), &base_key).await {
+                let md = serve.derive(&md, math, profile).await;
+                return serve.respond(md).await;
             }
         }
     }
 
+    let mut report = ConversionReport::new();
+    let start = Instant::now();
     // Fetch metadata (title, abstract). If not implemented, continue without them.
     let metadata = match client.get_metadata(&id).await {
         Ok(m) => Some(m),
@@ -185,22 +204,69 @@ pub async fn paper(
         }
     };
 
-    let (body_md, skip_metadata) = match client.get_source_archive(&id).await {
+    report.time("metadata", start);
+
+    let mut figures = Vec::new();
+    let start = Instant::now();
+    let archive = client.get_source_archive(&id).await;
+    report.time("source", start);
+    let (body_md, body_source) = match archive {
         Ok(bytes) => {
-            match convert_latex_with_retries(converter.as_ref(), &bytes, &id, convert_limit.clone())
+            let format = eprint::sniff(&bytes);
+            report.archive_format = Some(format.name().to_string());
+            // PostScript and DVI e-prints have no LaTeX for pandoc to read.
+            let latex = if format.legacy().is_some() {
+                None
+            } else {
+                match convert_latex_with_retries(
+                    converter.as_ref(),
+                    &bytes,
+                    &opts,
+                    &id,
+                    convert_limit.clone(),
+                    &mut report,
+                )
                 .await
-            {
-                Ok(s) => (s, false),
-                Err(_err) => match pdf_fallback(
-                    client.as_ref(),
+                {
+                    Ok(out) => Some(out),
+                    // `?main=` naming a file that isn't there is the
+                    // request's mistake; the PDF would be another document.
+                    Err(err @ ConvertError::MainNotFound(_)) => {
+                        return map_convert_err("latex", &id, err)
+                    }
+                    Err(_) => None,
+                }
+            };
+            match latex {
+                Some(out) => {
+                    figures = out.figures;
+                    (out.markdown, BodySource::Latex)
+                }
+                None => match legacy_eprint(
                     converter.as_ref(),
+                    &bytes,
                     &id,
                     convert_limit.clone(),
+                    &mut report,
                 )
                 .await
                 {
-                    Ok(s) => (s, true),
-                    Err(resp) => return resp,
+                    Some((s, format)) => (s, BodySource::Legacy(format)),
+                    None => match pdf_fallback(
+                        client.as_ref(),
+                        converter.as_ref(),
+                        &id,
+                        convert_limit.clone(),
+                        &mut report,
+                    )
+                    .await
+                    {
+                        Ok(s) => (s, BodySource::PdfText),
+                        Err(resp) => {
+                            store_report(&cache, disk.as_deref(), &base_key, &report).await;
+                            return resp;
+                        }
+                    },
                 },
             }
         }
@@ -211,39 +277,413 @@ pub async fn paper(
                 converter.as_ref(),
                 &id,
                 convert_limit.clone(),
+                &mut report,
             )
             .await
             {
-                Ok(s) => (s, true),
-                Err(resp) => return resp,
+                Ok(s) => (s, BodySource::PdfText),
+                Err(resp) => {
+                    store_report(&cache, disk.as_deref(), &base_key, &report).await;
+                    return resp;
+                }
             }
         }
         Err(err) => return map_arxiv_err("source_archive", &id, err),
     };
 
-    // Enrich figure placeholders with arxiv HTML image links (addresses #1).
-    // Falls back gracefully (no links) if the paper has no HTML version.
-    let figure_urls = client
-        .get_html_figure_image_urls(&id)
-        .await
-        .unwrap_or_default();
-    let body_md = add_arxiv_figure_html_links(&body_md, &figure_urls);
+    // Serve the figures from the source archive and show them above their
+    // captions. Papers without usable source figures fall back to arxiv HTML
+    // image links (addresses #1), or no links if there is no HTML version.
+    let body_md = if figures.is_empty() {
+        let figure_urls = client
+            .get_html_figure_image_urls(&id)
+            .await
+            .unwrap_or_default();
+        add_arxiv_figure_html_links(&body_md, &figure_urls)
+    } else {
+        let names: Vec<String> = figures.iter().map(|f| f.name.clone()).collect();
+        let md = add_figure_images(&body_md, &id, &names, &figure_query(&opts));
+        let figures_key = format!("/abs/{}{}", id, figure_query(&opts));
+        store_figures(&figure_cache, disk.as_deref(), &figures_key, figures).await;
+        md
+    };
+
+    // The report gets the authors as the header shows them.
+    if let Some(meta) = &metadata {
+        report.front_matter.authors = authors::merge(&meta.authors, &report.front_matter.authors);
+    }
+    let final_md = prepend_metadata(
+        metadata.as_ref(),
+        &report.front_matter,
+        &body_md,
+        body_source,
+    );
 
-    let final_md = if skip_metadata {
-        body_md
-    } else if let Some(meta) = metadata {
-        prepend_metadata(&meta, &body_md)
+    store(&cache, disk.as_deref(), &base_key, &final_md).await;
+    store_report(&cache, disk.as_deref(), &base_key, &report).await;
+    serve.rederive_cached(&final_md).await;
+    let final_md = if cache_key != base_key {
+        serve.derive(&final_md, math, profile).await
     } else {
-        body_md
+        final_md
+    };
+    serve.respond(final_md).await
+}
+
+/// Serve the diagnostics of a paper's conversion as JSON, converting the
+/// paper first if it has no report yet.
+#[allow(clippy::too_many_arguments)]
+pub async fn report(
+    State(cache): State<Arc<Mutex<MkCache>>>,
+    State(figure_cache): State<Arc<Mutex<FigureCache>>>,
+    State(client): State<Arc<dyn ArxivClient + Send + Sync>>,
+    State(converter): State<Arc<dyn Converter + Send + Sync>>,
+    State(disk): State<Option<Arc<DiskCache>>>,
+    State(convert_limit): State<Arc<Semaphore>>,
+    Path(raw_id): Path<String>,
+    original_uri: OriginalUri,
+    raw_query: Option<RawQuery>,
+) -> Response {
+    let id = normalize_id(raw_id.trim()).to_string();
+    if id.is_empty() || !id.is_ascii() {
+        return (StatusCode::BAD_REQUEST, "invalid id").into_response();
+    }
+    let query = raw_query.and_then(|q| q.0).unwrap_or_default();
+    let Some(opts) = latex_options(&query) else {
+        return (StatusCode::BAD_REQUEST, "invalid main file").into_response();
+    };
+    let key = report_key(&paper_key(&id, &opts));
+    let mut found = cached(&cache, disk.as_deref(), &key).await;
+    if found.is_none() {
+        // The paper may be cached without its report (evicted, or cached
+        // before reports existed), so convert it again.
+        let query = if query.is_empty() {
+            "refresh=1".to_string()
+        } else {
+            format!("{}&refresh=1", query)
+        };
+        let resp = paper(
+            State(cache.clone()),
+            State(figure_cache),
+            State(client),
+            State(converter),
+            State(disk.clone()),
+            State(convert_limit),
+            Path(raw_id),
+            original_uri,
+            Some(RawQuery(Some(query))),
+        )
+        .await;
+        found = cached(&cache, disk.as_deref(), &key).await;
+        if found.is_none() {
+            let status = if resp.status().is_success() {
+                StatusCode::SERVICE_UNAVAILABLE
+            } else {
+                resp.status()
+            };
+            let error = serde_json::json!({ "error": "no conversion report for this paper" });
+            return json_response(status, error.to_string());
+        }
+    }
+    json_response(StatusCode::OK, found.unwrap_or_default())
+}
+
+fn json_response(status: StatusCode, json: String) -> Response {
+    (
+        status,
+        [(
+            axum::http::header::CONTENT_TYPE,
+            "application/json; charset=utf-8",
+        )],
+        json,
+    )
+        .into_response()
+}
+
+/// Cache key of the conversion report stored next to a paper's rendering.
+fn report_key(base_key: &str) -> String {
+    format!("{}#report", base_key)
+}
+
+async fn store_report(
+    cache: &Mutex<MkCache>,
+    disk: Option<&DiskCache>,
+    base_key: &str,
+    report: &ConversionReport,
+) {
+    store(cache, disk, &report_key(base_key), &report.to_json()).await;
+}
+
+/// Cache key of the number of tokens a profile saved on a rendering.
+fn tokens_saved_key(cache_key: &str) -> String {
+    format!("{}#tokens-saved", cache_key)
+}
+
+/// How a request for a paper is answered once its Markdown is at hand.
+struct Serve<'a> {
+    cache: &'a Mutex<MkCache>,
+    disk: Option<&'a DiskCache>,
+    /// Key of the LaTeX-math, full-profile rendering.
+    base_key: &'a str,
+    /// Key of the rendering this request asks for.
+    cache_key: &'a str,
+    debug: bool,
+    toc: bool,
+    content_location: &'a str,
+}
+
+impl Serve<'_> {
+    /// Derive and cache the requested rendering from the base one,
+    /// recording what the profile saved.
+    async fn derive(&self, base_md: &str, math: MathMode, profile: Profile) -> String {
+        self.derive_into(self.cache_key, base_md, math, profile)
+            .await
+    }
+
+    /// Replace the other renderings already cached for this paper with
+    /// ones derived from a fresh conversion, so `?refresh=1` reaches them
+    /// too.
+    async fn rederive_cached(&self, base_md: &str) {
+        for math in MathMode::ALL {
+            for profile in Profile::ALL {
+                let key = profile.cache_key(&math.cache_key(self.base_key));
+                if key == self.base_key || key == self.cache_key {
+                    continue;
+                }
+                if cached(self.cache, self.disk, &key).await.is_some() {
+                    self.derive_into(&key, base_md, math, profile).await;
+                }
+            }
+        }
+    }
+
+    async fn derive_into(
+        &self,
+        key: &str,
+        base_md: &str,
+        math: MathMode,
+        profile: Profile,
+    ) -> String {
+        let md = render_math(base_md, math);
+        let md = if profile == Profile::Full {
+            md
+        } else {
+            let lean = profile.apply(&md);
+            let saved = estimate_tokens(&md).saturating_sub(estimate_tokens(&lean));
+            let saved_key = tokens_saved_key(key);
+            store(self.cache, self.disk, &saved_key, &saved.to_string()).await;
+            lean
+        };
+        store(self.cache, self.disk, key, &md).await;
+        md
+    }
+
+    /// The Markdown response, with a table of contents for `?toc=1`, the
+    /// paper's cached conversion report appended for `?debug=1` and the
+    /// tokens a profile saved in `X-Tokens-Saved`.
+    async fn respond(&self, md: String) -> Response {
+        let md = if self.toc {
+            headings::insert_toc(&md)
+        } else {
+            md
+        };
+        let md = if self.debug {
+            match cached(self.cache, self.disk, &report_key(self.base_key)).await {
+                Some(json) => report::append_to_markdown(&md, &json),
+                None => md,
+            }
+        } else {
+            md
+        };
+        let mut resp = markdown_response(md, self.content_location);
+        if let Some(saved) = cached(self.cache, self.disk, &tokens_saved_key(self.cache_key)).await
+        {
+            if let Ok(val) = axum::http::HeaderValue::from_str(&saved) {
+                resp.headers_mut().insert("x-tokens-saved", val);
+            }
+        }
+        resp
+    }
+}
+
+/// Conversion options from the query string: `?main=path.tex` picks the
+/// main file when the heuristic gets it wrong, and `?supplementary=1`
+/// appends secondary documents. Figures are always extracted, since they
+/// are served next to the paper. `None` for an invalid path.
+fn latex_options(query: &str) -> Option<LatexOptions> {
+    let main = match query_param(query, "main") {
+        None => None,
+        Some(main) if is_valid_main(main) => Some(main.to_string()),
+        Some(_) => return None,
+    };
+    Some(LatexOptions {
+        main,
+        supplementary: query_param(query, "supplementary") == Some("1"),
+        figures: true,
+    })
+}
+
+/// A relative `.tex` path inside the archive, in characters that need no
+/// escaping in a URL.
+fn is_valid_main(main: &str) -> bool {
+    main.ends_with(".tex")
+        && !main.starts_with('/')
+        && main.split('/').all(|part| !part.is_empty() && part != "..")
+        && main
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "/._-+".contains(c))
+}
+
+/// Cache key for a paper's LaTeX rendering; non-default options get their
+/// own entries.
+fn paper_key(id: &str, opts: &LatexOptions) -> String {
+    let mut key = format!("/abs/{}{}", id, figure_query(opts));
+    if opts.supplementary {
+        key.push(if opts.main.is_some() { '&' } else { '?' });
+        key.push_str("supplementary=1");
+    }
+    key
+}
+
+/// Query for figure links, so they are served from the same main file.
+/// Figures only come from the main document, so this is also the figure
+/// cache key's suffix.
+fn figure_query(opts: &LatexOptions) -> String {
+    match &opts.main {
+        Some(main) => format!("?main={}", main),
+        None => String::new(),
+    }
+}
+
+/// Value of `key` in a raw query string, `Some("")` for a bare `key`.
+fn query_param<'a>(query: &'a str, key: &str) -> Option<&'a str> {
+    query.split('&').find_map(|kv| {
+        let mut it = kv.splitn(2, '=');
+        (it.next()? == key).then(|| it.next().unwrap_or(""))
+    })
+}
+
+/// A cached rendering from memory, or from disk (promoted to memory).
+async fn cached(cache: &Mutex<MkCache>, disk: Option<&DiskCache>, key: &str) -> Option<String> {
+    if let Some(md) = cache.lock().await.get(key) {
+        return Some(md);
+    }
+    match disk?.get(key).await {
+        Ok(Some(md)) => {
+            cache.lock().await.put(key.to_string(), md.clone());
+            Some(md)
+        }
+        Ok(None) => None,
+        Err(e) => {
+            tracing::error!(error = %e, "disk cache read error");
+            None
+        }
+    }
+}
+
+async fn store(cache: &Mutex<MkCache>, disk: Option<&DiskCache>, key: &str, md: &str) {
+    cache.lock().await.put(key.to_string(), md.to_string());
+    if let Some(dc) = disk {
+        if let Err(e) = dc.put(key, md).await {
+            tracing::error!(error = %e, cache_key = %key, "disk cache write error");
+        }
+    }
+}
+
+/// Serve a figure extracted from the paper's source archive.
+pub async fn figure(
+    State(figure_cache): State<Arc<Mutex<FigureCache>>>,
+    State(client): State<Arc<dyn ArxivClient + Send + Sync>>,
+    State(converter): State<Arc<dyn Converter + Send + Sync>>,
+    State(disk): State<Option<Arc<DiskCache>>>,
+    State(convert_limit): State<Arc<Semaphore>>,
+    Path((raw_id, name)): Path<(String, String)>,
+    raw_query: Option<RawQuery>,
+) -> Response {
+    let id = normalize_id(raw_id.trim()).to_string();
+    if id.is_empty() || !id.is_ascii() || !is_valid_name(&name) {
+        return (StatusCode::BAD_REQUEST, "invalid id").into_response();
+    }
+    let query = raw_query.and_then(|q| q.0).unwrap_or_default();
+    let Some(opts) = latex_options(&query) else {
+        return (StatusCode::BAD_REQUEST, "invalid main file").into_response();
     };
+    let cache_key = format!("/abs/{}{}", id, figure_query(&opts));
 
-    cache.lock().await.put(cache_key.clone(), final_md.clone());
+    if let Some(figures) = figure_cache.lock().await.get(&cache_key) {
+        return match figures.iter().find(|f| f.name == name) {
+            Some(f) => image_response(f.clone()),
+            None => (StatusCode::NOT_FOUND, "figure not found").into_response(),
+        };
+    }
     if let Some(dc) = &disk {
-        if let Err(e) = dc.put(&cache_key, &final_md).await {
-            tracing::error!(error = %e, cache_key = %cache_key, "disk cache write error");
+        match dc.get_figure(&cache_key, &name).await {
+            Ok(Some(f)) => return image_response(f),
+            Ok(None) => {}
+            Err(e) => tracing::error!(error = %e, "disk cache figure read error"),
+        }
+    }
+
+    // Not cached (e.g. after a restart without a disk cache): extract again.
+    let bytes = match client.get_source_archive(&id).await {
+        Ok(b) => b,
+        Err(err) => return map_arxiv_err("figures:source_archive", &id, err),
+    };
+    let figures = extract_figures(converter.as_ref(), &bytes, &opts, &id, convert_limit).await;
+    let found = figures.iter().find(|f| f.name == name).cloned();
+    store_figures(&figure_cache, disk.as_deref(), &cache_key, figures).await;
+    match found {
+        Some(f) => image_response(f),
+        None => (StatusCode::NOT_FOUND, "figure not found").into_response(),
+    }
+}
+
+async fn extract_figures(
+    converter: &(dyn Converter + Send + Sync),
+    tar_bytes: &[u8],
+    opts: &LatexOptions,
+    id: &str,
+    limit: Arc<Semaphore>,
+) -> Vec<FigureImage> {
+    let Ok(_permit) = limit.acquire_owned().await else {
+        return Vec::new();
+    };
+    match converter.latex_tar_figures(tar_bytes, opts).await {
+        Ok(figures) => figures,
+        Err(err) => {
+            tracing::warn!(paper_id = %id, error = %err, "figure extraction failed");
+            Vec::new()
+        }
+    }
+}
+
+async fn store_figures(
+    figure_cache: &Mutex<FigureCache>,
+    disk: Option<&DiskCache>,
+    cache_key: &str,
+    figures: Vec<FigureImage>,
+) {
+    if let Some(dc) = disk {
+        if let Err(e) = dc.put_figures(cache_key, &figures).await {
+            tracing::error!(error = %e, cache_key = %cache_key, "disk cache figure write error");
         }
     }
-    markdown_response(final_md, &original_path)
+    figure_cache
+        .lock()
+        .await
+        .put(cache_key.to_string(), Arc::new(figures));
+}
+
+fn image_response(figure: FigureImage) -> Response {
+    (
+        StatusCode::OK,
+        [(
+            axum::http::header::CONTENT_TYPE,
+            figure.format.content_type(),
+        )],
+        figure.bytes,
+    )
+        .into_response()
 }
 
 fn normalize_id(raw: &str) -> &str {
@@ -299,6 +739,10 @@ fn map_convert_err(context: &str, id: &str, e: ConvertError) -> Response {
             tracing::warn!(paper_id = %id, context = %context, "conversion not implemented");
             (StatusCode::NOT_IMPLEMENTED, "not implemented").into_response()
         }
+        err @ ConvertError::MainNotFound(_) => {
+            tracing::warn!(paper_id = %id, context = %context, error = %err, "main file not found");
+            (StatusCode::NOT_FOUND, err.to_string()).into_response()
+        }
     }
 }
 
@@ -307,10 +751,14 @@ async fn pdf_fallback(
     converter: &(dyn Converter + Send + Sync),
     id: &str,
     limit: Arc<Semaphore>,
+    report: &mut ConversionReport,
 ) -> Result<String, Response> {
     let pdf_bytes = match client.get_pdf(id).await {
         Ok(b) => b,
-        Err(err) => return Err(map_arxiv_err("pdf_fallback:get_pdf", id, err)),
+        Err(err) => {
+            report.errors.push(format!("pdf download: {}", err));
+            return Err(map_arxiv_err("pdf_fallback:get_pdf", id, err));
+        }
     };
     let _permit = match limit.clone().acquire_owned().await {
         Ok(permit) => permit,
@@ -322,40 +770,106 @@ async fn pdf_fallback(
             ))
         }
     };
-    match converter.pdf_to_markdown(&pdf_bytes).await {
-        Ok(s) => Ok(s),
-        Err(err) => Err(map_convert_err("pdf_fallback:pdf_to_markdown", id, err)),
+    let start = Instant::now();
+    let result = converter.pdf_to_markdown(&pdf_bytes).await;
+    report.time("pdf_fallback", start);
+    match result {
+        Ok(s) => {
+            report.mode = Some("pdftotext".into());
+            Ok(s)
+        }
+        Err(err) => {
+            report.errors.push(format!("pdftotext: {}", err));
+            Err(map_convert_err("pdf_fallback:pdf_to_markdown", id, err))
+        }
+    }
+}
+
+/// Convert a PostScript or DVI e-print (or a tar of one), if that's what
+/// the source archive holds; `None` sends the caller on to the PDF.
+async fn legacy_eprint(
+    converter: &(dyn Converter + Send + Sync),
+    bytes: &[u8],
+    id: &str,
+    limit: Arc<Semaphore>,
+    report: &mut ConversionReport,
+) -> Option<(String, LegacyFormat)> {
+    let format = eprint::sniff(bytes);
+    if format.legacy().is_none() && format != EprintFormat::Tar {
+        return None;
+    }
+    let _permit = limit.clone().acquire_owned().await.ok()?;
+    let start = Instant::now();
+    let result = converter.legacy_eprint_to_markdown(bytes).await;
+    report.time("legacy", start);
+    match result {
+        Ok((md, format)) => {
+            tracing::info!(paper_id = %id, route = format.route(), "converted legacy e-print");
+            report.mode = Some(format.route().to_string());
+            Some((md, format))
+        }
+        Err(ConvertError::NotImplemented) => None,
+        Err(err) => {
+            tracing::warn!(paper_id = %id, error = %err, "legacy e-print conversion failed");
+            report.errors.push(format!("legacy e-print: {}", err));
+            None
+        }
     }
 }
 
 async fn convert_latex_with_retries(
     converter: &(dyn Converter + Send + Sync),
     tar_bytes: &[u8],
+    opts: &LatexOptions,
     id: &str,
     limit: Arc<Semaphore>,
-) -> Result<String, ConvertError> {
+    report: &mut ConversionReport,
+) -> Result<LatexOutput, ConvertError> {
     let _permit = limit
         .clone()
         .acquire_owned()
         .await
         .map_err(|_| ConvertError::Failed("conversion limit unavailable".into()))?;
     const MAX_ATTEMPTS: usize = 2; // initial try + up to 1 retry
+    let first_stage = report.stages.len();
     for attempt in 1..=MAX_ATTEMPTS {
+        let attempt_stage = report.stages.len();
         if attempt == 2 {
             tracing::info!(
                 attempt,
                 paper_id = %id,
                 "retrying pandoc conversion with latex macros disabled"
             );
+            // The retry counts the floats again; keep its own timings
+            // apart from the failed attempt's.
+            report.figures = 0;
+            report.tables = 0;
+            report.label_stages(first_stage, "attempt 1");
         }
+        let mode = if attempt == 1 {
+            "pandoc"
+        } else {
+            "pandoc (macros disabled)"
+        };
         let result = if attempt == 1 {
-            converter.latex_tar_to_markdown(tar_bytes).await
+            converter
+                .latex_tar_to_markdown(tar_bytes, opts, report)
+                .await
         } else {
             converter
-                .latex_tar_to_markdown_without_macros(tar_bytes)
+                .latex_tar_to_markdown_without_macros(tar_bytes, opts, report)
                 .await
         };
+        if attempt > 1 {
+            report.label_stages(attempt_stage, &format!("attempt {}", attempt));
+        }
+        match &result {
+            Ok(_) => report.mode = Some(mode.to_string()),
+            Err(err) => report.errors.push(format!("{}: {}", mode, err)),
+        }
         match result {
+            // Disabling macros won't put the file in the archive.
+            Err(err @ ConvertError::MainNotFound(_)) => return Err(err),
             Ok(md) => {
                 if attempt > 1 {
                     tracing::info!(
@@ -391,61 +905,6 @@ async fn convert_latex_with_retries(
     ))
 }
 
-fn strip_html_tags(input: &str) -> String {
-    let mut out = String::with_capacity(input.len());
-    let mut in_tag = false;
-    let chars = input.chars();
-    for ch in chars {
-        match ch {
-            '<' => {
-                in_tag = true;
-            }
-            '>' => {
-                if in_tag {
-                    in_tag = false;
-                } else {
-                    out.push(ch);
-                }
-            }
-            _ => {
-                if !in_tag {
-                    out.push(ch);
-                }
-            }
-        }
-    }
-    out
-}
-
-fn prepend_metadata(meta: &Metadata, body_md: &str) -> String {
-    let title = strip_html_tags(&meta.title).trim().to_string();
-    let abstract_text = strip_html_tags(&meta.summary).trim().to_string();
-    let authors: Vec<String> = meta
-        .authors
-        .iter()
-        .map(|a| strip_html_tags(a).trim().to_string())
-        .filter(|a| !a.is_empty())
-        .collect();
-    let mut out = String::new();
-    if !title.is_empty() {
-        out.push_str("# ");
-        out.push_str(&title);
-        out.push_str("\n\n");
-    }
-    if !authors.is_empty() {
-        out.push_str("## Authors\n");
-        out.push_str(&authors.join(", "));
-        out.push_str("\n\n");
-    }
-    if !abstract_text.is_empty() {
-        out.push_str("## Abstract\n");
-        out.push_str(&abstract_text);
-        out.push_str("\n\n");
-    }
-    out.push_str(body_md);
-    out
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -455,7 +914,9 @@ mod tests {
     use tower::ServiceExt; // for `oneshot`
 
     use crate::arxiv::test_helpers::MockArxivClient;
+    use crate::arxiv::Metadata;
     use crate::convert::test_helpers::MockConverter;
+    use crate::header::PDF_TEXT_NOTE;
     use crate::state::AppState;
 
     #[test]
@@ -516,17 +977,6 @@ mod tests {
         assert_eq!(resp.status(), StatusCode::INTERNAL_SERVER_ERROR);
     }
 
-    #[test]
-    fn prepend_metadata_includes_authors_section() {
-        let meta = Metadata {
-            title: "Sample Title".into(),
-            summary: "Sample abstract".into(),
-            authors: vec!["Alice Example".into(), "Bob <i>Author</i>".into()],
-        };
-        let out = super::prepend_metadata(&meta, "Body");
-        assert!(out.starts_with("# Sample Title\n\n## Authors\nAlice Example, Bob Author\n\n## Abstract\nSample abstract\n\nBody"));
-    }
-
     #[tokio::test]
     async fn health_ok() {
         let app = Router::new().route("/health", get(super::health));
@@ -602,6 +1052,121 @@ mod tests {
         assert_eq!(cl2, format!("/abs/{}", id));
     }
 
+    #[tokio::test]
+    async fn math_modes_have_their_own_cache_entries() {
+        let id = "1234.5678";
+        let md = "Let $\\alpha \\le \\beta^2$ and $\\begin{matrix}a\\end{matrix}$.".to_string();
+        let client = MockArxivClient::new(
+            Ok(true),
+            Ok(Bytes::from_static(b"tar-bytes")),
+            Err(ArxivError::NotImplemented),
+            Err(ArxivError::NotImplemented),
+        );
+        let converter = MockConverter::new(Ok(md.clone()), Ok(md.clone()));
+        let latex_calls = converter.latex_calls.clone();
+        let state = AppState::new(8, client, converter, None);
+        let app = Router::new()
+            .route("/abs/:id", get(super::paper))
+            .with_state(state.clone());
+        let get_body = |uri: String| {
+            let app = app.clone();
+            async move {
+                let res = app
+                    .oneshot(
+                        axum::http::Request::builder()
+                            .uri(uri)
+                            .body(axum::body::Body::empty())
+                            .unwrap(),
+                    )
+                    .await
+                    .unwrap();
+                let status = res.status();
+                let body = to_bytes(res.into_body(), usize::MAX).await.unwrap();
+                (status, String::from_utf8(body.to_vec()).unwrap())
+            }
+        };
+
+        let (status, unicode) = get_body(format!("/abs/{}?math=unicode", id)).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(unicode, "Let α ≤ β² and $\\begin{matrix}a\\end{matrix}$.");
+        let (_, latex) = get_body(format!("/abs/{}", id)).await;
+        assert_eq!(latex, md);
+        assert_eq!(latex_calls.load(Ordering::SeqCst), 1);
+
+        let mut cache = state.cache.lock().await;
+        assert_eq!(cache.get(&format!("/abs/{}", id)), Some(md));
+        assert_eq!(
+            cache.get(&format!("/abs/{}?math=unicode", id)),
+            Some(unicode)
+        );
+        drop(cache);
+
+        let (status, mathml) = get_body(format!("/abs/{}?math=mathml", id)).await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(mathml.starts_with("Let <math xmlns=\"http://www.w3.org/1998/Math/MathML\" display=\"inline\"><mrow><mi>α</mi><mo>≤</mo>"));
+        assert!(mathml.contains("<mtable><mtr><mtd><mi>a</mi></mtd></mtr></mtable>"));
+        assert_eq!(latex_calls.load(Ordering::SeqCst), 1);
+
+        let (status, _) = get_body(format!("/abs/{}?math=braille", id)).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn latex_options_are_validated_and_cached_separately() {
+        let id = "1234.5678";
+        let md = "Body $x$".to_string();
+        let client = MockArxivClient::new(
+            Ok(true),
+            Ok(Bytes::from_static(b"tar-bytes")),
+            Err(ArxivError::NotImplemented),
+            Err(ArxivError::NotImplemented),
+        );
+        let converter = MockConverter::new(Ok(md.clone()), Ok(md.clone()));
+        let state = AppState::new(8, client, converter, None);
+        let app = Router::new()
+            .route("/abs/:id", get(super::paper))
+            .with_state(state.clone());
+        let get_status = |uri: String| {
+            let app = app.clone();
+            async move {
+                app.oneshot(
+                    axum::http::Request::builder()
+                        .uri(uri)
+                        .body(axum::body::Body::empty())
+                        .unwrap(),
+                )
+                .await
+                .unwrap()
+                .status()
+            }
+        };
+
+        for bad in ["../x.tex", "/etc/x.tex", "paper.bib", "a%20b.tex"] {
+            let status = get_status(format!("/abs/{}?main={}", id, bad)).await;
+            assert_eq!(status, StatusCode::BAD_REQUEST, "{}", bad);
+        }
+        let status = get_status(format!("/abs/{}?main=sub/paper.tex&math=unicode", id)).await;
+        assert_eq!(status, StatusCode::OK);
+
+        let mut cache = state.cache.lock().await;
+        assert_eq!(
+            cache.get(&format!("/abs/{}?main=sub/paper.tex", id)),
+            Some(md.clone())
+        );
+        assert_eq!(
+            cache.get(&format!("/abs/{}?main=sub/paper.tex&math=unicode", id)),
+            Some("Body x".to_string())
+        );
+        assert_eq!(cache.get(&format!("/abs/{}", id)), None);
+        drop(cache);
+
+        let status = get_status(format!("/abs/{}?supplementary=1", id)).await;
+        assert_eq!(status, StatusCode::OK);
+        let mut cache = state.cache.lock().await;
+        assert_eq!(cache.get(&format!("/abs/{}?supplementary=1", id)), Some(md));
+        assert_eq!(cache.get(&format!("/abs/{}", id)), None);
+    }
+
     #[tokio::test]
     async fn pdf_route_accepts_pdf_suffix_and_hits_cache() {
         let id = "1234.5678v3";
@@ -766,24 +1331,473 @@ mod tests {
         let status = res.status();
         let body = to_bytes(res.into_body(), usize::MAX).await.unwrap();
         assert_eq!(status, StatusCode::OK);
-        assert_eq!(body.as_ref(), b"pdf text");
+        assert_eq!(
+            String::from_utf8(body.to_vec()).unwrap(),
+            format!(
+                "# Sample Title\n\n## Authors\nAuthor One\n\n## Abstract\nSample abstract\n\n{}\n\npdf text",
+                PDF_TEXT_NOTE
+            )
+        );
         assert_eq!(archive_calls.load(Ordering::SeqCst), 1);
         assert_eq!(pdf_calls.load(Ordering::SeqCst), 1);
     }
 
     #[tokio::test]
-    async fn pandoc_failure_falls_back_to_pdftotext() {
+    async fn postscript_eprint_skips_pandoc_and_notes_the_route() {
         let id = "1234.5678";
-        let tar = Bytes::from_static(b"tar-bytes");
         let client = MockArxivClient::new(
             Ok(true),
-            Ok(tar),
+            Ok(Bytes::from_static(b"%!PS-Adobe-2.0\n")),
             Ok(Bytes::from_static(b"pdf-bytes")),
-            Ok(Metadata {
-                title: "Sample Title".into(),
-                summary: "Sample abstract".into(),
-                authors: vec!["Author One".into()],
-            }),
+            Err(ArxivError::NotImplemented),
+        );
+        let pdf_calls = client.pdf_calls.clone();
+        let mut converter = MockConverter::new(Ok("latex".into()), Ok("pdf text".into()));
+        converter.legacy_result = Ok(("ps text".into(), LegacyFormat::PostScript));
+        let latex_calls = converter.latex_calls.clone();
+        let legacy_calls = converter.legacy_calls.clone();
+        let state = AppState::new(8, client, converter, None);
+
+        let app = Router::new()
+            .route("/abs/:id", get(super::paper))
+            .with_state(state);
+
+        let res = app
+            .oneshot(
+                axum::http::Request::builder()
+                    .uri(format!("/abs/{}", id))
+                    .body(axum::body::Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        let status = res.status();
+        let body = to_bytes(res.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(
+            String::from_utf8(body.to_vec()).unwrap(),
+            format!(
+                "{}\n\nps text",
+                crate::header::legacy_note(LegacyFormat::PostScript)
+            )
+        );
+        assert_eq!(latex_calls.load(Ordering::SeqCst), 0);
+        assert_eq!(legacy_calls.load(Ordering::SeqCst), 1);
+        assert_eq!(pdf_calls.load(Ordering::SeqCst), 0);
+    }
+
+    #[tokio::test]
+    async fn conversion_report_is_served_as_json_and_with_debug() {
+        let id = "1234.5678";
+        let client = MockArxivClient::new(
+            Ok(true),
+            Ok(Bytes::from_static(b"tar-bytes")),
+            Ok(Bytes::from_static(b"pdf-bytes")),
+            Err(ArxivError::NotImplemented),
+        );
+        let converter = MockConverter::new(
+            Err(ConvertError::Failed("bad macro".into())),
+            Ok("pdf text".into()),
+        );
+        let latex_calls = converter.latex_calls.clone();
+        let state = AppState::new(8, client, converter, None);
+        let app = Router::new()
+            .route("/abs/:id", get(super::paper))
+            .route("/abs/:id/report", get(super::report))
+            .with_state(state);
+        let get_body = |uri: String| {
+            let app = app.clone();
+            async move {
+                let res = app
+                    .oneshot(
+                        axum::http::Request::builder()
+                            .uri(uri)
+                            .body(axum::body::Body::empty())
+                            .unwrap(),
+                    )
+                    .await
+                    .unwrap();
+                assert_eq!(res.status(), StatusCode::OK);
+                let body = to_bytes(res.into_body(), usize::MAX).await.unwrap();
+                String::from_utf8(body.to_vec()).unwrap()
+            }
+        };
+
+        // The report route converts the paper when it has no report yet.
+        let json = get_body(format!("/abs/{}/report", id)).await;
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(value["archive_format"], "other");
+        assert_eq!(value["mode"], "pdftotext");
+        assert_eq!(value["errors"][0], "pandoc: conversion failed: bad macro");
+        assert_eq!(
+            value["errors"][1],
+            "pandoc (macros disabled): conversion failed: bad macro"
+        );
+        assert!(value["stages"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .any(|s| s["stage"] == "pdf_fallback"));
+
+        // Served from the cache, with the report appended.
+        let md = get_body(format!("/abs/{}?debug=1", id)).await;
+        assert_eq!(
+            md,
+            report::append_to_markdown(&format!("{}\n\npdf text", PDF_TEXT_NOTE), &json)
+        );
+        let md = get_body(format!("/abs/{}", id)).await;
+        assert!(!md.contains("Conversion report"));
+        assert_eq!(latex_calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn report_missing_from_the_cache_is_regenerated() {
+        let id = "1234.5678";
+        let client = MockArxivClient::new(
+            Ok(true),
+            Ok(Bytes::from_static(b"tar-bytes")),
+            Err(ArxivError::NotImplemented),
+            Err(ArxivError::NotImplemented),
+        );
+        let converter = MockConverter::new(Ok("Body".into()), Ok(String::new()));
+        let latex_calls = converter.latex_calls.clone();
+        let state = AppState::new(8, client, converter, None);
+        // The paper is cached but its report was evicted.
+        state
+            .cache
+            .lock()
+            .await
+            .put(format!("/abs/{}", id), "cached body".into());
+        let app = Router::new()
+            .route("/abs/:id/report", get(super::report))
+            .with_state(state);
+
+        let res = app
+            .oneshot(
+                axum::http::Request::builder()
+                    .uri(format!("/abs/{}/report", id))
+                    .body(axum::body::Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(
+            res.headers().get(axum::http::header::CONTENT_TYPE).unwrap(),
+            "application/json; charset=utf-8"
+        );
+        let body = to_bytes(res.into_body(), usize::MAX).await.unwrap();
+        let value: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(value["mode"], "pandoc");
+        assert_eq!(latex_calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn report_of_a_failed_conversion_is_a_json_error() {
+        let client = MockArxivClient::new(
+            Ok(false),
+            Err(ArxivError::NotFound),
+            Err(ArxivError::NotFound),
+            Err(ArxivError::NotFound),
+        );
+        let converter = MockConverter::new(Ok(String::new()), Ok(String::new()));
+        let state = AppState::new(8, client, converter, None);
+        let app = Router::new()
+            .route("/abs/:id/report", get(super::report))
+            .with_state(state);
+
+        let res = app
+            .oneshot(
+                axum::http::Request::builder()
+                    .uri("/abs/1234.5678/report")
+                    .body(axum::body::Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert!(!res.status().is_success());
+        assert_eq!(
+            res.headers().get(axum::http::header::CONTENT_TYPE).unwrap(),
+            "application/json; charset=utf-8"
+        );
+        let body = to_bytes(res.into_body(), usize::MAX).await.unwrap();
+        let value: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert!(value["error"].is_string());
+    }
+
+    /// Counts a figure and times a stage on each attempt, like the real
+    /// converter, and fails the first one.
+    struct FailsOnceConverter;
+
+    #[async_trait::async_trait]
+    impl Converter for FailsOnceConverter {
+        async fn latex_tar_to_markdown(
+            &self,
+            _tar_bytes: &[u8],
+            _opts: &LatexOptions,
+            report: &mut ConversionReport,
+        ) -> Result<LatexOutput, ConvertError> {
+            report.count_floats("\\begin{figure}x\\end{figure}");
+            report.time("pandoc", Instant::now());
+            Err(ConvertError::Failed("macro trouble".into()))
+        }
+
+        async fn latex_tar_to_markdown_without_macros(
+            &self,
+            _tar_bytes: &[u8],
+            _opts: &LatexOptions,
+            report: &mut ConversionReport,
+        ) -> Result<LatexOutput, ConvertError> {
+            report.count_floats("\\begin{figure}x\\end{figure}");
+            report.time("pandoc", Instant::now());
+            Ok(LatexOutput {
+                markdown: "Body".into(),
+                figures: Vec::new(),
+            })
+        }
+
+        async fn pdf_to_markdown(&self, _pdf_bytes: &[u8]) -> Result<String, ConvertError> {
+            Err(ConvertError::NotImplemented)
+        }
+    }
+
+    #[tokio::test]
+    async fn retried_conversion_reports_each_attempt_once() {
+        let client = MockArxivClient::new(
+            Ok(true),
+            Ok(Bytes::from_static(b"tar-bytes")),
+            Err(ArxivError::NotImplemented),
+            Err(ArxivError::NotImplemented),
+        );
+        let state = AppState::new(8, client, FailsOnceConverter, None);
+        let app = Router::new()
+            .route("/abs/:id/report", get(super::report))
+            .with_state(state);
+
+        let res = app
+            .oneshot(
+                axum::http::Request::builder()
+                    .uri("/abs/1234.5678/report")
+                    .body(axum::body::Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        let body = to_bytes(res.into_body(), usize::MAX).await.unwrap();
+        let value: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(value["mode"], "pandoc (macros disabled)");
+        assert_eq!(value["figures"], 1);
+        let stages: Vec<&str> = value["stages"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|s| s["stage"].as_str().unwrap())
+            .collect();
+        assert!(stages.contains(&"attempt 1: pandoc"));
+        assert!(stages.contains(&"attempt 2: pandoc"));
+        assert!(!stages.contains(&"pandoc"));
+    }
+
+    #[tokio::test]
+    async fn toc_is_added_on_request_only() {
+        let id = "1234.5678";
+        let client = MockArxivClient::new(
+            Ok(true),
+            Ok(Bytes::from_static(b"tar-bytes")),
+            Err(ArxivError::NotImplemented),
+            Err(ArxivError::NotImplemented),
+        );
+        let converter = MockConverter::new(
+            Ok("# Intro\n\nText\n\n## Detail\n\nMore".into()),
+            Ok(String::new()),
+        );
+        let latex_calls = converter.latex_calls.clone();
+        let state = AppState::new(8, client, converter, None);
+        let app = Router::new()
+            .route("/abs/:id", get(super::paper))
+            .with_state(state);
+        let get_body = |uri: String| {
+            let app = app.clone();
+            async move {
+                let res = app
+                    .oneshot(
+                        axum::http::Request::builder()
+                            .uri(uri)
+                            .body(axum::body::Body::empty())
+                            .unwrap(),
+                    )
+                    .await
+                    .unwrap();
+                let body = to_bytes(res.into_body(), usize::MAX).await.unwrap();
+                String::from_utf8(body.to_vec()).unwrap()
+            }
+        };
+
+        let md = get_body(format!("/abs/{}", id)).await;
+        assert_eq!(
+            md,
+            "## Intro<a id=\"intro\"></a>\n\nText\n\n### Detail<a id=\"detail\"></a>\n\nMore"
+        );
+        let md = get_body(format!("/abs/{}?toc=1", id)).await;
+        assert!(
+            md.starts_with("## Contents\n\n- [Intro](#intro)\n  - [Detail](#detail)\n\n## Intro")
+        );
+        assert_eq!(latex_calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn refresh_replaces_derived_renderings() {
+        let id = "1234.5678";
+        let client = MockArxivClient::new(
+            Ok(true),
+            Ok(Bytes::from_static(b"tar-bytes")),
+            Err(ArxivError::NotImplemented),
+            Err(ArxivError::NotImplemented),
+        );
+        let converter = MockConverter::new(Ok("Fresh $x$".into()), Ok(String::new()));
+        let state = AppState::new(8, client, converter, None);
+        {
+            let mut cache = state.cache.lock().await;
+            cache.put(format!("/abs/{}", id), "Old $x$".into());
+            cache.put(format!("/abs/{}?math=unicode", id), "Old x".into());
+            cache.put(format!("/abs/{}?profile=lean", id), "Old lean".into());
+        }
+        let app = Router::new()
+            .route("/abs/:id", get(super::paper))
+            .with_state(state);
+        let get_body = |uri: String| {
+            let app = app.clone();
+            async move {
+                let res = app
+                    .oneshot(
+                        axum::http::Request::builder()
+                            .uri(uri)
+                            .body(axum::body::Body::empty())
+                            .unwrap(),
+                    )
+                    .await
+                    .unwrap();
+                let body = to_bytes(res.into_body(), usize::MAX).await.unwrap();
+                String::from_utf8(body.to_vec()).unwrap()
+            }
+        };
+
+        assert_eq!(get_body(format!("/abs/{}?math=unicode", id)).await, "Old x");
+        assert_eq!(
+            get_body(format!("/abs/{}?refresh=1", id)).await,
+            "Fresh $x$"
+        );
+        assert_eq!(
+            get_body(format!("/abs/{}?math=unicode", id)).await,
+            "Fresh x"
+        );
+        assert_eq!(
+            get_body(format!("/abs/{}?profile=lean", id)).await,
+            "Fresh $x$\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn lean_profile_is_derived_and_reports_savings() {
+        let id = "1234.5678";
+        let client = MockArxivClient::new(
+            Ok(true),
+            Ok(Bytes::from_static(b"tar-bytes")),
+            Err(ArxivError::NotImplemented),
+            Err(ArxivError::NotImplemented),
+        );
+        let converter = MockConverter::new(
+            Ok("# Intro\n\nText\n\n# References\n\n1. A long reference list.".into()),
+            Ok(String::new()),
+        );
+        let latex_calls = converter.latex_calls.clone();
+        let state = AppState::new(8, client, converter, None);
+        let app = Router::new()
+            .route("/abs/:id", get(super::paper))
+            .with_state(state);
+        let get = |uri: String| {
+            let app = app.clone();
+            async move {
+                app.oneshot(
+                    axum::http::Request::builder()
+                        .uri(uri)
+                        .body(axum::body::Body::empty())
+                        .unwrap(),
+                )
+                .await
+                .unwrap()
+            }
+        };
+
+        let res = get(format!("/abs/{}", id)).await;
+        assert!(res.headers().get("x-tokens-saved").is_none());
+        for _ in 0..2 {
+            let res = get(format!("/abs/{}?profile=lean", id)).await;
+            assert_eq!(res.status(), StatusCode::OK);
+            assert_eq!(res.headers().get("x-tokens-saved").unwrap(), "16");
+            let body = to_bytes(res.into_body(), usize::MAX).await.unwrap();
+            assert_eq!(body, "## Intro<a id=\"intro\"></a>\n\nText\n");
+        }
+        let res = get(format!("/abs/{}?profile=tiny", id)).await;
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(latex_calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn missing_main_file_is_not_found_without_fallback() {
+        let client = MockArxivClient::new(
+            Ok(true),
+            Ok(Bytes::from_static(b"tar-bytes")),
+            Ok(Bytes::from_static(b"pdf-bytes")),
+            Err(ArxivError::NotImplemented),
+        );
+        let pdf_calls = client.pdf_calls.clone();
+        let converter = MockConverter::new(
+            Err(ConvertError::MainNotFound("missing.tex".into())),
+            Ok("pdf text".into()),
+        );
+        let latex_calls = converter.latex_calls.clone();
+        let latex_nomacro_calls = converter.latex_nomacro_calls.clone();
+        let state = AppState::new(8, client, converter, None);
+        let app = Router::new()
+            .route("/abs/:id", get(super::paper))
+            .with_state(state);
+
+        for _ in 0..2 {
+            let res = app
+                .clone()
+                .oneshot(
+                    axum::http::Request::builder()
+                        .uri("/abs/1234.5678?main=missing.tex")
+                        .body(axum::body::Body::empty())
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            assert_eq!(res.status(), StatusCode::NOT_FOUND);
+            let body = to_bytes(res.into_body(), usize::MAX).await.unwrap();
+            assert_eq!(body, "main file missing.tex is not in the archive");
+        }
+        // Not retried, not served from the PDF and not cached.
+        assert_eq!(latex_calls.load(Ordering::SeqCst), 2);
+        assert_eq!(latex_nomacro_calls.load(Ordering::SeqCst), 0);
+        assert_eq!(pdf_calls.load(Ordering::SeqCst), 0);
+    }
+
+    #[tokio::test]
+    async fn pandoc_failure_falls_back_to_pdftotext() {
+        let id = "1234.5678";
+        let tar = Bytes::from_static(b"tar-bytes");
+        let client = MockArxivClient::new(
+            Ok(true),
+            Ok(tar),
+            Ok(Bytes::from_static(b"pdf-bytes")),
+            Ok(Metadata {
+                title: "Sample Title".into(),
+                summary: "Sample abstract".into(),
+                authors: vec!["Author One".into()],
+            }),
         );
         let pdf_calls = client.pdf_calls.clone();
         let archive_calls = client.archive_calls.clone();
@@ -812,7 +1826,13 @@ mod tests {
         let status = res.status();
         let body = to_bytes(res.into_body(), usize::MAX).await.unwrap();
         assert_eq!(status, StatusCode::OK);
-        assert_eq!(body.as_ref(), b"pdf text");
+        assert_eq!(
+            String::from_utf8(body.to_vec()).unwrap(),
+            format!(
+                "# Sample Title\n\n## Authors\nAuthor One\n\n## Abstract\nSample abstract\n\n{}\n\npdf text",
+                PDF_TEXT_NOTE
+            )
+        );
         assert_eq!(archive_calls.load(Ordering::SeqCst), 1);
         assert_eq!(pdf_calls.load(Ordering::SeqCst), 1);
         assert_eq!(latex_calls.load(Ordering::SeqCst), 1);
@@ -820,6 +1840,126 @@ mod tests {
         assert_eq!(converter_pdf_calls.load(Ordering::SeqCst), 1);
     }
 
+    #[tokio::test]
+    async fn paper_links_and_serves_source_figures() {
+        let tar = Bytes::from_static(b"tar-bytes");
+        let md = "Intro\n\n> **Figure 1:** A plot\n".to_string();
+        let client = MockArxivClient::new(
+            Ok(true),
+            Ok(tar),
+            Err(ArxivError::NotImplemented),
+            Err(ArxivError::NotImplemented),
+        );
+        let mut converter = MockConverter::new(Ok(md), Ok(String::new()));
+        converter.figures = vec![FigureImage {
+            name: "1".into(),
+            format: crate::figures::ImageFormat::Png,
+            bytes: b"png".to_vec(),
+        }];
+        let figure_calls = converter.figure_calls.clone();
+        let state = AppState::new(8, client, converter, None);
+
+        let app = Router::new()
+            .route("/abs/:id", get(super::paper))
+            .route("/abs/:id/figures/:n", get(super::figure))
+            .with_state(state);
+
+        let res = app
+            .clone()
+            .oneshot(
+                axum::http::Request::builder()
+                    .uri("/abs/1234.5678")
+                    .body(axum::body::Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        let body = to_bytes(res.into_body(), usize::MAX).await.unwrap();
+        let body = String::from_utf8(body.to_vec()).unwrap();
+        assert!(body.contains(
+            "> ![Figure 1](https://markxiv.org/abs/1234.5678/figures/1)\n>\n> **Figure 1:** A plot"
+        ));
+
+        let res = app
+            .clone()
+            .oneshot(
+                axum::http::Request::builder()
+                    .uri("/abs/1234.5678/figures/1")
+                    .body(axum::body::Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(
+            res.headers()
+                .get(axum::http::header::CONTENT_TYPE)
+                .and_then(|h| h.to_str().ok()),
+            Some("image/png")
+        );
+        let body = to_bytes(res.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(body.as_ref(), b"png");
+
+        let res = app
+            .oneshot(
+                axum::http::Request::builder()
+                    .uri("/abs/1234.5678/figures/2")
+                    .body(axum::body::Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+        // The figures came with the conversion and are served from the
+        // figure cache; the archive is never extracted on its own.
+        assert_eq!(figure_calls.load(Ordering::SeqCst), 0);
+    }
+
+    #[tokio::test]
+    async fn figure_route_extracts_when_not_cached() {
+        let client = MockArxivClient::new(
+            Ok(true),
+            Ok(Bytes::from_static(b"tar-bytes")),
+            Err(ArxivError::NotImplemented),
+            Err(ArxivError::NotImplemented),
+        );
+        let archive_calls = client.archive_calls.clone();
+        let mut converter = MockConverter::new(Ok(String::new()), Ok(String::new()));
+        converter.figures = vec![FigureImage {
+            name: "2b".into(),
+            format: crate::figures::ImageFormat::Jpeg,
+            bytes: b"jpeg".to_vec(),
+        }];
+        let state = AppState::new(8, client, converter, None);
+        let app = Router::new()
+            .route("/abs/:id/figures/:n", get(super::figure))
+            .with_state(state);
+
+        let res = app
+            .clone()
+            .oneshot(
+                axum::http::Request::builder()
+                    .uri("/abs/1234.5678/figures/2b")
+                    .body(axum::body::Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(archive_calls.load(Ordering::SeqCst), 1);
+
+        let res = app
+            .oneshot(
+                axum::http::Request::builder()
+                    .uri("/abs/1234.5678/figures/..%2Fx")
+                    .body(axum::body::Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+    }
+
     #[tokio::test]
     async fn invalid_id_400() {
         let client = MockArxivClient::new(
@@ -4,13 +4,14 @@ use axum::extract::FromRef;
 use tokio::sync::{Mutex, Semaphore};
 
 use crate::arxiv::ArxivClient;
-use crate::cache::MkCache;
+use crate::cache::{FigureCache, MkCache};
 use crate::convert::Converter;
 use crate::disk_cache::DiskCache;
 
 #[derive(Clone)]
 pub struct AppState {
     pub cache: Arc<Mutex<MkCache>>,
+    pub figures: Arc<Mutex<FigureCache>>,
     pub client: Arc<dyn ArxivClient + Send + Sync>,
     pub converter: Arc<dyn Converter + Send + Sync>,
     pub disk: Option<Arc<DiskCache>>,
@@ -26,6 +27,7 @@ impl AppState {
         let permits = num_cpus::get().max(1);
         Self {
             cache: Arc::new(Mutex::new(MkCache::new(cap))),
+            figures: Arc::new(Mutex::new(FigureCache::new(cap))),
             client: Arc::new(client),
             converter: Arc::new(converter),
             disk,
@@ -40,6 +42,12 @@ impl FromRef<AppState> for Arc<Mutex<MkCache>> {
     }
 }
 
+impl FromRef<AppState> for Arc<Mutex<FigureCache>> {
+    fn from_ref(input: &AppState) -> Self {
+        input.figures.clone()
+    }
+}
+
 impl FromRef<AppState> for Arc<dyn ArxivClient + Send + Sync> {
     fn from_ref(input: &AppState) -> Self {
         input.client.clone()
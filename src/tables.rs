@@ -0,0 +1,568 @@
+/// Structural tags kept by the HTML stripper so fallback tables survive.
+pub const TABLE_TAGS: &[&str] = &[
+    "table", "caption", "thead", "tbody", "tfoot", "tr", "th", "td",
+];
+
+/// Inline tags kept inside fallback tables.
+pub const TABLE_INLINE_TAGS: &[&str] = &["em", "strong", "code", "sup", "sub", "br", "a"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Align {
+    Left,
+    Center,
+    Right,
+}
+
+impl Align {
+    fn parse(tag: &str) -> Option<Self> {
+        let value = attr(tag, "align").or_else(|| {
+            let style = attr(tag, "style")?;
+            let rest = &style[style.find("text-align")? + "text-align".len()..];
+            let rest = rest.trim_start().strip_prefix(':')?;
+            Some(rest.split(';').next().unwrap_or("").trim().to_string())
+        })?;
+        match value.to_ascii_lowercase().as_str() {
+            "left" => Some(Align::Left),
+            "center" => Some(Align::Center),
+            "right" => Some(Align::Right),
+            _ => None,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Align::Left => "left",
+            Align::Center => "center",
+            Align::Right => "right",
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Cell {
+    /// Sanitized inline HTML, for the `<table>` fallback.
+    html: String,
+    /// Inline Markdown, for pipe tables.
+    markdown: String,
+    colspan: usize,
+    rowspan: usize,
+    header: bool,
+    align: Option<Align>,
+    /// Holds more than one paragraph or a line break.
+    multiline: bool,
+    /// Target of the link being written to `markdown`.
+    link: Option<String>,
+}
+
+#[derive(Debug, Default)]
+struct Table {
+    caption: Option<Cell>,
+    head: Vec<Vec<Cell>>,
+    body: Vec<Vec<Cell>>,
+}
+
+/// Rewrite pandoc's raw HTML `<table>` blocks. Tables a GFM pipe table can
+/// express (no merged cells, at most one header row, single-line cells)
+/// become pipe tables; the rest are re-emitted as whitelisted HTML that
+/// [`sanitize_tag`] and the tag stripper leave alone. Captions follow the
+/// table as a paragraph, as pandoc writes them for pipe tables.
+pub fn convert_tables(md: &str) -> String {
+    let mut out = String::with_capacity(md.len());
+    let mut rest = md;
+    while let Some(start) = find_tag(rest, "table", 0) {
+        let Some(end) = table_end(rest, start) else {
+            break;
+        };
+        out.push_str(&rest[..start]);
+        let table = parse_table(&rest[start..end]);
+        let rendered = if table.fits_pipe() {
+            table.to_pipe()
+        } else {
+            table.to_html()
+        };
+        // Tables are blocks: a blank line on either side.
+        if !out.is_empty() && !out.ends_with("\n\n") {
+            out.push_str(if out.ends_with('\n') { "\n" } else { "\n\n" });
+        }
+        out.push_str(&rendered);
+        rest = rest[end..].trim_start_matches('\n');
+        if !rest.is_empty() {
+            out.push('\n');
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Rebuild an HTML tag from its name and a few harmless attributes, or
+/// `None` if the tag is not one tables may keep.
+pub fn sanitize_tag(tag: &str) -> Option<String> {
+    let (name, closing) = tag_name(tag)?;
+    if !TABLE_TAGS.contains(&name.as_str()) && !TABLE_INLINE_TAGS.contains(&name.as_str()) {
+        return None;
+    }
+    if closing {
+        return Some(format!("</{}>", name));
+    }
+    let mut out = format!("<{}", name);
+    match name.as_str() {
+        "th" | "td" => {
+            for key in ["colspan", "rowspan"] {
+                if let Some(n) = attr(tag, key).and_then(|v| v.trim().parse::<usize>().ok()) {
+                    if n > 1 {
+                        out.push_str(&format!(" {}=\"{}\"", key, n));
+                    }
+                }
+            }
+            if let Some(a) = Align::parse(tag) {
+                out.push_str(&format!(" align=\"{}\"", a.name()));
+            }
+        }
+        "a" => {
+            if let Some(href) = attr(tag, "href").filter(|h| safe_href(h)) {
+                out.push_str(&format!(" href=\"{}\"", href.replace('"', "&quot;")));
+            }
+        }
+        _ => {}
+    }
+    out.push('>');
+    Some(out)
+}
+
+fn safe_href(href: &str) -> bool {
+    let h = href.trim().to_ascii_lowercase();
+    h.starts_with("http://") || h.starts_with("https://") || h.starts_with('#')
+}
+
+/// Lowercase tag name and whether it is a closing tag.
+pub fn tag_name(tag: &str) -> Option<(String, bool)> {
+    let inner = tag.strip_prefix('<')?;
+    let (closing, inner) = match inner.strip_prefix('/') {
+        Some(rest) => (true, rest),
+        None => (false, inner),
+    };
+    let name: String = inner
+        .chars()
+        .take_while(|c| c.is_ascii_alphanumeric())
+        .collect::<String>()
+        .to_ascii_lowercase();
+    if name.is_empty() {
+        None
+    } else {
+        Some((name, closing))
+    }
+}
+
+fn attr(tag: &str, key: &str) -> Option<String> {
+    let lower = tag.to_ascii_lowercase();
+    let mut from = 0;
+    while let Some(rel) = lower[from..].find(key) {
+        let at = from + rel;
+        from = at + key.len();
+        let before_ok = lower[..at]
+            .chars()
+            .next_back()
+            .is_some_and(|c| c.is_ascii_whitespace());
+        let rest = lower[from..].trim_start();
+        if !before_ok || !rest.starts_with('=') {
+            continue;
+        }
+        let value_start = tag.len() - rest.len() + 1;
+        let value = tag[value_start..].trim_start();
+        return Some(match value.chars().next() {
+            Some(q @ ('"' | '\'')) => value[1..].split(q).next().unwrap_or("").to_string(),
+            _ => value
+                .split(|c: char| c.is_ascii_whitespace() || c == '>')
+                .next()
+                .unwrap_or("")
+                .to_string(),
+        });
+    }
+    None
+}
+
+/// Byte offset of the next `<name` or `<name ...>` tag at or after `from`.
+fn find_tag(s: &str, name: &str, from: usize) -> Option<usize> {
+    let needle = format!("<{}", name);
+    let mut pos = from;
+    while let Some(rel) = s[pos..].find(&needle) {
+        let at = pos + rel;
+        let next = s[at + needle.len()..].chars().next();
+        if matches!(next, Some('>' | ' ' | '\n' | '\t' | '/')) {
+            return Some(at);
+        }
+        pos = at + needle.len();
+    }
+    None
+}
+
+/// End of the `<table>` block starting at `start`, past its matching close.
+fn table_end(s: &str, start: usize) -> Option<usize> {
+    let mut depth = 0usize;
+    let mut pos = start;
+    loop {
+        let open = find_tag(s, "table", pos);
+        let close = s[pos..].find("</table>").map(|r| pos + r)?;
+        match open {
+            Some(o) if o < close => {
+                depth += 1;
+                pos = o + "<table".len();
+            }
+            _ => {
+                depth -= 1;
+                pos = close + "</table>".len();
+                if depth == 0 {
+                    return Some(pos);
+                }
+            }
+        }
+    }
+}
+
+enum Section {
+    Head,
+    Body,
+}
+
+fn parse_table(block: &str) -> Table {
+    let mut table = Table::default();
+    let mut section = Section::Body;
+    let mut row: Option<Vec<Cell>> = None;
+    let mut cell: Option<Cell> = None;
+    let mut in_caption = false;
+    let mut nested = 0usize;
+    let mut i = 0;
+    // Skip the outer <table ...> tag itself.
+    if let Some(gt) = block.find('>') {
+        i = gt + 1;
+    }
+    while i < block.len() {
+        let rest = &block[i..];
+        if rest.starts_with('<') {
+            let Some(gt) = rest.find('>') else {
+                break;
+            };
+            let tag = &rest[..=gt];
+            i += gt + 1;
+            let Some((name, closing)) = tag_name(tag) else {
+                continue;
+            };
+            if name == "table" {
+                if closing {
+                    nested = nested.saturating_sub(1);
+                } else {
+                    nested += 1;
+                }
+                continue;
+            }
+            if nested > 0 {
+                continue;
+            }
+            match (name.as_str(), closing) {
+                ("caption", false) => {
+                    in_caption = true;
+                    cell = Some(Cell::default());
+                }
+                ("caption", true) => {
+                    in_caption = false;
+                    table.caption = cell.take();
+                }
+                ("thead", false) => section = Section::Head,
+                ("tbody" | "tfoot", false) | ("thead", true) => section = Section::Body,
+                ("tr", false) => row = Some(Vec::new()),
+                ("tr", true) => {
+                    if let Some(r) = row.take() {
+                        match section {
+                            Section::Head => table.head.push(r),
+                            Section::Body => table.body.push(r),
+                        }
+                    }
+                }
+                ("th" | "td", false) => {
+                    cell = Some(Cell {
+                        colspan: span(tag, "colspan"),
+                        rowspan: span(tag, "rowspan"),
+                        header: name == "th",
+                        align: Align::parse(tag),
+                        ..Default::default()
+                    });
+                }
+                ("th" | "td", true) if !in_caption => {
+                    if let (Some(c), Some(r)) = (cell.take(), row.as_mut()) {
+                        r.push(c.finish());
+                    }
+                }
+                _ => {
+                    if let Some(c) = cell.as_mut() {
+                        c.push_tag(&name, closing, tag);
+                    }
+                }
+            }
+        } else {
+            let end = rest.find('<').unwrap_or(rest.len());
+            if nested == 0 {
+                if let Some(c) = cell.as_mut() {
+                    c.push_text(&rest[..end]);
+                }
+            }
+            i += end;
+        }
+    }
+    if let Some(c) = table.caption.take() {
+        let c = c.finish();
+        if !c.markdown.is_empty() {
+            table.caption = Some(c);
+        }
+    }
+    table
+}
+
+fn span(tag: &str, key: &str) -> usize {
+    attr(tag, key)
+        .and_then(|v| v.trim().parse::<usize>().ok())
+        .filter(|n| *n >= 1)
+        .unwrap_or(1)
+}
+
+impl Cell {
+    /// Append text with whitespace collapsed, as HTML renders it.
+    fn push_text(&mut self, text: &str) {
+        for (i, word) in text.split(char::is_whitespace).enumerate() {
+            if i > 0 && !self.html.is_empty() && !self.html.ends_with(' ') {
+                self.html.push(' ');
+                self.markdown.push(' ');
+            }
+            self.html.push_str(word);
+            self.markdown.push_str(&word.replace('|', "\\|"));
+        }
+    }
+
+    fn push_tag(&mut self, name: &str, closing: bool, tag: &str) {
+        match name {
+            "p" | "div" if !closing && !self.html.trim().is_empty() => {
+                self.multiline = true;
+                self.html = self.html.trim_end().to_string();
+                self.html.push_str("<br>");
+                self.markdown = self.markdown.trim_end().to_string();
+                self.markdown.push(' ');
+            }
+            "br" => {
+                self.multiline = true;
+                self.html.push_str("<br>");
+                self.markdown.push(' ');
+            }
+            "em" | "i" => {
+                self.html.push_str(if closing { "</em>" } else { "<em>" });
+                self.markdown.push('*');
+            }
+            "strong" | "b" => {
+                self.html
+                    .push_str(if closing { "</strong>" } else { "<strong>" });
+                self.markdown.push_str("**");
+            }
+            "code" => {
+                self.html
+                    .push_str(if closing { "</code>" } else { "<code>" });
+                self.markdown.push('`');
+            }
+            "sup" | "sub" => {
+                if let Some(t) = sanitize_tag(tag) {
+                    self.html.push_str(&t);
+                }
+            }
+            "a" => {
+                if let Some(t) = sanitize_tag(tag) {
+                    self.html.push_str(&t);
+                }
+                if closing {
+                    if let Some(href) = self.link.take() {
+                        self.markdown.push_str(&format!("]({})", href));
+                    }
+                } else if let Some(href) = attr(tag, "href").filter(|h| safe_href(h)) {
+                    self.markdown.push('[');
+                    self.link = Some(href);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn finish(mut self) -> Self {
+        self.html = math_delimiters(self.html.trim());
+        self.markdown = math_delimiters(self.markdown.trim());
+        self
+    }
+}
+
+/// pandoc's HTML writer marks math as `\(..\)`/`\[..\]`; use the dollar
+/// delimiters the rest of the output uses.
+fn math_delimiters(s: &str) -> String {
+    s.replace("\\(", "$")
+        .replace("\\)", "$")
+        .replace("\\[", "$$")
+        .replace("\\]", "$$")
+}
+
+impl Table {
+    fn columns(&self) -> usize {
+        self.head
+            .iter()
+            .chain(self.body.iter())
+            .map(|r| r.iter().map(|c| c.colspan).sum::<usize>())
+            .max()
+            .unwrap_or(0)
+    }
+
+    fn fits_pipe(&self) -> bool {
+        self.head.len() <= 1
+            && self.columns() > 0
+            && self
+                .head
+                .iter()
+                .chain(self.body.iter())
+                .flatten()
+                .all(|c| c.colspan == 1 && c.rowspan == 1 && !c.multiline)
+    }
+
+    fn to_pipe(&self) -> String {
+        let cols = self.columns();
+        let mut aligns: Vec<Option<Align>> = vec![None; cols];
+        for row in self.head.iter().chain(self.body.iter()) {
+            for (i, c) in row.iter().enumerate() {
+                if aligns[i].is_none() {
+                    aligns[i] = c.align;
+                }
+            }
+        }
+        let line = |cells: Vec<&str>| {
+            let mut s = String::from("|");
+            for i in 0..cols {
+                s.push(' ');
+                s.push_str(cells.get(i).copied().unwrap_or(""));
+                s.push_str(" |");
+            }
+            s.push('\n');
+            s
+        };
+        let mut out = String::new();
+        let header: Vec<&str> = self
+            .head
+            .first()
+            .map(|r| r.iter().map(|c| c.markdown.as_str()).collect())
+            .unwrap_or_default();
+        out.push_str(&line(header));
+        let rule: Vec<&str> = aligns
+            .iter()
+            .map(|a| match a {
+                Some(Align::Left) => ":---",
+                Some(Align::Center) => ":---:",
+                Some(Align::Right) => "---:",
+                None => "---",
+            })
+            .collect();
+        out.push_str(&line(rule));
+        for row in &self.body {
+            out.push_str(&line(row.iter().map(|c| c.markdown.as_str()).collect()));
+        }
+        self.push_caption(&mut out);
+        out
+    }
+
+    fn to_html(&self) -> String {
+        let mut out = String::from("<table>\n");
+        for (rows, wrapper) in [(&self.head, "thead"), (&self.body, "tbody")] {
+            if rows.is_empty() {
+                continue;
+            }
+            out.push_str(&format!("<{}>\n", wrapper));
+            for row in rows {
+                out.push_str("<tr>\n");
+                for c in row {
+                    let name = if c.header || wrapper == "thead" {
+                        "th"
+                    } else {
+                        "td"
+                    };
+                    out.push('<');
+                    out.push_str(name);
+                    if c.colspan > 1 {
+                        out.push_str(&format!(" colspan=\"{}\"", c.colspan));
+                    }
+                    if c.rowspan > 1 {
+                        out.push_str(&format!(" rowspan=\"{}\"", c.rowspan));
+                    }
+                    if let Some(a) = c.align {
+                        out.push_str(&format!(" align=\"{}\"", a.name()));
+                    }
+                    out.push('>');
+                    out.push_str(&c.html);
+                    out.push_str(&format!("</{}>\n", name));
+                }
+                out.push_str("</tr>\n");
+            }
+            out.push_str(&format!("</{}>\n", wrapper));
+        }
+        out.push_str("</table>\n");
+        self.push_caption(&mut out);
+        out
+    }
+
+    fn push_caption(&self, out: &mut String) {
+        if let Some(c) = &self.caption {
+            out.push('\n');
+            out.push_str(&c.markdown);
+            out.push('\n');
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn simple_html_table_becomes_pipe_table_with_caption() {
+        let md = "Before\n\n<table>\n<caption><p>MXPH3Z Results on <em>GLUE</em>.</p></caption>\n<thead>\n<tr>\n<th style=\"text-align: left;\">Model</th>\n<th style=\"text-align: right;\">Acc</th>\n</tr>\n</thead>\n<tbody>\n<tr>\n<td style=\"text-align: left;\">A|B</td>\n<td style=\"text-align: right;\"><strong>91.2</strong></td>\n</tr>\n</tbody>\n</table>\n\nAfter";
+        let out = convert_tables(md);
+        assert_eq!(
+            out,
+            "Before\n\n| Model | Acc |\n| :--- | ---: |\n| A\\|B | **91.2** |\n\nMXPH3Z Results on *GLUE*.\n\nAfter"
+        );
+    }
+
+    #[test]
+    fn merged_cells_keep_sanitized_html() {
+        let md = "<table>\n<thead>\n<tr>\n<th rowspan=\"2\" class=\"x\">Method</th>\n<th colspan=\"2\" style=\"text-align: center;\" onclick=\"evil()\">Score</th>\n</tr>\n<tr>\n<th>P</th>\n<th>R</th>\n</tr>\n</thead>\n<tbody>\n<tr>\n<td>Ours</td>\n<td>0.9<sup>†</sup></td>\n<td><span class=\"math inline\">\\(x^2\\)</span></td>\n</tr>\n</tbody>\n</table>";
+        let out = convert_tables(md);
+        assert!(out.starts_with("<table>\n<thead>\n<tr>\n<th rowspan=\"2\">Method</th>\n<th colspan=\"2\" align=\"center\">Score</th>\n</tr>"));
+        assert!(out.contains("<td>0.9<sup>†</sup></td>\n<td>$x^2$</td>"));
+        assert!(!out.contains("onclick"));
+        assert!(!out.contains("class"));
+    }
+
+    #[test]
+    fn headerless_and_multi_paragraph_tables() {
+        let md = "<table>\n<tbody>\n<tr>\n<td>a</td>\n<td><a href=\"https://x.org\">link</a></td>\n</tr>\n</tbody>\n</table>";
+        assert_eq!(
+            convert_tables(md),
+            "|  |  |\n| --- | --- |\n| a | [link](https://x.org) |\n"
+        );
+        let md =
+            "<table>\n<tbody>\n<tr>\n<td><p>one</p>\n<p>two</p></td>\n</tr>\n</tbody>\n</table>";
+        assert!(convert_tables(md).contains("<td>one<br>two</td>"));
+    }
+
+    #[test]
+    fn sanitize_tag_whitelists_tags_and_attributes() {
+        assert_eq!(
+            sanitize_tag("<td colspan=\"3\" style=\"color:red\">").as_deref(),
+            Some("<td colspan=\"3\">")
+        );
+        assert_eq!(
+            sanitize_tag("<a href=\"javascript:alert(1)\">").as_deref(),
+            Some("<a>")
+        );
+        assert_eq!(sanitize_tag("</TR>").as_deref(), Some("</tr>"));
+        assert_eq!(sanitize_tag("<script>"), None);
+    }
+}
@@ -0,0 +1,339 @@
+use std::ops::Range;
+
+use crate::tex_util::{
+    apply_edits, find_environment, group_end, in_ranges, is_escaped, read_arg, read_opt_arg,
+};
+
+/// Environments whose contents TeX reads verbatim, so `%` is not a comment.
+const VERBATIM_ENVS: [&str; 6] = [
+    "verbatim",
+    "verbatim*",
+    "Verbatim",
+    "lstlisting",
+    "minted",
+    "alltt",
+];
+
+/// Commands whose first argument is read verbatim, like URLs with `%20`.
+const VERBATIM_ARGS: [&str; 2] = ["url", "href"];
+
+/// Conditionals whose true branch holds drafting material.
+const FALSE_CONDITIONALS: [&str; 2] = ["iffalse", "ifdraft"];
+
+/// What to do with an annotation macro from todonotes, changes, soul and
+/// friends: how many mandatory arguments it takes (after an optional
+/// `[...]`), and which one, if any, is real text to keep.
+struct Annotation {
+    name: &'static str,
+    args: usize,
+    keep: Option<usize>,
+}
+
+const fn drop(name: &'static str, args: usize) -> Annotation {
+    Annotation {
+        name,
+        args,
+        keep: None,
+    }
+}
+
+const fn unwrap(name: &'static str, args: usize, keep: usize) -> Annotation {
+    Annotation {
+        name,
+        args,
+        keep: Some(keep),
+    }
+}
+
+const ANNOTATIONS: [Annotation; 20] = [
+    // todonotes
+    drop("todo", 1),
+    drop("missingfigure", 1),
+    drop("listoftodos", 0),
+    // changes: additions and replacements are the text going forward
+    unwrap("added", 1, 0),
+    drop("deleted", 1),
+    unwrap("replaced", 2, 0),
+    unwrap("highlight", 1, 0),
+    drop("comment", 1),
+    drop("listofchanges", 0),
+    // soul
+    unwrap("hl", 1, 0),
+    unwrap("st", 1, 0),
+    unwrap("ul", 1, 0),
+    unwrap("so", 1, 0),
+    unwrap("caps", 1, 0),
+    // fixme
+    drop("fxnote", 1),
+    drop("fxwarning", 1),
+    drop("fxerror", 1),
+    drop("fxfatal", 1),
+    drop("fixme", 1),
+    drop("listoffixmes", 0),
+];
+
+/// Remove what authors leave in their sources but not in their papers:
+/// `%` comments (keeping `\%`), `\iffalse` and `\ifdraft` branches,
+/// `comment` environments and annotation macros such as `\todo`, `\hl`
+/// and `\deleted`. Lines left empty by a removal go with it, so no
+/// paragraph breaks appear.
+pub fn clean(tex: &str) -> String {
+    let tex = strip_comments(tex);
+    let tex = strip_false_branches(&tex);
+    let tex = strip_comment_environments(&tex);
+    strip_annotations(&tex)
+}
+
+/// Ranges where `%` is literal: verbatim environments, `\verb` and URLs.
+fn verbatim_ranges(tex: &str) -> Vec<Range<usize>> {
+    let mut ranges = Vec::new();
+    for env in VERBATIM_ENVS {
+        let mut cursor = 0;
+        while let Some((range, _)) = find_environment(tex, env, cursor) {
+            cursor = range.end;
+            ranges.push(range);
+        }
+    }
+    let mut i = 0;
+    while let Some(off) = tex[i..].find('\\') {
+        let at = i + off;
+        let (name, end) = control_word(tex, at);
+        i = end.max(at + 1);
+        if name == "verb" {
+            let start = end + usize::from(tex[end..].starts_with('*'));
+            let Some(delim) = tex[start..].chars().next() else {
+                break;
+            };
+            let body = start + delim.len_utf8();
+            if let Some(close) = tex[body..].find(delim) {
+                i = body + close + delim.len_utf8();
+                ranges.push(at..i);
+            }
+        } else if VERBATIM_ARGS.contains(&name) {
+            let open = end + tex[end..].len() - tex[end..].trim_start().len();
+            if let Some(close) = group_end(tex, open) {
+                ranges.push(at..close);
+                i = close;
+            }
+        }
+    }
+    ranges
+}
+
+/// The control word at the backslash at `at` and the index after it; the
+/// name is empty for control symbols like `\%` and `\\`, which are skipped
+/// whole.
+fn control_word(tex: &str, at: usize) -> (&str, usize) {
+    let start = at + 1;
+    let len = tex[start..]
+        .find(|c: char| !c.is_ascii_alphabetic())
+        .unwrap_or(tex.len() - start);
+    if len > 0 {
+        return (&tex[start..start + len], start + len);
+    }
+    let symbol = tex[start..].chars().next().map_or(0, char::len_utf8);
+    ("", start + symbol)
+}
+
+fn strip_comments(tex: &str) -> String {
+    let verbatim = verbatim_ranges(tex);
+    let mut out = String::with_capacity(tex.len());
+    let mut pos = 0;
+    for line in tex.split_inclusive('\n') {
+        let start = pos;
+        pos += line.len();
+        let comment = line.char_indices().find(|&(i, c)| {
+            c == '%' && !is_escaped(tex, start + i) && !in_ranges(&verbatim, start + i)
+        });
+        match comment {
+            // A comment line disappears, newline and all, as it does for TeX.
+            Some((i, _)) if line[..i].trim().is_empty() => {}
+            // A bare `%` still joins the line to the next one.
+            Some((i, _)) => {
+                out.push_str(&line[..=i]);
+                if line.ends_with('\n') {
+                    out.push('\n');
+                }
+            }
+            None => out.push_str(line),
+        }
+    }
+    out
+}
+
+/// Start of a conditional: a control word `\if...` other than `\iff`, and
+/// not a declaration like `\newif\iffoo` or `\let\iffoo\iftrue`.
+fn opens_conditional(tex: &str, at: usize, name: &str) -> bool {
+    if !name.starts_with("if") || name == "iff" || name == "ifthenelse" {
+        return false;
+    }
+    let before = tex[..at].trim_end();
+    !["\\newif", "\\let", "\\def"]
+        .iter()
+        .any(|decl| before.ends_with(decl))
+        && !preceded_by_let(before)
+}
+
+/// True for the second name of `\let\a\b`.
+fn preceded_by_let(before: &str) -> bool {
+    before
+        .rfind('\\')
+        .is_some_and(|i| before[..i].trim_end().ends_with("\\let"))
+}
+
+/// Remove the true branches of `\iffalse` and `\ifdraft` with their `\fi`,
+/// keeping any `\else` branch. Nested conditionals are matched.
+fn strip_false_branches(tex: &str) -> String {
+    let mut edits: Vec<Range<usize>> = Vec::new();
+    let mut i = 0;
+    while let Some(off) = tex[i..].find('\\') {
+        let at = i + off;
+        let (name, end) = control_word(tex, at);
+        i = end.max(at + 1);
+        if !FALSE_CONDITIONALS.contains(&name) || !opens_conditional(tex, at, name) {
+            continue;
+        }
+        let Some((branch_end, is_else)) = branch_end(tex, end) else {
+            break;
+        };
+        edits.push(line_of(tex, at..branch_end));
+        i = branch_end;
+        if is_else {
+            if let Some(fi) = branch_end_fi(tex, branch_end) {
+                edits.push(line_of(tex, fi.clone()));
+                i = fi.end;
+            }
+        }
+    }
+    apply_edits(tex, edits.into_iter().map(|r| (r, String::new())).collect())
+}
+
+/// The end of the branch starting at `from`: just past the `\else` or
+/// `\fi` at its nesting level, and whether it was an `\else`.
+fn branch_end(tex: &str, from: usize) -> Option<(usize, bool)> {
+    let mut depth = 0;
+    let mut j = from;
+    while let Some(off) = tex[j..].find('\\') {
+        let at = j + off;
+        let (name, end) = control_word(tex, at);
+        j = end.max(at + 1);
+        match name {
+            "fi" if depth == 0 => return Some((end, false)),
+            "else" if depth == 0 => return Some((end, true)),
+            "fi" => depth -= 1,
+            _ if opens_conditional(tex, at, name) => depth += 1,
+            _ => {}
+        }
+    }
+    None
+}
+
+/// The `\fi` closing the `\else` branch starting at `from`.
+fn branch_end_fi(tex: &str, from: usize) -> Option<Range<usize>> {
+    let (end, is_else) = branch_end(tex, from)?;
+    (!is_else).then(|| end - "\\fi".len()..end)
+}
+
+fn strip_comment_environments(tex: &str) -> String {
+    let mut edits = Vec::new();
+    let mut cursor = 0;
+    while let Some((range, _)) = find_environment(tex, "comment", cursor) {
+        cursor = range.end;
+        edits.push((line_of(tex, range), String::new()));
+    }
+    apply_edits(tex, edits)
+}
+
+fn strip_annotations(tex: &str) -> String {
+    let mut edits = Vec::new();
+    let mut i = 0;
+    while let Some(off) = tex[i..].find('\\') {
+        let at = i + off;
+        let (name, end) = control_word(tex, at);
+        i = end.max(at + 1);
+        let Some(note) = ANNOTATIONS.iter().find(|a| a.name == name) else {
+            continue;
+        };
+        if in_definition(tex, at) {
+            continue;
+        }
+        let mut pos = read_opt_arg(tex, end).map_or(end, |(_, e)| e);
+        let mut args = Vec::new();
+        for _ in 0..note.args {
+            let Some((arg, e)) = read_arg(tex, pos) else {
+                break;
+            };
+            args.push(arg);
+            pos = e;
+        }
+        if args.len() < note.args {
+            continue;
+        }
+        match note.keep {
+            Some(k) => edits.push((at..pos, strip_annotations(args[k]))),
+            None => edits.push((line_of(tex, at..pos), String::new())),
+        }
+        i = pos;
+    }
+    apply_edits(tex, edits)
+}
+
+/// True when the command at `at` is being defined rather than used, as in
+/// `\newcommand{\todo}[1]{}` or `\let\hl\relax`.
+fn in_definition(tex: &str, at: usize) -> bool {
+    let before = tex[..at].trim_end().trim_end_matches('{').trim_end();
+    let before = before.trim_end_matches('*');
+    [
+        "\\newcommand",
+        "\\renewcommand",
+        "\\providecommand",
+        "\\DeclareRobustCommand",
+        "\\def",
+        "\\let",
+    ]
+    .iter()
+    .any(|decl| before.ends_with(decl))
+}
+
+/// `range` widened to its whole line, newline included, when nothing else
+/// is on that line.
+fn line_of(tex: &str, range: Range<usize>) -> Range<usize> {
+    let start = tex[..range.start].rfind('\n').map_or(0, |i| i + 1);
+    let end = tex[range.end..]
+        .find('\n')
+        .map_or(tex.len(), |i| range.end + i + 1);
+    if tex[start..range.start].trim().is_empty() && tex[range.end..end].trim().is_empty() {
+        start..end
+    } else {
+        range
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn comments_go_but_escaped_and_verbatim_percent_stay() {
+        let tex = "50\\% of runs% noisy\n% whole line\n  %indented\nnext\n\\url{a%20b} \\verb|x%y|\n\\begin{verbatim}\n% kept\n\\end{verbatim}\n";
+        assert_eq!(
+            clean(tex),
+            "50\\% of runs%\nnext\n\\url{a%20b} \\verb|x%y|\n\\begin{verbatim}\n% kept\n\\end{verbatim}\n"
+        );
+    }
+
+    #[test]
+    fn false_branches_are_removed_and_else_kept() {
+        let tex = "\\newif\\ifdraft\nA\n\\iffalse\nold \\ifx\\a\\b x\\fi text\n\\fi\nB\n\\ifdraft\nreviewer note\n\\else\nfinal\n\\fi\nC $a \\iff b$\n";
+        assert_eq!(clean(tex), "\\newif\\ifdraft\nA\nB\nfinal\nC $a \\iff b$\n");
+    }
+
+    #[test]
+    fn annotations_are_dropped_or_unwrapped() {
+        let tex = "\\newcommand{\\todo}[1]{}\nWe \\hl{show} it\\todo[inline]{cite this}.\n\\todo{check}\nIt \\replaced[id=R1]{is}{was} \\deleted{very} \\added{quite} good.\n\\begin{comment}\nhidden\n\\end{comment}\nEnd.";
+        assert_eq!(
+            clean(tex),
+            "\\newcommand{\\todo}[1]{}\nWe show it.\nIt is  quite good.\nEnd."
+        );
+    }
+}
@@ -0,0 +1,127 @@
+/// 8-bit encodings older TeX sources are written in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    /// ISO-8859-1.
+    Latin1,
+    /// ISO-8859-15, Latin-1 with the euro sign and a few letters swapped in.
+    Latin9,
+    /// Windows-1252, Latin-1 with printable characters in 0x80–0x9F.
+    Cp1252,
+}
+
+impl Encoding {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Latin1 => "latin1",
+            Self::Latin9 => "latin9",
+            Self::Cp1252 => "cp1252",
+        }
+    }
+
+    /// The encoding named by an `inputenc` option, if it is one we decode.
+    fn from_inputenc(option: &str) -> Option<Self> {
+        match option.trim() {
+            "latin1" => Some(Self::Latin1),
+            "latin9" => Some(Self::Latin9),
+            "cp1252" | "ansinew" => Some(Self::Cp1252),
+            _ => None,
+        }
+    }
+
+    fn decode_byte(self, b: u8) -> char {
+        match (self, b) {
+            (Self::Cp1252, 0x80..=0x9f) => CP1252_HIGH[(b - 0x80) as usize],
+            (Self::Latin9, 0xa4) => '€',
+            (Self::Latin9, 0xa6) => 'Š',
+            (Self::Latin9, 0xa8) => 'š',
+            (Self::Latin9, 0xb4) => 'Ž',
+            (Self::Latin9, 0xb8) => 'ž',
+            (Self::Latin9, 0xbc) => 'Œ',
+            (Self::Latin9, 0xbd) => 'œ',
+            (Self::Latin9, 0xbe) => 'Ÿ',
+            _ => b as char,
+        }
+    }
+}
+
+/// Windows-1252 0x80–0x9F; the five undefined slots keep their C1 code point.
+const CP1252_HIGH: [char; 32] = [
+    '€', '\u{81}', '‚', 'ƒ', '„', '…', '†', '‡', 'ˆ', '‰', 'Š', '‹', 'Œ', '\u{8d}', 'Ž', '\u{8f}',
+    '\u{90}', '‘', '’', '“', '”', '•', '–', '—', '˜', '™', 'š', '›', 'œ', '\u{9d}', 'ž', 'Ÿ',
+];
+
+/// Decode a TeX source file to UTF-8 text. Valid UTF-8 (with or without a
+/// BOM) is returned as is; anything else is decoded with the encoding its
+/// `\usepackage[...]{inputenc}` or `\inputencoding{...}` names, or else
+/// Windows-1252, which agrees with Latin-1 on every printable character.
+/// The encoding is returned when the bytes were transcoded.
+pub fn decode(bytes: &[u8]) -> (String, Option<Encoding>) {
+    let bytes = bytes.strip_prefix(b"\xef\xbb\xbf").unwrap_or(bytes);
+    if let Ok(s) = std::str::from_utf8(bytes) {
+        return (s.to_string(), None);
+    }
+    let encoding = declared_encoding(&String::from_utf8_lossy(bytes)).unwrap_or(Encoding::Cp1252);
+    let text = bytes.iter().map(|&b| encoding.decode_byte(b)).collect();
+    (text, Some(encoding))
+}
+
+/// The 8-bit encoding declared through `inputenc`, if any.
+fn declared_encoding(text: &str) -> Option<Encoding> {
+    let mut pos = 0;
+    while let Some(off) = text[pos..].find("{inputenc}") {
+        let end = pos + off;
+        pos = end + 1;
+        let head = text[..end].trim_end();
+        let Some(opts) = head.strip_suffix(']') else {
+            continue;
+        };
+        let Some(open) = opts.rfind('[') else {
+            continue;
+        };
+        if !opts[..open].trim_end().ends_with("\\usepackage") {
+            continue;
+        }
+        if let Some(enc) = opts[open + 1..]
+            .split(',')
+            .find_map(Encoding::from_inputenc)
+        {
+            return Some(enc);
+        }
+    }
+    text.match_indices("\\inputencoding{").find_map(|(i, m)| {
+        let rest = &text[i + m.len()..];
+        Encoding::from_inputenc(&rest[..rest.find('}')?])
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn utf8_passes_through() {
+        let (text, enc) = decode("\u{feff}Café".as_bytes());
+        assert_eq!(text, "Café");
+        assert_eq!(enc, None);
+    }
+
+    #[test]
+    fn inputenc_hint_picks_the_encoding() {
+        let src =
+            b"\\usepackage[T1]{fontenc}\n\\usepackage[latin9]{inputenc}\nPrix: 5 \xa4, \xe9t\xe9";
+        let (text, enc) = decode(src);
+        assert_eq!(enc, Some(Encoding::Latin9));
+        assert!(text.ends_with("Prix: 5 €, été"));
+
+        let (text, enc) = decode(b"\\usepackage[latin1]{inputenc}\n\xa4");
+        assert_eq!(enc, Some(Encoding::Latin1));
+        assert!(text.ends_with('¤'));
+    }
+
+    #[test]
+    fn undeclared_8bit_text_is_read_as_cp1252() {
+        let (text, enc) = decode(b"\x93quoted\x94 na\xefve");
+        assert_eq!(enc, Some(Encoding::Cp1252));
+        assert_eq!(text, "“quoted” naïve");
+    }
+}
@@ -1,55 +1,270 @@
 use std::path::{Path, PathBuf};
 
-// Heuristic: choose main .tex among (path, contents)
-pub fn select_main_tex(files: &[(PathBuf, String)]) -> Option<PathBuf> {
-    if files.is_empty() {
-        return None;
+use crate::tex_util::{flatten_inputs, input_names};
+
+/// arXiv's per-submission processing directives, from `00README.json` or
+/// the older `00README.XXD`. Paths are relative to the archive root.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Readme {
+    /// Files arXiv compiles as top-level documents, in order.
+    pub toplevel: Vec<String>,
+    /// Files arXiv skips.
+    pub ignore: Vec<String>,
+}
+
+impl Readme {
+    /// Parse a README by file name; `None` for other files or unreadable
+    /// contents.
+    pub fn parse(file_name: &str, contents: &str) -> Option<Self> {
+        match file_name {
+            "00README.json" => Self::parse_json(contents),
+            "00README.XXD" | "00README" => Some(Self::parse_xxd(contents)),
+            _ => None,
+        }
+    }
+
+    /// `{"sources": [{"filename": "main.tex", "usage": "toplevel"}, ...]}`
+    fn parse_json(contents: &str) -> Option<Self> {
+        let value: serde_json::Value = serde_json::from_str(contents).ok()?;
+        let mut readme = Self::default();
+        for source in value.get("sources")?.as_array()? {
+            let (Some(name), Some(usage)) = (
+                source.get("filename").and_then(|v| v.as_str()),
+                source.get("usage").and_then(|v| v.as_str()),
+            ) else {
+                continue;
+            };
+            match usage {
+                "toplevel" => readme.toplevel.push(name.to_string()),
+                "ignore" => readme.ignore.push(name.to_string()),
+                _ => {}
+            }
+        }
+        Some(readme)
+    }
+
+    /// One `filename directive` pair per line, e.g. `main.tex toplevelfile`.
+    fn parse_xxd(contents: &str) -> Self {
+        let mut readme = Self::default();
+        for line in contents.lines() {
+            let mut words = line.split_whitespace();
+            let (Some(name), Some(directive)) = (words.next(), words.next()) else {
+                continue;
+            };
+            match directive {
+                "toplevelfile" => readme.toplevel.push(name.to_string()),
+                "ignore" => readme.ignore.push(name.to_string()),
+                _ => {}
+            }
+        }
+        readme
     }
-    let mut tex_files: Vec<(PathBuf, String)> = files
+}
+
+/// `path` names the archive file `rel`, compared by trailing components so
+/// absolute paths under the extraction directory match.
+fn names(path: &Path, rel: &str) -> bool {
+    let rel = rel.trim_start_matches("./");
+    !rel.is_empty() && path.ends_with(rel)
+}
+
+// Heuristic: choose main .tex among (path, contents)
+pub fn select_main_tex(files: &[(PathBuf, String)], readme: Option<&Readme>) -> Option<PathBuf> {
+    rank_main_tex(files, readme).into_iter().next()
+}
+
+/// Main-file candidates, best first. A `toplevel` README directive wins
+/// outright; otherwise files with `\begin{document}` come first, then
+/// real documents over `standalone`/`beamer` ones, non-supplementary
+/// names, the file that `\input`s the most others, and finally the longest.
+pub fn rank_main_tex(files: &[(PathBuf, String)], readme: Option<&Readme>) -> Vec<PathBuf> {
+    let ignored = |p: &Path| readme.is_some_and(|r| r.ignore.iter().any(|i| names(p, i)));
+    let tex_files: Vec<&(PathBuf, String)> = files
         .iter()
         .filter(|(p, _)| p.extension().map(|e| e == "tex").unwrap_or(false))
-        .map(|(p, c)| (p.clone(), c.clone()))
+        .filter(|(p, _)| !ignored(p))
         .collect();
-    if tex_files.is_empty() {
-        return None;
-    }
-    if tex_files.len() == 1 {
-        return Some(tex_files.remove(0).0);
+
+    let mut ranked: Vec<PathBuf> = Vec::new();
+    if let Some(readme) = readme {
+        for top in &readme.toplevel {
+            if let Some((p, _)) = tex_files.iter().find(|(p, _)| names(p, top)) {
+                if !ranked.contains(p) {
+                    ranked.push(p.clone());
+                }
+            }
+        }
     }
-    // Prefer files containing \documentclass and not matching supplementary pattern
-    tex_files.sort_by_key(|(p, c)| {
-        let has_dc = c.contains("\\documentclass");
-        let is_supp = is_supplementary_name(p);
+
+    let mut rest: Vec<(&PathBuf, &String)> = tex_files
+        .iter()
+        .filter(|(p, _)| !ranked.contains(p))
+        .map(|(p, c)| (p, c))
+        .collect();
+    rest.sort_by_key(|(p, c)| {
         (
-            !has_dc,                    // prefer has documentclass
-            is_supp,                    // avoid supplementary
+            !c.contains("\\begin{document}"), // require a document body
+            is_secondary_class(c),            // avoid standalone figures, slides
+            !c.contains("\\documentclass"),   // prefer has documentclass
+            is_supplementary_name(p),         // avoid supplementary
+            std::cmp::Reverse(input_count(p, files)),
             std::cmp::Reverse(c.len()), // longer content last key to reverse preference
         )
     });
-    tex_files.into_iter().next().map(|(p, _)| p)
+    ranked.extend(rest.into_iter().map(|(p, _)| p.clone()));
+    ranked
+}
+
+/// The main file named by a `?main=` override (relative to the archive
+/// `root`), if the archive has it.
+pub fn find_main_override(root: &Path, files: &[(PathBuf, String)], main: &str) -> Option<PathBuf> {
+    let path = root.join(main.trim_start_matches("./"));
+    files
+        .iter()
+        .find(|(p, _)| *p == path)
+        .map(|(p, _)| p.clone())
+}
+
+/// Standalone supplementary documents besides `main`: files with a
+/// supplementary name and their own `\begin{document}` that `main` doesn't
+/// already pull in. Ordered by where `main` first mentions them (e.g.
+/// `\externaldocument{supp}`), unmentioned ones last by name.
+pub fn secondary_documents(
+    files: &[(PathBuf, String)],
+    main: &Path,
+    readme: Option<&Readme>,
+) -> Vec<PathBuf> {
+    let main_text = flatten_inputs(main, files).unwrap_or_default();
+    let included = included_files(main, files);
+    let mut docs: Vec<(usize, &PathBuf)> = files
+        .iter()
+        .filter(|(p, c)| {
+            p.as_path() != main
+                && p.extension().is_some_and(|e| e == "tex")
+                && is_supplementary_name(p)
+                && c.contains("\\begin{document}")
+                && !is_secondary_class(c)
+                && !included.contains(p)
+                && !readme.is_some_and(|r| r.ignore.iter().any(|i| names(p, i)))
+        })
+        .map(|(p, _)| {
+            let stem = p.file_stem().and_then(|s| s.to_str()).unwrap_or("");
+            let mention = mention_position(&main_text, stem).unwrap_or(usize::MAX);
+            (mention, p)
+        })
+        .collect();
+    docs.sort();
+    docs.into_iter().map(|(_, p)| p.clone()).collect()
+}
+
+/// First position of `stem` in `text` as a whole word (not part of a
+/// longer name).
+fn mention_position(text: &str, stem: &str) -> Option<usize> {
+    if stem.is_empty() {
+        return None;
+    }
+    let is_name = |c: char| c.is_alphanumeric() || c == '_' || c == '-';
+    text.match_indices(stem).map(|(at, _)| at).find(|&at| {
+        let before = text[..at].chars().next_back();
+        let after = text[at + stem.len()..].chars().next();
+        !before.is_some_and(is_name) && !after.is_some_and(is_name)
+    })
+}
+
+/// Files `main` pulls in, directly or through other inputs.
+fn included_files(main: &Path, files: &[(PathBuf, String)]) -> Vec<PathBuf> {
+    let mut seen: Vec<PathBuf> = Vec::new();
+    let mut stack = vec![main.to_path_buf()];
+    while let Some(path) = stack.pop() {
+        for target in input_targets(&path, files) {
+            if !seen.contains(&target) && target != main {
+                seen.push(target.clone());
+                stack.push(target);
+            }
+        }
+    }
+    seen
 }
 
+/// `\documentclass{standalone}` (TikZ figures) and `beamer` (slides) are
+/// rarely the paper itself.
+fn is_secondary_class(contents: &str) -> bool {
+    let Some(at) = contents.find("\\documentclass") else {
+        return false;
+    };
+    let rest = &contents[at..];
+    let class = rest
+        .find('{')
+        .and_then(|open| {
+            rest[open + 1..]
+                .find('}')
+                .map(|len| &rest[open + 1..open + 1 + len])
+        })
+        .unwrap_or("");
+    matches!(class.trim(), "standalone" | "beamer")
+}
+
+/// How many other archive files `path` pulls in with `\input` and friends.
+fn input_count(path: &Path, files: &[(PathBuf, String)]) -> usize {
+    input_targets(path, files).len()
+}
+
+/// The other archive files `path` names in `\input` and friends.
+fn input_targets(path: &Path, files: &[(PathBuf, String)]) -> Vec<PathBuf> {
+    let Some((_, contents)) = files.iter().find(|(p, _)| p == path) else {
+        return Vec::new();
+    };
+    let base = path.parent().unwrap_or(Path::new(""));
+    input_names(contents)
+        .into_iter()
+        .filter_map(|name| {
+            let mut candidates = vec![base.join(name)];
+            if !name.ends_with(".tex") {
+                candidates.push(base.join(format!("{}.tex", name)));
+            }
+            files
+                .iter()
+                .find(|(p, _)| p != path && candidates.contains(p))
+                .map(|(p, _)| p.clone())
+        })
+        .collect()
+}
+
+/// A file name with a whole word marking a supplement: `supp`,
+/// `supplement(ary|al)`, `appendix`, or `si`, optionally numbered, so
+/// `si-extra.tex` and `appendix_b.tex` count but `analysis.tex` and
+/// `thesis.tex` don't.
 fn is_supplementary_name(p: &Path) -> bool {
     let name = p
         .file_stem()
         .and_then(|s| s.to_str())
         .unwrap_or("")
         .to_ascii_lowercase();
-    let bad = ["supp", "supplement", "appendix", "si", "supplementary"];
-    bad.iter().any(|b| name.contains(b))
+    name.split(|c: char| !c.is_ascii_alphanumeric())
+        .any(|word| {
+            let word = word.trim_end_matches(|c: char| c.is_ascii_digit());
+            matches!(
+                word,
+                "supp" | "suppl" | "suppmat" | "si" | "appendix" | "appendices"
+            ) || word.starts_with("supplement")
+        })
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    fn file(path: &str, contents: &str) -> (PathBuf, String) {
+        (PathBuf::from(path), contents.to_string())
+    }
+
     #[test]
     fn picks_single_tex() {
         let files = vec![(
             PathBuf::from("main.tex"),
             String::from("\\documentclass{article}"),
         )];
-        let pick = select_main_tex(&files).unwrap();
+        let pick = select_main_tex(&files, None).unwrap();
         assert_eq!(pick, PathBuf::from("main.tex"));
     }
 
@@ -62,7 +277,7 @@ mod tests {
                 String::from("% preamble\n\\documentclass{article}\n\\begin{document}"),
             ),
         ];
-        let pick = select_main_tex(&files).unwrap();
+        let pick = select_main_tex(&files, None).unwrap();
         assert_eq!(pick, PathBuf::from("paper.tex"));
     }
 
@@ -78,13 +293,143 @@ mod tests {
                 String::from("\\documentclass{article}"),
             ),
         ];
-        let pick = select_main_tex(&files).unwrap();
+        let pick = select_main_tex(&files, None).unwrap();
         assert_eq!(pick, PathBuf::from("main.tex"));
     }
 
     #[test]
     fn returns_none_when_no_tex() {
         let files = vec![(PathBuf::from("readme.md"), String::from("hello"))];
-        assert!(select_main_tex(&files).is_none());
+        assert!(select_main_tex(&files, None).is_none());
+    }
+
+    #[test]
+    fn prefers_documents_that_input_others_over_standalone_and_letters() {
+        let doc = "\\documentclass{article}\n\\begin{document}\n";
+        let files = vec![
+            file(
+                "/x/fig.tex",
+                &format!(
+                    "\\documentclass{{standalone}}\n\\begin{{document}}{}",
+                    "t".repeat(500)
+                ),
+            ),
+            file(
+                "/x/letter.tex",
+                &format!("{}Dear editor, {}", doc, "x".repeat(300)),
+            ),
+            file(
+                "/x/paper.tex",
+                &format!(
+                    "{}\\input{{intro}}\n\\include{{sec/method}}\n% \\input{{old}}",
+                    doc
+                ),
+            ),
+            file("/x/intro.tex", "Intro."),
+            file("/x/sec/method.tex", "Method."),
+            file("/x/old.tex", "Old."),
+            file("/x/macros.tex", "\\documentclass{article}"),
+        ];
+        let ranked = rank_main_tex(&files, None);
+        assert_eq!(ranked[0], PathBuf::from("/x/paper.tex"));
+        assert_eq!(ranked[1], PathBuf::from("/x/letter.tex"));
+        assert_eq!(ranked[2], PathBuf::from("/x/fig.tex"));
+    }
+
+    #[test]
+    fn honors_readme_directives() {
+        let doc = "\\documentclass{article}\n\\begin{document}\n";
+        let files = vec![
+            file("/x/a.tex", &format!("{}{}", doc, "long ".repeat(100))),
+            file("/x/sub/b.tex", doc),
+        ];
+        let json = r#"{"process": {"compiler": "pdflatex"},
+            "sources": [{"filename": "sub/b.tex", "usage": "toplevel"},
+                        {"filename": "a.tex", "usage": "ignore"}]}"#;
+        let readme = Readme::parse("00README.json", json).unwrap();
+        assert_eq!(readme.toplevel, vec!["sub/b.tex"]);
+        assert_eq!(
+            rank_main_tex(&files, Some(&readme)),
+            vec![PathBuf::from("/x/sub/b.tex")]
+        );
+
+        let readme = Readme::parse("00README.XXD", "sub/b.tex toplevelfile\nnostamp\n").unwrap();
+        assert_eq!(
+            select_main_tex(&files, Some(&readme)),
+            Some(PathBuf::from("/x/sub/b.tex"))
+        );
+        assert_eq!(Readme::parse("README.md", ""), None);
+    }
+
+    #[test]
+    fn finds_supplements_in_reference_order() {
+        let doc = "\\documentclass{article}\n\\begin{document}\n";
+        let files = vec![
+            file(
+                "/x/main.tex",
+                &format!(
+                    "{}\\externaldocument{{appendix-b}}\n\\input{{supp-inline}}\nSee supplement.",
+                    doc
+                ),
+            ),
+            file("/x/supplement.tex", doc),
+            file("/x/appendix-b.tex", doc),
+            file("/x/supp-inline.tex", doc),
+            file(
+                "/x/supp-fig.tex",
+                "\\documentclass{standalone}\n\\begin{document}",
+            ),
+            file("/x/si-extra.tex", doc),
+            file("/x/letter.tex", doc),
+        ];
+        assert_eq!(
+            secondary_documents(&files, Path::new("/x/main.tex"), None),
+            vec![
+                PathBuf::from("/x/appendix-b.tex"),
+                PathBuf::from("/x/supplement.tex"),
+                PathBuf::from("/x/si-extra.tex"),
+            ]
+        );
+    }
+
+    #[test]
+    fn supplementary_names_match_whole_words() {
+        for name in [
+            "supp.tex",
+            "Supplementary.tex",
+            "paper_supplemental.tex",
+            "appendix-b.tex",
+            "appendix2.tex",
+            "si.tex",
+            "main-SI.tex",
+            "suppmat.tex",
+        ] {
+            assert!(is_supplementary_name(Path::new(name)), "{}", name);
+        }
+        for name in [
+            "analysis.tex",
+            "thesis.tex",
+            "revision.tex",
+            "support.tex",
+            "single.tex",
+            "main.tex",
+        ] {
+            assert!(!is_supplementary_name(Path::new(name)), "{}", name);
+        }
+    }
+
+    #[test]
+    fn main_override_matches_relative_path() {
+        let files = vec![file("/x/sub/b.tex", ""), file("/x/b.tex", "")];
+        let root = Path::new("/x");
+        assert_eq!(
+            find_main_override(root, &files, "sub/b.tex"),
+            Some(PathBuf::from("/x/sub/b.tex"))
+        );
+        assert_eq!(
+            find_main_override(root, &files, "./b.tex"),
+            Some(PathBuf::from("/x/b.tex"))
+        );
+        assert_eq!(find_main_override(root, &files, "c.tex"), None);
     }
 }
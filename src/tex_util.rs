@@ -0,0 +1,649 @@
+use std::collections::HashMap;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+/// Given the byte index of an opening `{`, return the index just past its
+/// matching `}`. Escaped braces (`\{`, `\}`) are ignored.
+pub fn group_end(s: &str, open: usize) -> Option<usize> {
+    let bytes = s.as_bytes();
+    if bytes.get(open) != Some(&b'{') {
+        return None;
+    }
+    let mut depth = 0usize;
+    let mut i = open;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\\' => {
+                i += 2;
+                continue;
+            }
+            b'{' => depth += 1,
+            b'}' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i + 1);
+                }
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    None
+}
+
+/// Read a mandatory `{...}` argument starting at `pos` (leading whitespace is
+/// skipped). Returns the inner text and the index just past the closing brace.
+pub fn read_arg(s: &str, pos: usize) -> Option<(&str, usize)> {
+    let start = skip_ws(s, pos);
+    let end = group_end(s, start)?;
+    Some((&s[start + 1..end - 1], end))
+}
+
+/// Read an optional `[...]` argument starting at `pos`. Brackets nested inside
+/// braces do not terminate the argument.
+pub fn read_opt_arg(s: &str, pos: usize) -> Option<(&str, usize)> {
+    let start = skip_ws(s, pos);
+    let bytes = s.as_bytes();
+    if bytes.get(start) != Some(&b'[') {
+        return None;
+    }
+    let mut depth = 0usize;
+    let mut i = start + 1;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\\' => {
+                i += 2;
+                continue;
+            }
+            b'{' => depth += 1,
+            b'}' => depth = depth.saturating_sub(1),
+            b']' if depth == 0 => return Some((&s[start + 1..i], i + 1)),
+            _ => {}
+        }
+        i += 1;
+    }
+    None
+}
+
+/// Skip spaces, tabs and at most one newline, mirroring how TeX looks for
+/// macro arguments.
+pub fn skip_ws(s: &str, mut pos: usize) -> usize {
+    let bytes = s.as_bytes();
+    let mut newlines = 0;
+    while pos < bytes.len() {
+        match bytes[pos] {
+            b' ' | b'\t' | b'\r' => pos += 1,
+            b'\n' if newlines == 0 => {
+                newlines += 1;
+                pos += 1;
+            }
+            _ => break,
+        }
+    }
+    pos
+}
+
+/// Find the next occurrence of the control sequence `\name` at or after
+/// `from`, making sure it is not a prefix of a longer command name and is not
+/// itself escaped (`\\name`).
+pub fn find_command(s: &str, name: &str, from: usize) -> Option<usize> {
+    let needle = format!("\\{}", name);
+    let mut search = from;
+    while let Some(rel) = s.get(search..)?.find(&needle) {
+        let at = search + rel;
+        let after = at + needle.len();
+        let next_is_letter = s[after..]
+            .chars()
+            .next()
+            .map(|c| c.is_ascii_alphabetic())
+            .unwrap_or(false);
+        let escaped = at > 0 && is_escaped(s, at);
+        if !next_is_letter && !escaped {
+            return Some(at);
+        }
+        search = after;
+    }
+    None
+}
+
+/// True when the byte at `at` is preceded by an odd number of backslashes.
+pub fn is_escaped(s: &str, at: usize) -> bool {
+    let bytes = s.as_bytes();
+    let mut n = 0;
+    let mut i = at;
+    while i > 0 && bytes[i - 1] == b'\\' {
+        n += 1;
+        i -= 1;
+    }
+    n % 2 == 1
+}
+
+/// Locate `\begin{env}` ... `\end{env}` starting at or after `from`. Returns
+/// the byte range of the whole environment and the range of its body.
+pub fn find_environment(s: &str, env: &str, from: usize) -> Option<(Range<usize>, Range<usize>)> {
+    let open = format!("\\begin{{{}}}", env);
+    let close = format!("\\end{{{}}}", env);
+    let start = from + s.get(from..)?.find(&open)?;
+    let body_start = start + open.len();
+    let mut depth = 1usize;
+    let mut i = body_start;
+    loop {
+        let next_open = s[i..].find(&open).map(|r| i + r);
+        let next_close = s[i..].find(&close).map(|r| i + r)?;
+        match next_open {
+            Some(o) if o < next_close => {
+                depth += 1;
+                i = o + open.len();
+            }
+            _ => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some((start..next_close + close.len(), body_start..next_close));
+                }
+                i = next_close + close.len();
+            }
+        }
+    }
+}
+
+const MATH_ENVS: &[&str] = &[
+    "equation",
+    "equation*",
+    "align",
+    "align*",
+    "gather",
+    "gather*",
+    "multline",
+    "multline*",
+    "eqnarray",
+    "eqnarray*",
+    "flalign",
+    "flalign*",
+    "alignat",
+    "alignat*",
+    "math",
+    "displaymath",
+];
+
+/// Byte ranges of math regions: `$...$`, `$$...$$`, `\(...\)`, `\[...\]` and
+/// the usual display environments. Ranges include their delimiters.
+pub fn math_ranges(s: &str) -> Vec<Range<usize>> {
+    let bytes = s.as_bytes();
+    let mut out = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\\' => {
+                let rest = &s[i..];
+                if rest.starts_with("\\(") || rest.starts_with("\\[") {
+                    let close = if rest.starts_with("\\(") {
+                        "\\)"
+                    } else {
+                        "\\]"
+                    };
+                    if let Some(rel) = rest[2..].find(close) {
+                        let end = i + 2 + rel + 2;
+                        out.push(i..end);
+                        i = end;
+                        continue;
+                    }
+                } else if rest.starts_with("\\begin{") {
+                    let name_end = rest.find('}').unwrap_or(rest.len());
+                    let name = &rest[7..name_end];
+                    if MATH_ENVS.contains(&name) {
+                        if let Some((range, _)) = find_environment(s, name, i) {
+                            i = range.end;
+                            out.push(range);
+                            continue;
+                        }
+                    }
+                }
+                i += 2;
+            }
+            b'$' => {
+                let display = bytes.get(i + 1) == Some(&b'$');
+                let delim = if display { "$$" } else { "$" };
+                let body = i + delim.len();
+                let mut j = body;
+                let mut end = None;
+                while j < bytes.len() {
+                    if bytes[j] == b'\\' {
+                        j += 2;
+                        continue;
+                    }
+                    if bytes[j..].starts_with(delim.as_bytes()) {
+                        end = Some(j + delim.len());
+                        break;
+                    }
+                    j += 1;
+                }
+                match end {
+                    Some(e) => {
+                        out.push(i..e);
+                        i = e;
+                    }
+                    None => i += delim.len(),
+                }
+            }
+            _ => i += 1,
+        }
+    }
+    out
+}
+
+pub fn in_ranges(ranges: &[Range<usize>], pos: usize) -> bool {
+    ranges.iter().any(|r| r.contains(&pos))
+}
+
+/// Replace byte ranges of `tex`, given in any order. An edit overlapping
+/// one that starts earlier is skipped.
+pub(crate) fn apply_edits(tex: &str, mut edits: Vec<(Range<usize>, String)>) -> String {
+    edits.sort_by_key(|(r, _)| (r.start, r.end));
+    let mut out = String::with_capacity(tex.len());
+    let mut cursor = 0;
+    for (range, replacement) in edits {
+        if range.start < cursor {
+            continue; // overlapping edit; keep the first one
+        }
+        out.push_str(&tex[cursor..range.start]);
+        out.push_str(&replacement);
+        cursor = range.end;
+    }
+    out.push_str(&tex[cursor..]);
+    out
+}
+
+/// Inline `\input{...}` and `\include{...}` directives recursively, resolving
+/// names against the collected `.tex` files. Unknown includes are left as-is
+/// so pandoc can still try to resolve them.
+pub fn flatten_inputs(main: &Path, files: &[(PathBuf, String)]) -> Option<String> {
+    let by_path: HashMap<&Path, &str> = files
+        .iter()
+        .map(|(p, c)| (p.as_path(), c.as_str()))
+        .collect();
+    let text = by_path.get(main)?;
+    let base = main.parent().unwrap_or(Path::new(""));
+    Some(flatten_rec(text, base, &by_path, 0))
+}
+
+fn flatten_rec(text: &str, base: &Path, files: &HashMap<&Path, &str>, depth: usize) -> String {
+    if depth > 16 {
+        return text.to_string();
+    }
+    let mut out = String::with_capacity(text.len());
+    let mut i = 0;
+    while i < text.len() {
+        let next = ["input", "include", "subfile"]
+            .iter()
+            .filter_map(|name| find_command(text, name, i).map(|at| (at, *name)))
+            .min_by_key(|(at, _)| *at);
+        let Some((at, name)) = next else { break };
+        let Some((arg, end)) = read_arg(text, at + name.len() + 1) else {
+            out.push_str(&text[i..at + name.len() + 1]);
+            i = at + name.len() + 1;
+            continue;
+        };
+        if line_is_commented(text, at) {
+            out.push_str(&text[i..end]);
+            i = end;
+            continue;
+        }
+        out.push_str(&text[i..at]);
+        match resolve_input(arg.trim(), base, files) {
+            Some(content) => {
+                out.push_str(&flatten_rec(content, base, files, depth + 1));
+                out.push('\n');
+            }
+            None => out.push_str(&text[at..end]),
+        }
+        i = end;
+    }
+    out.push_str(&text[i..]);
+    out
+}
+
+/// Arguments of the uncommented `\input`, `\include` and `\subfile`
+/// commands in `text`, in order.
+pub fn input_names(text: &str) -> Vec<&str> {
+    let mut names = Vec::new();
+    for name in ["input", "include", "subfile"] {
+        let mut from = 0;
+        while let Some(at) = find_command(text, name, from) {
+            from = at + name.len() + 1;
+            if line_is_commented(text, at) {
+                continue;
+            }
+            if let Some((arg, end)) = read_arg(text, from) {
+                names.push((at, arg.trim()));
+                from = end;
+            }
+        }
+    }
+    names.sort_by_key(|(at, _)| *at);
+    names.into_iter().map(|(_, n)| n).collect()
+}
+
+fn resolve_input<'a>(name: &str, base: &Path, files: &HashMap<&Path, &'a str>) -> Option<&'a str> {
+    let mut candidates = vec![base.join(name)];
+    if !name.ends_with(".tex") {
+        candidates.push(base.join(format!("{}.tex", name)));
+    }
+    candidates
+        .iter()
+        .find_map(|c| files.get(c.as_path()).copied())
+}
+
+/// True when an unescaped `%` precedes `pos` on the same line.
+pub fn line_is_commented(s: &str, pos: usize) -> bool {
+    let line_start = s[..pos].rfind('\n').map(|i| i + 1).unwrap_or(0);
+    s[line_start..pos]
+        .char_indices()
+        .any(|(i, c)| c == '%' && !is_escaped(s, line_start + i))
+}
+
+/// Convert a short run of TeX text (bibliography entries, captions, author
+/// blocks) into plain Markdown. This is deliberately small: it understands
+/// the font and link macros that show up in `.bbl` files and drops the rest
+/// of the markup while keeping math verbatim.
+pub fn tex_to_markdown_inline(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut i = 0;
+    let bytes = s.as_bytes();
+    while i < bytes.len() {
+        match bytes[i] {
+            b'$' => {
+                let end = math_ranges(&s[i..])
+                    .first()
+                    .filter(|r| r.start == 0)
+                    .map(|r| i + r.end)
+                    .unwrap_or(i + 1);
+                out.push_str(&s[i..end]);
+                i = end;
+            }
+            b'~' => {
+                out.push(' ');
+                i += 1;
+            }
+            b'{' | b'}' => i += 1,
+            b'\\' => {
+                let rest = &s[i + 1..];
+                let name_len = rest.chars().take_while(|c| c.is_ascii_alphabetic()).count();
+                if name_len == 0 {
+                    let Some(c) = rest.chars().next() else { break };
+                    i += 1 + c.len_utf8();
+                    match c {
+                        '&' | '%' | '$' | '#' | '_' | '{' | '}' => out.push(c),
+                        '\\' | ',' | ' ' => out.push(' '),
+                        '"' | '\'' | '`' | '^' | '~' | '=' | '.' => {
+                            let (letter, end) = accent_target(s, i);
+                            out.push_str(&apply_accent(c, letter));
+                            i = end;
+                        }
+                        _ => {}
+                    }
+                    continue;
+                }
+                let name = &rest[..name_len];
+                let after = i + 1 + name_len;
+                i = after;
+                match name {
+                    "emph" | "textit" | "textsl" => {
+                        if let Some((arg, end)) = read_arg(s, after) {
+                            out.push('*');
+                            out.push_str(tex_to_markdown_inline(arg).trim());
+                            out.push('*');
+                            i = end;
+                        }
+                    }
+                    "textbf" => {
+                        if let Some((arg, end)) = read_arg(s, after) {
+                            out.push_str("**");
+                            out.push_str(tex_to_markdown_inline(arg).trim());
+                            out.push_str("**");
+                            i = end;
+                        }
+                    }
+                    "url" | "path" | "nolinkurl" => {
+                        if let Some((arg, end)) = read_arg(s, after) {
+                            out.push('<');
+                            out.push_str(arg.trim());
+                            out.push('>');
+                            i = end;
+                        }
+                    }
+                    "href" => {
+                        if let Some((url, end)) = read_arg(s, after) {
+                            if let Some((text, end2)) = read_arg(s, end) {
+                                out.push('[');
+                                out.push_str(tex_to_markdown_inline(text).trim());
+                                out.push_str("](");
+                                out.push_str(url.trim());
+                                out.push(')');
+                                i = end2;
+                            } else {
+                                i = end;
+                            }
+                        }
+                    }
+                    "em" | "it" | "bf" | "sc" | "rm" | "tt" | "sf" | "newblock" | "natexlab"
+                    | "penalty" | "relax" | "protect" | "noopsort" | "bibinfo" | "bibfield"
+                    | "showarticletitle" | "showeprint" | "textsc" | "textrm" | "texttt"
+                    | "textsf" | "mbox" | "hbox" | "textup" | "textnormal" | "BibitemOpen"
+                    | "BibitemShut" | "bibnamefont" | "bibfnamefont" | "citenamefont" | "doi"
+                    | "enquote" | "ignorespaces" => {
+                        // Drop the macro, keep its argument(s) as plain text.
+                        if matches!(name, "bibinfo" | "bibfield") {
+                            if let Some((_, end)) = read_arg(s, after) {
+                                i = end;
+                            }
+                        }
+                        if name == "noopsort" {
+                            if let Some((_, end)) = read_arg(s, after) {
+                                i = end;
+                            }
+                        }
+                        if name == "doi" {
+                            if let Some((arg, end)) = read_arg(s, after) {
+                                out.push_str("doi:");
+                                out.push_str(arg.trim());
+                                i = end;
+                            }
+                        }
+                    }
+                    "ss" => out.push('ß'),
+                    "ae" => out.push('æ'),
+                    "oe" => out.push('œ'),
+                    "o" => out.push('ø'),
+                    "O" => out.push('Ø'),
+                    "l" => out.push('ł'),
+                    "i" => out.push('ı'),
+                    "aa" => out.push('å'),
+                    "AA" => out.push('Å'),
+                    "c" | "v" | "u" | "H" | "k" | "r" => {
+                        let (letter, end) = accent_target(s, after);
+                        let accent = match name {
+                            "c" => 'c',
+                            "v" => 'v',
+                            "u" => 'u',
+                            "H" => 'H',
+                            "k" => 'k',
+                            _ => 'r',
+                        };
+                        out.push_str(&apply_accent(accent, letter));
+                        i = end;
+                    }
+                    "and" => out.push_str(", "),
+                    "textendash" => out.push('–'),
+                    "textemdash" => out.push('—'),
+                    "ldots" | "dots" => out.push('…'),
+                    "LaTeX" => out.push_str("LaTeX"),
+                    "TeX" => out.push_str("TeX"),
+                    _ => {}
+                }
+                // Control words swallow one following space.
+                if i < bytes.len() && bytes[i] == b' ' && i == after {
+                    i += 1;
+                }
+            }
+            b'-' if s[i..].starts_with("---") => {
+                out.push('—');
+                i += 3;
+            }
+            b'-' if s[i..].starts_with("--") => {
+                out.push('–');
+                i += 2;
+            }
+            b'`' if s[i..].starts_with("``") => {
+                out.push('“');
+                i += 2;
+            }
+            b'\'' if s[i..].starts_with("''") => {
+                out.push('”');
+                i += 2;
+            }
+            _ => {
+                let ch = s[i..].chars().next().unwrap();
+                out.push(ch);
+                i += ch.len_utf8();
+            }
+        }
+    }
+    collapse_ws(&out)
+}
+
+fn accent_target(s: &str, pos: usize) -> (Option<char>, usize) {
+    let pos = skip_ws(s, pos);
+    if let Some((arg, end)) = read_arg(s, pos) {
+        let letter = arg.trim().trim_start_matches('\\').chars().next();
+        return (letter, end);
+    }
+    match s[pos..].chars().next() {
+        Some(c) => (Some(c), pos + c.len_utf8()),
+        None => (None, pos),
+    }
+}
+
+fn apply_accent(accent: char, letter: Option<char>) -> String {
+    let Some(letter) = letter else {
+        return String::new();
+    };
+    let table: &[(char, &str, &str)] = &[
+        ('"', "aeiouyAEIOU", "äëïöüÿÄËÏÖÜ"),
+        ('\'', "aeiouycnszAEIOUYCNSZ", "áéíóúýćńśźÁÉÍÓÚÝĆŃŚŹ"),
+        ('`', "aeiouAEIOU", "àèìòùÀÈÌÒÙ"),
+        ('^', "aeiouAEIOU", "âêîôûÂÊÎÔÛ"),
+        ('~', "anoANO", "ãñõÃÑÕ"),
+        ('=', "aeiouAEIOU", "āēīōūĀĒĪŌŪ"),
+        ('.', "zZ", "żŻ"),
+        ('c', "csCS", "çşÇŞ"),
+        ('v', "csznrzeCSZNRE", "čšžňřžěČŠŽŇŘĚ"),
+        ('u', "agAG", "ăğĂĞ"),
+        ('H', "ouOU", "őűŐŰ"),
+        ('k', "aeAE", "ąęĄĘ"),
+        ('r', "uU", "ůŮ"),
+    ];
+    for (a, from, to) in table {
+        if *a == accent {
+            if let Some(idx) = from.chars().position(|c| c == letter) {
+                if let Some(c) = to.chars().nth(idx) {
+                    return c.to_string();
+                }
+            }
+        }
+    }
+    letter.to_string()
+}
+
+fn collapse_ws(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut prev_space = false;
+    for ch in s.chars() {
+        if ch.is_whitespace() {
+            if !prev_space {
+                out.push(' ');
+            }
+            prev_space = true;
+        } else {
+            out.push(ch);
+            prev_space = false;
+        }
+    }
+    out.trim().to_string()
+}
+
+/// Turn a LaTeX label into an anchor id usable in Markdown links.
+pub fn anchor_id(prefix: &str, label: &str) -> String {
+    let mut slug = String::with_capacity(prefix.len() + label.len() + 1);
+    slug.push_str(prefix);
+    for ch in label.trim().chars() {
+        if ch.is_ascii_alphanumeric() {
+            slug.push(ch.to_ascii_lowercase());
+        } else if !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    slug.trim_end_matches('-').to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read_arg_handles_nested_braces() {
+        let s = r"\cite{a{b}c} rest";
+        let (arg, end) = read_arg(s, 5).unwrap();
+        assert_eq!(arg, "a{b}c");
+        assert_eq!(&s[end..], " rest");
+    }
+
+    #[test]
+    fn find_command_skips_longer_names() {
+        let s = r"\citet{a} \cite{b}";
+        assert_eq!(find_command(s, "cite", 0), Some(10));
+    }
+
+    #[test]
+    fn math_ranges_cover_inline_and_environments() {
+        let s = r"a $x$ b \begin{equation}y\end{equation} c \(z\)";
+        let r = math_ranges(s);
+        assert_eq!(r.len(), 3);
+        assert_eq!(&s[r[0].clone()], "$x$");
+        assert_eq!(&s[r[2].clone()], r"\(z\)");
+    }
+
+    #[test]
+    fn flatten_inlines_nested_inputs() {
+        let files = vec![
+            (
+                PathBuf::from("/w/main.tex"),
+                String::from("A\n\\input{sec/intro}\nC"),
+            ),
+            (
+                PathBuf::from("/w/sec/intro.tex"),
+                String::from("B \\include{sec/more.tex}"),
+            ),
+            (PathBuf::from("/w/sec/more.tex"), String::from("B2")),
+        ];
+        let flat = flatten_inputs(Path::new("/w/main.tex"), &files).unwrap();
+        assert!(flat.contains("A\nB B2"));
+        assert!(flat.ends_with("C"));
+    }
+
+    #[test]
+    fn tex_inline_converts_markup() {
+        let s = r#"A.~Vaswani and N.~Shazeer. \newblock {\em Attention} is all you need. \newblock In \emph{NeurIPS}, 2017. G\"{o}del \url{https://x.org}"#;
+        assert_eq!(
+            tex_to_markdown_inline(s),
+            "A. Vaswani and N. Shazeer. Attention is all you need. In *NeurIPS*, 2017. Gödel <https://x.org>"
+        );
+    }
+
+    #[test]
+    fn anchor_id_slugifies_labels() {
+        assert_eq!(
+            anchor_id("ref-", "Vaswani:2017_attn"),
+            "ref-vaswani-2017-attn"
+        );
+    }
+}
@@ -0,0 +1,130 @@
+use std::ops::Range;
+
+use crate::crossref::Numbering;
+use crate::placeholder::Placeholders;
+use crate::tex_util::{
+    apply_edits, find_environment, line_is_commented, read_opt_arg, tex_to_markdown_inline,
+};
+
+/// End-of-proof marker, LaTeX's `\qed` square.
+pub const QED: &str = "∎";
+
+/// Replace the `\begin`/`\end` of `\newtheorem` environments and `proof`
+/// with a bold label at the start of the body, e.g.
+/// `**Theorem 2** (Convergence).`, and end proofs with [`QED`]. The body
+/// stays TeX for pandoc. Runs after [`crate::crossref::resolve`], which
+/// numbers the environments and plants the anchors `\ref` links point at.
+pub fn render(tex: &str, numbering: &Numbering, ph: &mut Placeholders) -> String {
+    let mut edits: Vec<(Range<usize>, String)> = Vec::new();
+    for decl in &numbering.theorems {
+        let mut cursor = 0;
+        let mut index = 0;
+        while let Some((range, body)) = find_environment(tex, &decl.env, cursor) {
+            cursor = body.start;
+            if line_is_commented(tex, range.start) {
+                continue;
+            }
+            let number = numbering.environment_number(&decl.env, index);
+            index += 1;
+            let (note, body_start) = note(tex, body.start);
+            let mut label = match number {
+                Some(n) => format!("**{} {}**", decl.title, n),
+                None => format!("**{}**", decl.title),
+            };
+            if let Some(note) = note {
+                label.push_str(&format!(" ({})", note));
+            }
+            label.push('.');
+            edits.push((
+                range.start..body_start,
+                format!("\n\n{} ", ph.inline(label)),
+            ));
+            edits.push((body.end..range.end, "\n\n".into()));
+        }
+    }
+
+    let mut cursor = 0;
+    while let Some((range, body)) = find_environment(tex, "proof", cursor) {
+        cursor = body.start;
+        if line_is_commented(tex, range.start) {
+            continue;
+        }
+        let (note, body_start) = note(tex, body.start);
+        let label = format!("*{}.*", note.unwrap_or_else(|| "Proof".into()));
+        edits.push((
+            range.start..body_start,
+            format!("\n\n{} ", ph.inline(label)),
+        ));
+        edits.push((body.end..range.end, format!(" {}\n\n", ph.inline(QED))));
+    }
+
+    if edits.is_empty() {
+        return tex.to_string();
+    }
+    apply_edits(tex, edits)
+}
+
+/// The optional `[note]` after `\begin{env}`, rendered as Markdown, and the
+/// position the body starts after it.
+fn note(tex: &str, pos: usize) -> (Option<String>, usize) {
+    match read_opt_arg(tex, pos) {
+        Some((n, end)) if tex[pos..].trim_start_matches([' ', '\t']).starts_with('[') => {
+            let n = tex_to_markdown_inline(n).trim().to_string();
+            ((!n.is_empty()).then_some(n), end)
+        }
+        _ => (None, pos),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crossref;
+
+    fn run(tex: &str) -> String {
+        let mut ph = Placeholders::new();
+        let (resolved, numbering) = crossref::resolve(tex, &mut ph);
+        let out = render(&resolved, &numbering, &mut ph);
+        ph.resolve(&out)
+    }
+
+    #[test]
+    fn labels_theorems_with_shared_counters() {
+        let tex = r"\newtheorem{theorem}{Theorem}
+\newtheorem{lemma}[theorem]{Lemma}
+\newtheorem*{remark}{Remark}
+\begin{lemma}Bounded.\end{lemma}
+\begin{theorem}[Convergence]\label{thm:conv}
+The iterates converge.
+\end{theorem}
+\begin{remark}Sharp.\end{remark}
+By \autoref{thm:conv}.";
+        let md = run(tex);
+        assert!(md.contains("**Lemma 1**. Bounded.\n\n"));
+        let anchor = md.find("<a id=\"theorem-2\"></a>").unwrap();
+        let label = md
+            .find("**Theorem 2** (Convergence). \nThe iterates")
+            .unwrap();
+        assert!(anchor < label);
+        assert!(md.contains("**Remark**. Sharp."));
+        assert!(md.contains("By [Theorem 2](#theorem-2)."));
+        assert!(!md.contains("\\begin"));
+    }
+
+    #[test]
+    fn proofs_get_label_and_end_marker() {
+        let tex = r"\begin{proof}Trivial.\end{proof}
+\begin{proof}[Proof of \cref{x}]Also trivial.\end{proof}";
+        let md = run(tex);
+        assert!(md.contains("*Proof.* Trivial. ∎\n\n"));
+        assert!(md.contains("*Proof of ??.* Also trivial. ∎"));
+    }
+
+    #[test]
+    fn ignores_commented_environments() {
+        let tex = "\\newtheorem{theorem}{Theorem}\n% \\begin{theorem}old\\end{theorem}\n";
+        let mut ph = Placeholders::new();
+        let (resolved, numbering) = crossref::resolve(tex, &mut ph);
+        assert_eq!(render(&resolved, &numbering, &mut ph), resolved);
+    }
+}
@@ -0,0 +1,670 @@
+use crate::markdown::math_spans;
+use crate::tex_util::{find_command, is_escaped, read_arg, read_opt_arg, skip_ws};
+
+/// Math symbols and their Unicode renderings. Operator names (`\log`,
+/// `\max`, ...) map to their plain names.
+const SYMBOLS: &[(&str, &str)] = &[
+    ("alpha", "α"),
+    ("beta", "β"),
+    ("gamma", "γ"),
+    ("delta", "δ"),
+    ("epsilon", "ϵ"),
+    ("varepsilon", "ε"),
+    ("zeta", "ζ"),
+    ("eta", "η"),
+    ("theta", "θ"),
+    ("vartheta", "ϑ"),
+    ("iota", "ι"),
+    ("kappa", "κ"),
+    ("lambda", "λ"),
+    ("mu", "μ"),
+    ("nu", "ν"),
+    ("xi", "ξ"),
+    ("pi", "π"),
+    ("varpi", "ϖ"),
+    ("rho", "ρ"),
+    ("varrho", "ϱ"),
+    ("sigma", "σ"),
+    ("varsigma", "ς"),
+    ("tau", "τ"),
+    ("upsilon", "υ"),
+    ("phi", "ϕ"),
+    ("varphi", "φ"),
+    ("chi", "χ"),
+    ("psi", "ψ"),
+    ("omega", "ω"),
+    ("Gamma", "Γ"),
+    ("Delta", "Δ"),
+    ("Theta", "Θ"),
+    ("Lambda", "Λ"),
+    ("Xi", "Ξ"),
+    ("Pi", "Π"),
+    ("Sigma", "Σ"),
+    ("Upsilon", "Υ"),
+    ("Phi", "Φ"),
+    ("Psi", "Ψ"),
+    ("Omega", "Ω"),
+    ("gets", "←"),
+    ("leftarrow", "←"),
+    ("rightarrow", "→"),
+    ("to", "→"),
+    ("Leftarrow", "⇐"),
+    ("Rightarrow", "⇒"),
+    ("implies", "⇒"),
+    ("leftrightarrow", "↔"),
+    ("Leftrightarrow", "⇔"),
+    ("iff", "⇔"),
+    ("mapsto", "↦"),
+    ("uparrow", "↑"),
+    ("downarrow", "↓"),
+    ("leq", "≤"),
+    ("le", "≤"),
+    ("geq", "≥"),
+    ("ge", "≥"),
+    ("neq", "≠"),
+    ("ne", "≠"),
+    ("ll", "≪"),
+    ("gg", "≫"),
+    ("approx", "≈"),
+    ("sim", "∼"),
+    ("simeq", "≃"),
+    ("cong", "≅"),
+    ("equiv", "≡"),
+    ("propto", "∝"),
+    ("coloneqq", "≔"),
+    ("triangleq", "≜"),
+    ("times", "×"),
+    ("cdot", "·"),
+    ("cdots", "⋯"),
+    ("ldots", "…"),
+    ("dots", "…"),
+    ("vdots", "⋮"),
+    ("ddots", "⋱"),
+    ("pm", "±"),
+    ("mp", "∓"),
+    ("div", "÷"),
+    ("ast", "∗"),
+    ("star", "⋆"),
+    ("circ", "∘"),
+    ("bullet", "•"),
+    ("oplus", "⊕"),
+    ("otimes", "⊗"),
+    ("odot", "⊙"),
+    ("in", "∈"),
+    ("notin", "∉"),
+    ("ni", "∋"),
+    ("subset", "⊂"),
+    ("subseteq", "⊆"),
+    ("supset", "⊃"),
+    ("supseteq", "⊇"),
+    ("cup", "∪"),
+    ("cap", "∩"),
+    ("bigcup", "⋃"),
+    ("bigcap", "⋂"),
+    ("setminus", "∖"),
+    ("emptyset", "∅"),
+    ("varnothing", "∅"),
+    ("infty", "∞"),
+    ("partial", "∂"),
+    ("nabla", "∇"),
+    ("sum", "∑"),
+    ("prod", "∏"),
+    ("int", "∫"),
+    ("oint", "∮"),
+    ("forall", "∀"),
+    ("exists", "∃"),
+    ("neg", "¬"),
+    ("lnot", "¬"),
+    ("land", "∧"),
+    ("wedge", "∧"),
+    ("lor", "∨"),
+    ("vee", "∨"),
+    ("top", "⊤"),
+    ("bot", "⊥"),
+    ("perp", "⊥"),
+    ("parallel", "∥"),
+    ("mid", "|"),
+    ("vert", "|"),
+    ("Vert", "‖"),
+    ("langle", "⟨"),
+    ("rangle", "⟩"),
+    ("lfloor", "⌊"),
+    ("rfloor", "⌋"),
+    ("lceil", "⌈"),
+    ("rceil", "⌉"),
+    ("ell", "ℓ"),
+    ("hbar", "ℏ"),
+    ("prime", "′"),
+    ("degree", "°"),
+    ("triangleright", "▷"),
+    ("colon", ":"),
+    ("log", "log"),
+    ("ln", "ln"),
+    ("exp", "exp"),
+    ("min", "min"),
+    ("max", "max"),
+    ("sup", "sup"),
+    ("inf", "inf"),
+    ("lim", "lim"),
+    ("arg", "arg"),
+    ("argmin", "argmin"),
+    ("argmax", "argmax"),
+    ("det", "det"),
+    ("sin", "sin"),
+    ("cos", "cos"),
+    ("tan", "tan"),
+    ("tanh", "tanh"),
+    ("Pr", "Pr"),
+    ("quad", "  "),
+    ("qquad", "    "),
+];
+
+/// Relations `\not` has a precomposed negation for; others get U+0338.
+const NEGATIONS: &[(&str, &str)] = &[
+    ("=", "≠"),
+    ("<", "≮"),
+    (">", "≯"),
+    ("\\in", "∉"),
+    ("\\ni", "∌"),
+    ("\\le", "≰"),
+    ("\\leq", "≰"),
+    ("\\ge", "≱"),
+    ("\\geq", "≱"),
+    ("\\equiv", "≢"),
+    ("\\sim", "≁"),
+    ("\\simeq", "≄"),
+    ("\\approx", "≉"),
+    ("\\cong", "≇"),
+    ("\\subset", "⊄"),
+    ("\\supset", "⊅"),
+    ("\\subseteq", "⊈"),
+    ("\\supseteq", "⊉"),
+    ("\\mid", "∤"),
+    ("\\parallel", "∦"),
+];
+
+/// Macros that only change the font; their argument is kept.
+const FONT_WRAPPERS: &[&str] = &[
+    "mathrm",
+    "mathit",
+    "mathbf",
+    "mathsf",
+    "mathtt",
+    "mathcal",
+    "mathscr",
+    "mathfrak",
+    "boldsymbol",
+    "bm",
+    "text",
+    "textrm",
+    "textbf",
+    "textit",
+    "textsf",
+    "texttt",
+    "textsc",
+    "textnormal",
+    "emph",
+    "mbox",
+    "operatorname",
+    "FuncSty",
+    "KwSty",
+    "ArgSty",
+    "DataSty",
+];
+
+/// Sizing and spacing macros dropped without output.
+const DROPPED: &[&str] = &[
+    "left",
+    "right",
+    "big",
+    "Big",
+    "bigg",
+    "Bigg",
+    "bigl",
+    "bigr",
+    "Bigl",
+    "Bigr",
+    "displaystyle",
+    "textstyle",
+    "limits",
+    "nolimits",
+    "nonumber",
+    "notag",
+];
+
+const SUPERSCRIPTS: &[(char, char)] = &[
+    ('0', '⁰'),
+    ('1', '¹'),
+    ('2', '²'),
+    ('3', '³'),
+    ('4', '⁴'),
+    ('5', '⁵'),
+    ('6', '⁶'),
+    ('7', '⁷'),
+    ('8', '⁸'),
+    ('9', '⁹'),
+    ('+', '⁺'),
+    ('-', '⁻'),
+    ('=', '⁼'),
+    ('(', '⁽'),
+    (')', '⁾'),
+    ('n', 'ⁿ'),
+    ('i', 'ⁱ'),
+    ('T', 'ᵀ'),
+    ('′', '′'),
+    ('*', '*'),
+];
+
+const SUBSCRIPTS: &[(char, char)] = &[
+    ('0', '₀'),
+    ('1', '₁'),
+    ('2', '₂'),
+    ('3', '₃'),
+    ('4', '₄'),
+    ('5', '₅'),
+    ('6', '₆'),
+    ('7', '₇'),
+    ('8', '₈'),
+    ('9', '₉'),
+    ('+', '₊'),
+    ('-', '₋'),
+    ('=', '₌'),
+    ('(', '₍'),
+    (')', '₎'),
+    ('a', 'ₐ'),
+    ('e', 'ₑ'),
+    ('h', 'ₕ'),
+    ('i', 'ᵢ'),
+    ('j', 'ⱼ'),
+    ('k', 'ₖ'),
+    ('l', 'ₗ'),
+    ('m', 'ₘ'),
+    ('n', 'ₙ'),
+    ('o', 'ₒ'),
+    ('p', 'ₚ'),
+    ('r', 'ᵣ'),
+    ('s', 'ₛ'),
+    ('t', 'ₜ'),
+    ('u', 'ᵤ'),
+    ('v', 'ᵥ'),
+    ('x', 'ₓ'),
+];
+
+pub fn symbol(name: &str) -> Option<&'static str> {
+    SYMBOLS.iter().find(|(n, _)| *n == name).map(|(_, s)| *s)
+}
+
+/// Best-effort plain-text rendering of TeX math: symbols become Unicode,
+/// font wrappers are dropped, fractions become `a/b`, and scripts use
+/// Unicode super/subscript characters where every character has one.
+pub fn math_to_unicode(math: &str) -> String {
+    collapse_spaces(&convert(math, true))
+}
+
+/// Like [`math_to_unicode`], but `None` for math that doesn't read well as
+/// a line of text: environments, alignments and line breaks, nested
+/// fractions, or commands without a Unicode rendering.
+pub fn try_math_to_unicode(math: &str) -> Option<String> {
+    if math.contains("\\\\")
+        || math.contains('&')
+        || find_command(math, "begin", 0).is_some()
+        || nested_fraction(math)
+    {
+        return None;
+    }
+    let out = math_to_unicode(math);
+    (!out.contains('\\') && !out.is_empty()).then_some(out)
+}
+
+fn nested_fraction(math: &str) -> bool {
+    let mut from = 0;
+    while let Some(at) = find_command(math, "frac", from) {
+        let after = at + "\\frac".len();
+        if let Some((num, e1)) = read_arg(math, after) {
+            let den = read_arg(math, e1).map(|(d, _)| d).unwrap_or("");
+            if num.contains("\\frac") || den.contains("\\frac") {
+                return true;
+            }
+        }
+        from = after;
+    }
+    false
+}
+
+/// Replace the `$...$` and `$$...$$` math in Markdown with Unicode text
+/// where [`try_math_to_unicode`] can render it; the rest stays LaTeX.
+/// Characters Markdown would read as markup are escaped.
+pub fn markdown_math_to_unicode(md: &str) -> String {
+    let mut out = String::with_capacity(md.len());
+    let mut last = 0;
+    for span in math_spans(md) {
+        let delim = if md[..span.start].ends_with("$$") {
+            2
+        } else {
+            1
+        };
+        let Some(text) = try_math_to_unicode(&md[span.clone()]) else {
+            continue;
+        };
+        out.push_str(&md[last..span.start - delim]);
+        for c in text.chars() {
+            if matches!(c, '*' | '_' | '`' | '|' | '<' | '[' | ']') {
+                out.push('\\');
+            }
+            out.push(c);
+        }
+        last = span.end + delim;
+    }
+    out.push_str(&md[last..]);
+    out
+}
+
+/// Plain text for a run of TeX mixing prose and `$...$` math, as used in
+/// pseudocode lines.
+pub fn tex_to_plain(s: &str) -> String {
+    collapse_spaces(&convert(s, false))
+}
+
+/// `\not` applied to the relation at `pos` (`\not=` is ≠, `\not\in` is
+/// ∉), and the index past that relation.
+fn negation(s: &str, pos: usize, math: bool) -> (String, usize) {
+    let start = skip_ws(s, pos);
+    let rest = &s[start..];
+    let len = match rest.strip_prefix('\\') {
+        Some(cmd) => 1 + cmd.chars().take_while(|c| c.is_ascii_alphabetic()).count(),
+        None => rest.chars().next().map_or(0, char::len_utf8),
+    };
+    let relation = &rest[..len];
+    if let Some((_, sym)) = NEGATIONS.iter().find(|(r, _)| *r == relation) {
+        return (sym.to_string(), start + len);
+    }
+    let mut out = convert(relation, math);
+    if !out.is_empty() {
+        out.push('\u{338}');
+    }
+    (out, start + len)
+}
+
+/// Index of the next unescaped `delim` at or after `from`.
+fn find_unescaped(s: &str, delim: &str, from: usize) -> Option<usize> {
+    let mut pos = from;
+    while let Some(rel) = s.get(pos..)?.find(delim) {
+        let at = pos + rel;
+        if !is_escaped(s, at) {
+            return Some(at);
+        }
+        pos = at + delim.len();
+    }
+    None
+}
+
+/// Convert the math opened at `start` and closed by `close`; returns the
+/// rendering and the index just past the closing delimiter.
+fn convert_math_span(s: &str, start: usize, close: &str) -> (String, usize) {
+    let end = find_unescaped(s, close, start).unwrap_or(s.len());
+    (
+        convert(&s[start..end], true),
+        (end + close.len()).min(s.len()),
+    )
+}
+
+fn collapse_spaces(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for (i, word) in s.split_whitespace().enumerate() {
+        if i > 0 {
+            out.push(' ');
+        }
+        out.push_str(word);
+    }
+    out
+}
+
+fn convert(s: &str, math: bool) -> String {
+    let mut out = String::with_capacity(s.len());
+    let bytes = s.as_bytes();
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\\' => {
+                let rest = &s[i + 1..];
+                let name_len = rest.chars().take_while(|c| c.is_ascii_alphabetic()).count();
+                if name_len == 0 {
+                    let Some(c) = rest.chars().next() else { break };
+                    i += 1 + c.len_utf8();
+                    match c {
+                        '(' | '[' if !math => {
+                            let close = if c == '(' { "\\)" } else { "\\]" };
+                            let (m, end) = convert_math_span(s, i, close);
+                            out.push_str(&m);
+                            i = end;
+                        }
+                        '{' | '}' | '_' | '&' | '%' | '$' | '#' => out.push(c),
+                        '|' => out.push('‖'),
+                        ',' | ';' | ':' | ' ' | '\\' => out.push(' '),
+                        _ => {}
+                    }
+                    continue;
+                }
+                let name = &rest[..name_len];
+                let after = i + 1 + name_len;
+                i = after;
+                if name == "not" {
+                    let (negated, end) = negation(s, after, math);
+                    out.push_str(&negated);
+                    i = end;
+                } else if let Some(sym) = symbol(name) {
+                    out.push_str(sym);
+                    // Keep "\alpha x" apart; "\alpha_1" and "\log(x)" need no space.
+                    if name.len() > 1
+                        && s[after..].starts_with(' ')
+                        && sym.chars().all(|c| c.is_alphabetic())
+                    {
+                        out.push(' ');
+                    }
+                } else if FONT_WRAPPERS.contains(&name) {
+                    if let Some((arg, end)) = read_arg(s, after) {
+                        out.push_str(&convert(arg, math && !name.starts_with("text")));
+                        i = end;
+                    }
+                } else if name == "mathbb" {
+                    if let Some((arg, end)) = read_arg(s, after) {
+                        out.extend(arg.trim().chars().map(double_struck));
+                        i = end;
+                    }
+                } else if matches!(name, "frac" | "dfrac" | "tfrac") {
+                    if let Some((num, e1)) = read_arg(s, after) {
+                        if let Some((den, e2)) = read_arg(s, e1) {
+                            out.push_str(&group(&convert(num, math)));
+                            out.push('/');
+                            out.push_str(&group(&convert(den, math)));
+                            i = e2;
+                        }
+                    }
+                } else if name == "sqrt" {
+                    let mut pos = after;
+                    if let Some((_, e)) = read_opt_arg(s, pos) {
+                        pos = e;
+                    }
+                    if let Some((arg, end)) = read_arg(s, pos) {
+                        out.push('√');
+                        out.push_str(&group(&convert(arg, math)));
+                        i = end;
+                    }
+                } else if matches!(name, "hat" | "bar" | "tilde" | "vec" | "dot" | "overline") {
+                    if let Some((arg, end)) = read_arg(s, after) {
+                        let mark = match name {
+                            "hat" => '\u{302}',
+                            "bar" | "overline" => '\u{304}',
+                            "tilde" => '\u{303}',
+                            "vec" => '\u{20d7}',
+                            _ => '\u{307}',
+                        };
+                        let inner = convert(arg, math);
+                        out.push_str(&inner);
+                        if inner.chars().count() == 1 {
+                            out.push(mark);
+                        }
+                        i = end;
+                    }
+                } else if name == "Call" {
+                    if let Some((f, e1)) = read_arg(s, after) {
+                        let (args, e2) = read_arg(s, e1).unwrap_or(("", e1));
+                        out.push_str(&convert(f, false));
+                        out.push('(');
+                        out.push_str(&convert(args, math));
+                        out.push(')');
+                        i = e2;
+                    }
+                } else if DROPPED.contains(&name) {
+                } else {
+                    out.push('\\');
+                    out.push_str(name);
+                }
+            }
+            b'$' if !math => {
+                let close = if s[i..].starts_with("$$") { "$$" } else { "$" };
+                let (m, end) = convert_math_span(s, i + close.len(), close);
+                out.push_str(&m);
+                i = end;
+            }
+            b'$' => i += 1,
+            b'^' | b'_' if math => {
+                let sup = bytes[i] == b'^';
+                i += 1;
+                let (arg, end) = script_arg(s, i);
+                let inner = convert(arg, math);
+                out.push_str(&script(&inner, sup));
+                i = end;
+            }
+            b'{' | b'}' => i += 1,
+            b'~' => {
+                out.push(' ');
+                i += 1;
+            }
+            b'&' if math => {
+                out.push(' ');
+                i += 1;
+            }
+            _ => {
+                let c = s[i..].chars().next().unwrap();
+                out.push(if c == '\n' { ' ' } else { c });
+                i += c.len_utf8();
+            }
+        }
+    }
+    out
+}
+
+/// The argument of `^`/`_`: a braced group, a control word, or one character.
+fn script_arg(s: &str, pos: usize) -> (&str, usize) {
+    let pos = skip_ws(s, pos);
+    if let Some((arg, end)) = read_arg(s, pos) {
+        if s.as_bytes().get(pos) == Some(&b'{') {
+            return (arg, end);
+        }
+    }
+    if s[pos..].starts_with('\\') {
+        let len = s[pos + 1..]
+            .chars()
+            .take_while(|c| c.is_ascii_alphabetic())
+            .count()
+            .max(1);
+        let end = (pos + 1 + len).min(s.len());
+        return (&s[pos..end], end);
+    }
+    match s[pos..].chars().next() {
+        Some(c) => (&s[pos..pos + c.len_utf8()], pos + c.len_utf8()),
+        None => ("", pos),
+    }
+}
+
+fn script(inner: &str, sup: bool) -> String {
+    let table = if sup { SUPERSCRIPTS } else { SUBSCRIPTS };
+    let mapped: Option<String> = inner
+        .chars()
+        .map(|c| table.iter().find(|(from, _)| *from == c).map(|(_, to)| *to))
+        .collect();
+    match mapped {
+        Some(m) if !m.is_empty() => m,
+        _ => {
+            let mark = if sup { '^' } else { '_' };
+            if inner.chars().count() == 1 {
+                format!("{}{}", mark, inner)
+            } else {
+                format!("{}({})", mark, inner)
+            }
+        }
+    }
+}
+
+/// Parenthesize multi-character operands of `/` and `√`.
+fn group(s: &str) -> String {
+    let s = s.trim();
+    if s.chars().count() <= 1 || s.chars().all(|c| c.is_alphanumeric()) {
+        s.to_string()
+    } else {
+        format!("({})", s)
+    }
+}
+
+fn double_struck(c: char) -> char {
+    match c {
+        'R' => 'ℝ',
+        'N' => 'ℕ',
+        'Z' => 'ℤ',
+        'Q' => 'ℚ',
+        'C' => 'ℂ',
+        'E' => '𝔼',
+        'P' => 'ℙ',
+        '1' => '𝟙',
+        _ => c,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_common_math() {
+        assert_eq!(
+            math_to_unicode(r"x \gets x - \eta \nabla_\theta L"),
+            "x ← x - η ∇_θ L"
+        );
+        assert_eq!(math_to_unicode(r"\sum_{i=1}^{n} x_i^2"), "∑ᵢ₌₁ⁿ xᵢ²");
+        assert_eq!(
+            math_to_unicode(r"\frac{a+b}{2} \leq \sqrt{x}"),
+            "(a+b)/2 ≤ √x"
+        );
+        assert_eq!(math_to_unicode(r"\theta \in \mathbb{R}^{d}"), "θ ∈ ℝ^d");
+        assert_eq!(math_to_unicode(r"\mathbf{W}^\top x_{t-1}"), "W^⊤ xₜ₋₁");
+    }
+
+    #[test]
+    fn negated_relations() {
+        assert_eq!(math_to_unicode(r"r \not= 0"), "r ≠ 0");
+        assert_eq!(math_to_unicode(r"x\not\in S"), "x∉ S");
+        assert_eq!(math_to_unicode(r"a \not\le b"), "a ≰ b");
+        assert_eq!(math_to_unicode(r"a \not\rightarrow b"), "a →\u{338} b");
+    }
+
+    #[test]
+    fn plain_text_mixes_prose_and_math() {
+        assert_eq!(
+            tex_to_plain(r"update \textbf{all} weights $w_k \gets 0$~now"),
+            "update all weights wₖ ← 0 now"
+        );
+        assert_eq!(tex_to_plain(r"\Call{Train}{$D$, $\alpha$}"), "Train(D, α)");
+    }
+
+    #[test]
+    fn markdown_math_becomes_unicode_when_simple() {
+        let md = "If $\\alpha \\le \\beta^2$ then\n\n$$\n\\|x\\| = \\frac{1}{2}\n$$\n\nbut $\\frac{\\frac{a}{b}}{c}$, `$x$` and $a * b$.";
+        assert_eq!(
+            markdown_math_to_unicode(md),
+            "If α ≤ β² then\n\n‖x‖ = 1/2\n\nbut $\\frac{\\frac{a}{b}}{c}$, `$x$` and a \\* b."
+        );
+        assert_eq!(try_math_to_unicode(r"\begin{cases}1\end{cases}"), None);
+        assert_eq!(try_math_to_unicode(r"\unknowncmd x"), None);
+    }
+}
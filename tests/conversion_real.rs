@@ -1,4 +1,5 @@
-use markxiv::convert::{Converter, PandocConverter};
+use markxiv::convert::{Converter, LatexOptions, PandocConverter};
+use markxiv::report::ConversionReport;
 
 async fn read_fixture(path: &str) -> Result<Vec<u8>, std::io::Error> {
     tokio::fs::read(path).await
@@ -12,9 +13,14 @@ async fn latex_tar_to_markdown_uses_real_arxiv_source() {
     let converter = PandocConverter::new();
 
     let md = converter
-        .latex_tar_to_markdown_without_macros(&tar_bytes)
+        .latex_tar_to_markdown_without_macros(
+            &tar_bytes,
+            &LatexOptions::default(),
+            &mut ConversionReport::new(),
+        )
         .await
-        .expect("pandoc failed to convert tarball");
+        .expect("pandoc failed to convert tarball")
+        .markdown;
 
     assert!(!md.is_empty(), "markdown output should not be empty");
     assert!(
@@ -11,6 +11,7 @@ use tower::ServiceExt;
 use markxiv::arxiv::{test_helpers::MockArxivClient, ArxivError, Metadata};
 use markxiv::convert::{test_helpers::MockConverter, ConvertError};
 use markxiv::disk_cache::{DiskCache, DiskCacheConfig};
+use markxiv::header::PDF_TEXT_NOTE;
 use markxiv::routes;
 use markxiv::state::AppState;
 
@@ -34,6 +35,7 @@ async fn disk_cache_survives_across_states() {
         root: root.clone(),
         cap_bytes: 1_000_000,
         sweep_interval: Duration::from_secs(600),
+        pipeline_version: markxiv::convert::PIPELINE_VERSION,
     };
     let disk = DiskCache::new(cfg).await.unwrap();
 
@@ -47,6 +49,8 @@ async fn disk_cache_survives_across_states() {
         Err(ArxivError::NotImplemented),
     );
     let converter1 = MockConverter::new(Ok(body_md.clone()), Ok(body_md.clone()));
+    // Body headings are moved below the (here absent) title and anchored.
+    let served_md = "## Hello<a id=\"hello\"></a>";
     let state1 = AppState::new(8, client1, converter1, Some(disk.clone()));
 
     let app1 = Router::new()
@@ -65,7 +69,7 @@ async fn disk_cache_survives_across_states() {
         .unwrap();
     assert_eq!(res.status(), axum::http::StatusCode::OK);
     let body = to_bytes(res.into_body(), usize::MAX).await.unwrap();
-    assert_eq!(body.as_ref(), body_md.as_bytes());
+    assert_eq!(body.as_ref(), served_md.as_bytes());
 
     // New state with failing client should be satisfied from disk
     let client2 = MockArxivClient::new(
@@ -99,7 +103,7 @@ async fn disk_cache_survives_across_states() {
         .unwrap();
     assert_eq!(res2.status(), axum::http::StatusCode::OK);
     let body2 = to_bytes(res2.into_body(), usize::MAX).await.unwrap();
-    assert_eq!(body2.as_ref(), body_md.as_bytes());
+    assert_eq!(body2.as_ref(), served_md.as_bytes());
     assert_eq!(archive_calls.load(Ordering::SeqCst), 0);
     assert_eq!(pdf_calls.load(Ordering::SeqCst), 0);
     assert_eq!(metadata_calls.load(Ordering::SeqCst), 0);
@@ -107,6 +111,55 @@ async fn disk_cache_survives_across_states() {
     let _ = tokio::fs::remove_dir_all(root).await;
 }
 
+#[tokio::test]
+async fn pipeline_version_bump_reconverts_cached_papers() {
+    let root = tmp_dir("disk-cache-version");
+    let cfg = DiskCacheConfig {
+        root: root.clone(),
+        cap_bytes: 1_000_000,
+        sweep_interval: Duration::from_secs(600),
+        pipeline_version: 1,
+    };
+    let req = || {
+        Request::builder()
+            .uri("/abs/1234.5678")
+            .body(Body::empty())
+            .unwrap()
+    };
+    let mut latex_calls = Vec::new();
+    for (version, body_md) in [(1, "# Old"), (1, "# Unused"), (2, "# New")] {
+        let disk = DiskCache::new(DiskCacheConfig {
+            pipeline_version: version,
+            ..cfg.clone()
+        })
+        .await
+        .unwrap();
+        let client = MockArxivClient::new(
+            Ok(true),
+            Ok(Bytes::from_static(b"tar-bytes")),
+            Err(ArxivError::NotImplemented),
+            Err(ArxivError::NotImplemented),
+        );
+        let converter = MockConverter::new(Ok(body_md.into()), Ok(String::new()));
+        latex_calls.push(converter.latex_calls.clone());
+        let state = AppState::new(8, client, converter, Some(disk));
+        let app = Router::new()
+            .route("/abs/:id", get(routes::paper))
+            .with_state(state);
+        let res = app.oneshot(req()).await.unwrap();
+        let body = to_bytes(res.into_body(), usize::MAX).await.unwrap();
+        let expected = if version == 1 { "## Old" } else { "## New" };
+        assert!(body.starts_with(expected.as_bytes()), "{:?}", body);
+    }
+    let calls: Vec<usize> = latex_calls
+        .iter()
+        .map(|c| c.load(Ordering::SeqCst))
+        .collect();
+    assert_eq!(calls, vec![1, 0, 1]);
+
+    let _ = tokio::fs::remove_dir_all(root).await;
+}
+
 #[tokio::test]
 async fn refresh_query_triggers_pdf_fallback() {
     let tar_bytes = Bytes::from_static(b"tar-bytes");
@@ -145,7 +198,8 @@ async fn refresh_query_triggers_pdf_fallback() {
         let resp = app.clone().oneshot(req(uri)).await.unwrap();
         assert_eq!(resp.status(), axum::http::StatusCode::OK);
         let body = to_bytes(resp.into_body(), usize::MAX).await.unwrap();
-        assert_eq!(body.as_ref(), b"pdf text");
+        let expected = format!("{}\n\npdf text", PDF_TEXT_NOTE);
+        assert_eq!(body.as_ref(), expected.as_bytes());
     }
 
     assert_eq!(archive_calls.load(Ordering::SeqCst), 2);